@@ -1,7 +1,115 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use serde_json::json;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use regex_automata::dfa::{dense, StartKind};
+use regex_automata::MatchKind;
+use serde_json::{json, Value};
 
-use guidance_rs::guidance::to_regex;
+use guidance_rs::compat::{Index, Vocabulary};
+use guidance_rs::guidance::{build_regex_from_schema, to_regex};
+use guidance_rs::tools::{build_regex_for_tool_calls, ToolSchema};
+
+/// Schemas shaped like the ones seen in practice, by name.
+fn schemas() -> Vec<(&'static str, Value)> {
+    let address = json!({
+        "type": "object",
+        "properties": {
+            "street": {"type": "string"},
+            "city": {"type": "string", "minLength": 1, "maxLength": 40},
+            "zip": {"type": "string", "pattern": "^[0-9]{5}$"}
+        },
+        "required": ["street", "city"]
+    });
+    let countries: Vec<String> = (0..200).map(|i| format!("country-{}", i)).collect();
+
+    vec![
+        ("integer", json!({"type": "integer"})),
+        (
+            "nested_objects",
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "age": {"type": "integer"},
+                    "home": address,
+                    "work": {
+                        "type": "object",
+                        "properties": {
+                            "company": {"type": "string"},
+                            "address": address,
+                            "since": {"type": "string", "format": "date"}
+                        },
+                        "required": ["company"]
+                    },
+                    "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 5}
+                },
+                "required": ["name", "home"]
+            }),
+        ),
+        ("large_enum", json!({"enum": countries})),
+        (
+            "all_of",
+            json!({
+                "allOf": [
+                    {"type": "object", "properties": {"id": {"type": "integer"}}, "required": ["id"]},
+                    {"type": "object", "properties": {"label": {"type": "string"}}, "required": ["label"]},
+                    {"type": "object", "properties": {"score": {"type": "number"}}, "required": ["score"]}
+                ]
+            }),
+        ),
+    ]
+}
+
+fn tools() -> Vec<ToolSchema> {
+    (0..8)
+        .map(|i| {
+            ToolSchema::new(
+                &format!("tool_{}", i),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "query": {"type": "string"},
+                        "limit": {"type": "integer"},
+                        "mode": {"enum": ["fast", "exact"]}
+                    },
+                    "required": ["query"]
+                }),
+            )
+        })
+        .collect()
+}
+
+/// Every regex benchmarked by the later stages, by name.
+fn regexes() -> Vec<(&'static str, String)> {
+    let mut regexes: Vec<_> = schemas()
+        .into_iter()
+        .map(|(name, schema)| {
+            let regex = build_regex_from_schema(&schema.to_string(), None).unwrap();
+            (name, regex)
+        })
+        .collect();
+    regexes.push((
+        "tool_calls",
+        build_regex_for_tool_calls(&tools(), None).unwrap(),
+    ));
+    regexes
+}
+
+/// Single characters plus common JSON fragments, as a stand-in for a tokenizer.
+fn vocabulary() -> Vocabulary {
+    let mut vocabulary = Vocabulary::new(0);
+    let fragments = [
+        "{", "}", "[", "]", "\"", "\":", "\": ", ", \"", "true", "false", "null", "name", "city",
+        "street", "tool_", "query", "id", "country-",
+    ];
+    let tokens = (b' '..=b'~').map(|byte| vec![byte]).chain(
+        fragments
+            .iter()
+            .map(|fragment| fragment.as_bytes().to_vec()),
+    );
+    for (id, token) in (1..).zip(tokens) {
+        vocabulary.try_insert(token, id).unwrap();
+    }
+    vocabulary
+}
 
 fn benchmark_to_regex(c: &mut Criterion) {
     let json_value = json!({
@@ -13,5 +121,70 @@ fn benchmark_to_regex(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, benchmark_to_regex);
+fn benchmark_generation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generation");
+    for (name, schema) in schemas() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &schema, |b, schema| {
+            b.iter(|| to_regex(black_box(schema), None, schema).unwrap())
+        });
+    }
+    let tools = tools();
+    group.bench_function("tool_calls", |b| {
+        b.iter(|| build_regex_for_tool_calls(black_box(&tools), None).unwrap())
+    });
+    group.finish();
+}
+
+fn benchmark_hir(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hir");
+    for (name, regex) in regexes() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &regex, |b, regex| {
+            b.iter(|| regex_syntax::Parser::new().parse(black_box(regex)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn benchmark_dfa(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dfa");
+    group.sample_size(10);
+    for (name, regex) in regexes() {
+        // the same configuration the index is built with
+        let pattern = format!("(?:{})$", regex);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &pattern, |b, pattern| {
+            b.iter(|| {
+                dense::Builder::new()
+                    .configure(
+                        dense::Config::new()
+                            .match_kind(MatchKind::All)
+                            .start_kind(StartKind::Anchored),
+                    )
+                    .build(black_box(pattern))
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn benchmark_index(c: &mut Criterion) {
+    let mut group = c.benchmark_group("index");
+    group.sample_size(10);
+    let vocabulary = vocabulary();
+    for (name, regex) in regexes() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &regex, |b, regex| {
+            b.iter(|| Index::new(black_box(regex), &vocabulary).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_to_regex,
+    benchmark_generation,
+    benchmark_hir,
+    benchmark_dfa,
+    benchmark_index
+);
 criterion_main!(benches);
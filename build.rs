@@ -0,0 +1,46 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    grpc_service();
+}
+
+/// Generates the tonic service for the messages written by hand in
+/// src/grpc.rs, so building doesn't need `protoc`.
+#[cfg(feature = "grpc")]
+fn grpc_service() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::{}", input))
+            .output_type(format!("crate::grpc::{}", output))
+            .codec_path("tonic::codec::ProstCodec")
+            .build()
+    };
+
+    let service = Service::builder()
+        .name("Guidance")
+        .package("guidance")
+        .method(method(
+            "compile",
+            "Compile",
+            "CompileRequest",
+            "CompileResponse",
+        ))
+        .method(method(
+            "validate",
+            "Validate",
+            "ValidateRequest",
+            "ValidateResponse",
+        ))
+        .method(method(
+            "build_index",
+            "BuildIndex",
+            "BuildIndexRequest",
+            "BuildIndexResponse",
+        ))
+        .build();
+
+    Builder::new().build_client(false).compile(&[service]);
+}
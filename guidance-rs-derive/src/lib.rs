@@ -0,0 +1,153 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Fields, GenericArgument, PathArguments, Result,
+    Type,
+};
+
+/// Implements `guidance_rs::derive::GuidedOutput` for a struct with named
+/// fields or an enum with unit variants.
+///
+/// Primitive, `String`, `Option`, `Vec` and `Box` fields are mapped directly;
+/// any other field type must implement `GuidedOutput` and is inlined.
+#[proc_macro_derive(GuidedOutput)]
+pub fn derive_guided_output(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "GuidedOutput can't be derived for generic types",
+        ));
+    }
+
+    let schema = match &input.data {
+        Data::Struct(data) => struct_schema(&data.fields)?,
+        Data::Enum(data) => {
+            let variants = data
+                .variants
+                .iter()
+                .map(|variant| match variant.fields {
+                    Fields::Unit => Ok(variant.ident.to_string()),
+                    _ => Err(Error::new_spanned(
+                        variant,
+                        "GuidedOutput only supports enums with unit variants",
+                    )),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            quote!(::guidance_rs::derive::json!({"type": "string", "enum": [#(#variants),*]}))
+        }
+        Data::Union(data) => {
+            return Err(Error::new_spanned(
+                data.union_token,
+                "GuidedOutput can't be derived for unions",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    Ok(quote! {
+        impl ::guidance_rs::derive::GuidedOutput for #name {
+            fn json_schema() -> ::guidance_rs::derive::Value {
+                #schema
+            }
+
+            fn regex() -> &'static str {
+                static REGEX: ::std::sync::OnceLock<String> = ::std::sync::OnceLock::new();
+                REGEX.get_or_init(|| {
+                    let schema = <Self as ::guidance_rs::derive::GuidedOutput>::json_schema();
+                    ::guidance_rs::guidance::build_regex_from_schema(&schema.to_string(), None)
+                        .expect("derived schemas are always supported")
+                })
+            }
+        }
+    })
+}
+
+fn struct_schema(fields: &Fields) -> Result<TokenStream2> {
+    let Fields::Named(fields) = fields else {
+        return Err(Error::new_spanned(
+            fields,
+            "GuidedOutput only supports structs with named fields",
+        ));
+    };
+
+    let mut properties = Vec::new();
+    let mut required = Vec::new();
+    for field in &fields.named {
+        let name = field.ident.as_ref().unwrap().to_string();
+        let name = name.strip_prefix("r#").unwrap_or(&name).to_string();
+        if option_inner(&field.ty).is_none() {
+            required.push(name.clone());
+        }
+        let schema = type_schema(&field.ty)?;
+        properties.push(quote!(#name: #schema));
+    }
+
+    Ok(quote! {
+        ::guidance_rs::derive::json!({
+            "type": "object",
+            "properties": {#(#properties),*},
+            "required": [#(#required),*]
+        })
+    })
+}
+
+fn type_schema(ty: &Type) -> Result<TokenStream2> {
+    if let Some(inner) = option_inner(ty) {
+        let inner = type_schema(inner)?;
+        return Ok(quote!(
+            ::guidance_rs::derive::json!({"anyOf": [#inner, {"type": "null"}]})
+        ));
+    }
+
+    let Type::Path(path) = ty else {
+        return Err(Error::new_spanned(ty, "unsupported field type"));
+    };
+    let segment = path.path.segments.last().unwrap();
+    let schema = match segment.ident.to_string().as_str() {
+        "String" => quote!({"type": "string"}),
+        "char" => quote!({"type": "string", "minLength": 1, "maxLength": 1}),
+        "bool" => quote!({"type": "boolean"}),
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+        | "usize" => quote!({"type": "integer"}),
+        "f32" | "f64" => quote!({"type": "number"}),
+        "Vec" => {
+            let items = type_schema(generic_argument(ty)?)?;
+            quote!({"type": "array", "items": #items})
+        }
+        "Box" => return type_schema(generic_argument(ty)?),
+        _ => return Ok(quote!(<#ty as ::guidance_rs::derive::GuidedOutput>::json_schema())),
+    };
+    Ok(quote!(::guidance_rs::derive::json!(#schema)))
+}
+
+/// The `T` of an `Option<T>` field.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    if path.path.segments.last()?.ident != "Option" {
+        return None;
+    }
+    generic_argument(ty).ok()
+}
+
+fn generic_argument(ty: &Type) -> Result<&Type> {
+    if let Type::Path(path) = ty {
+        if let PathArguments::AngleBracketed(arguments) =
+            &path.path.segments.last().unwrap().arguments
+        {
+            if let Some(GenericArgument::Type(inner)) = arguments.args.first() {
+                return Ok(inner);
+            }
+        }
+    }
+    Err(Error::new_spanned(ty, "expected a type argument"))
+}
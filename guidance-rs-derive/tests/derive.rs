@@ -0,0 +1,63 @@
+use guidance_rs::derive::GuidedOutput;
+use regex::Regex;
+use serde_json::json;
+
+#[allow(dead_code)]
+#[derive(GuidedOutput)]
+enum Role {
+    Admin,
+    Guest,
+}
+
+#[allow(dead_code)]
+#[derive(GuidedOutput)]
+struct User {
+    name: String,
+    age: u8,
+    role: Role,
+    tags: Vec<String>,
+    nickname: Option<String>,
+}
+
+fn is_full_match(regex: &str, text: &str) -> bool {
+    Regex::new(&format!("^(?:{})$", regex))
+        .unwrap()
+        .is_match(text)
+}
+
+#[test]
+fn test_schema() {
+    assert_eq!(
+        User::json_schema(),
+        json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "age": {"type": "integer"},
+                "role": {"type": "string", "enum": ["Admin", "Guest"]},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "nickname": {"anyOf": [{"type": "string"}, {"type": "null"}]}
+            },
+            "required": ["name", "age", "role", "tags"]
+        })
+    );
+}
+
+#[test]
+fn test_regex_is_built_once() {
+    let regex = User::regex();
+    assert!(std::ptr::eq(regex, User::regex()));
+
+    assert!(is_full_match(
+        regex,
+        r#"{ "age": 36, "name": "Ada", "role": "Admin", "tags": [] }"#
+    ));
+    assert!(is_full_match(
+        regex,
+        r#"{"age":36,"name":"Ada","nickname":null,"role":"Guest","tags":["x"]}"#
+    ));
+    assert!(!is_full_match(
+        regex,
+        r#"{"age":36,"name":"Ada","role":"Owner","tags":[]}"#
+    ));
+}
@@ -0,0 +1,894 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use anyhow::{anyhow, Result};
+use regex_automata::dfa::{dense, Automaton, StartKind};
+use regex_automata::hybrid::{self, LazyStateID};
+use regex_automata::nfa::thompson::NFA;
+use regex_automata::util::primitives::StateID;
+use regex_automata::util::start;
+use regex_automata::{Anchored, MatchKind};
+use regex_syntax::hir::{Hir, HirKind};
+
+use crate::explain;
+use crate::guidance::{build_regex_from_schema_with_options, RegexOptions};
+
+/// Builds the anchored DFA used for guided generation.
+///
+/// Every state is reported once it holds a complete match, and the end is
+/// anchored so that nothing can follow one.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(regex_len = regex.len()))
+)]
+pub(crate) fn build_dfa(regex: &str) -> Result<dense::DFA<Vec<u32>>> {
+    build_dfa_with_options(regex, &DfaBuildOptions::default())
+}
+
+/// Limits on building a DFA.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DfaBuildOptions {
+    /// Fail with [`DfaTooLarge`] once the DFA, or what it takes to build it,
+    /// uses more than this many bytes.
+    pub max_memory_bytes: Option<usize>,
+}
+
+/// The DFA of a regex is larger than [`DfaBuildOptions::max_memory_bytes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DfaTooLarge {
+    pub max_memory_bytes: usize,
+    /// The parts of the schema with the largest DFAs, innermost first, when
+    /// it was built from a schema, see [`schema_dfa_stats`].
+    pub largest: Vec<DfaContribution>,
+}
+
+/// The DFA of the regex of one subschema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DfaContribution {
+    /// JSON Pointer to the subschema.
+    pub pointer: String,
+    /// Bytes its DFA uses, `None` when it's over the limit on its own.
+    pub memory_usage: Option<usize>,
+}
+
+impl fmt::Display for DfaTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The DFA takes more than {} bytes (max_memory_bytes)",
+            self.max_memory_bytes
+        )?;
+        for (i, part) in self.largest.iter().enumerate() {
+            f.write_str(if i == 0 {
+                ", the largest parts are "
+            } else {
+                ", "
+            })?;
+            let pointer = if part.pointer.is_empty() {
+                "#"
+            } else {
+                &part.pointer
+            };
+            match part.memory_usage {
+                Some(bytes) => write!(f, "{} ({} bytes)", pointer, bytes)?,
+                None => write!(f, "{} (over the limit)", pointer)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DfaTooLarge {}
+
+/// [`build_dfa`] within the limits of `options`.
+pub(crate) fn build_dfa_with_options(
+    regex: &str,
+    options: &DfaBuildOptions,
+) -> Result<dense::DFA<Vec<u32>>> {
+    dense::Builder::new()
+        .configure(
+            dense::Config::new()
+                .match_kind(MatchKind::All)
+                .start_kind(StartKind::Anchored)
+                .dfa_size_limit(options.max_memory_bytes)
+                .determinize_size_limit(options.max_memory_bytes),
+        )
+        .build(&format!("(?:{})$", regex))
+        .map_err(|e| match options.max_memory_bytes {
+            Some(max_memory_bytes) if e.is_size_limit_exceeded() => DfaTooLarge {
+                max_memory_bytes,
+                largest: Vec::new(),
+            }
+            .into(),
+            _ => anyhow!("Failed to build DFA for regex: {}", e),
+        })
+}
+
+pub(crate) fn start_state(dfa: &dense::DFA<Vec<u32>>) -> Result<StateID> {
+    dfa.start_state(&start::Config::new().anchored(Anchored::Yes))
+        .map_err(|e| anyhow!("Failed to compute DFA start state: {}", e))
+}
+
+/// Size of the DFA compiled for a regex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DfaStats {
+    /// States reachable from the start state, excluding the dead state.
+    pub states: usize,
+    pub final_states: usize,
+    /// Byte transitions between reachable states.
+    pub transitions: usize,
+    /// Heap memory used by the DFA, in bytes.
+    pub memory_usage: usize,
+}
+
+pub fn dfa_stats(regex: &str) -> Result<DfaStats> {
+    dfa_stats_with_options(regex, &DfaBuildOptions::default())
+}
+
+pub fn dfa_stats_with_options(regex: &str, options: &DfaBuildOptions) -> Result<DfaStats> {
+    let dfa = build_dfa_with_options(regex, options)?;
+    let start = start_state(&dfa)?;
+
+    let mut final_states = 0;
+    let mut transitions = 0;
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(state) = queue.pop_front() {
+        if dfa.is_match_state(dfa.next_eoi_state(state)) {
+            final_states += 1;
+        }
+        for byte in 0..=u8::MAX {
+            let next = dfa.next_state(state, byte);
+            if dfa.is_dead_state(next) || dfa.is_quit_state(next) {
+                continue;
+            }
+            transitions += 1;
+            if seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    Ok(DfaStats {
+        states: seen.len(),
+        final_states,
+        transitions,
+        memory_usage: dfa.memory_usage(),
+    })
+}
+
+/// Parts of the schema listed by [`DfaTooLarge`].
+const LARGEST_PARTS: usize = 3;
+
+/// The [`dfa_stats_with_options`] of the regex of `json`. When the DFA is too
+/// large, the [`DfaTooLarge`] error lists the subschemas with the largest
+/// DFAs, found by building the DFA of each fragment of
+/// [`explain::explain_with_options`].
+pub fn schema_dfa_stats(
+    json: &str,
+    options: &RegexOptions,
+    dfa_options: &DfaBuildOptions,
+) -> Result<DfaStats> {
+    let regex = build_regex_from_schema_with_options(json, options)?;
+    let error = match dfa_stats_with_options(&regex, dfa_options) {
+        Err(error) if error.is::<DfaTooLarge>() => error,
+        result => return result,
+    };
+    let Ok(mut too_large) = error.downcast::<DfaTooLarge>() else {
+        unreachable!("checked above");
+    };
+
+    let fragments = explain::explain_with_options(json, options)?.fragments;
+    let memory_usage: Vec<Option<usize>> = fragments
+        .iter()
+        .map(|fragment| {
+            build_dfa_with_options(&fragment.regex, dfa_options)
+                .ok()
+                .map(|dfa| dfa.memory_usage())
+        })
+        .collect();
+    // the parts over the limit without a part over it inside them, then the
+    // largest of the others
+    let mut parts: Vec<(bool, usize)> = (1..fragments.len())
+        .map(|i| {
+            let inside = fragments[i + 1..]
+                .iter()
+                .take_while(|fragment| fragment.depth > fragments[i].depth)
+                .count();
+            let innermost = memory_usage[i + 1..=i + inside].iter().all(Option::is_some);
+            (memory_usage[i].is_none() && innermost, i)
+        })
+        .collect();
+    parts.sort_by_key(|&(culprit, i)| {
+        (
+            std::cmp::Reverse(culprit),
+            std::cmp::Reverse(memory_usage[i].unwrap_or(usize::MAX)),
+        )
+    });
+    too_large.largest = parts
+        .into_iter()
+        .take(LARGEST_PARTS)
+        .map(|(_, i)| DfaContribution {
+            pointer: fragments[i].pointer.clone(),
+            memory_usage: memory_usage[i],
+        })
+        .collect();
+    Err(too_large.into())
+}
+
+/// Size of a regex and of the NFA compiled from it, which is cheap to build
+/// even when the DFA would be large.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexStats {
+    /// Length of the pattern, in bytes.
+    pub length: usize,
+    /// Alternations in the syntax tree, each a choice between two or more branches.
+    pub alternations: usize,
+    pub nfa_states: usize,
+    /// Heap memory used by the NFA, in bytes.
+    pub nfa_memory_usage: usize,
+}
+
+pub fn regex_stats(regex: &str) -> Result<RegexStats> {
+    let hir = regex_syntax::Parser::new()
+        .parse(regex)
+        .map_err(|e| anyhow!("Failed to parse regex: {}", e))?;
+    let nfa = NFA::new(regex).map_err(|e| anyhow!("Failed to build NFA for regex: {}", e))?;
+
+    Ok(RegexStats {
+        length: regex.len(),
+        alternations: count_alternations(&hir),
+        nfa_states: nfa.states().len(),
+        nfa_memory_usage: nfa.memory_usage(),
+    })
+}
+
+fn count_alternations(hir: &Hir) -> usize {
+    match hir.kind() {
+        HirKind::Alternation(subs) => 1 + subs.iter().map(count_alternations).sum::<usize>(),
+        HirKind::Concat(subs) => subs.iter().map(count_alternations).sum(),
+        HirKind::Capture(capture) => count_alternations(&capture.sub),
+        HirKind::Repetition(repetition) => count_alternations(&repetition.sub),
+        HirKind::Empty | HirKind::Literal(_) | HirKind::Class(_) | HirKind::Look(_) => 0,
+    }
+}
+
+/// Outcome of running an input through the DFA of a regex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validation {
+    Match,
+    Mismatch(Mismatch),
+    /// The input is a valid prefix but stops before a match is complete.
+    Incomplete,
+}
+
+/// Where an input stops matching a regex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Offset of the first byte no match continues with, which is also the
+    /// length of the longest prefix of the input a match starts with.
+    pub offset: usize,
+    pub byte: u8,
+    /// The bytes a match could have continued with instead.
+    pub expected: ByteSet,
+}
+
+/// Checks whether the whole input matches the regex, and where it fails otherwise.
+pub fn validate(regex: &str, input: &[u8]) -> Result<Validation> {
+    let dfa = build_dfa(regex)?;
+    let mut state = start_state(&dfa)?;
+
+    for (offset, &byte) in input.iter().enumerate() {
+        let Some(next) = walk(&dfa, state, &[byte]) else {
+            return Ok(Validation::Mismatch(Mismatch {
+                offset,
+                byte,
+                expected: allowed_bytes(&dfa, state),
+            }));
+        };
+        state = next;
+    }
+
+    if dfa.is_match_state(dfa.next_eoi_state(state)) {
+        Ok(Validation::Match)
+    } else {
+        Ok(Validation::Incomplete)
+    }
+}
+
+/// How far input fed to a [`PartialMatcher`] got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchStatus {
+    /// The input so far starts a match, but isn't one yet.
+    Valid,
+    /// The input so far is a match, which more input may still extend.
+    CompleteMatch,
+    /// No match starts with the input so far, whatever comes next.
+    Dead,
+}
+
+/// Checks input against a regex as it arrives, like the chunks of a streamed
+/// response, to find out as soon as possible that it can't match anymore.
+///
+/// It walks a lazy DFA, which only builds the states the input reaches, so
+/// large regexes are cheap to check against short inputs.
+pub struct PartialMatcher {
+    dfa: hybrid::dfa::DFA,
+    cache: hybrid::dfa::Cache,
+    /// The state after the input, or after its bytes before the mismatch.
+    state: LazyStateID,
+    /// Bytes fed until the input died, or so far.
+    offset: usize,
+    mismatch: Option<Mismatch>,
+}
+
+impl PartialMatcher {
+    pub fn new(regex: &str) -> Result<Self> {
+        let dfa = hybrid::dfa::DFA::builder()
+            .configure(hybrid::dfa::DFA::config().match_kind(MatchKind::All))
+            .build(&format!("(?:{})$", regex))
+            .map_err(|e| anyhow!("Failed to build lazy DFA for regex: {}", e))?;
+        let mut cache = dfa.create_cache();
+        let state = dfa
+            .start_state(&mut cache, &start::Config::new().anchored(Anchored::Yes))
+            .map_err(|e| anyhow!("Failed to compute lazy DFA start state: {}", e))?;
+        Ok(Self {
+            dfa,
+            cache,
+            state,
+            offset: 0,
+            mismatch: None,
+        })
+    }
+
+    /// Adds `bytes` to the input. Once the input is dead, it stays so.
+    pub fn feed(&mut self, bytes: &[u8]) -> MatchStatus {
+        for &byte in bytes {
+            if self.mismatch.is_some() {
+                break;
+            }
+            if let Some(next) = self.next_state(byte) {
+                self.state = next;
+                self.offset += 1;
+                continue;
+            }
+            let mut expected = ByteSet::default();
+            for other in 0..=u8::MAX {
+                if self.next_state(other).is_some() {
+                    expected.insert(other);
+                }
+            }
+            self.mismatch = Some(Mismatch {
+                offset: self.offset,
+                byte,
+                expected,
+            });
+        }
+        self.status()
+    }
+
+    pub fn status(&mut self) -> MatchStatus {
+        if self.mismatch.is_some() {
+            return MatchStatus::Dead;
+        }
+        let eoi = self
+            .dfa
+            .next_eoi_state(&mut self.cache, self.state)
+            .expect("the lazy DFA clears its cache as needed");
+        if eoi.is_match() {
+            MatchStatus::CompleteMatch
+        } else {
+            MatchStatus::Valid
+        }
+    }
+
+    /// How many bytes of the input start a match: all of them unless it's
+    /// dead, and the offset of the byte that killed it otherwise.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Where the input died, with the bytes that could have come instead.
+    pub fn mismatch(&self) -> Option<&Mismatch> {
+        self.mismatch.as_ref()
+    }
+
+    /// The state after `byte`, `None` when no match goes through it.
+    fn next_state(&mut self, byte: u8) -> Option<LazyStateID> {
+        let next = self
+            .dfa
+            .next_state(&mut self.cache, self.state, byte)
+            // only a minimum count of cache clears makes this fail
+            .expect("the lazy DFA clears its cache as needed");
+        (!next.is_dead() && !next.is_quit()).then_some(next)
+    }
+}
+
+/// A set of bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ByteSet([u128; 2]);
+
+impl ByteSet {
+    pub fn insert(&mut self, byte: u8) {
+        self.0[usize::from(byte / 128)] |= 1 << (byte % 128);
+    }
+
+    pub fn contains(&self, byte: u8) -> bool {
+        self.0[usize::from(byte / 128)] & (1 << (byte % 128)) != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|bits| bits.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == [0, 0]
+    }
+
+    /// The bytes of the set, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=u8::MAX).filter(|&byte| self.contains(byte))
+    }
+}
+
+/// Written like a regex class, such as `[",0-9\]]`.
+impl fmt::Display for ByteSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write_byte = |f: &mut fmt::Formatter<'_>, byte: u8| match byte {
+            b'\\' | b']' | b'[' | b'-' | b'^' => write!(f, "\\{}", char::from(byte)),
+            b' '..=b'~' => write!(f, "{}", char::from(byte)),
+            _ => write!(f, "\\x{:02X}", byte),
+        };
+        f.write_str("[")?;
+        let bytes: Vec<u8> = self.iter().collect();
+        for run in bytes.chunk_by(|a, b| a + 1 == *b) {
+            let (first, last) = (run[0], run[run.len() - 1]);
+            write_byte(f, first)?;
+            if last > first.saturating_add(1) {
+                f.write_str("-")?;
+            }
+            if last > first {
+                write_byte(f, last)?;
+            }
+        }
+        f.write_str("]")
+    }
+}
+
+impl FromIterator<u8> for ByteSet {
+    fn from_iter<I: IntoIterator<Item = u8>>(bytes: I) -> Self {
+        let mut set = Self::default();
+        for byte in bytes {
+            set.insert(byte);
+        }
+        set
+    }
+}
+
+/// The DFA of a regex, for samplers choosing the output a byte or a character
+/// at a time instead of through the tokens of a vocabulary.
+///
+/// States are the ones of the DFA, starting from [`CharGuide::start_state`];
+/// ids that didn't come from the same guide may panic.
+pub struct CharGuide {
+    dfa: dense::DFA<Vec<u32>>,
+}
+
+impl CharGuide {
+    pub fn new(regex: &str) -> Result<Self> {
+        Ok(Self {
+            dfa: build_dfa(regex)?,
+        })
+    }
+
+    pub fn start_state(&self) -> Result<u32> {
+        Ok(start_state(&self.dfa)?.as_u32())
+    }
+
+    /// Whether the output can end in `state`.
+    pub fn is_final_state(&self, state: u32) -> bool {
+        self.dfa
+            .is_match_state(self.dfa.next_eoi_state(Self::id(state)))
+    }
+
+    /// The state after `byte`, `None` when no match goes through it.
+    pub fn next_state(&self, state: u32, byte: u8) -> Option<u32> {
+        walk(&self.dfa, Self::id(state), &[byte]).map(|next| next.as_u32())
+    }
+
+    /// The state after the UTF-8 bytes of `c`, `None` when no match goes
+    /// through them.
+    pub fn next_state_for_char(&self, state: u32, c: char) -> Option<u32> {
+        let mut utf8 = [0; 4];
+        walk(
+            &self.dfa,
+            Self::id(state),
+            c.encode_utf8(&mut utf8).as_bytes(),
+        )
+        .map(|next| next.as_u32())
+    }
+
+    /// The state after `input`, `None` when no match goes through it.
+    pub fn next_state_for_bytes(&self, state: u32, input: &[u8]) -> Option<u32> {
+        walk(&self.dfa, Self::id(state), input).map(|next| next.as_u32())
+    }
+
+    /// The shortest text taking `state` to the end of a match, the smallest
+    /// one among those of that length, like the quotes and braces closing a
+    /// truncated output.
+    ///
+    /// `None` when no match can be completed from `state`, or when `state` is
+    /// in the middle of the UTF-8 bytes of a character, as the rest of them
+    /// isn't text on its own.
+    pub fn shortest_completion(&self, state: u32) -> Option<String> {
+        let start = Self::id(state);
+        let mut previous = HashMap::from([(start, None)]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(state) = queue.pop_front() {
+            if self.dfa.is_match_state(self.dfa.next_eoi_state(state)) {
+                let mut completion = Vec::new();
+                let mut current = state;
+                while let Some(&Some((before, byte))) = previous.get(&current) {
+                    completion.push(byte);
+                    current = before;
+                }
+                completion.reverse();
+                return String::from_utf8(completion).ok();
+            }
+            for byte in 0..=u8::MAX {
+                let Some(next) = walk(&self.dfa, state, &[byte]) else {
+                    continue;
+                };
+                if let Entry::Vacant(entry) = previous.entry(next) {
+                    entry.insert(Some((state, byte)));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// The bytes that can come next in `state`.
+    pub fn allowed_next_bytes(&self, state: u32) -> ByteSet {
+        allowed_bytes(&self.dfa, Self::id(state))
+    }
+
+    /// The characters that can come next in `state`, as ranges in increasing
+    /// order.
+    pub fn allowed_next_chars(&self, state: u32) -> Vec<RangeInclusive<char>> {
+        let mut ranges: Vec<RangeInclusive<char>> = Vec::new();
+        let mut push = |c: char| match ranges.last_mut() {
+            Some(last) if u32::from(*last.end()) + 1 == u32::from(c) => {
+                *last = *last.start()..=c;
+            }
+            _ => ranges.push(c..=c),
+        };
+        let mut utf8 = Vec::with_capacity(4);
+        self.chars(Self::id(state), &mut utf8, &mut push);
+        ranges
+    }
+
+    /// Calls `found` with every character whose UTF-8 bytes are `utf8` and
+    /// then the ones reaching `state` from there, in increasing order.
+    fn chars(&self, state: StateID, utf8: &mut Vec<u8>, found: &mut impl FnMut(char)) {
+        let bytes = match utf8.first() {
+            None => 0x00..=0xF4,
+            Some(&lead) if utf8.len() == utf8_len(lead) => {
+                if let Some(c) = std::str::from_utf8(utf8)
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                {
+                    found(c);
+                }
+                return;
+            }
+            Some(_) => 0x80..=0xBF,
+        };
+        for byte in bytes {
+            if utf8.is_empty() && (0x80..0xC2).contains(&byte) {
+                continue;
+            }
+            let Some(next) = walk(&self.dfa, state, &[byte]) else {
+                continue;
+            };
+            utf8.push(byte);
+            self.chars(next, utf8, found);
+            utf8.pop();
+        }
+    }
+
+    fn id(state: u32) -> StateID {
+        StateID::new(state as usize).expect("state ids come from the DFA")
+    }
+}
+
+/// How many bytes the UTF-8 sequence starting with `lead` takes.
+fn utf8_len(lead: u8) -> usize {
+    match lead {
+        0x00..=0x7F => 1,
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        _ => 4,
+    }
+}
+
+/// The bytes going from `state` to a state that isn't dead.
+fn allowed_bytes(dfa: &dense::DFA<Vec<u32>>, state: StateID) -> ByteSet {
+    (0..=u8::MAX)
+        .filter(|&byte| walk(dfa, state, &[byte]).is_some())
+        .collect()
+}
+
+/// The bytes every match goes on with from `state`, up to `limit` of them:
+/// those read while only one byte is allowed and the match can't end yet.
+pub(crate) fn forced_bytes(
+    dfa: &dense::DFA<Vec<u32>>,
+    mut state: StateID,
+    limit: usize,
+) -> Vec<u8> {
+    let mut forced = Vec::new();
+    while forced.len() < limit && !dfa.is_match_state(dfa.next_eoi_state(state)) {
+        let allowed = allowed_bytes(dfa, state);
+        let (1, Some(byte)) = (allowed.len(), allowed.iter().next()) else {
+            break;
+        };
+        forced.push(byte);
+        state = dfa.next_state(state, byte);
+    }
+    forced
+}
+
+/// Feeds `input` through the DFA from `state`, returning `None` when it dies.
+pub(crate) fn walk(
+    dfa: &dense::DFA<Vec<u32>>,
+    mut state: StateID,
+    input: &[u8],
+) -> Option<StateID> {
+    for &byte in input {
+        state = dfa.next_state(state, byte);
+        if dfa.is_dead_state(state) || dfa.is_quit_state(state) {
+            return None;
+        }
+    }
+    Some(state)
+}
+
+/// The longest input, up to `limit` bytes, that starts a match of both DFAs,
+/// the smallest one among those of that length.
+///
+/// A matcher reading it can't tell yet which of the two regexes it's in.
+pub(crate) fn shared_prefix(
+    a: &dense::DFA<Vec<u32>>,
+    b: &dense::DFA<Vec<u32>>,
+    limit: usize,
+) -> Result<Vec<u8>> {
+    let alive =
+        |dfa: &dense::DFA<Vec<u32>>, state| !dfa.is_dead_state(state) && !dfa.is_quit_state(state);
+
+    // the pairs of states reached by the inputs of the current length, with
+    // the smallest input reaching each
+    let mut current = vec![((start_state(a)?, start_state(b)?), Vec::new())];
+    for _ in 0..limit {
+        let mut seen = HashSet::new();
+        let mut next = Vec::new();
+        for ((state_a, state_b), input) in &current {
+            for byte in 0..=u8::MAX {
+                let pair = (a.next_state(*state_a, byte), b.next_state(*state_b, byte));
+                if alive(a, pair.0) && alive(b, pair.1) && seen.insert(pair) {
+                    let mut input = input.clone();
+                    input.push(byte);
+                    next.push((pair, input));
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        current = next;
+    }
+    Ok(current.swap_remove(0).1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dfa_stats() {
+        let stats = dfa_stats("a[bc]").unwrap();
+        assert_eq!(stats.states, 3);
+        assert_eq!(stats.final_states, 1);
+        assert_eq!(stats.transitions, 3);
+        assert!(stats.memory_usage > 0);
+    }
+
+    #[test]
+    fn test_dfa_memory_limit() {
+        let limited = DfaBuildOptions {
+            max_memory_bytes: Some(1 << 20),
+        };
+        assert!(dfa_stats_with_options("a[bc]", &limited).is_ok());
+
+        let schema = r#"{
+            "type": "object",
+            "properties": {
+                "id": {"type": "integer"},
+                "code": {"type": "string", "pattern": "[a-z]*a[a-z]{16}"}
+            },
+            "required": ["id", "code"]
+        }"#;
+        let error = schema_dfa_stats(schema, &RegexOptions::default(), &limited).unwrap_err();
+        let too_large = error.downcast_ref::<DfaTooLarge>().unwrap();
+        assert_eq!(too_large.largest[0].pointer, "/properties/code");
+        assert_eq!(too_large.largest[0].memory_usage, None);
+        assert!(too_large.largest[1].memory_usage.is_some());
+        assert!(error
+            .to_string()
+            .starts_with("The DFA takes more than 1048576 bytes (max_memory_bytes), the largest parts are /properties/code (over the limit), /properties/id ("));
+    }
+
+    #[test]
+    fn test_regex_stats() {
+        let stats = regex_stats("(ab|cd)e(fg|h|ij)").unwrap();
+        assert_eq!(stats.length, 17);
+        assert_eq!(stats.alternations, 2);
+        assert!(stats.nfa_states > 0);
+        assert!(stats.nfa_memory_usage > 0);
+    }
+
+    #[test]
+    fn test_validate() {
+        let regex = r#"\{"a":(true|false)\}"#;
+        assert_eq!(
+            validate(regex, br#"{"a":true}"#).unwrap(),
+            Validation::Match
+        );
+        assert_eq!(
+            validate(regex, br#"{"a":yes}"#).unwrap(),
+            Validation::Mismatch(Mismatch {
+                offset: 5,
+                byte: b'y',
+                expected: ByteSet::from_iter(*b"ft"),
+            })
+        );
+        assert_eq!(
+            validate(regex, br#"{"a":true}x"#).unwrap(),
+            Validation::Mismatch(Mismatch {
+                offset: 10,
+                byte: b'x',
+                expected: ByteSet::default(),
+            })
+        );
+        assert_eq!(
+            validate(regex, br#"{"a":tr"#).unwrap(),
+            Validation::Incomplete
+        );
+    }
+
+    #[test]
+    fn test_partial_matcher() {
+        let mut matcher = PartialMatcher::new(r#"\{"n":(0|[1-9][0-9]*)\}"#).unwrap();
+        assert_eq!(matcher.feed(b""), MatchStatus::Valid);
+        assert_eq!(matcher.feed(br#"{"n""#), MatchStatus::Valid);
+        assert_eq!(matcher.feed(b":12"), MatchStatus::Valid);
+        assert_eq!(matcher.feed(b"}"), MatchStatus::CompleteMatch);
+        assert_eq!(matcher.offset(), 8);
+        assert_eq!(matcher.mismatch(), None);
+        assert_eq!(matcher.feed(b" more"), MatchStatus::Dead);
+        assert_eq!(matcher.feed(b"}"), MatchStatus::Dead);
+        assert_eq!(matcher.offset(), 8);
+        assert_eq!(
+            matcher.mismatch(),
+            Some(&Mismatch {
+                offset: 8,
+                byte: b' ',
+                expected: ByteSet::default(),
+            })
+        );
+
+        let mut matcher = PartialMatcher::new("[0-9]+").unwrap();
+        assert_eq!(matcher.feed(b"12"), MatchStatus::CompleteMatch);
+        assert_eq!(matcher.feed(b"3"), MatchStatus::CompleteMatch);
+        assert_eq!(matcher.feed(b"x4"), MatchStatus::Dead);
+        assert_eq!(matcher.offset(), 3);
+        let mismatch = matcher.mismatch().unwrap();
+        assert_eq!(
+            (mismatch.byte, mismatch.expected.to_string()),
+            (b'x', "[0-9]".to_string())
+        );
+    }
+
+    #[test]
+    fn test_char_guide() {
+        let guide = CharGuide::new(r#""[a-cé]+"|null"#).unwrap();
+        let start = guide.start_state().unwrap();
+        assert_eq!(
+            guide.allowed_next_bytes(start).iter().collect::<Vec<_>>(),
+            b"\"n"
+        );
+        assert_eq!(guide.allowed_next_chars(start), ['"'..='"', 'n'..='n']);
+        assert!(!guide.is_final_state(start));
+
+        let quote = guide.next_state(start, b'"').unwrap();
+        assert_eq!(guide.allowed_next_chars(quote), ['a'..='c', 'é'..='é']);
+        assert_eq!(guide.allowed_next_bytes(quote).len(), 4);
+        assert!(guide.allowed_next_bytes(quote).contains(0xC3));
+
+        let letter = guide.next_state_for_char(quote, 'é').unwrap();
+        assert_eq!(
+            guide.allowed_next_chars(letter),
+            ['"'..='"', 'a'..='c', 'é'..='é']
+        );
+        let end = guide.next_state(letter, b'"').unwrap();
+        assert!(guide.is_final_state(end));
+        assert!(guide.allowed_next_bytes(end).is_empty());
+        assert!(guide.allowed_next_chars(end).is_empty());
+        assert_eq!(guide.next_state_for_char(quote, 'd'), None);
+
+        let any = CharGuide::new("(?s:.)").unwrap();
+        let start = any.start_state().unwrap();
+        assert_eq!(
+            any.allowed_next_chars(start),
+            ['\0'..='\u{D7FF}', '\u{E000}'..=char::MAX]
+        );
+    }
+
+    #[test]
+    fn test_byte_set() {
+        let set = ByteSet::from_iter(*b"0123456789-,]");
+        assert_eq!(set.len(), 13);
+        assert_eq!(set.to_string(), r"[,\-0-9\]]");
+        assert_eq!(
+            ByteSet::from_iter([b'a', b'b', 0xC3]).to_string(),
+            r"[ab\xC3]"
+        );
+        assert_eq!(ByteSet::default().to_string(), "[]");
+    }
+
+    #[test]
+    fn test_shortest_completion() {
+        let regex = crate::guidance::build_regex_from_schema(
+            r#"{"type": "object", "properties": {"name": {"type": "string"}, "tags": {"type": "array", "items": {"type": "integer"}}}, "required": ["name", "tags"]}"#,
+            None,
+        )
+        .unwrap();
+        let guide = CharGuide::new(&regex).unwrap();
+        let start = guide.start_state().unwrap();
+        assert_eq!(
+            guide.shortest_completion(start).as_deref(),
+            Some(r#"{"name":"","tags":[]}"#)
+        );
+
+        let truncated = guide
+            .next_state_for_bytes(start, br#"{"name": "Ada", "tags": [1, 2"#)
+            .unwrap();
+        assert_eq!(guide.shortest_completion(truncated).as_deref(), Some("]}"));
+        let done = guide.next_state_for_bytes(truncated, b"]}").unwrap();
+        assert_eq!(guide.shortest_completion(done).as_deref(), Some(""));
+
+        let guide = CharGuide::new("é").unwrap();
+        let start = guide.start_state().unwrap();
+        let inside = guide.next_state(start, 0xC3).unwrap();
+        assert_eq!(guide.shortest_completion(inside), None);
+    }
+
+    #[test]
+    fn test_shared_prefix() {
+        let shared = |a: &str, b: &str, limit| {
+            let (a, b) = (build_dfa(a).unwrap(), build_dfa(b).unwrap());
+            String::from_utf8(shared_prefix(&a, &b, limit).unwrap()).unwrap()
+        };
+        assert_eq!(shared("abcd", "abxy", 10), "ab");
+        assert_eq!(shared("[a-z]+1", "[c-f]+2", 10), "cccccccccc");
+        assert_eq!(shared("[a-z]+1", "[c-f]+2", 3), "ccc");
+        assert_eq!(shared("a", "b", 10), "");
+        assert_eq!(shared("ab?", "abc", 10), "ab");
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        assert!(dfa_stats("(").is_err());
+    }
+}
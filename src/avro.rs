@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+
+use crate::guidance::{
+    check_with_options, configured_generator, finish_regex, resolve_whitespace_pattern,
+    RegexOptions,
+};
+
+/// Builds a regex for JSON values of the Avro schema in `json`.
+pub fn build_regex_from_avro(json: &str, whitespace_pattern: Option<&str>) -> Result<String> {
+    let options = RegexOptions {
+        whitespace_pattern: whitespace_pattern.map(str::to_string),
+        ..RegexOptions::default()
+    };
+    build_regex_from_avro_with_options(json, &options)
+}
+
+/// Like [`build_regex_from_avro`], with the converted schema generated with
+/// `options`.
+pub fn build_regex_from_avro_with_options(json: &str, options: &RegexOptions) -> Result<String> {
+    let avro: Value = serde_json::from_str(json)?;
+    let schema = avro_to_schema(&avro)?;
+    check_with_options(&schema, options)?;
+
+    let whitespace_pattern = resolve_whitespace_pattern(options.whitespace_pattern.as_deref())?;
+    let generator = configured_generator(&whitespace_pattern, &schema, options);
+    finish_regex(&generator.to_regex(&schema)?, options)
+}
+
+/// Converts an Avro schema to the equivalent JSON schema.
+///
+/// Values are plain JSON rather than the Avro JSON encoding: union branches
+/// aren't wrapped in a single-key object, `bytes` and `fixed` are strings,
+/// and the `date` logical type is an ISO 8601 date instead of a day count.
+/// Named types can be referenced after their definition; recursive types
+/// are rejected.
+pub fn avro_to_schema(avro: &Value) -> Result<Value> {
+    AvroConverter::default().convert(avro, "", &mut Vec::new())
+}
+
+#[derive(Default)]
+struct AvroConverter {
+    named_types: HashMap<String, Value>,
+}
+
+impl AvroConverter {
+    fn convert(&mut self, avro: &Value, namespace: &str, stack: &mut Vec<String>) -> Result<Value> {
+        match avro {
+            Value::String(name) => self.named_or_primitive(name, namespace, stack),
+            Value::Array(branches) => {
+                let mut schemas = Vec::new();
+                let mut nullable = false;
+                for branch in branches {
+                    if branch == "null" {
+                        nullable = true;
+                    } else {
+                        schemas.push(self.convert(branch, namespace, stack)?);
+                    }
+                }
+                // keep `null` last so the common ["null", T] reads as "T or null"
+                if nullable {
+                    schemas.push(json!({"type": "null"}));
+                }
+                match schemas.len() {
+                    0 => Err(anyhow!("Avro unions can't be empty")),
+                    1 => Ok(schemas.pop().unwrap()),
+                    _ => Ok(json!({"anyOf": schemas})),
+                }
+            }
+            Value::Object(obj) => self.complex(obj, namespace, stack),
+            _ => Err(anyhow!("Invalid Avro schema: {}", avro)),
+        }
+    }
+
+    fn named_or_primitive(&self, name: &str, namespace: &str, stack: &[String]) -> Result<Value> {
+        Ok(match name {
+            "null" => json!({"type": "null"}),
+            "boolean" => json!({"type": "boolean"}),
+            "int" | "long" => json!({"type": "integer"}),
+            "float" | "double" => json!({"type": "number"}),
+            "bytes" | "string" => json!({"type": "string"}),
+            _ => {
+                let full_name = full_name(name, namespace);
+                if stack.contains(&full_name) {
+                    return Err(anyhow!(
+                        "Recursive type {} can't be mapped to a regex",
+                        full_name
+                    ));
+                }
+                self.named_types
+                    .get(&full_name)
+                    .or_else(|| self.named_types.get(name))
+                    .cloned()
+                    .ok_or_else(|| anyhow!("Unknown Avro type {}", name))?
+            }
+        })
+    }
+
+    fn complex(
+        &mut self,
+        obj: &Map<String, Value>,
+        namespace: &str,
+        stack: &mut Vec<String>,
+    ) -> Result<Value> {
+        let avro_type = obj
+            .get("type")
+            .ok_or_else(|| anyhow!("Avro schema is missing 'type'"))?;
+
+        match (
+            avro_type.as_str(),
+            obj.get("logicalType").and_then(Value::as_str),
+        ) {
+            (Some("int"), Some("date")) => return Ok(json!({"type": "string", "format": "date"})),
+            (Some("string"), Some("uuid")) => {
+                return Ok(json!({"type": "string", "format": "uuid"}))
+            }
+            _ => {}
+        }
+
+        let Some(avro_type) = avro_type.as_str() else {
+            // {"type": [...]} or {"type": {...}} wraps another schema
+            return self.convert(avro_type, namespace, stack);
+        };
+
+        match avro_type {
+            "record" | "error" => {
+                let (name, namespace) = self.name(obj, namespace)?;
+                stack.push(name.clone());
+                let fields = obj
+                    .get("fields")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| anyhow!("Record {} needs a 'fields' array", name))?;
+
+                let mut properties = Map::new();
+                let mut required = Vec::new();
+                for field in fields {
+                    let field_name = field
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .ok_or_else(|| anyhow!("Fields of {} need a name", name))?;
+                    let field_type = field
+                        .get("type")
+                        .ok_or_else(|| anyhow!("Field {} needs a type", field_name))?;
+                    properties.insert(
+                        field_name.to_string(),
+                        self.convert(field_type, &namespace, stack)?,
+                    );
+                    required.push(field_name.to_string());
+                }
+                stack.pop();
+
+                let schema =
+                    json!({"type": "object", "properties": properties, "required": required});
+                self.named_types.insert(name, schema.clone());
+                Ok(schema)
+            }
+            "enum" => {
+                let (name, _) = self.name(obj, namespace)?;
+                let symbols = obj
+                    .get("symbols")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| anyhow!("Enum {} needs a 'symbols' array", name))?;
+                let schema = json!({"enum": symbols});
+                self.named_types.insert(name, schema.clone());
+                Ok(schema)
+            }
+            "fixed" => {
+                let (name, _) = self.name(obj, namespace)?;
+                let schema = json!({"type": "string"});
+                self.named_types.insert(name, schema.clone());
+                Ok(schema)
+            }
+            "array" => {
+                let items = obj
+                    .get("items")
+                    .ok_or_else(|| anyhow!("Avro arrays need 'items'"))?;
+                Ok(json!({"type": "array", "items": self.convert(items, namespace, stack)?}))
+            }
+            "map" => {
+                let values = obj
+                    .get("values")
+                    .ok_or_else(|| anyhow!("Avro maps need 'values'"))?;
+                Ok(json!({
+                    "type": "object",
+                    "additionalProperties": self.convert(values, namespace, stack)?
+                }))
+            }
+            primitive => self.named_or_primitive(primitive, namespace, stack),
+        }
+    }
+
+    /// The full name of a named type and the namespace its children inherit.
+    fn name(&self, obj: &Map<String, Value>, namespace: &str) -> Result<(String, String)> {
+        let name = obj
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("Named Avro types need a 'name'"))?;
+        let namespace = obj
+            .get("namespace")
+            .and_then(Value::as_str)
+            .unwrap_or(namespace);
+
+        let full_name = full_name(name, namespace);
+        let namespace = full_name
+            .rsplit_once('.')
+            .map_or(String::new(), |(namespace, _)| namespace.to_string());
+        Ok((full_name, namespace))
+    }
+}
+
+fn full_name(name: &str, namespace: &str) -> String {
+    if name.contains('.') || namespace.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", namespace, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+    use crate::types::NumberSyntax;
+
+    fn user() -> Value {
+        json!({
+            "type": "record",
+            "name": "User",
+            "namespace": "com.example",
+            "fields": [
+                {"name": "email", "type": ["null", "string"], "default": null},
+                {"name": "id", "type": {"type": "string", "logicalType": "uuid"}},
+                {"name": "joined", "type": {"type": "int", "logicalType": "date"}},
+                {
+                    "name": "previous",
+                    "type": {
+                        "type": "array",
+                        "items": {"type": "enum", "name": "Status", "symbols": ["ACTIVE", "BANNED"]}
+                    }
+                },
+                {"name": "scores", "type": {"type": "map", "values": "double"}},
+                {"name": "status", "type": "Status"}
+            ]
+        })
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = avro_to_schema(&user()).unwrap();
+
+        assert_eq!(
+            schema["properties"]["email"],
+            json!({"anyOf": [{"type": "string"}, {"type": "null"}]})
+        );
+        assert_eq!(
+            schema["properties"]["previous"],
+            json!({"type": "array", "items": {"enum": ["ACTIVE", "BANNED"]}})
+        );
+        assert_eq!(schema["required"].as_array().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_regex() {
+        let regex = build_regex_from_avro(&user().to_string(), Some("")).unwrap();
+        let regex = Regex::new(&format!("^(?:{})$", regex)).unwrap();
+
+        assert!(regex.is_match(concat!(
+            r#"{"email":null,"id":"123e4567-e89b-12d3-a456-426614174000","#,
+            r#""joined":"2024-02-29","previous":["BANNED"],"scores":{"math":1.5},"status":"ACTIVE"}"#
+        )));
+        assert!(!regex.is_match(concat!(
+            r#"{"email":null,"id":"123e4567-e89b-12d3-a456-426614174000","#,
+            r#""joined":19782,"previous":[],"scores":{},"status":"ACTIVE"}"#
+        )));
+    }
+
+    #[test]
+    fn test_options() {
+        let avro =
+            json!({"type": "record", "name": "Point", "fields": [{"name": "x", "type": "int"}]});
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            number_syntax: NumberSyntax {
+                plus_sign: true,
+                ..NumberSyntax::default()
+            },
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let regex = build_regex_from_avro_with_options(&avro.to_string(), &options).unwrap();
+        assert!(regex.starts_with('^'));
+        let regex = Regex::new(&regex).unwrap();
+
+        assert!(regex.is_match(r#"{"x":+1}"#));
+        assert!(!regex.is_match(r#"{"x": 1}"#));
+    }
+
+    #[test]
+    fn test_invalid_schemas() {
+        let recursive = json!({
+            "type": "record",
+            "name": "Node",
+            "fields": [{"name": "next", "type": ["null", "Node"]}]
+        });
+        assert!(avro_to_schema(&recursive).is_err());
+        assert!(avro_to_schema(&json!("Unknown")).is_err());
+        assert!(avro_to_schema(&json!([])).is_err());
+    }
+}
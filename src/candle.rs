@@ -0,0 +1,57 @@
+use anyhow::{anyhow, Result};
+use candle_core::Tensor;
+
+/// Masks a logits tensor so that only `allowed_tokens` can be sampled.
+///
+/// The last dimension of `logits` is the vocabulary; every token id not in
+/// `allowed_tokens` is set to `-inf`. Leading dimensions (e.g. batch) are
+/// broadcast over, so this works for both `[vocab]` and `[batch, vocab]` logits.
+pub fn apply_token_mask(logits: &Tensor, allowed_tokens: &[u32]) -> Result<Tensor> {
+    let vocab_size = logits.dims().last().copied().unwrap_or(0);
+
+    let mut mask = vec![f32::NEG_INFINITY; vocab_size];
+    for &token_id in allowed_tokens {
+        let slot = mask
+            .get_mut(token_id as usize)
+            .ok_or_else(|| anyhow!("Token id {} is outside the vocabulary", token_id))?;
+        *slot = 0.0;
+    }
+
+    let mask = Tensor::from_vec(mask, vocab_size, logits.device())?.to_dtype(logits.dtype())?;
+    Ok(logits.broadcast_add(&mask)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use candle_core::Device;
+
+    use super::*;
+
+    #[test]
+    fn test_disallowed_tokens_are_masked() {
+        let logits = Tensor::new(&[1.0f32, 2.0, 3.0, 4.0], &Device::Cpu).unwrap();
+        let masked = apply_token_mask(&logits, &[1, 3]).unwrap();
+
+        let values = masked.to_vec1::<f32>().unwrap();
+        assert_eq!(values[0], f32::NEG_INFINITY);
+        assert_eq!(values[1], 2.0);
+        assert_eq!(values[2], f32::NEG_INFINITY);
+        assert_eq!(values[3], 4.0);
+    }
+
+    #[test]
+    fn test_batched_logits() {
+        let logits = Tensor::new(&[[1.0f32, 2.0], [3.0, 4.0]], &Device::Cpu).unwrap();
+        let masked = apply_token_mask(&logits, &[0]).unwrap();
+
+        let values = masked.to_vec2::<f32>().unwrap();
+        assert_eq!(values[0], vec![1.0, f32::NEG_INFINITY]);
+        assert_eq!(values[1], vec![3.0, f32::NEG_INFINITY]);
+    }
+
+    #[test]
+    fn test_out_of_range_token() {
+        let logits = Tensor::new(&[1.0f32, 2.0], &Device::Cpu).unwrap();
+        assert!(apply_token_mask(&logits, &[2]).is_err());
+    }
+}
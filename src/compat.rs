@@ -0,0 +1,876 @@
+//! Types mirroring the `Vocabulary` / `Index` / `Guide` API of outlines-core,
+//! so code written against outlines-core can swap its backend for this crate.
+
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use regex_automata::dfa::{dense, Automaton};
+
+use crate::automaton::{
+    build_dfa, build_dfa_with_options, forced_bytes, start_state, walk, DfaBuildOptions,
+};
+use crate::guidance::{build_regex_from_schema_with_options, RegexOptions};
+
+pub type TokenId = u32;
+pub type StateId = u32;
+pub type Token = Vec<u8>;
+
+/// Maps token bytes to the token ids sharing them, plus the end-of-sequence token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vocabulary {
+    eos_token_id: TokenId,
+    tokens: HashMap<Token, Vec<TokenId>>,
+}
+
+impl Vocabulary {
+    pub fn new(eos_token_id: TokenId) -> Self {
+        Self {
+            eos_token_id,
+            tokens: HashMap::new(),
+        }
+    }
+
+    pub fn eos_token_id(&self) -> TokenId {
+        self.eos_token_id
+    }
+
+    pub fn tokens(&self) -> &HashMap<Token, Vec<TokenId>> {
+        &self.tokens
+    }
+
+    pub fn token_ids(&self, token: impl AsRef<[u8]>) -> Option<&Vec<TokenId>> {
+        self.tokens.get(token.as_ref())
+    }
+
+    pub fn try_insert(&mut self, token: impl Into<Token>, id: TokenId) -> Result<()> {
+        if id == self.eos_token_id {
+            return Err(anyhow!(
+                "EOS token id {} should not be inserted into the vocabulary",
+                id
+            ));
+        }
+        let ids = self.tokens.entry(token.into()).or_default();
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, token: impl AsRef<[u8]>) {
+        self.tokens.remove(token.as_ref());
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+impl TryFrom<(TokenId, HashMap<Token, Vec<TokenId>>)> for Vocabulary {
+    type Error = anyhow::Error;
+
+    fn try_from((eos_token_id, tokens): (TokenId, HashMap<Token, Vec<TokenId>>)) -> Result<Self> {
+        let mut vocabulary = Vocabulary::new(eos_token_id);
+        for (token, ids) in tokens {
+            for id in ids {
+                vocabulary.try_insert(token.clone(), id)?;
+            }
+        }
+        Ok(vocabulary)
+    }
+}
+
+/// How an [`Index`] is built.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexOptions {
+    /// Walk the automaton once per group of tokens it can't tell apart, the
+    /// tokens whose bytes are in the same classes of the regex, like all the
+    /// words of letters inside a string. It's much faster for large
+    /// vocabularies and stores the transitions of a group once, at the cost
+    /// of expanding the groups in each [`Index::allowed_tokens`].
+    pub coarse: bool,
+    pub dfa: DfaBuildOptions,
+}
+
+/// Token-level transition table of a regex automaton.
+///
+/// For every reachable automaton state it stores which tokens are allowed and
+/// the state each of them leads to. The EOS token is allowed in final states
+/// and loops back onto the same state.
+#[derive(Debug, Clone)]
+pub struct Index {
+    initial_state: StateId,
+    final_states: HashSet<StateId>,
+    transitions: HashMap<StateId, HashMap<TokenId, StateId>>,
+    eos_token_id: TokenId,
+    /// The fewest tokens finishing a match from each state that can finish one.
+    tokens_to_finish: HashMap<StateId, usize>,
+    /// The token every output goes on with from each state where there's one.
+    forced_tokens: HashMap<StateId, TokenId>,
+    /// The tokens of each group by its first one, when coarse.
+    groups: HashMap<TokenId, Vec<TokenId>>,
+    /// The first token of the group of each token, when coarse.
+    representatives: HashMap<TokenId, TokenId>,
+    /// Tokens of the vocabulary, EOS included.
+    token_count: usize,
+    build_time: Duration,
+}
+
+/// Indexes are equal when they have the same transitions, however long they
+/// took to build.
+impl PartialEq for Index {
+    fn eq(&self, other: &Self) -> bool {
+        self.initial_state == other.initial_state
+            && self.final_states == other.final_states
+            && self.transitions == other.transitions
+            && self.eos_token_id == other.eos_token_id
+            && self.groups == other.groups
+            && self.token_count == other.token_count
+    }
+}
+
+impl Index {
+    pub fn new(regex: &str, vocabulary: &Vocabulary) -> Result<Self> {
+        Self::with_options(regex, vocabulary, &IndexOptions::default())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(regex_len = regex.len(), vocabulary = vocabulary.len(), coarse = options.coarse)
+        )
+    )]
+    pub fn with_options(
+        regex: &str,
+        vocabulary: &Vocabulary,
+        options: &IndexOptions,
+    ) -> Result<Self> {
+        let build_start = Instant::now();
+        let dfa = build_dfa_with_options(regex, &options.dfa)?;
+        let start = start_state(&dfa)?;
+
+        let (tokens, groups): (TokenWalks, _) = if options.coarse {
+            token_groups(&dfa, vocabulary)
+        } else {
+            let tokens = vocabulary.tokens().iter();
+            let tokens = tokens.map(|(token, ids)| (token, ids.clone())).collect();
+            (tokens, HashMap::new())
+        };
+        let representatives = groups
+            .iter()
+            .flat_map(|(&first, ids)| ids.iter().map(move |&id| (id, first)))
+            .collect();
+
+        let eos_token_id = vocabulary.eos_token_id();
+        let mut final_states = HashSet::new();
+        let mut transitions: HashMap<StateId, HashMap<TokenId, StateId>> = HashMap::new();
+        let mut forced_tokens = HashMap::new();
+        let longest_token = vocabulary.tokens().keys().map(Vec::len).max().unwrap_or(0);
+
+        let mut seen = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(state) = queue.pop_front() {
+            let state_id = state.as_u32();
+            let state_transitions = transitions.entry(state_id).or_default();
+
+            if dfa.is_match_state(dfa.next_eoi_state(state)) {
+                final_states.insert(state_id);
+                state_transitions.insert(eos_token_id, state_id);
+            }
+
+            // the longest token made only of forced bytes, the smallest id
+            // among those sharing its bytes
+            let forced_len = forced_bytes(&dfa, state, longest_token).len();
+            let mut forced: Option<(usize, Reverse<TokenId>)> = None;
+            for (token, ids) in &tokens {
+                let Some(next) = walk(&dfa, state, token) else {
+                    continue;
+                };
+                for &id in ids.iter() {
+                    state_transitions.insert(id, next.as_u32());
+                }
+                if seen.insert(next) {
+                    queue.push_back(next);
+                }
+                if !token.is_empty() && token.len() <= forced_len {
+                    let id = ids.iter().copied().min().map(Reverse);
+                    forced = forced.max(id.map(|id| (token.len(), id)));
+                }
+            }
+            if let Some((_, Reverse(id))) = forced {
+                forced_tokens.insert(state_id, id);
+            }
+        }
+
+        if transitions[&start.as_u32()].is_empty() {
+            return Err(anyhow!(
+                "No token of the vocabulary can start a match of the regex"
+            ));
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(states = transitions.len(), "built index");
+        let tokens_to_finish = tokens_to_finish(&transitions, &final_states, eos_token_id);
+        Ok(Self {
+            initial_state: start.as_u32(),
+            final_states,
+            transitions,
+            eos_token_id,
+            tokens_to_finish,
+            forced_tokens,
+            groups,
+            representatives,
+            token_count: vocabulary.tokens().values().map(Vec::len).sum::<usize>() + 1,
+            build_time: build_start.elapsed(),
+        })
+    }
+
+    pub fn initial_state(&self) -> StateId {
+        self.initial_state
+    }
+
+    pub fn final_states(&self) -> &HashSet<StateId> {
+        &self.final_states
+    }
+
+    /// Time taken to build the index, the automaton of the regex included.
+    pub fn build_time(&self) -> Duration {
+        self.build_time
+    }
+
+    /// The transitions by token, where a [`IndexOptions::coarse`] index only
+    /// has the first token of each group.
+    pub fn transitions(&self) -> &HashMap<StateId, HashMap<TokenId, StateId>> {
+        &self.transitions
+    }
+
+    pub fn is_final_state(&self, state: &StateId) -> bool {
+        self.final_states.contains(state)
+    }
+
+    pub fn allowed_tokens(&self, state: &StateId) -> Option<Vec<TokenId>> {
+        self.transitions.get(state).map(|next| {
+            let mut tokens: Vec<TokenId> = next
+                .keys()
+                .flat_map(|first| match self.groups.get(first) {
+                    Some(group) => group.as_slice(),
+                    None => std::slice::from_ref(first),
+                })
+                .copied()
+                .collect();
+            tokens.sort_unstable();
+            tokens
+        })
+    }
+
+    pub fn next_state(&self, state: &StateId, token_id: &TokenId) -> Option<StateId> {
+        if *token_id == self.eos_token_id {
+            return None;
+        }
+        let token_id = self.representatives.get(token_id).unwrap_or(token_id);
+        self.transitions.get(state)?.get(token_id).copied()
+    }
+
+    /// The fewest tokens, EOS aside, taking `state` to a final state, `None`
+    /// when none can be reached.
+    pub fn tokens_to_finish(&self, state: &StateId) -> Option<usize> {
+        self.tokens_to_finish.get(state).copied()
+    }
+
+    /// Whether some output of the regex fits in `max_tokens` tokens, EOS
+    /// aside. A [`Guide::with_max_tokens`] then always finishes in time.
+    pub fn fits_in(&self, max_tokens: usize) -> bool {
+        self.tokens_to_finish(&self.initial_state)
+            .is_some_and(|tokens| tokens <= max_tokens)
+    }
+
+    /// An index for token healing: when the prompt ends with `prompt_tail`, the
+    /// token the model would pick after it may span the boundary, like ` {`
+    /// after a prompt ending with a space, and isn't allowed by a plain index.
+    ///
+    /// The end of the tail that starts such a token, the longest one on a char
+    /// boundary, is to be trimmed from the prompt, and the index's outputs
+    /// start with it before matching `regex`. The engine leaves those `trim`
+    /// bytes out of the constrained text. `regex` shouldn't start with `^`.
+    pub fn healed(regex: &str, vocabulary: &Vocabulary, prompt_tail: &[u8]) -> Result<Healing> {
+        let dfa = build_dfa(regex)?;
+        let start = start_state(&dfa)?;
+        let longest_token = vocabulary.tokens().keys().map(Vec::len).max().unwrap_or(0);
+
+        let overlap = (1..=prompt_tail.len().min(longest_token))
+            .rev()
+            .map(|len| &prompt_tail[prompt_tail.len() - len..])
+            .filter_map(|overlap| std::str::from_utf8(overlap).ok())
+            .find(|overlap| {
+                vocabulary.tokens().keys().any(|token| {
+                    token.len() > overlap.len()
+                        && token.starts_with(overlap.as_bytes())
+                        && walk(&dfa, start, &token[overlap.len()..]).is_some()
+                })
+            })
+            .unwrap_or_default();
+
+        let index = Index::new(
+            &format!("{}(?:{})", regex::escape(overlap), regex),
+            vocabulary,
+        )?;
+        Ok(Healing {
+            trim: overlap.len(),
+            index,
+        })
+    }
+
+    /// The token every output goes on with from `state`, when its next bytes
+    /// are fixed, like the keys and punctuation of objects. It's the longest
+    /// token of the vocabulary made of those bytes only, so the output is
+    /// tokenized greedily.
+    pub fn forced_token(&self, state: &StateId) -> Option<TokenId> {
+        self.forced_tokens.get(state).copied()
+    }
+}
+
+/// Tokens to walk the automaton with, and the ids their transitions are
+/// stored for.
+type TokenWalks<'a> = Vec<(&'a Token, Vec<TokenId>)>;
+
+/// The tokens of `vocabulary` grouped by the classes of their bytes, which
+/// take the DFA to the same states, each with the first of its ids, and the
+/// ids of each group by its first one.
+fn token_groups<'a>(
+    dfa: &dense::DFA<Vec<u32>>,
+    vocabulary: &'a Vocabulary,
+) -> (TokenWalks<'a>, HashMap<TokenId, Vec<TokenId>>) {
+    let classes = dfa.byte_classes();
+    let mut by_classes: HashMap<Vec<u8>, (&Token, Vec<TokenId>)> = HashMap::new();
+    for (token, ids) in vocabulary.tokens() {
+        let key = token.iter().map(|&byte| classes.get(byte)).collect();
+        by_classes
+            .entry(key)
+            .or_insert_with(|| (token, Vec::new()))
+            .1
+            .extend(ids);
+    }
+
+    let mut tokens = Vec::with_capacity(by_classes.len());
+    let mut groups = HashMap::with_capacity(by_classes.len());
+    for (token, mut ids) in by_classes.into_values() {
+        ids.sort_unstable();
+        tokens.push((token, vec![ids[0]]));
+        groups.insert(ids[0], ids);
+    }
+    (tokens, groups)
+}
+
+/// The fewest tokens from each state to a final one, by a breadth-first
+/// search from the final states along the transitions reversed.
+fn tokens_to_finish(
+    transitions: &HashMap<StateId, HashMap<TokenId, StateId>>,
+    final_states: &HashSet<StateId>,
+    eos_token_id: TokenId,
+) -> HashMap<StateId, usize> {
+    let mut previous: HashMap<StateId, HashSet<StateId>> = HashMap::new();
+    for (&state, next) in transitions {
+        for (&token_id, &next) in next {
+            if token_id != eos_token_id {
+                previous.entry(next).or_default().insert(state);
+            }
+        }
+    }
+
+    let mut tokens: HashMap<StateId, usize> =
+        final_states.iter().map(|&state| (state, 0)).collect();
+    let mut queue: VecDeque<StateId> = final_states.iter().copied().collect();
+    while let Some(state) = queue.pop_front() {
+        let next_tokens = tokens[&state] + 1;
+        for &before in previous.get(&state).into_iter().flatten() {
+            if let Entry::Vacant(entry) = tokens.entry(before) {
+                entry.insert(next_tokens);
+                queue.push_back(before);
+            }
+        }
+    }
+    tokens
+}
+
+/// An index for the output after a prompt, see [`Index::healed`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Healing {
+    /// Bytes to remove from the end of the prompt, which the output starts
+    /// with instead.
+    pub trim: usize,
+    pub index: Index,
+}
+
+/// Counters of a [`Guide`], to monitor the overhead of the constraint, see
+/// [`Guide::with_metrics`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GuideMetrics {
+    /// Tokens consumed, EOS included.
+    pub steps: usize,
+    /// Distinct states the guide has been in, the initial one included.
+    pub states_visited: usize,
+    /// Share of the vocabulary not allowed after the last step.
+    pub last_masked_fraction: f64,
+    /// Mean share of the vocabulary not allowed after each step.
+    pub mean_masked_fraction: f64,
+    /// Steps taking the token [`Guide::next_forced_tokens`] starts with, which
+    /// the engine can append without running the model.
+    pub fast_forwarded_tokens: usize,
+    pub index_build_time: Duration,
+}
+
+/// A schema compiled for a vocabulary once, to share between the guides of
+/// many requests, like in a server. Cloning it is cheap and nothing in it
+/// changes after it's built, so it's `Send + Sync`.
+#[derive(Debug, Clone)]
+pub struct CompiledSchema {
+    regex: Arc<str>,
+    index: Arc<Index>,
+}
+
+impl CompiledSchema {
+    pub fn new(schema: &str, options: &RegexOptions, vocabulary: &Vocabulary) -> Result<Self> {
+        let regex = build_regex_from_schema_with_options(schema, options)?;
+        Self::from_regex(&regex, vocabulary)
+    }
+
+    pub fn from_regex(regex: &str, vocabulary: &Vocabulary) -> Result<Self> {
+        Ok(Self {
+            regex: Arc::from(regex),
+            index: Arc::new(Index::new(regex, vocabulary)?),
+        })
+    }
+
+    pub fn regex(&self) -> &str {
+        &self.regex
+    }
+
+    pub fn index(&self) -> &Arc<Index> {
+        &self.index
+    }
+}
+
+/// Walks an [`Index`] one token at a time, tracking the current state.
+#[derive(Debug, Clone)]
+pub struct Guide {
+    state: StateId,
+    index: Arc<Index>,
+    /// Tokens left, when the output has to fit in a number of them.
+    remaining_tokens: Option<usize>,
+    metrics: Option<GuideMetrics>,
+    visited: HashSet<StateId>,
+}
+
+impl Guide {
+    pub fn new(index: Index) -> Self {
+        Self::from_shared(Arc::new(index))
+    }
+
+    /// A guide sharing the index of `compiled`, which it only reads, so
+    /// guides of many threads don't wait on each other.
+    pub fn new_from(compiled: &CompiledSchema) -> Self {
+        Self::from_shared(Arc::clone(&compiled.index))
+    }
+
+    fn from_shared(index: Arc<Index>) -> Self {
+        Self {
+            state: index.initial_state(),
+            index,
+            remaining_tokens: None,
+            metrics: None,
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Counts what the guide does from here on, see [`Guide::metrics`].
+    pub fn with_metrics(mut self) -> Self {
+        self.visited = HashSet::from([self.state]);
+        self.metrics = Some(GuideMetrics {
+            states_visited: 1,
+            index_build_time: self.index.build_time(),
+            ..GuideMetrics::default()
+        });
+        self
+    }
+
+    /// The counters of [`Guide::with_metrics`], `None` without it.
+    pub fn metrics(&self) -> Option<&GuideMetrics> {
+        self.metrics.as_ref()
+    }
+
+    /// Keeps the output within `max_tokens` tokens, EOS aside, by only
+    /// allowing the tokens after which it can still be finished in time, which
+    /// cuts arrays and strings short as the budget runs out. Fails when no
+    /// output of the regex fits.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Result<Self> {
+        let needed = self.index.tokens_to_finish(&self.state);
+        if needed.is_none_or(|needed| needed > max_tokens) {
+            return Err(anyhow!(
+                "No output of the regex fits in {} tokens{}",
+                max_tokens,
+                needed.map_or(String::new(), |needed| format!(", it takes {}", needed))
+            ));
+        }
+        self.remaining_tokens = Some(max_tokens);
+        Ok(self)
+    }
+
+    /// Tokens left from the budget of [`Guide::with_max_tokens`].
+    pub fn remaining_tokens(&self) -> Option<usize> {
+        self.remaining_tokens
+    }
+
+    pub fn get_state(&self) -> StateId {
+        self.state
+    }
+
+    pub fn get_tokens(&self) -> Result<Vec<TokenId>> {
+        let mut tokens = self
+            .index
+            .allowed_tokens(&self.state)
+            .ok_or_else(|| anyhow!("No allowed tokens available for state {}", self.state))?;
+        if self.remaining_tokens.is_some() {
+            tokens.retain(|&token_id| self.fits_budget(token_id));
+        }
+        Ok(tokens)
+    }
+
+    /// Whether the output can still be finished in time after `token_id`.
+    fn fits_budget(&self, token_id: TokenId) -> bool {
+        self.fits_budget_from(self.state, self.remaining_tokens, token_id)
+    }
+
+    fn fits_budget_from(
+        &self,
+        state: StateId,
+        remaining: Option<usize>,
+        token_id: TokenId,
+    ) -> bool {
+        let Some(remaining) = remaining else {
+            return true;
+        };
+        if token_id == self.index.eos_token_id {
+            return true;
+        }
+        let next = self.index.next_state(&state, &token_id);
+        next.and_then(|next| self.index.tokens_to_finish(&next))
+            .is_some_and(|needed| needed < remaining)
+    }
+
+    /// The tokens every output goes on with from here, which can be appended
+    /// without sampling by [`Guide::advance`]-ing through them, like the
+    /// tokens of `{"name": "` at the start of an object. Empty when the next
+    /// token is up to the model. See [`Index::forced_token`].
+    pub fn next_forced_tokens(&self) -> Vec<TokenId> {
+        let mut tokens = Vec::new();
+        let mut state = self.state;
+        let mut remaining = self.remaining_tokens;
+        let mut seen = HashSet::from([state]);
+        while let Some(token_id) = self.index.forced_token(&state) {
+            if !self.fits_budget_from(state, remaining, token_id) {
+                break;
+            }
+            let Some(next) = self.index.next_state(&state, &token_id) else {
+                break;
+            };
+            tokens.push(token_id);
+            remaining = remaining.map(|remaining| remaining - 1);
+            state = next;
+            if !seen.insert(state) {
+                break;
+            }
+        }
+        tokens
+    }
+
+    /// Consumes `token_id` and returns the tokens allowed in the new state.
+    pub fn advance(&mut self, token_id: TokenId) -> Result<Vec<TokenId>> {
+        if !self.fits_budget(token_id) {
+            return Err(anyhow!(
+                "Token {} leaves too few of the {} remaining tokens to finish",
+                token_id,
+                self.remaining_tokens.unwrap_or_default()
+            ));
+        }
+        let forced = self.index.forced_token(&self.state) == Some(token_id);
+        self.state = match self.index.next_state(&self.state, &token_id) {
+            Some(next) => {
+                if let Some(remaining) = &mut self.remaining_tokens {
+                    *remaining -= 1;
+                }
+                next
+            }
+            None if token_id == self.index.eos_token_id && self.is_finished() => self.state,
+            None => {
+                return Err(anyhow!(
+                    "Token {} is not allowed in state {}",
+                    token_id,
+                    self.state
+                ))
+            }
+        };
+        let tokens = self.get_tokens()?;
+        self.count_step(forced, tokens.len());
+        Ok(tokens)
+    }
+
+    fn count_step(&mut self, forced: bool, allowed: usize) {
+        let Some(metrics) = &mut self.metrics else {
+            return;
+        };
+        self.visited.insert(self.state);
+        let masked = 1.0 - allowed as f64 / self.index.token_count as f64;
+        metrics.steps += 1;
+        metrics.states_visited = self.visited.len();
+        metrics.last_masked_fraction = masked;
+        metrics.mean_masked_fraction +=
+            (masked - metrics.mean_masked_fraction) / metrics.steps as f64;
+        metrics.fast_forwarded_tokens += usize::from(forced);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.index.is_final_state(&self.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vocabulary() -> Vocabulary {
+        let mut vocabulary = Vocabulary::new(0);
+        for (id, token) in ["{", "}", "\"", "a", "ab", "b", "1", "12", "x"]
+            .iter()
+            .enumerate()
+        {
+            vocabulary.try_insert(*token, id as TokenId + 1).unwrap();
+        }
+        vocabulary
+    }
+
+    fn id(vocabulary: &Vocabulary, token: &str) -> TokenId {
+        vocabulary.token_ids(token).unwrap()[0]
+    }
+
+    #[test]
+    fn test_eos_cannot_be_inserted() {
+        let mut vocabulary = Vocabulary::new(7);
+        assert!(vocabulary.try_insert("a", 7).is_err());
+    }
+
+    #[test]
+    fn test_index_allowed_tokens() {
+        let vocabulary = vocabulary();
+        let index = Index::new("a[b]?1", &vocabulary).unwrap();
+
+        let initial = index.initial_state();
+        assert_eq!(
+            index.allowed_tokens(&initial).unwrap(),
+            vec![id(&vocabulary, "a"), id(&vocabulary, "ab")]
+        );
+        assert!(!index.is_final_state(&initial));
+    }
+
+    #[test]
+    fn test_guide_walks_to_final_state() {
+        let vocabulary = vocabulary();
+        let index = Index::new("a[b]?1", &vocabulary).unwrap();
+        let mut guide = Guide::new(index);
+
+        guide.advance(id(&vocabulary, "ab")).unwrap();
+        assert!(!guide.is_finished());
+
+        let allowed = guide.advance(id(&vocabulary, "1")).unwrap();
+        assert!(guide.is_finished());
+        assert_eq!(allowed, vec![vocabulary.eos_token_id()]);
+    }
+
+    #[test]
+    fn test_guide_rejects_disallowed_token() {
+        let vocabulary = vocabulary();
+        let index = Index::new("a[b]?1", &vocabulary).unwrap();
+        let mut guide = Guide::new(index);
+
+        assert!(guide.advance(id(&vocabulary, "x")).is_err());
+    }
+
+    #[test]
+    fn test_guide_within_max_tokens() {
+        let vocabulary = vocabulary();
+        let index = Index::new(r"\{(a|b)*\}", &vocabulary).unwrap();
+        assert_eq!(index.tokens_to_finish(&index.initial_state()), Some(2));
+        assert!(index.fits_in(2));
+        assert!(!index.fits_in(1));
+        assert!(Guide::new(index.clone()).with_max_tokens(1).is_err());
+
+        let mut guide = Guide::new(index).with_max_tokens(4).unwrap();
+        let (a, close) = (id(&vocabulary, "a"), id(&vocabulary, "}"));
+        assert!(guide.advance(id(&vocabulary, "{")).unwrap().contains(&a));
+        assert_eq!(guide.remaining_tokens(), Some(3));
+        assert!(guide.advance(a).unwrap().contains(&a));
+        // the last token has to close the object
+        assert_eq!(guide.advance(id(&vocabulary, "ab")).unwrap(), vec![close]);
+        assert!(guide.advance(a).is_err());
+        guide.advance(close).unwrap();
+        assert!(guide.is_finished());
+        assert_eq!(guide.remaining_tokens(), Some(0));
+        assert_eq!(guide.get_tokens().unwrap(), vec![vocabulary.eos_token_id()]);
+    }
+
+    #[test]
+    fn test_guide_next_forced_tokens() {
+        let vocabulary = vocabulary();
+        let index = Index::new(r#"\{"ab"(1|x)\}"#, &vocabulary).unwrap();
+        let mut guide = Guide::new(index.clone());
+
+        let forced = guide.next_forced_tokens();
+        let expected = ["{", "\"", "ab", "\""].map(|token| id(&vocabulary, token));
+        assert_eq!(forced, expected);
+        for token_id in forced {
+            guide.advance(token_id).unwrap();
+        }
+        assert!(guide.next_forced_tokens().is_empty());
+        guide.advance(id(&vocabulary, "x")).unwrap();
+        assert_eq!(guide.next_forced_tokens(), vec![id(&vocabulary, "}")]);
+
+        // the shortest output takes these tokens too
+        let guide = Guide::new(index).with_max_tokens(6).unwrap();
+        assert_eq!(guide.next_forced_tokens().len(), 4);
+    }
+
+    #[test]
+    fn test_index_healed() {
+        let vocabulary = vocabulary();
+        let healing = Index::healed("b1", &vocabulary, b"1a").unwrap();
+        assert_eq!(healing.trim, 1);
+        let mut guide = Guide::new(healing.index);
+        for token in ["ab", "1"] {
+            guide.advance(id(&vocabulary, token)).unwrap();
+        }
+        assert!(guide.is_finished());
+
+        // no token goes on from the end of the prompt into the output
+        let healing = Index::healed("b1", &vocabulary, b"x").unwrap();
+        assert_eq!(healing.trim, 0);
+        assert_eq!(healing.index, Index::new("b1", &vocabulary).unwrap());
+    }
+
+    #[test]
+    fn test_coarse_index() {
+        let vocabulary = vocabulary();
+        let regex = r#"\{"[a-z]*"(1|12)\}"#;
+        let exact = Index::new(regex, &vocabulary).unwrap();
+        let coarse = Index::with_options(
+            regex,
+            &vocabulary,
+            &IndexOptions {
+                coarse: true,
+                ..IndexOptions::default()
+            },
+        );
+        let coarse = coarse.unwrap();
+
+        // `a`, `b` and `x` are one group, next to `ab` and `"`
+        let state = coarse
+            .next_state(&coarse.initial_state(), &id(&vocabulary, "{"))
+            .unwrap();
+        let state = coarse.next_state(&state, &id(&vocabulary, "\"")).unwrap();
+        assert_eq!(coarse.transitions()[&state].len(), 3);
+        let exact_state = exact.next_state(&exact.initial_state(), &id(&vocabulary, "{"));
+        let exact_state = exact.next_state(&exact_state.unwrap(), &id(&vocabulary, "\""));
+        assert_eq!(
+            coarse.allowed_tokens(&state),
+            exact.allowed_tokens(&exact_state.unwrap())
+        );
+
+        let mut guide = Guide::new(coarse);
+        assert_eq!(guide.next_forced_tokens().len(), 2);
+        for token in ["{", "\"", "x", "ab", "\"", "12", "}"] {
+            guide.advance(id(&vocabulary, token)).unwrap();
+        }
+        assert!(guide.is_finished());
+    }
+
+    #[test]
+    fn test_guide_metrics() {
+        let vocabulary = vocabulary();
+        let index = Index::new("a[b]?1", &vocabulary).unwrap();
+        assert!(Guide::new(index.clone()).metrics().is_none());
+
+        let mut guide = Guide::new(index).with_metrics();
+        for token in ["a", "b", "1"] {
+            guide.advance(id(&vocabulary, token)).unwrap();
+        }
+        guide.advance(vocabulary.eos_token_id()).unwrap();
+        let metrics = guide.metrics().unwrap();
+        assert_eq!(metrics.steps, 4);
+        assert_eq!(metrics.states_visited, 4);
+        // every output starts with `a`, and goes on with `1` after `b`
+        assert_eq!(metrics.fast_forwarded_tokens, 2);
+        assert_eq!(metrics.last_masked_fraction, 0.9);
+        assert!(metrics.mean_masked_fraction > 0.8 && metrics.mean_masked_fraction < 0.9);
+    }
+
+    #[test]
+    fn test_compiled_schema_is_shared() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<CompiledSchema>();
+        assert_send_sync::<Guide>();
+        assert_send_sync::<crate::automaton::CharGuide>();
+
+        let vocabulary = vocabulary();
+        let schema = r#"{"const": "ab"}"#;
+        let compiled = CompiledSchema::new(schema, &RegexOptions::default(), &vocabulary);
+        let compiled = compiled.unwrap();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let compiled = compiled.clone();
+                std::thread::spawn(move || {
+                    let mut guide = Guide::new_from(&compiled);
+                    while let Some(&token_id) = guide.next_forced_tokens().first() {
+                        guide.advance(token_id).unwrap();
+                    }
+                    guide.is_finished()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+        assert_eq!(Arc::strong_count(compiled.index()), 1);
+    }
+
+    #[test]
+    fn test_index_requires_a_reachable_token() {
+        let vocabulary = vocabulary();
+        assert!(Index::new("z", &vocabulary).is_err());
+    }
+
+    #[test]
+    fn test_index_from_schema_regex() {
+        let mut vocabulary = vocabulary();
+        vocabulary.try_insert(":", 100).unwrap();
+
+        let regex = crate::guidance::build_regex_from_schema(
+            r#"{"type": "object", "properties": {"ab": {"type": "integer"}}, "required": ["ab"]}"#,
+            Some(""),
+        )
+        .unwrap();
+        let mut guide = Guide::new(Index::new(&regex, &vocabulary).unwrap());
+
+        for token in ["{", "\"", "ab", "\"", ":", "12", "}"] {
+            assert!(!guide.is_finished());
+            guide.advance(id(&vocabulary, token)).unwrap();
+        }
+        assert!(guide.is_finished());
+    }
+}
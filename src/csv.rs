@@ -0,0 +1,366 @@
+use anyhow::{anyhow, Result};
+use regex::escape;
+use serde_json::Value;
+
+use crate::error::SchemaError;
+use crate::guidance::{
+    configured_generator, finish_regex, parse_with_options, resolve_whitespace_pattern, Generator,
+    RegexOptions,
+};
+use crate::handle_types::get_num_items_pattern;
+use crate::keywords;
+
+/// Layout of the delimited output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    /// Field separator, `,` for CSV and `\t` for TSV.
+    pub delimiter: char,
+    /// Whether the rows are preceded by a header row with the column names.
+    pub header: bool,
+    pub min_rows: u64,
+    pub max_rows: Option<u64>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            header: false,
+            min_rows: 1,
+            max_rows: Some(1),
+        }
+    }
+}
+
+impl CsvOptions {
+    pub fn tsv() -> Self {
+        Self {
+            delimiter: '\t',
+            ..Self::default()
+        }
+    }
+}
+
+/// Builds a regex for delimited rows whose columns follow the schema.
+///
+/// The schema is either an object of scalar properties, one column per
+/// property, or a `prefixItems` tuple of scalars.
+/// Optional properties and `null` values are written as empty fields.
+/// Strings are either bare or double-quoted with `""` escapes, as in RFC 4180.
+/// Rows are separated by line breaks, with an optional trailing one.
+pub fn build_csv_regex_from_schema(json: &str, options: &CsvOptions) -> Result<String> {
+    build_csv_regex_from_schema_with_options(json, options, &RegexOptions::default())
+}
+
+/// Like [`build_csv_regex_from_schema`], with the numbers and booleans
+/// generated with `regex_options`.
+pub fn build_csv_regex_from_schema_with_options(
+    json: &str,
+    options: &CsvOptions,
+    regex_options: &RegexOptions,
+) -> Result<String> {
+    if matches!(options.delimiter, '"' | '\r' | '\n') {
+        return Err(anyhow!(
+            "{:?} can't be used as a delimiter",
+            options.delimiter
+        ));
+    }
+
+    let json_value = parse_with_options(json, regex_options)?;
+    let whitespace_pattern = resolve_whitespace_pattern(Some(
+        regex_options.whitespace_pattern.as_deref().unwrap_or(""),
+    ))?;
+    let generator = configured_generator(&whitespace_pattern, &json_value, regex_options);
+
+    let csv = CsvGenerator {
+        delimiter: options.delimiter,
+    };
+    let columns = csv.columns(&generator, &json_value)?;
+    let delimiter = escape(&options.delimiter.to_string());
+
+    let row = columns
+        .iter()
+        .map(|column| {
+            if column.required {
+                column.field.clone()
+            } else {
+                format!("({})?", column.field)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(&delimiter);
+
+    let mut regex = String::new();
+    if options.header {
+        let names: Option<Vec<String>> = columns
+            .iter()
+            .map(|column| column.name.as_deref().map(|name| escape(&csv.quote(name))))
+            .collect();
+        let names = names.ok_or_else(|| anyhow!("A header needs named columns"))?;
+        regex += &format!(r"{}\r?\n", names.join(&delimiter));
+    }
+
+    match get_num_items_pattern(Some(options.min_rows), options.max_rows) {
+        Some(num_repeats) => {
+            let allow_empty = if options.min_rows == 0 { "?" } else { "" };
+            regex += &format!(
+                r"(({0})(\r?\n({0})){1}(\r?\n)?){2}",
+                row, num_repeats, allow_empty
+            );
+        }
+        None if options.header => regex = format!(r"{}?", regex.trim_end_matches(r"\r?\n")),
+        None => return Err(anyhow!("At least one row or a header is needed")),
+    }
+
+    finish_regex(&regex, regex_options)
+}
+
+struct Column {
+    name: Option<String>,
+    field: String,
+    required: bool,
+}
+
+struct CsvGenerator {
+    delimiter: char,
+}
+
+impl CsvGenerator {
+    fn columns(&self, generator: &Generator, schema: &Value) -> Result<Vec<Column>> {
+        generator.resolve(schema, |generator, schema| {
+            let obj = schema
+                .as_object()
+                .ok_or_else(|| anyhow!("Invalid JSON Schema: expected an object"))?;
+
+            if let Some(properties) = keywords::get_object(obj, "properties")? {
+                let required_properties =
+                    keywords::get_strings(obj, "required")?.unwrap_or_default();
+                let generator = generator.at("properties");
+                return properties
+                    .iter()
+                    .map(|(name, value)| {
+                        Ok(Column {
+                            name: Some(name.clone()),
+                            field: self.field(&generator.at(name.as_str()), value)?,
+                            required: required_properties.contains(&name.as_str()),
+                        })
+                    })
+                    .collect();
+            }
+
+            if let Some(prefix_items) = keywords::get_array(obj, "prefixItems")? {
+                let generator = generator.at("prefixItems");
+                return prefix_items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| {
+                        Ok(Column {
+                            name: value
+                                .get("title")
+                                .and_then(Value::as_str)
+                                .map(str::to_string),
+                            field: self.field(&generator.at(i), value)?,
+                            required: true,
+                        })
+                    })
+                    .collect();
+            }
+
+            Err(anyhow!(
+                "Rows need an object with 'properties' or a 'prefixItems' tuple"
+            ))
+        })
+    }
+
+    fn field(&self, generator: &Generator, schema: &Value) -> Result<String> {
+        generator.resolve(schema, |generator, schema| {
+            let obj = schema
+                .as_object()
+                .ok_or_else(|| anyhow!("Invalid JSON Schema: expected an object"))?;
+
+            let literals: Option<(&str, Vec<&Value>)> =
+                match (keywords::get_array(obj, "enum")?, obj.get("const")) {
+                    (Some(values), _) => Some(("enum", values.iter().collect())),
+                    (_, Some(value)) => Some(("const", vec![value])),
+                    _ => None,
+                };
+            if let Some((keyword, literals)) = literals {
+                let choices: Result<Vec<String>> = literals
+                    .into_iter()
+                    .map(|literal| match literal {
+                        Value::String(s) => Ok(escape(&self.quote(s))),
+                        Value::Null => Ok(String::new()),
+                        Value::Bool(_) | Value::Number(_) => Ok(escape(&literal.to_string())),
+                        _ => Err(
+                            SchemaError::new("Only scalar literals can be written in a row")
+                                .with_keyword(keyword)
+                                .into(),
+                        ),
+                    })
+                    .collect();
+                return Ok(format!("({})", choices?.join("|")));
+            }
+
+            for keyword in ["anyOf", "oneOf"] {
+                if let Some(branches) = keywords::get_array(obj, keyword)? {
+                    let generator = generator.at(keyword);
+                    let patterns: Result<Vec<String>> = branches
+                        .iter()
+                        .enumerate()
+                        .map(|(i, branch)| self.field(&generator.at(i), branch))
+                        .collect();
+                    return Ok(format!("({})", patterns?.join("|")));
+                }
+            }
+
+            match keywords::get_str(obj, "type")? {
+                Some("string") => {
+                    let delimiter = escape(&self.delimiter.to_string());
+                    Ok(format!(r#"([^"{}\r\n]*|"([^"]|"")*")"#, delimiter))
+                }
+                Some("integer") | Some("number") | Some("boolean") => {
+                    Ok(format!("({})", generator.to_regex(schema)?))
+                }
+                Some("null") => Ok(String::new()),
+                _ => Err(anyhow!(
+                    "Only scalar columns can be written in a row, got {}",
+                    schema
+                )),
+            }
+        })
+    }
+
+    /// Quotes a field when it contains the delimiter, a quote or a line break.
+    fn quote(&self, field: &str) -> String {
+        if field.contains([self.delimiter, '"', '\r', '\n']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use serde_json::json;
+
+    use super::*;
+    use crate::types::NumberSyntax;
+
+    fn csv_regex(schema: &Value, options: &CsvOptions) -> Regex {
+        let regex = build_csv_regex_from_schema(&schema.to_string(), options).unwrap();
+        Regex::new(&format!("^(?:{})$", regex)).unwrap()
+    }
+
+    fn person() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "age": {"type": "integer"},
+                "name": {"type": "string"},
+                "role": {"enum": ["admin", "a,b"]}
+            },
+            "required": ["name", "role"]
+        })
+    }
+
+    #[test]
+    fn test_single_row() {
+        let regex = csv_regex(&person(), &CsvOptions::default());
+
+        assert!(regex.is_match("36,Ada,admin"));
+        assert!(regex.is_match(",\"Lovelace, Ada\",\"a,b\""));
+        assert!(regex.is_match("36,\"say \"\"hi\"\"\",admin\n"));
+        assert!(!regex.is_match("x,Ada,admin"));
+        assert!(!regex.is_match("36,Ada,a,b"));
+        assert!(!regex.is_match("36,Ada,admin\n1,Bob,admin"));
+    }
+
+    #[test]
+    fn test_header_and_rows() {
+        let options = CsvOptions {
+            header: true,
+            min_rows: 1,
+            max_rows: None,
+            ..CsvOptions::default()
+        };
+        let regex = csv_regex(&person(), &options);
+
+        assert!(regex.is_match("age,name,role\n36,Ada,admin\n,Bob,\"a,b\"\n"));
+        assert!(!regex.is_match("age,name,role\n"));
+        assert!(!regex.is_match("36,Ada,admin\n"));
+    }
+
+    #[test]
+    fn test_tsv_tuple() {
+        let schema = json!({
+            "prefixItems": [{"type": "string"}, {"type": "number"}, {"type": "boolean"}]
+        });
+        let regex = csv_regex(&schema, &CsvOptions::tsv());
+
+        assert!(regex.is_match("a,b\t1.5\ttrue"));
+        assert!(!regex.is_match("a\tb\t1.5\ttrue"));
+    }
+
+    #[test]
+    fn test_options() {
+        let schema = json!({"prefixItems": [{"type": "number"}, {"type": "integer"}]});
+        let options = RegexOptions {
+            number_syntax: NumberSyntax {
+                plus_sign: true,
+                ..NumberSyntax::default()
+            },
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let regex = build_csv_regex_from_schema_with_options(
+            &schema.to_string(),
+            &CsvOptions::default(),
+            &options,
+        )
+        .unwrap();
+        assert!(regex.starts_with('^'));
+        let regex = Regex::new(&regex).unwrap();
+        assert!(regex.is_match("+1.5,+2"));
+        assert!(!regex.is_match("+1.5,+2,3"));
+    }
+
+    #[test]
+    fn test_errors_are_located() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"name": {"type": "string"}, "role": {"enum": ["a", ["b"]]}}
+        });
+        let error =
+            build_csv_regex_from_schema(&schema.to_string(), &CsvOptions::default()).unwrap_err();
+        assert_eq!(
+            SchemaError::find(&error).unwrap().pointer.as_deref(),
+            Some("/properties/role/enum")
+        );
+
+        let schema = json!({
+            "prefixItems": [{"type": "string"}, {"$ref": "#/$defs/tags"}],
+            "$defs": {"tags": {"type": "array"}}
+        });
+        let error =
+            build_csv_regex_from_schema(&schema.to_string(), &CsvOptions::default()).unwrap_err();
+        assert_eq!(
+            SchemaError::find(&error).unwrap().pointer.as_deref(),
+            Some("/$defs/tags")
+        );
+    }
+
+    #[test]
+    fn test_unsupported_columns() {
+        let schema = json!({"type": "object", "properties": {"tags": {"type": "array"}}});
+        assert!(build_csv_regex_from_schema(&schema.to_string(), &CsvOptions::default()).is_err());
+
+        let tuple = json!({"prefixItems": [{"type": "string"}]});
+        let options = CsvOptions {
+            header: true,
+            ..CsvOptions::default()
+        };
+        assert!(build_csv_regex_from_schema(&tuple.to_string(), &options).is_err());
+    }
+}
@@ -0,0 +1,21 @@
+#[cfg(feature = "derive")]
+pub use guidance_rs_derive::GuidedOutput;
+
+pub use serde_json::Value;
+
+// used by the code generated by `#[derive(GuidedOutput)]`
+#[doc(hidden)]
+pub use serde_json::json;
+
+/// A type whose JSON representation can be constrained with a regex.
+///
+/// With the `derive` feature, `#[derive(GuidedOutput)]` implements it for
+/// structs with named fields and enums with unit variants. Fields of other
+/// types must implement `GuidedOutput` themselves.
+pub trait GuidedOutput {
+    /// The JSON schema of the type, with nested types inlined.
+    fn json_schema() -> Value;
+
+    /// The regex for `json_schema`, built once on first use.
+    fn regex() -> &'static str;
+}
@@ -0,0 +1,246 @@
+//! Rendering of [`SchemaError`]s with `miette`, showing the part of the
+//! schema they're about next to the hint.
+
+use std::fmt;
+use std::ops::Range;
+
+use miette::{
+    Diagnostic, GraphicalReportHandler, GraphicalTheme, LabeledSpan, NamedSource, SourceCode,
+};
+
+use crate::error::SchemaError;
+
+/// A [`SchemaError`] along with the text of the schema it's about.
+#[derive(Debug)]
+pub struct SchemaDiagnostic {
+    error: SchemaError,
+    source: NamedSource<String>,
+    span: Option<Range<usize>>,
+}
+
+impl SchemaDiagnostic {
+    /// Diagnoses `error` in `source`, the JSON text of the schema, shown as `name`.
+    pub fn new(error: SchemaError, name: impl AsRef<str>, source: String) -> Self {
+        let span = error
+            .pointer
+            .as_deref()
+            .and_then(|pointer| span(&source, pointer));
+        Self {
+            error,
+            source: NamedSource::new(name, source).with_language("json"),
+            span,
+        }
+    }
+
+    pub fn error(&self) -> &SchemaError {
+        &self.error
+    }
+
+    /// The diagnostic as text for a terminal, without colors.
+    pub fn render(&self) -> String {
+        let mut rendered = String::new();
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut rendered, self)
+            .expect("writing into a String can't fail");
+        rendered
+    }
+}
+
+impl fmt::Display for SchemaDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for SchemaDiagnostic {}
+
+impl Diagnostic for SchemaDiagnostic {
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let hint = self.error.hint.as_ref()?;
+        Some(Box::new(hint))
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.source)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let span = self.span.clone()?;
+        let label = match self.error.pointer.as_deref() {
+            Some("") | None => "this schema".to_string(),
+            Some(pointer) => pointer.to_string(),
+        };
+        Some(Box::new(std::iter::once(LabeledSpan::new_with_span(
+            Some(label),
+            span,
+        ))))
+    }
+}
+
+/// Byte range of the value at the JSON Pointer `pointer` in the JSON text `source`.
+fn span(source: &str, pointer: &str) -> Option<Range<usize>> {
+    let path: Vec<String> = pointer
+        .split('/')
+        .skip(1)
+        .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+        .collect();
+    Scanner {
+        bytes: source.as_bytes(),
+        pos: 0,
+    }
+    .find(&path)
+}
+
+/// Walks JSON text without building values, to locate the ones of a pointer.
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    /// The range of the value at `path` below the value at the current position.
+    fn find(&mut self, path: &[String]) -> Option<Range<usize>> {
+        self.skip_whitespace();
+        let Some((segment, rest)) = path.split_first() else {
+            let start = self.pos;
+            self.skip_value()?;
+            return Some(start..self.pos);
+        };
+
+        match self.bytes.get(self.pos)? {
+            b'{' => {
+                self.pos += 1;
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(b':')?;
+                    if key == *segment {
+                        return self.find(rest);
+                    }
+                    self.skip_value()?;
+                    self.skip_whitespace();
+                    self.expect(b',')?;
+                }
+            }
+            b'[' => {
+                let index: usize = segment.parse().ok()?;
+                self.pos += 1;
+                for _ in 0..index {
+                    self.skip_value()?;
+                    self.skip_whitespace();
+                    self.expect(b',')?;
+                }
+                self.find(rest)
+            }
+            _ => None,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        (self.bytes.get(self.pos) == Some(&byte)).then(|| self.pos += 1)
+    }
+
+    /// Reads the string at the current position, unescaped.
+    fn string(&mut self) -> Option<String> {
+        let start = self.pos;
+        self.skip_string()?;
+        serde_json::from_slice(&self.bytes[start..self.pos]).ok()
+    }
+
+    fn skip_string(&mut self) -> Option<()> {
+        self.expect(b'"')?;
+        loop {
+            match self.bytes.get(self.pos)? {
+                b'"' => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                b'\\' => self.pos += 2,
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        self.skip_whitespace();
+        match self.bytes.get(self.pos)? {
+            b'"' => self.skip_string(),
+            b'{' | b'[' => {
+                let mut depth = 0;
+                loop {
+                    match self.bytes.get(self.pos)? {
+                        b'"' => {
+                            self.skip_string()?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => depth -= 1,
+                        _ => {}
+                    }
+                    self.pos += 1;
+                    if depth == 0 {
+                        return Some(());
+                    }
+                }
+            }
+            _ => {
+                while !matches!(
+                    self.bytes.get(self.pos),
+                    None | Some(b',' | b'}' | b']' | b' ' | b'\t' | b'\r' | b'\n')
+                ) {
+                    self.pos += 1;
+                }
+                Some(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guidance::build_regex_from_schema;
+
+    const SCHEMA: &str = r#"{
+  "properties": {
+    "a/b": {"type": "string"},
+    "tags": {"type": "array", "prefixItems": [{"type": "integer"}, {"type": "date"}]}
+  }
+}"#;
+
+    #[test]
+    fn test_span() {
+        let at = |pointer| span(SCHEMA, pointer).map(|span| &SCHEMA[span]);
+        assert_eq!(at(""), Some(SCHEMA));
+        assert_eq!(at("/properties/a~1b"), Some(r#"{"type": "string"}"#));
+        assert_eq!(at("/properties/tags/prefixItems/1/type"), Some(r#""date""#));
+        assert_eq!(at("/properties/missing"), None);
+        assert_eq!(at("/properties/tags/prefixItems/2"), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let error = build_regex_from_schema(SCHEMA, None).unwrap_err();
+        let error = SchemaError::find(&error).unwrap().clone();
+        assert_eq!(
+            error.pointer.as_deref(),
+            Some("/properties/tags/prefixItems/1")
+        );
+
+        let rendered = SchemaDiagnostic::new(error, "schema.json", SCHEMA.to_string()).render();
+        assert!(rendered.contains("Unsupported type: date"), "{}", rendered);
+        assert!(rendered.contains(r#"{"type": "date"}"#), "{}", rendered);
+        assert!(rendered.contains("help: the types are"), "{}", rendered);
+    }
+}
@@ -0,0 +1,361 @@
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+use regex_syntax::Parser;
+
+/// Regex engine the generated pattern is rendered for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dialect {
+    /// The `regex` crate, which is what the generator emits natively.
+    #[default]
+    Rust,
+    Re2,
+    /// PCRE and PCRE2, with UTF mode enabled.
+    Pcre,
+    /// Python's `re` module.
+    Python,
+    /// ECMAScript, compiled with the `u` flag.
+    JavaScript,
+}
+
+impl FromStr for Dialect {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "rust" => Ok(Dialect::Rust),
+            "re2" => Ok(Dialect::Re2),
+            "pcre" | "pcre2" => Ok(Dialect::Pcre),
+            "python" | "re" => Ok(Dialect::Python),
+            "javascript" | "js" | "ecmascript" => Ok(Dialect::JavaScript),
+            _ => Err(anyhow!("Unknown regex dialect: {}", s)),
+        }
+    }
+}
+
+// RE2 rejects counted repetitions above this
+static RE2_MAX_REPEAT: u32 = 1000;
+
+/// Renders a pattern produced by this crate for another regex engine.
+///
+/// The pattern is parsed and printed back from its high-level IR, so only
+/// constructs every dialect agrees on are emitted: classes are spelled out as
+/// ranges, every character outside a small safe set is written as a hex
+/// escape and groups are non-capturing unless captured in the input. The
+/// Rust dialect returns the pattern unchanged.
+pub fn render(regex: &str, dialect: Dialect) -> Result<String> {
+    let hir = Parser::new()
+        .parse(regex)
+        .map_err(|e| anyhow!("Failed to parse regex: {}", e))?;
+    if dialect == Dialect::Rust {
+        return Ok(regex.to_string());
+    }
+
+    print(&hir, dialect)
+}
+
+/// Prints `hir` as a pattern for `dialect`. Unlike [`render`], the Rust
+/// dialect is printed too, with shorthand and negated classes where shorter.
+pub(crate) fn print(hir: &Hir, dialect: Dialect) -> Result<String> {
+    let mut out = String::new();
+    Printer { dialect }.hir(hir, &mut out)?;
+    Ok(out)
+}
+
+/// The Unicode-aware Perl classes of the regex crate, for printing them back.
+fn shorthand_classes() -> &'static [(&'static str, Class)] {
+    static CLASSES: OnceLock<Vec<(&'static str, Class)>> = OnceLock::new();
+    CLASSES.get_or_init(|| {
+        [r"\d", r"\D", r"\w", r"\W", r"\s", r"\S"]
+            .into_iter()
+            .filter_map(
+                |shorthand| match Parser::new().parse(shorthand).ok()?.into_kind() {
+                    HirKind::Class(class) => Some((shorthand, class)),
+                    _ => None,
+                },
+            )
+            .collect()
+    })
+}
+
+struct Printer {
+    dialect: Dialect,
+}
+
+impl Printer {
+    fn hir(&self, hir: &Hir, out: &mut String) -> Result<()> {
+        match hir.kind() {
+            HirKind::Empty => out.push_str("(?:)"),
+            HirKind::Literal(literal) => {
+                let text = std::str::from_utf8(&literal.0).map_err(|_| {
+                    anyhow!("Byte literals can't be rendered for {:?}", self.dialect)
+                })?;
+                for c in text.chars() {
+                    self.char(c, false, out);
+                }
+            }
+            HirKind::Class(class) => self.class(class, out)?,
+            HirKind::Look(look) => out.push_str(self.look(*look)?),
+            HirKind::Repetition(repetition) => {
+                let atomic = match repetition.sub.kind() {
+                    HirKind::Literal(literal) => {
+                        std::str::from_utf8(&literal.0).is_ok_and(|text| text.chars().count() == 1)
+                    }
+                    HirKind::Class(_) | HirKind::Capture(_) => true,
+                    _ => false,
+                };
+                if atomic {
+                    self.hir(&repetition.sub, out)?;
+                } else {
+                    out.push_str("(?:");
+                    self.hir(&repetition.sub, out)?;
+                    out.push(')');
+                }
+
+                let (min, max) = (repetition.min, repetition.max);
+                if self.dialect == Dialect::Re2 && max.unwrap_or(min) > RE2_MAX_REPEAT {
+                    return Err(anyhow!(
+                        "RE2 doesn't support repetitions above {}",
+                        RE2_MAX_REPEAT
+                    ));
+                }
+                match (min, max) {
+                    (0, None) => out.push('*'),
+                    (1, None) => out.push('+'),
+                    (0, Some(1)) => out.push('?'),
+                    (min, None) => write!(out, "{{{},}}", min)?,
+                    (min, Some(max)) if min == max => write!(out, "{{{}}}", min)?,
+                    (min, Some(max)) => write!(out, "{{{},{}}}", min, max)?,
+                }
+                if !repetition.greedy {
+                    out.push('?');
+                }
+            }
+            HirKind::Capture(capture) => {
+                match (&capture.name, self.dialect) {
+                    (None, _) => out.push('('),
+                    (Some(name), Dialect::Python | Dialect::Re2) => write!(out, "(?P<{}>", name)?,
+                    (Some(name), _) => write!(out, "(?<{}>", name)?,
+                }
+                self.hir(&capture.sub, out)?;
+                out.push(')');
+            }
+            HirKind::Concat(subs) => {
+                for sub in subs {
+                    if matches!(sub.kind(), HirKind::Alternation(_)) {
+                        out.push_str("(?:");
+                        self.hir(sub, out)?;
+                        out.push(')');
+                    } else {
+                        self.hir(sub, out)?;
+                    }
+                }
+            }
+            HirKind::Alternation(subs) => {
+                for (i, sub) in subs.iter().enumerate() {
+                    if i > 0 {
+                        out.push('|');
+                    }
+                    self.hir(sub, out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn class(&self, class: &Class, out: &mut String) -> Result<()> {
+        if let (Dialect::Rust, Class::Unicode(unicode)) = (self.dialect, class) {
+            if let Some((shorthand, _)) = shorthand_classes().iter().find(|(_, c)| c == class) {
+                out.push_str(shorthand);
+                return Ok(());
+            }
+            let mut negated = unicode.clone();
+            negated.negate();
+            // classes reaching the last code point are usually written negated
+            let reaches_max = unicode
+                .ranges()
+                .last()
+                .is_some_and(|r| r.end() == char::MAX);
+            let (len, negated_len) = (unicode.ranges().len(), negated.ranges().len());
+            if negated_len > 0 && (negated_len < len || (negated_len == len && reaches_max)) {
+                out.push_str("[^");
+                self.ranges(&self.class_ranges(&Class::Unicode(negated))?, out);
+                out.push(']');
+                return Ok(());
+            }
+        }
+
+        let ranges = self.class_ranges(class)?;
+        if ranges.is_empty() {
+            // a class that matches nothing, which not every dialect can spell as `[]`
+            out.push_str("[^");
+            self.char('\0', true, out);
+            out.push('-');
+            self.char(char::MAX, true, out);
+            out.push(']');
+            return Ok(());
+        }
+
+        out.push('[');
+        self.ranges(&ranges, out);
+        out.push(']');
+        Ok(())
+    }
+
+    fn class_ranges(&self, class: &Class) -> Result<Vec<(char, char)>> {
+        match class {
+            Class::Unicode(class) => Ok(class.iter().map(|r| (r.start(), r.end())).collect()),
+            Class::Bytes(class) => class
+                .iter()
+                .map(|r| {
+                    if r.end().is_ascii() {
+                        Ok((char::from(r.start()), char::from(r.end())))
+                    } else {
+                        Err(anyhow!(
+                            "Non-ASCII byte classes can't be rendered for {:?}",
+                            self.dialect
+                        ))
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn ranges(&self, ranges: &[(char, char)], out: &mut String) {
+        for &(start, end) in ranges {
+            self.char(start, true, out);
+            if start != end {
+                let adjacent = char::from_u32(start as u32 + 1) == Some(end);
+                if !(adjacent && self.dialect == Dialect::Rust) {
+                    out.push('-');
+                }
+                self.char(end, true, out);
+            }
+        }
+    }
+
+    fn look(&self, look: Look) -> Result<&'static str> {
+        match (look, self.dialect) {
+            (Look::StartLF, Dialect::Rust) => Ok("(?m:^)"),
+            (Look::EndLF, Dialect::Rust) => Ok("(?m:$)"),
+            (Look::WordAscii, Dialect::Rust) => Ok(r"(?-u:\b)"),
+            (Look::WordAsciiNegate, Dialect::Rust) => Ok(r"(?-u:\B)"),
+            (Look::WordUnicode, Dialect::Rust) => Ok(r"\b"),
+            (Look::WordUnicodeNegate, Dialect::Rust) => Ok(r"\B"),
+            (Look::Start, Dialect::JavaScript) => Ok("^"),
+            (Look::Start, _) => Ok(r"\A"),
+            (Look::End, Dialect::JavaScript) => Ok("$"),
+            (Look::End, Dialect::Python) => Ok(r"\Z"),
+            (Look::End, _) => Ok(r"\z"),
+            (look, dialect) => Err(anyhow!("{:?} can't be rendered for {:?}", look, dialect)),
+        }
+    }
+
+    fn char(&self, c: char, in_class: bool, out: &mut String) {
+        if self.dialect == Dialect::Rust {
+            // the regex crate accepts escaping any meta character, in classes or not
+            if regex_syntax::is_meta_character(c) {
+                out.push('\\');
+                out.push(c);
+            } else if c == ' ' || c.is_ascii_graphic() {
+                out.push(c);
+            } else {
+                self.hex(c, out);
+            }
+            return;
+        }
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => out.push(c),
+            ' ' | '_' | ',' | ':' | ';' | '\'' | '"' | '!' | '@' | '%' | '=' | '<' | '>' => {
+                out.push(c)
+            }
+            '-' if in_class => out.push_str(r"\-"),
+            '-' | '&' | '~' | '#' | '`' if !in_class => out.push(c),
+            '\\' | '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$'
+            | '/' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => self.hex(c, out),
+        }
+    }
+
+    fn hex(&self, c: char, out: &mut String) {
+        let code = c as u32;
+        let _ = match self.dialect {
+            Dialect::Python if code <= 0xFF => write!(out, r"\x{:02X}", code),
+            Dialect::Python if code <= 0xFFFF => write!(out, r"\u{:04X}", code),
+            Dialect::Python => write!(out, r"\U{:08X}", code),
+            Dialect::JavaScript if code <= 0xFF => write!(out, r"\x{:02X}", code),
+            Dialect::JavaScript => write!(out, r"\u{{{:X}}}", code),
+            Dialect::Rust if code <= 0xFF => write!(out, r"\x{:02X}", code),
+            _ => write!(out, r"\x{{{:X}}}", code),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+    use crate::guidance::build_regex_from_schema;
+
+    #[test]
+    fn test_rust_is_unchanged() {
+        let regex = r#"\{[ ]?"a"[ ]?:[ ]?(true|false)[ ]?\}"#;
+        assert_eq!(render(regex, Dialect::Rust).unwrap(), regex);
+        assert!(render("(", Dialect::Rust).is_err());
+    }
+
+    #[test]
+    fn test_python() {
+        let regex = r#"\A"([^"\\\x00-\x1F]|\\["\\])*"(-)?\z"#;
+        assert_eq!(
+            render(regex, Dialect::Python).unwrap(),
+            r#"\A"([ -!\x23-\[\]-\U0010FFFF]|\\["\\])*"(-)?\Z"#
+        );
+    }
+
+    #[test]
+    fn test_named_groups_and_anchors() {
+        let regex = r"^(?P<year>[0-9]{4})-(a|bc)$";
+        assert_eq!(
+            render(regex, Dialect::JavaScript).unwrap(),
+            "^(?<year>[0-9]{4})-(a|bc)$"
+        );
+        assert_eq!(
+            render(regex, Dialect::Re2).unwrap(),
+            r"\A(?P<year>[0-9]{4})-(a|bc)\z"
+        );
+        assert_eq!(render("x(?:a|b)y", Dialect::Pcre).unwrap(), "x[a-b]y");
+    }
+
+    #[test]
+    fn test_re2_repetition_limit() {
+        assert!(render("a{1001}", Dialect::Re2).is_err());
+        assert!(render("a{1001}", Dialect::Pcre).is_ok());
+    }
+
+    #[test]
+    fn test_rendered_schema_keeps_meaning() {
+        let schema = r#"{"type": "object", "properties": {"name": {"type": "string", "minLength": 1, "maxLength": 3}}, "required": ["name"]}"#;
+        let regex = build_regex_from_schema(schema, None).unwrap();
+        let rendered = render(&regex, Dialect::Re2).unwrap();
+
+        // RE2 syntax is close enough to the regex crate to check the result directly
+        let rendered = Regex::new(&format!("^(?:{})$", rendered)).unwrap();
+        assert!(rendered.is_match(r#"{"name": "a\"b"}"#));
+        assert!(!rendered.is_match(r#"{"name": "abcd"}"#));
+    }
+
+    #[test]
+    fn test_dialect_from_str() {
+        assert_eq!("JS".parse::<Dialect>().unwrap(), Dialect::JavaScript);
+        assert_eq!("pcre2".parse::<Dialect>().unwrap(), Dialect::Pcre);
+        assert!("perl".parse::<Dialect>().is_err());
+    }
+}
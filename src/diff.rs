@@ -0,0 +1,162 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{anyhow, Result};
+use regex_automata::dfa::Automaton;
+use regex_syntax::hir::{Hir, HirKind};
+use regex_syntax::Parser;
+
+use crate::automaton::{build_dfa, start_state};
+
+/// An input that only one of two regexes matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample {
+    pub input: String,
+    pub matched_by_left: bool,
+}
+
+/// Comparison of two regexes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexDiff {
+    /// Shortest input accepted by exactly one side, if any.
+    pub counterexample: Option<Counterexample>,
+    /// Pairs of sub-patterns where the syntax trees diverge, left then right.
+    pub fragments: Vec<(String, String)>,
+}
+
+impl RegexDiff {
+    /// Whether both regexes match exactly the same inputs.
+    pub fn is_equivalent(&self) -> bool {
+        self.counterexample.is_none()
+    }
+}
+
+/// Compares the languages and the syntax trees of two regexes.
+///
+/// Equivalence is decided on the DFAs, so regexes that are written
+/// differently but match the same inputs are equivalent even when
+/// `fragments` isn't empty.
+pub fn diff(left: &str, right: &str) -> Result<RegexDiff> {
+    let parse = |regex| {
+        Parser::new()
+            .parse(regex)
+            .map_err(|e| anyhow!("Failed to parse regex: {}", e))
+    };
+    let (left_hir, right_hir) = (parse(left)?, parse(right)?);
+
+    let mut fragments = Vec::new();
+    diff_hir(&left_hir, &right_hir, &mut fragments);
+
+    Ok(RegexDiff {
+        counterexample: counterexample(left, right)?,
+        fragments,
+    })
+}
+
+fn diff_hir(left: &Hir, right: &Hir, fragments: &mut Vec<(String, String)>) {
+    if left == right {
+        return;
+    }
+    match (left.kind(), right.kind()) {
+        (HirKind::Concat(lefts), HirKind::Concat(rights))
+        | (HirKind::Alternation(lefts), HirKind::Alternation(rights))
+            if lefts.len() == rights.len() =>
+        {
+            for (left, right) in lefts.iter().zip(rights) {
+                diff_hir(left, right, fragments);
+            }
+        }
+        (HirKind::Capture(left), HirKind::Capture(right)) => {
+            diff_hir(&left.sub, &right.sub, fragments)
+        }
+        (HirKind::Repetition(left), HirKind::Repetition(right))
+            if (left.min, left.max, left.greedy) == (right.min, right.max, right.greedy) =>
+        {
+            diff_hir(&left.sub, &right.sub, fragments)
+        }
+        _ => fragments.push((left.to_string(), right.to_string())),
+    }
+}
+
+/// Breadth-first search over pairs of DFA states for the shortest input
+/// where exactly one side reaches a match.
+fn counterexample(left: &str, right: &str) -> Result<Option<Counterexample>> {
+    let (left_dfa, right_dfa) = (build_dfa(left)?, build_dfa(right)?);
+    let start = (start_state(&left_dfa)?, start_state(&right_dfa)?);
+
+    let mut parents = HashMap::from([(start, None)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(pair @ (l, r)) = queue.pop_front() {
+        let left_matches = left_dfa.is_match_state(left_dfa.next_eoi_state(l));
+        let right_matches = right_dfa.is_match_state(right_dfa.next_eoi_state(r));
+        if left_matches != right_matches {
+            let mut input = Vec::new();
+            let mut current = pair;
+            while let Some(&Some((parent, byte))) = parents.get(&current) {
+                input.push(byte);
+                current = parent;
+            }
+            input.reverse();
+            return Ok(Some(Counterexample {
+                input: String::from_utf8_lossy(&input).into_owned(),
+                matched_by_left: left_matches,
+            }));
+        }
+
+        for byte in 0..=u8::MAX {
+            let next = (left_dfa.next_state(l, byte), right_dfa.next_state(r, byte));
+            let left_dead = left_dfa.is_dead_state(next.0) || left_dfa.is_quit_state(next.0);
+            let right_dead = right_dfa.is_dead_state(next.1) || right_dfa.is_quit_state(next.1);
+            if left_dead && right_dead {
+                continue;
+            }
+            if let Entry::Vacant(entry) = parents.entry(next) {
+                entry.insert(Some((pair, byte)));
+                queue.push_back(next);
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equivalent() {
+        let result = diff("(true|false)", "(false|true)").unwrap();
+        assert!(result.is_equivalent());
+        assert_eq!(
+            result.fragments,
+            [
+                ("(?:true)".to_string(), "(?:false)".to_string()),
+                ("(?:false)".to_string(), "(?:true)".to_string())
+            ]
+        );
+
+        assert_eq!(
+            diff("[0-9]+", "[0-9]+").unwrap(),
+            RegexDiff {
+                counterexample: None,
+                fragments: Vec::new()
+            }
+        );
+    }
+
+    #[test]
+    fn test_counterexample() {
+        let result = diff("a[0-9]{1,2}", "a[0-9]{1,3}").unwrap();
+        assert_eq!(
+            result.counterexample,
+            Some(Counterexample {
+                input: "a000".to_string(),
+                matched_by_left: false
+            })
+        );
+        assert_eq!(
+            result.fragments,
+            [("[0-9]{1,2}".to_string(), "[0-9]{1,3}".to_string())]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! JSON Schema drafts, which give some keywords other meanings.
+
+use jsonschema::error::ValidationErrorKind;
+use jsonschema::{JSONSchema, ValidationError};
+use serde_json::Value;
+
+use crate::error::SchemaError;
+
+/// A JSON Schema draft whose keywords the generator reads differently than
+/// the others'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Draft {
+    /// `exclusiveMinimum` and `exclusiveMaximum` are booleans making
+    /// `minimum` and `maximum` exclusive.
+    Draft4,
+    Draft6,
+    Draft7,
+    /// The last draft where an `items` array is a tuple, without
+    /// `prefixItems`.
+    Draft2019_09,
+    Draft2020_12,
+}
+
+impl Draft {
+    /// The draft the `$schema` of `schema` declares, `None` when it has none
+    /// or an unknown one.
+    pub fn detect(schema: &Value) -> Option<Draft> {
+        let uri = schema.get("$schema")?.as_str()?;
+        [
+            ("draft-04", Draft::Draft4),
+            ("draft-06", Draft::Draft6),
+            ("draft-07", Draft::Draft7),
+            ("draft/2019-09", Draft::Draft2019_09),
+            ("draft/2020-12", Draft::Draft2020_12),
+        ]
+        .into_iter()
+        .find_map(|(name, draft)| uri.contains(name).then_some(draft))
+    }
+}
+
+/// Validates `schema` against the meta-schema of `draft`, or of the draft its
+/// `$schema` declares when `None`, draft 7 without either.
+///
+/// The meta-schemas are vendored by `jsonschema`, those of 2019-09 and
+/// 2020-12 with the `validate-schema` feature only, draft 7 standing in for
+/// them otherwise.
+pub(crate) fn validate_schema(schema: &Value, draft: Option<Draft>) -> Result<(), SchemaError> {
+    let mut options = JSONSchema::options();
+    if let Some(draft) = draft {
+        options.with_draft(validator_draft(draft));
+    }
+    match options.compile(schema) {
+        // a keyword `jsonschema` failed to compile after the schema passed
+        // meta-validation, like a `"type": "date"` the meta-schema of 2020-12
+        // doesn't reach through `$dynamicRef`: the error has no location, and
+        // the generator reports the keyword with its pointer
+        Err(error) if matches!(error.kind, ValidationErrorKind::Schema) => Ok(()),
+        compiled => compiled.map(drop).map_err(meta_schema_error),
+    }
+}
+
+fn validator_draft(draft: Draft) -> jsonschema::Draft {
+    match draft {
+        Draft::Draft4 => jsonschema::Draft::Draft4,
+        Draft::Draft6 => jsonschema::Draft::Draft6,
+        #[cfg(feature = "validate-schema")]
+        Draft::Draft2019_09 => jsonschema::Draft::Draft201909,
+        #[cfg(feature = "validate-schema")]
+        Draft::Draft2020_12 => jsonschema::Draft::Draft202012,
+        _ => jsonschema::Draft::Draft7,
+    }
+}
+
+/// The error of a schema failing meta-validation, with the keyword whose
+/// value the meta-schema rejected, as meta-schemas describe keywords by their
+/// `properties`.
+fn meta_schema_error(error: ValidationError) -> SchemaError {
+    let path = error.schema_path.clone().into_vec();
+    let mut keyword = None;
+    let mut i = 0;
+    while i < path.len() {
+        if path[i] == "properties" && i + 1 < path.len() {
+            keyword = Some(path[i + 1].clone());
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+
+    let error = SchemaError::new(format!("Failed to compile JSON schema: {}", error))
+        .at(error.instance_path.to_string());
+    match keyword {
+        Some(keyword) => error.with_keyword(keyword),
+        None => error,
+    }
+}
+
+/// Whether `prefixItems` holds the items of tuples with `draft`, as in
+/// 2020-12 and when it isn't known.
+pub(crate) fn has_prefix_items(draft: Option<Draft>) -> bool {
+    draft.is_none_or(|draft| draft >= Draft::Draft2020_12)
+}
+
+/// Whether an `items` array holds the items of tuples with `draft`, as
+/// before 2020-12 and when it isn't known.
+pub(crate) fn has_tuple_items(draft: Option<Draft>) -> bool {
+    draft.is_none_or(|draft| draft < Draft::Draft2020_12)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        for (uri, draft) in [
+            (
+                "http://json-schema.org/draft-04/schema#",
+                Some(Draft::Draft4),
+            ),
+            (
+                "http://json-schema.org/draft-07/schema",
+                Some(Draft::Draft7),
+            ),
+            (
+                "https://json-schema.org/draft/2019-09/schema",
+                Some(Draft::Draft2019_09),
+            ),
+            (
+                "https://json-schema.org/draft/2020-12/schema",
+                Some(Draft::Draft2020_12),
+            ),
+            ("https://example.com/my-schema", None),
+        ] {
+            assert_eq!(Draft::detect(&json!({"$schema": uri})), draft, "{}", uri);
+        }
+        assert_eq!(Draft::detect(&json!({"type": "string"})), None);
+    }
+
+    #[test]
+    fn test_validate_schema() {
+        let error = validate_schema(
+            &json!({"type": "object", "properties": {"a": {"minLength": -1}}}),
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(error.keyword.as_deref(), Some("minLength"));
+        assert_eq!(error.pointer.as_deref(), Some("/properties/a/minLength"));
+
+        let draft_04 = json!({"type": "number", "minimum": 1, "exclusiveMinimum": true});
+        let error = validate_schema(&draft_04, None).unwrap_err();
+        assert_eq!(error.keyword.as_deref(), Some("exclusiveMinimum"));
+        assert!(validate_schema(&draft_04, Some(Draft::Draft4)).is_ok());
+
+        // 2020-12 is only told apart with its vendored meta-schema
+        let schema = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "prefixItems": 5
+        });
+        let error = validate_schema(&schema, None).err();
+        assert_eq!(error.is_some(), cfg!(feature = "validate-schema"));
+        if let Some(error) = error {
+            assert_eq!(error.keyword.as_deref(), Some("prefixItems"));
+        }
+    }
+}
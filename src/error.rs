@@ -0,0 +1,100 @@
+//! Errors pointing at the part of the schema they're about.
+
+use std::fmt;
+
+use serde_json::Value;
+
+/// Values longer than this are cut short in messages.
+const MAX_SHOWN_CHARS: usize = 40;
+
+/// An error caused by a specific subschema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    pub message: String,
+    /// JSON Pointer of the subschema in the schema document, once known.
+    pub pointer: Option<String>,
+    /// How the schema could be fixed, when there's a likely way.
+    pub hint: Option<String>,
+    /// The keyword of the subschema at fault, when it's a single one.
+    pub keyword: Option<String>,
+}
+
+impl SchemaError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            pointer: None,
+            hint: None,
+            keyword: None,
+        }
+    }
+
+    /// A keyword set to a value of the wrong type.
+    pub fn wrong_type(keyword: &str, expected: &str, got: &Value) -> Self {
+        Self {
+            keyword: Some(keyword.to_string()),
+            ..Self::new(format!(
+                "keyword '{}' expected {}, got {}",
+                keyword,
+                expected,
+                brief(got)
+            ))
+        }
+    }
+
+    pub fn at(mut self, pointer: impl Into<String>) -> Self {
+        self.pointer = Some(pointer.into());
+        self
+    }
+
+    /// Locates the error in the subschema at `pointer`, down to its keyword
+    /// when there's one.
+    pub fn in_subschema(self, pointer: &str) -> Self {
+        let pointer = match &self.keyword {
+            Some(keyword) => format!(
+                "{}/{}",
+                pointer,
+                keyword.replace('~', "~0").replace('/', "~1")
+            ),
+            None => pointer.to_string(),
+        };
+        self.at(pointer)
+    }
+
+    pub fn with_keyword(mut self, keyword: impl Into<String>) -> Self {
+        self.keyword = Some(keyword.into());
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// The [`SchemaError`] behind `error`, if there's one.
+    pub fn find(error: &anyhow::Error) -> Option<&SchemaError> {
+        error.chain().find_map(|cause| cause.downcast_ref())
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pointer.as_deref() {
+            None => f.write_str(&self.message),
+            Some("") => write!(f, "{} at the root of the schema", self.message),
+            Some(pointer) => write!(f, "{} at {}", self.message, pointer),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// `value` as JSON, cut short when it's long.
+pub(crate) fn brief(value: &Value) -> String {
+    let json = value.to_string();
+    if json.chars().count() > MAX_SHOWN_CHARS {
+        json.chars().take(MAX_SHOWN_CHARS).collect::<String>() + "..."
+    } else {
+        json
+    }
+}
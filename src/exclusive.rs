@@ -0,0 +1,716 @@
+//! `oneOf` branches made exclusive, so that values matching several of them,
+//! which validators reject, don't match the regex either, and the regexes of
+//! `allOf` branches intersected.
+//!
+//! The DFAs of the branches are run side by side, a character at a time,
+//! keeping the inputs matched by exactly one of them, or by all, and the
+//! states of that product are eliminated one by one until a regex is left.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use anyhow::Result;
+use regex_automata::dfa::{dense, Automaton};
+use regex_automata::util::primitives::StateID;
+
+use crate::automaton::{build_dfa_with_options, start_state, DfaBuildOptions, DfaTooLarge};
+
+/// Bytes the DFA of one branch may take.
+const MAX_BRANCH_DFA_BYTES: usize = 1 << 20;
+/// States the branches may take together, when telling them apart.
+const MAX_STATES: usize = 512;
+/// Bytes the regex of the exclusive branches may take.
+const MAX_REGEX_LEN: usize = 1 << 16;
+
+/// How the branches of a `oneOf` overlap.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Exclusivity {
+    /// No value matches several branches.
+    Disjoint,
+    /// A regex matching the values of exactly one branch.
+    Exclusive(String),
+    /// Every value matches several branches or none.
+    Unsatisfiable,
+    /// The branches overlap, or may, but can't be told apart, for this reason.
+    Infeasible(String),
+}
+
+/// The values matched by every one of some regexes.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Intersection {
+    /// A regex matching them.
+    Regex(String),
+    /// No value matches every regex.
+    Empty,
+    /// The regexes can't be intersected, for this reason.
+    Infeasible(String),
+}
+
+/// How the branches of the `regexes` overlap.
+pub(crate) fn exclusivity(regexes: &[String]) -> Result<Exclusivity> {
+    let Some(dfas) = build_dfas(regexes)? else {
+        return Ok(Exclusivity::Infeasible(
+            "the automaton of a branch is too large".to_string(),
+        ));
+    };
+    let Some(product) = Product::explore(&dfas, Mode::Exclusive)? else {
+        return Ok(Exclusivity::Infeasible(format!(
+            "the branches take more than {} states to tell apart",
+            MAX_STATES
+        )));
+    };
+    if !product.overlap {
+        return Ok(Exclusivity::Disjoint);
+    }
+    if product.invalid_utf8 {
+        return Ok(Exclusivity::Infeasible(
+            "the branches accept bytes out of UTF-8".to_string(),
+        ));
+    }
+    if !product.live_states()[0] {
+        return Ok(Exclusivity::Unsatisfiable);
+    }
+    Ok(match product.to_regex() {
+        Some(regex) => Exclusivity::Exclusive(regex),
+        None => Exclusivity::Infeasible(format!(
+            "the regex telling the branches apart takes more than {} bytes",
+            MAX_REGEX_LEN
+        )),
+    })
+}
+
+/// The values every one of the `regexes` matches.
+pub(crate) fn intersection(regexes: &[String]) -> Result<Intersection> {
+    let Some(dfas) = build_dfas(regexes)? else {
+        return Ok(Intersection::Infeasible(
+            "the automaton of a regex is too large".to_string(),
+        ));
+    };
+    let Some(product) = Product::explore(&dfas, Mode::Intersection)? else {
+        return Ok(Intersection::Infeasible(format!(
+            "the regexes take more than {} states together",
+            MAX_STATES
+        )));
+    };
+    if product.invalid_utf8 {
+        return Ok(Intersection::Infeasible(
+            "the regexes accept bytes out of UTF-8".to_string(),
+        ));
+    }
+    if !product.live_states()[0] {
+        return Ok(Intersection::Empty);
+    }
+    Ok(match product.to_regex() {
+        Some(regex) => Intersection::Regex(regex),
+        None => Intersection::Infeasible(format!(
+            "the regex of the intersection takes more than {} bytes",
+            MAX_REGEX_LEN
+        )),
+    })
+}
+
+/// The DFAs of the `regexes`, `None` when one is too large.
+fn build_dfas(regexes: &[String]) -> Result<Option<Vec<dense::DFA<Vec<u32>>>>> {
+    let options = DfaBuildOptions {
+        max_memory_bytes: Some(MAX_BRANCH_DFA_BYTES),
+    };
+    let mut dfas = Vec::with_capacity(regexes.len());
+    for regex in regexes {
+        match build_dfa_with_options(regex, &options) {
+            Ok(dfa) => dfas.push(dfa),
+            Err(e) if e.is::<DfaTooLarge>() => return Ok(None),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(dfas))
+}
+
+/// The inputs the product of the DFAs keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Those matched by exactly one DFA.
+    Exclusive,
+    /// Those matched by every DFA.
+    Intersection,
+}
+
+/// The DFAs of the branches run side by side.
+struct Product {
+    mode: Mode,
+    /// The transitions of each state, by target, the start being 0.
+    transitions: Vec<BTreeMap<usize, CharSet>>,
+    /// Whether the input so far is kept at each state.
+    accepting: Vec<bool>,
+    /// Whether several branches match at some state.
+    overlap: bool,
+    /// Whether some transition reads bytes that aren't a UTF-8 character.
+    invalid_utf8: bool,
+}
+
+/// The states of the DFAs of the branches, one each.
+type States = Vec<StateID>;
+
+impl Product {
+    /// The product of `dfas`, `None` when it has more than [`MAX_STATES`].
+    fn explore(dfas: &[dense::DFA<Vec<u32>>], mode: Mode) -> Result<Option<Product>> {
+        let start: States = dfas.iter().map(start_state).collect::<Result<_>>()?;
+
+        let mut product = Product {
+            mode,
+            transitions: Vec::new(),
+            accepting: Vec::new(),
+            overlap: false,
+            invalid_utf8: false,
+        };
+        let mut ids = HashMap::from([(start.clone(), 0)]);
+        let mut states = vec![start];
+        let mut i = 0;
+        while i < states.len() {
+            let matching = dfas
+                .iter()
+                .zip(&states[i])
+                .filter(|(dfa, &state)| {
+                    alive(dfa, state) && dfa.is_match_state(dfa.next_eoi_state(state))
+                })
+                .count();
+            product.overlap |= matching > 1;
+            product.accepting.push(match mode {
+                Mode::Exclusive => matching == 1,
+                Mode::Intersection => matching == dfas.len(),
+            });
+
+            let mut transitions: BTreeMap<usize, CharSet> = BTreeMap::new();
+            for (next, chars) in product.char_transitions(dfas, &states[i]) {
+                let id = match ids.get(&next) {
+                    Some(&id) => id,
+                    None if states.len() == MAX_STATES => return Ok(None),
+                    None => {
+                        ids.insert(next.clone(), states.len());
+                        states.push(next);
+                        states.len() - 1
+                    }
+                };
+                transitions.entry(id).or_default().extend(&chars);
+            }
+            product.transitions.push(transitions);
+            i += 1;
+        }
+        Ok(Some(product))
+    }
+
+    /// The states after each character from `states`, with the characters
+    /// leading to them.
+    fn char_transitions(
+        &mut self,
+        dfas: &[dense::DFA<Vec<u32>>],
+        states: &States,
+    ) -> Vec<(States, CharSet)> {
+        let mut transitions: Vec<(States, CharSet)> = Vec::new();
+        let mut add = |next: States, chars: CharSet| match transitions
+            .iter_mut()
+            .find(|(states, _)| *states == next)
+        {
+            Some((_, existing)) => existing.extend(&chars),
+            None => transitions.push((next, chars)),
+        };
+        for byte in 0..=u8::MAX {
+            let next = step(dfas, states, byte);
+            if !self.keeps(dfas, &next) {
+                continue;
+            }
+            // the bits of the first byte of a character, and the number of
+            // continuation bytes after it
+            let (bits, continuations) = match byte {
+                0x00..=0x7F => (u32::from(byte), 0),
+                0xC2..=0xDF => (u32::from(byte & 0x1F), 1),
+                0xE0..=0xEF => (u32::from(byte & 0x0F), 2),
+                0xF0..=0xF4 => (u32::from(byte & 0x07), 3),
+                _ => {
+                    self.invalid_utf8 = true;
+                    continue;
+                }
+            };
+            let base = bits << (6 * continuations);
+            for (next, suffixes) in self.continuations(dfas, next, continuations) {
+                let chars = suffixes
+                    .0
+                    .iter()
+                    .map(|&(first, last)| (base + first, base + last))
+                    .collect();
+                add(next, chars);
+            }
+        }
+        transitions
+    }
+
+    /// The states after `count` continuation bytes from `states`, with the
+    /// values of the 6 bits each of them carries, read as one number.
+    fn continuations(
+        &mut self,
+        dfas: &[dense::DFA<Vec<u32>>],
+        states: States,
+        count: u32,
+    ) -> Vec<(States, CharSet)> {
+        if count == 0 {
+            return vec![(states, CharSet::from_iter([(0, 0)]))];
+        }
+        // the continuation bytes leading to the same states are followed once
+        let mut groups: Vec<(States, Vec<u32>)> = Vec::new();
+        for byte in 0x80..=0xBF {
+            let next = step(dfas, &states, byte);
+            if !self.keeps(dfas, &next) {
+                continue;
+            }
+            let value = u32::from(byte & 0x3F);
+            match groups.iter_mut().find(|(states, _)| *states == next) {
+                Some((_, values)) => values.push(value),
+                None => groups.push((next, vec![value])),
+            }
+        }
+        if (0..=u8::MAX)
+            .filter(|byte| !(0x80..=0xBF).contains(byte))
+            .any(|byte| self.keeps(dfas, &step(dfas, &states, byte)))
+        {
+            self.invalid_utf8 = true;
+        }
+
+        let span = 1 << (6 * (count - 1));
+        let mut continuations: Vec<(States, CharSet)> = Vec::new();
+        for (next, values) in groups {
+            for (last, suffixes) in self.continuations(dfas, next, count - 1) {
+                let mut chars = CharSet::default();
+                for &value in &values {
+                    for &(first, last) in &suffixes.0 {
+                        chars.insert(value * span + first, value * span + last);
+                    }
+                }
+                match continuations.iter_mut().find(|(states, _)| *states == last) {
+                    Some((_, existing)) => existing.extend(&chars),
+                    None => continuations.push((last, chars)),
+                }
+            }
+        }
+        continuations
+    }
+
+    /// Whether an input reaching `states` may still be kept: when some DFA
+    /// may match it, or every one for an intersection.
+    fn keeps(&self, dfas: &[dense::DFA<Vec<u32>>], states: &States) -> bool {
+        let mut alive = dfas
+            .iter()
+            .zip(states)
+            .map(|(dfa, &state)| alive(dfa, state));
+        match self.mode {
+            Mode::Exclusive => alive.any(|alive| alive),
+            Mode::Intersection => alive.all(|alive| alive),
+        }
+    }
+
+    /// The states from which an accepting one can be reached.
+    fn live_states(&self) -> Vec<bool> {
+        let mut sources: Vec<Vec<usize>> = vec![Vec::new(); self.transitions.len()];
+        for (from, transitions) in self.transitions.iter().enumerate() {
+            for &to in transitions.keys() {
+                sources[to].push(from);
+            }
+        }
+        let mut live = self.accepting.clone();
+        let mut queue: VecDeque<usize> = (0..live.len()).filter(|&state| live[state]).collect();
+        while let Some(state) = queue.pop_front() {
+            for &from in &sources[state] {
+                if !live[from] {
+                    live[from] = true;
+                    queue.push_back(from);
+                }
+            }
+        }
+        live
+    }
+
+    /// The regex of the inputs reaching an accepting state, by eliminating
+    /// the states one by one, `None` when it's longer than [`MAX_REGEX_LEN`].
+    fn to_regex(&self) -> Option<String> {
+        let live = self.live_states();
+
+        // the states, with a new start and end around them
+        let (start, end) = (self.transitions.len(), self.transitions.len() + 1);
+        let mut edges: Vec<BTreeMap<usize, Re>> = vec![BTreeMap::new(); end + 1];
+        let mut sources: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); end + 1];
+        let add = |edges: &mut Vec<BTreeMap<usize, Re>>,
+                   sources: &mut Vec<BTreeSet<usize>>,
+                   (from, to): (usize, usize),
+                   re: Re| {
+            sources[to].insert(from);
+            let re = match edges[from].remove(&to) {
+                Some(existing) => Re::alternation([existing, re]),
+                None => re,
+            };
+            edges[from].insert(to, re);
+        };
+        add(&mut edges, &mut sources, (start, 0), Re::empty());
+        for (from, transitions) in self.transitions.iter().enumerate() {
+            if !live[from] {
+                continue;
+            }
+            for (&to, chars) in transitions {
+                if live[to] {
+                    add(
+                        &mut edges,
+                        &mut sources,
+                        (from, to),
+                        Re::Class(chars.clone()),
+                    );
+                }
+            }
+            if self.accepting[from] {
+                add(&mut edges, &mut sources, (from, end), Re::empty());
+            }
+        }
+
+        let mut remaining: BTreeSet<usize> = (0..start).filter(|&state| live[state]).collect();
+        while let Some(state) = remaining
+            .iter()
+            .copied()
+            .min_by_key(|&state| sources[state].len() * edges[state].len())
+        {
+            remaining.remove(&state);
+            let targets = std::mem::take(&mut edges[state]);
+            let repeated = targets.get(&state).cloned().map(Re::star);
+            for &from in &std::mem::take(&mut sources[state]) {
+                if from == state {
+                    continue;
+                }
+                let into = edges[from]
+                    .remove(&state)
+                    .expect("an edge from each source");
+                for (&to, out) in &targets {
+                    if to == state {
+                        continue;
+                    }
+                    let mut parts = vec![into.clone()];
+                    parts.extend(repeated.clone());
+                    parts.push(out.clone());
+                    add(&mut edges, &mut sources, (from, to), Re::concat(parts));
+                }
+            }
+            for to in targets.keys() {
+                sources[*to].remove(&state);
+            }
+            if edges
+                .iter()
+                .flat_map(BTreeMap::values)
+                .map(Re::len)
+                .sum::<usize>()
+                > MAX_REGEX_LEN
+            {
+                return None;
+            }
+        }
+        let regex = edges[start]
+            .get(&end)
+            .map(Re::to_string)
+            .unwrap_or_default();
+        (regex.len() <= MAX_REGEX_LEN).then_some(regex)
+    }
+}
+
+fn alive(dfa: &dense::DFA<Vec<u32>>, state: StateID) -> bool {
+    !dfa.is_dead_state(state) && !dfa.is_quit_state(state)
+}
+
+/// The states of `dfas` after `byte` from `states`.
+fn step(dfas: &[dense::DFA<Vec<u32>>], states: &States, byte: u8) -> States {
+    dfas.iter()
+        .zip(states)
+        .map(|(dfa, &state)| dfa.next_state(state, byte))
+        .collect()
+}
+
+/// Characters, as the sorted and disjoint ranges of their code points.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct CharSet(Vec<(u32, u32)>);
+
+impl CharSet {
+    fn insert(&mut self, first: u32, last: u32) {
+        let at = self
+            .0
+            .partition_point(|&(_, end)| end.saturating_add(1) < first);
+        let mut merged = (first, last);
+        while let Some(&(start, end)) = self.0.get(at) {
+            if start > merged.1.saturating_add(1) {
+                break;
+            }
+            merged = (merged.0.min(start), merged.1.max(end));
+            self.0.remove(at);
+        }
+        self.0.insert(at, merged);
+    }
+
+    fn extend(&mut self, other: &CharSet) {
+        for &(first, last) in &other.0 {
+            self.insert(first, last);
+        }
+    }
+
+    /// The character of a set of one.
+    fn single(&self) -> Option<char> {
+        match self.0[..] {
+            [(first, last)] if first == last => char::from_u32(first),
+            _ => None,
+        }
+    }
+}
+
+impl FromIterator<(u32, u32)> for CharSet {
+    fn from_iter<I: IntoIterator<Item = (u32, u32)>>(ranges: I) -> Self {
+        let mut set = Self::default();
+        for (first, last) in ranges {
+            set.insert(first, last);
+        }
+        set
+    }
+}
+
+/// Written like a regex class, such as `[",0-9\]é-ü]`, with the characters
+/// out of ASCII as they are but for control ones.
+impl std::fmt::Display for CharSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let write_char = |f: &mut std::fmt::Formatter<'_>, code: u32| {
+            let c = char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+            match c {
+                '\\' | ']' | '[' | '-' | '^' => write!(f, "\\{}", c),
+                ' '..='~' => write!(f, "{}", c),
+                _ if c.is_control() && code <= 0xFF => write!(f, "\\x{:02X}", code),
+                _ if c.is_control() => write!(f, "\\x{{{:X}}}", code),
+                _ => write!(f, "{}", c),
+            }
+        };
+        f.write_str("[")?;
+        for &(first, last) in &self.0 {
+            write_char(f, first)?;
+            if last > first + 1 {
+                f.write_str("-")?;
+            }
+            if last > first {
+                write_char(f, last)?;
+            }
+        }
+        f.write_str("]")
+    }
+}
+
+/// A regex being built from the transitions of the product.
+#[derive(Debug, Clone, PartialEq)]
+enum Re {
+    Class(CharSet),
+    Concat(Vec<Re>),
+    /// The branches, with the empty one last if it's there.
+    Alternation(Vec<Re>),
+    Star(Box<Re>),
+}
+
+impl Re {
+    /// The regex of the empty input.
+    fn empty() -> Re {
+        Re::Concat(Vec::new())
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self, Re::Concat(parts) if parts.is_empty())
+    }
+
+    fn concat(parts: Vec<Re>) -> Re {
+        let mut flat = Vec::new();
+        for part in parts {
+            match part {
+                Re::Concat(parts) => flat.extend(parts),
+                part => flat.push(part),
+            }
+        }
+        match <[Re; 1]>::try_from(flat) {
+            Ok([part]) => part,
+            Err(flat) => Re::Concat(flat),
+        }
+    }
+
+    fn alternation(branches: impl IntoIterator<Item = Re>) -> Re {
+        let mut flat = Vec::new();
+        for re in branches {
+            match re {
+                Re::Alternation(more) => flat.extend(more),
+                re => flat.push(re),
+            }
+        }
+        let mut merged: Vec<Re> = Vec::new();
+        let mut optional = false;
+        for branch in flat {
+            if branch.is_empty() {
+                optional = true;
+                continue;
+            }
+            // classes of single characters are merged
+            if let Re::Class(chars) = &branch {
+                if let Some(Re::Class(class)) =
+                    merged.iter_mut().find(|re| matches!(re, Re::Class(_)))
+                {
+                    class.extend(chars);
+                    continue;
+                }
+            }
+            if !merged.contains(&branch) {
+                merged.push(branch);
+            }
+        }
+        if optional {
+            merged.push(Re::empty());
+        }
+        match <[Re; 1]>::try_from(merged) {
+            Ok([re]) => re,
+            Err(merged) => Re::Alternation(merged),
+        }
+    }
+
+    fn star(self) -> Re {
+        match self {
+            re if re.is_empty() => re,
+            re @ Re::Star(_) => re,
+            Re::Alternation(mut branches) if branches.last().is_some_and(Re::is_empty) => {
+                branches.pop();
+                Re::Star(Box::new(Re::alternation(branches)))
+            }
+            re => Re::Star(Box::new(re)),
+        }
+    }
+
+    /// About the length of the regex, for the limit on it.
+    fn len(&self) -> usize {
+        match self {
+            Re::Class(chars) => 2 + 8 * chars.0.len(),
+            Re::Concat(parts) => parts.iter().map(Re::len).sum(),
+            Re::Alternation(branches) => branches.iter().map(|re| re.len() + 1).sum::<usize>() + 2,
+            Re::Star(re) => re.len() + 3,
+        }
+    }
+
+    /// Writes the regex as a single item, in a group unless it's one already.
+    fn write_item(&self, out: &mut String) {
+        match self {
+            Re::Class(_) => self.write(out),
+            _ => {
+                out.push('(');
+                self.write(out);
+                out.push(')');
+            }
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        match self {
+            Re::Class(chars) => match chars.single() {
+                Some(c)
+                    if c == ' ' || c.is_ascii_graphic() || (!c.is_ascii() && !c.is_control()) =>
+                {
+                    out.push_str(&regex_syntax::escape(&c.to_string()))
+                }
+                _ => out.push_str(&chars.to_string()),
+            },
+            Re::Concat(parts) => {
+                for part in parts {
+                    match part {
+                        Re::Alternation(branches) if !branches.last().is_some_and(Re::is_empty) => {
+                            part.write_item(out)
+                        }
+                        _ => part.write(out),
+                    }
+                }
+            }
+            Re::Alternation(branches) => match branches.split_last() {
+                Some((last, rest)) if last.is_empty() => {
+                    Re::alternation(rest.to_vec()).write_item(out);
+                    out.push('?');
+                }
+                _ => {
+                    for (i, branch) in branches.iter().enumerate() {
+                        if i > 0 {
+                            out.push('|');
+                        }
+                        branch.write(out);
+                    }
+                }
+            },
+            Re::Star(re) => {
+                re.write_item(out);
+                out.push('*');
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Re {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out);
+        f.write_str(&out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+
+    fn exclusive(regexes: &[&str]) -> Exclusivity {
+        let regexes: Vec<String> = regexes.iter().map(|regex| regex.to_string()).collect();
+        exclusivity(&regexes).unwrap()
+    }
+
+    #[test]
+    fn test_exclusivity() {
+        assert_eq!(exclusive(&["a[0-9]", "b[0-9]"]), Exclusivity::Disjoint);
+
+        let Exclusivity::Exclusive(regex) = exclusive(&["[0-9]", "[5-9]|x"]) else {
+            panic!("the branches overlap");
+        };
+        assert_eq!(regex, "[0-4x]");
+
+        let Exclusivity::Exclusive(regex) = exclusive(&["a+", "a{2,3}", "b"]) else {
+            panic!("the branches overlap");
+        };
+        let regex = Regex::new(&format!("^(?:{})$", regex)).unwrap();
+        for (input, matches) in [
+            ("a", true),
+            ("aa", false),
+            ("aaa", false),
+            ("aaaa", true),
+            ("b", true),
+            ("", false),
+        ] {
+            assert_eq!(regex.is_match(input), matches, "{}", input);
+        }
+
+        assert_eq!(exclusive(&["a|b", "b|a"]), Exclusivity::Unsatisfiable);
+
+        // characters out of ASCII are read whole
+        let Exclusivity::Exclusive(regex) = exclusive(&["é|x", "x"]) else {
+            panic!("the branches overlap");
+        };
+        assert_eq!(regex, "é");
+        let Exclusivity::Exclusive(regex) = exclusive(&[".", "[a-zé]"]) else {
+            panic!("the branches overlap");
+        };
+        let regex = Regex::new(&format!("^(?:{})$", regex)).unwrap();
+        for (input, matches) in [
+            ("b", false),
+            ("é", false),
+            ("ü", true),
+            ("π", true),
+            ("😀", true),
+        ] {
+            assert_eq!(regex.is_match(input), matches, "{}", input);
+        }
+        assert_eq!(
+            CharSet::from_iter([(0x61, 0x7A), (0xE9, 0xE9)]).to_string(),
+            "[a-zé]"
+        );
+    }
+}
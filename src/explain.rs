@@ -0,0 +1,239 @@
+use std::ops::Range;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::guidance::{
+    build_regex_from_schema_with_options, configured_generator, non_capturing,
+    resolve_whitespace_pattern, Generator, RegexOptions,
+};
+
+/// Regex generated for one node of a schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    /// JSON Pointer to the node, `""` for the root.
+    pub pointer: String,
+    /// How many schema nodes enclose this one.
+    pub depth: usize,
+    pub regex: String,
+    /// Where `regex` is written in the regex of the whole schema, once for
+    /// each time it's repeated, like the items of an array. Empty when it's
+    /// rewritten, like merged `allOf` branches or with `simplify`.
+    pub spans: Vec<Range<usize>>,
+}
+
+/// The regex of a schema with the fragments it's made of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    /// The regex [`build_regex_from_schema_with_options`] returns.
+    pub regex: String,
+    pub fragments: Vec<Fragment>,
+}
+
+impl Explanation {
+    /// The innermost fragment written at byte `offset` of the regex, like the
+    /// schema node a failing part of a document is checked against.
+    pub fn fragment_at(&self, offset: usize) -> Option<&Fragment> {
+        self.fragments
+            .iter()
+            .filter(|fragment| fragment.spans.iter().any(|span| span.contains(&offset)))
+            .max_by_key(|fragment| fragment.depth)
+    }
+}
+
+/// Lists the regex each schema node contributes, parents before children.
+///
+/// Only the keywords the generator reads are followed. `$ref` targets are
+/// shown as part of the referencing node rather than under `$defs`.
+pub fn explain(json: &str, whitespace_pattern: Option<&str>) -> Result<Vec<Fragment>> {
+    let options = RegexOptions {
+        whitespace_pattern: whitespace_pattern.map(String::from),
+        ..RegexOptions::default()
+    };
+    Ok(explain_with_options(json, &options)?.fragments)
+}
+
+/// The regex of `json` with `options`, and the fragments of [`explain`] along
+/// with where they are in it.
+pub fn explain_with_options(json: &str, options: &RegexOptions) -> Result<Explanation> {
+    let schema: Value = serde_json::from_str(json)?;
+    let whitespace_pattern = resolve_whitespace_pattern(options.whitespace_pattern.as_deref())?;
+    options.whitespace_positions.validate()?;
+    let generator = configured_generator(&whitespace_pattern, &schema, options);
+    let mut fragments = Vec::new();
+    walk(
+        &schema,
+        String::new(),
+        0,
+        &generator,
+        options,
+        &mut fragments,
+    )?;
+
+    let regex = build_regex_from_schema_with_options(json, options)?;
+    locate(&regex, &mut fragments);
+    Ok(Explanation { regex, fragments })
+}
+
+/// Sets the spans of `fragments`, each found in the spans of its parent.
+fn locate(regex: &str, fragments: &mut [Fragment]) {
+    let mut parents: Vec<(usize, Vec<Range<usize>>)> = Vec::new();
+    for fragment in fragments {
+        while parents
+            .last()
+            .is_some_and(|(depth, _)| *depth >= fragment.depth)
+        {
+            parents.pop();
+        }
+        let within = match parents.last() {
+            Some((_, spans)) => spans.clone(),
+            None => std::iter::once(0..regex.len()).collect(),
+        };
+        if !fragment.regex.is_empty() {
+            for span in within {
+                let found = regex[span.clone()].match_indices(&fragment.regex);
+                fragment.spans.extend(
+                    found.map(|(start, text)| span.start + start..span.start + start + text.len()),
+                );
+            }
+        }
+        parents.push((fragment.depth, fragment.spans.clone()));
+    }
+}
+
+fn walk(
+    node: &Value,
+    pointer: String,
+    depth: usize,
+    generator: &Generator,
+    options: &RegexOptions,
+    fragments: &mut Vec<Fragment>,
+) -> Result<()> {
+    let regex = generator.to_regex(node)?;
+    fragments.push(Fragment {
+        pointer: pointer.clone(),
+        depth,
+        regex: if options.capture_groups {
+            regex
+        } else {
+            non_capturing(&regex)
+        },
+        spans: Vec::new(),
+    });
+
+    let Some(obj) = node.as_object() else {
+        return Ok(());
+    };
+    let mut children = Vec::new();
+    if let Some(properties) = obj.get("properties").and_then(Value::as_object) {
+        for (name, value) in properties {
+            children.push((format!("properties/{}", escape_token(name)), value));
+        }
+    }
+    for keyword in ["allOf", "anyOf", "oneOf", "prefixItems"] {
+        if let Some(subschemas) = obj.get(keyword).and_then(Value::as_array) {
+            for (i, value) in subschemas.iter().enumerate() {
+                children.push((format!("{}/{}", keyword, i), value));
+            }
+        }
+    }
+    for keyword in ["items", "additionalProperties"] {
+        if let Some(value) = obj.get(keyword).filter(|value| value.is_object()) {
+            children.push((keyword.to_string(), value));
+        }
+    }
+
+    for (path, child) in children {
+        walk(
+            child,
+            format!("{}/{}", pointer, path),
+            depth + 1,
+            generator,
+            options,
+            fragments,
+        )?;
+    }
+    Ok(())
+}
+
+fn escape_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        let schema = r#"{
+            "type": "object",
+            "properties": {
+                "a/b": {"type": "boolean"},
+                "list": {"type": "array", "items": {"anyOf": [{"type": "null"}, {"const": 1}]}}
+            },
+            "required": ["a/b", "list"]
+        }"#;
+        let fragments = explain(schema, Some("")).unwrap();
+
+        let pointers: Vec<_> = fragments
+            .iter()
+            .map(|f| (f.pointer.as_str(), f.depth))
+            .collect();
+        assert_eq!(
+            pointers,
+            [
+                ("", 0),
+                ("/properties/a~1b", 1),
+                ("/properties/list", 1),
+                ("/properties/list/items", 2),
+                ("/properties/list/items/anyOf/0", 3),
+                ("/properties/list/items/anyOf/1", 3),
+            ]
+        );
+        assert_eq!(fragments[1].regex, "(?:true|false)");
+        assert_eq!(fragments[5].regex, "1");
+        assert!(fragments[0].regex.contains(&fragments[3].regex));
+        assert_eq!(
+            fragments[0].spans,
+            vec![Range {
+                start: 0,
+                end: fragments[0].regex.len()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_explain_with_options() {
+        let schema = r#"{
+            "type": "object",
+            "properties": {
+                "id": {"type": "integer"},
+                "tags": {"type": "array", "items": {"enum": ["x", "y"]}}
+            },
+            "required": ["id", "tags"]
+        }"#;
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let explanation = explain_with_options(schema, &options).unwrap();
+        assert!(explanation.regex.starts_with("^(?:"));
+
+        for fragment in &explanation.fragments {
+            for span in &fragment.spans {
+                assert_eq!(explanation.regex[span.clone()], fragment.regex);
+            }
+        }
+        let items = &explanation.fragments[3];
+        assert_eq!(items.pointer, "/properties/tags/items");
+        // the first item and the ones after a comma
+        assert_eq!(items.spans.len(), 2);
+
+        let id = &explanation.fragments[1];
+        let inside_id = id.spans[0].start + 1;
+        assert_eq!(explanation.fragment_at(inside_id), Some(id));
+        assert_eq!(explanation.fragment_at(0), None);
+    }
+}
@@ -0,0 +1,122 @@
+//! The text of each value of a matched document, by its JSON Pointer, read
+//! from the named groups of the regex instead of parsing the JSON.
+//!
+//! The groups follow the document whatever its whitespace and the order of
+//! optional parts, where offsets computed from the schema wouldn't.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+
+use crate::guidance::{build_regex_with_group_pointers, RegexOptions};
+
+/// The regex of a schema with named groups around the values of properties,
+/// and where each group's value is in the documents.
+#[derive(Debug, Clone)]
+pub struct FieldExtractor {
+    regex: Regex,
+    /// JSON Pointer of the value of each named group, by name.
+    pointers: HashMap<String, String>,
+}
+
+impl FieldExtractor {
+    /// Builds the regex of `json` with `options`, with named groups and
+    /// anchored whatever `options` say.
+    ///
+    /// Only the values with a named group are extracted: properties of
+    /// objects and of their `allOf`, `anyOf` and `oneOf` branches, and not
+    /// those of array items or `$ref` targets, see
+    /// [`RegexOptions::named_groups`].
+    pub fn new(json: &str, options: &RegexOptions) -> Result<Self> {
+        let options = RegexOptions {
+            named_groups: true,
+            anchored: true,
+            ..options.clone()
+        };
+        let (regex, pointers) = build_regex_with_group_pointers(json, &options)?;
+        let regex = Regex::new(&regex).map_err(|e| anyhow!("Failed to build regex: {}", e))?;
+        Ok(Self { regex, pointers })
+    }
+
+    /// The regex the documents are matched with.
+    pub fn regex(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// The text of each value of `matched_text` by its JSON Pointer, like
+    /// `"/address/city"`, or `None` when it isn't a document of the schema.
+    /// Values of optional properties that aren't there are left out.
+    pub fn extract_fields<'t>(&self, matched_text: &'t str) -> Option<HashMap<String, &'t str>> {
+        let captures = self.regex.captures(matched_text)?;
+        Some(
+            self.pointers
+                .iter()
+                .filter_map(|(name, pointer)| {
+                    Some((pointer.clone(), captures.name(name)?.as_str()))
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_extract_fields() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "age": {"type": "integer"},
+                "address": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"]
+                },
+                "tags": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {"id": {"type": "integer"}},
+                        "required": ["id"]
+                    }
+                }
+            },
+            "required": ["name", "address"]
+        })
+        .to_string();
+        let options = RegexOptions {
+            sort_properties: true,
+            ..RegexOptions::default()
+        };
+        let extractor = FieldExtractor::new(&schema, &options).unwrap();
+
+        let fields = extractor
+            .extract_fields(r#"{ "address": {"city" : "Paris"},"age":41, "name":"Ann" }"#)
+            .unwrap();
+        assert_eq!(
+            fields,
+            HashMap::from([
+                ("/address".to_string(), r#"{"city" : "Paris"}"#),
+                ("/address/city".to_string(), r#""Paris""#),
+                ("/age".to_string(), "41"),
+                ("/name".to_string(), r#""Ann""#),
+            ])
+        );
+
+        // optional values that aren't there, and values in arrays, are left out
+        let fields = extractor
+            .extract_fields(r#"{"address":{"city":"Oslo"},"name":"Bo","tags":[{"id":1}]}"#)
+            .unwrap();
+        assert_eq!(fields.get("/tags"), Some(&r#"[{"id":1}]"#));
+        assert_eq!(fields.get("/age"), None);
+        assert_eq!(fields.len(), 4);
+
+        assert_eq!(extractor.extract_fields(r#"{"name":"Ann"}"#), None);
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use anyhow::{anyhow, Result};
+use regex_syntax::ast::{self, Ast, GroupKind};
+use regex_syntax::hir::translate::Translator;
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+
+use crate::shared::shared_subexpressions;
+
+/// Subexpressions shorter than this in GBNF are written out wherever they
+/// appear, longer ones appearing several times are a rule of their own.
+const MIN_RULE_LEN: usize = 24;
+
+/// Converts a regex into an equivalent GBNF grammar, as used by llama.cpp.
+///
+/// The grammar starts with the `root` rule, followed by a rule for each
+/// long subexpression the regex repeats, like the regex of a `$ref` target
+/// used in several places, named `r1`, `r2`, and so on. Start and end
+/// anchors are dropped since grammars always match the whole output.
+pub fn regex_to_gbnf(regex: &str) -> Result<String> {
+    let hir = grouped_hir(regex)?;
+
+    let mut key = |hir: &Hir| {
+        let text = Printer::default().inline(hir)?;
+        Ok((text.len() >= MIN_RULE_LEN).then_some(text))
+    };
+    let mut printer = Printer {
+        shared: shared_subexpressions(&hir, &mut key)?,
+        ..Printer::default()
+    };
+    let mut out = String::from("root ::= ");
+    printer.expression(&hir, &mut out)?;
+    out.push('\n');
+    for (name, body) in printer.rules {
+        writeln!(out, "{} ::= {}", name, body)?;
+    }
+    Ok(out)
+}
+
+/// The IR of `regex` with its `(?:...)` groups kept, as groups without a
+/// name, where the IR would dissolve them into their surroundings. The
+/// subexpressions of the generator, like the regex of a subschema, are then
+/// there to be shared.
+fn grouped_hir(regex: &str) -> Result<Hir> {
+    let mut ast = ast::parse::Parser::new()
+        .parse(regex)
+        .map_err(|e| anyhow!("Failed to parse regex: {}", e))?;
+    keep_groups(&mut ast, &mut 0);
+    Translator::new()
+        .translate(regex, &ast)
+        .map_err(|e| anyhow!("Failed to parse regex: {}", e))
+}
+
+fn keep_groups(ast: &mut Ast, groups: &mut u32) {
+    match ast {
+        Ast::Group(group) => {
+            let plain =
+                matches!(&group.kind, GroupKind::NonCapturing(flags) if flags.items.is_empty());
+            if plain || !matches!(group.kind, GroupKind::NonCapturing(_)) {
+                *groups += 1;
+                match &mut group.kind {
+                    GroupKind::CaptureName { name, .. } => name.index = *groups,
+                    kind => *kind = GroupKind::CaptureIndex(*groups),
+                }
+            }
+            keep_groups(&mut group.ast, groups);
+        }
+        Ast::Repetition(repetition) => keep_groups(&mut repetition.ast, groups),
+        Ast::Alternation(alternation) => {
+            for ast in &mut alternation.asts {
+                keep_groups(ast, groups);
+            }
+        }
+        Ast::Concat(concat) => {
+            for ast in &mut concat.asts {
+                keep_groups(ast, groups);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Default)]
+struct Printer {
+    /// The GBNF of the subexpressions written as rules.
+    shared: HashSet<String>,
+    /// The name of each rule written so far, by its GBNF.
+    names: HashMap<String, String>,
+    /// The rules written so far, by name, after those they refer to.
+    rules: Vec<(String, String)>,
+}
+
+impl Printer {
+    /// The GBNF of `hir` without rules.
+    fn inline(&mut self, hir: &Hir) -> Result<String> {
+        let mut out = String::new();
+        self.write(hir, &mut out)?;
+        Ok(out)
+    }
+
+    /// Writes `hir`, or the name of its rule when it's shared.
+    fn expression(&mut self, hir: &Hir, out: &mut String) -> Result<()> {
+        if self.shared.is_empty() {
+            return self.write(hir, out);
+        }
+        let text = Printer::default().inline(hir)?;
+        if !self.shared.contains(&text) {
+            return self.write(hir, out);
+        }
+        if let Some(name) = self.names.get(&text) {
+            out.push_str(name);
+            return Ok(());
+        }
+        let name = format!("r{}", self.names.len() + 1);
+        self.names.insert(text, name.clone());
+        let body = self.inline(ungrouped(hir))?;
+        self.rules.push((name.clone(), body));
+        out.push_str(&name);
+        Ok(())
+    }
+
+    /// Writes `hir` within parentheses unless it's a single item.
+    fn item(&mut self, hir: &Hir, out: &mut String) -> Result<()> {
+        let single = matches!(hir.kind(), HirKind::Class(_) | HirKind::Capture(_))
+            || (!self.shared.is_empty() && self.shared.contains(&Printer::default().inline(hir)?));
+        if !single {
+            out.push('(');
+        }
+        self.expression(hir, out)?;
+        if !single {
+            out.push(')');
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, hir: &Hir, out: &mut String) -> Result<()> {
+        match hir.kind() {
+            HirKind::Empty => out.push_str("\"\""),
+            HirKind::Literal(literal) => {
+                let text = std::str::from_utf8(&literal.0)
+                    .map_err(|_| anyhow!("Byte literals can't be written in GBNF"))?;
+                out.push('"');
+                for c in text.chars() {
+                    push_char(c, false, out);
+                }
+                out.push('"');
+            }
+            HirKind::Class(class) => {
+                let ranges: Vec<(char, char)> = match class {
+                    Class::Unicode(class) => class.iter().map(|r| (r.start(), r.end())).collect(),
+                    Class::Bytes(class) if class.is_ascii() => class
+                        .iter()
+                        .map(|r| (char::from(r.start()), char::from(r.end())))
+                        .collect(),
+                    Class::Bytes(_) => {
+                        return Err(anyhow!("Byte classes can't be written in GBNF"))
+                    }
+                };
+                out.push('[');
+                for (start, end) in ranges {
+                    push_char(start, true, out);
+                    if start != end {
+                        out.push('-');
+                        push_char(end, true, out);
+                    }
+                }
+                out.push(']');
+            }
+            HirKind::Look(Look::Start | Look::End) => out.push_str("\"\""),
+            HirKind::Look(look) => return Err(anyhow!("{:?} can't be written in GBNF", look)),
+            HirKind::Repetition(repetition) => {
+                self.item(&repetition.sub, out)?;
+                match (repetition.min, repetition.max) {
+                    (0, None) => out.push('*'),
+                    (1, None) => out.push('+'),
+                    (0, Some(1)) => out.push('?'),
+                    (min, None) => write!(out, "{{{},}}", min)?,
+                    (min, Some(max)) if min == max => write!(out, "{{{}}}", min)?,
+                    (min, Some(max)) => write!(out, "{{{},{}}}", min, max)?,
+                }
+            }
+            // grammars have no groups, only parentheses where needed
+            HirKind::Capture(capture) => {
+                let sub = ungrouped(&capture.sub);
+                let single = matches!(sub.kind(), HirKind::Literal(_) | HirKind::Class(_));
+                if !single {
+                    out.push('(');
+                }
+                self.expression(sub, out)?;
+                if !single {
+                    out.push(')');
+                }
+            }
+            HirKind::Concat(subs) => {
+                let subs = subs
+                    .iter()
+                    .filter(|sub| !matches!(sub.kind(), HirKind::Look(Look::Start | Look::End)));
+                for (i, sub) in subs.enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    if matches!(sub.kind(), HirKind::Alternation(_)) {
+                        self.item(sub, out)?;
+                    } else {
+                        self.expression(sub, out)?;
+                    }
+                }
+            }
+            HirKind::Alternation(subs) => {
+                for (i, sub) in subs.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(" | ");
+                    }
+                    self.expression(ungrouped(sub), out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `hir` without the groups around it.
+fn ungrouped(hir: &Hir) -> &Hir {
+    match hir.kind() {
+        HirKind::Capture(capture) => ungrouped(&capture.sub),
+        _ => hir,
+    }
+}
+
+fn push_char(c: char, in_class: bool, out: &mut String) {
+    match c {
+        '"' if !in_class => out.push_str("\\\""),
+        '\\' => out.push_str("\\\\"),
+        ']' | '-' | '^' if in_class => {
+            out.push('\\');
+            out.push(c);
+        }
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        _ if c.is_ascii_graphic() || c == ' ' => out.push(c),
+        _ if (c as u32) <= 0xFF => {
+            let _ = write!(out, "\\x{:02X}", c as u32);
+        }
+        _ if (c as u32) <= 0xFFFF => {
+            let _ = write!(out, "\\u{:04X}", c as u32);
+        }
+        _ => {
+            let _ = write!(out, "\\U{:08X}", c as u32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regex_to_gbnf() {
+        assert_eq!(
+            regex_to_gbnf(r#"\{"a":(true|false)\}"#).unwrap(),
+            "root ::= \"{\\\"a\\\":\" (\"true\" | \"false\") \"}\"\n"
+        );
+        assert_eq!(
+            regex_to_gbnf(r#"^"[^"\\\x00-\x1F]{2,}"$"#).unwrap(),
+            "root ::= \"\\\"\" [ -!#-[\\]-\\U0010FFFF]{2,} \"\\\"\"\n"
+        );
+    }
+
+    #[test]
+    fn test_shared_rules() {
+        let string = r#""(?:[^"\\\x00-\x1F]|\\["\\])*""#;
+        let regex = format!(r#"\{{"a":{0},"b":{0}\}}"#, string);
+        assert_eq!(
+            regex_to_gbnf(&regex).unwrap(),
+            concat!(
+                "root ::= \"{\\\"a\\\":\\\"\" r1 \"\\\",\\\"b\\\":\\\"\" r1 \"\\\"}\"\n",
+                "r1 ::= ([ -!#-[\\]-\\U0010FFFF] | \"\\\\\" [\"\\\\])*\n",
+            )
+        );
+
+        // short ones are written out
+        assert_eq!(
+            regex_to_gbnf("(?:true|false),(?:true|false)").unwrap(),
+            "root ::= (\"true\" | \"false\") \",\" (\"true\" | \"false\")\n"
+        );
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert!(regex_to_gbnf(r"\bword").is_err());
+        assert!(regex_to_gbnf("(").is_err());
+    }
+}
@@ -0,0 +1,349 @@
+//! gRPC service mirroring the HTTP endpoints of [`crate::server`], defined in
+//! `proto/guidance.proto`. It shares the same caches and limits, and runs the
+//! generator on as many of tokio's blocking threads as the server has workers.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use prost::Message;
+use tonic::{Request, Response, Status};
+
+use crate::automaton::{self, Validation};
+use crate::compat::Vocabulary;
+use crate::gbnf;
+use crate::server::Server;
+
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/guidance.Guidance.rs"));
+}
+
+pub use generated::guidance_server::{Guidance, GuidanceServer};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CompileRequest {
+    #[prost(string, tag = "1")]
+    pub schema: String,
+    #[prost(string, optional, tag = "2")]
+    pub whitespace_pattern: Option<String>,
+    #[prost(bool, tag = "3")]
+    pub gbnf: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CompileResponse {
+    #[prost(string, tag = "1")]
+    pub regex: String,
+    #[prost(string, optional, tag = "2")]
+    pub gbnf: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ValidateRequest {
+    #[prost(string, tag = "1")]
+    pub schema: String,
+    #[prost(string, optional, tag = "2")]
+    pub whitespace_pattern: Option<String>,
+    #[prost(string, tag = "3")]
+    pub document: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ValidateResponse {
+    #[prost(enumeration = "validate_response::Result", tag = "1")]
+    pub result: i32,
+    #[prost(uint64, tag = "2")]
+    pub offset: u64,
+}
+
+pub mod validate_response {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Result {
+        Match = 0,
+        Mismatch = 1,
+        Incomplete = 2,
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Token {
+    #[prost(bytes = "vec", tag = "1")]
+    pub token: Vec<u8>,
+    #[prost(uint32, repeated, tag = "2")]
+    pub ids: Vec<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BuildIndexRequest {
+    #[prost(string, tag = "1")]
+    pub schema: String,
+    #[prost(string, optional, tag = "2")]
+    pub whitespace_pattern: Option<String>,
+    #[prost(message, repeated, tag = "3")]
+    pub vocabulary: Vec<Token>,
+    #[prost(uint32, tag = "4")]
+    pub eos_token_id: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Transition {
+    #[prost(uint32, tag = "1")]
+    pub state: u32,
+    #[prost(uint32, tag = "2")]
+    pub token_id: u32,
+    #[prost(uint32, tag = "3")]
+    pub next_state: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BuildIndexResponse {
+    #[prost(uint32, tag = "1")]
+    pub initial_state: u32,
+    #[prost(uint32, repeated, tag = "2")]
+    pub final_states: Vec<u32>,
+    #[prost(message, repeated, tag = "3")]
+    pub transitions: Vec<Transition>,
+}
+
+pub struct GuidanceService {
+    server: Arc<Server>,
+}
+
+impl GuidanceService {
+    /// Creates a service caching up to `cache_capacity` regexes and as many indexes.
+    pub fn new(cache_capacity: usize) -> Self {
+        Self::with_server(Server::new(cache_capacity))
+    }
+
+    /// Creates a service answering like `server`, with its caches and limits.
+    pub fn with_server(server: Server) -> Self {
+        Self {
+            server: Arc::new(server),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Guidance for GuidanceService {
+    async fn compile(
+        &self,
+        request: Request<CompileRequest>,
+    ) -> Result<Response<CompileResponse>, Status> {
+        let server = Arc::clone(&self.server);
+        blocking(move || {
+            let request = request.into_inner();
+            let regex = server.compiled_regex(request.schema, request.whitespace_pattern)?;
+            let gbnf = request
+                .gbnf
+                .then(|| gbnf::regex_to_gbnf(&regex))
+                .transpose()?;
+            Ok(CompileResponse {
+                regex: regex.to_string(),
+                gbnf,
+            })
+        })
+        .await
+    }
+
+    async fn validate(
+        &self,
+        request: Request<ValidateRequest>,
+    ) -> Result<Response<ValidateResponse>, Status> {
+        let server = Arc::clone(&self.server);
+        blocking(move || {
+            let request = request.into_inner();
+            let regex = server.compiled_regex(request.schema, request.whitespace_pattern)?;
+            let (result, offset) = match automaton::validate(&regex, request.document.as_bytes())? {
+                Validation::Match => (validate_response::Result::Match, 0),
+                Validation::Mismatch(mismatch) => {
+                    (validate_response::Result::Mismatch, mismatch.offset)
+                }
+                Validation::Incomplete => (validate_response::Result::Incomplete, 0),
+            };
+            Ok(ValidateResponse {
+                result: result.into(),
+                offset: offset as u64,
+            })
+        })
+        .await
+    }
+
+    async fn build_index(
+        &self,
+        request: Request<BuildIndexRequest>,
+    ) -> Result<Response<BuildIndexResponse>, Status> {
+        let server = Arc::clone(&self.server);
+        blocking(move || {
+            let request = request.into_inner();
+            let mut vocabulary = Vocabulary::new(request.eos_token_id);
+            for token in &request.vocabulary {
+                for &id in &token.ids {
+                    vocabulary.try_insert(token.token.clone(), id)?;
+                }
+            }
+            let key = server.index_key(
+                &request.schema,
+                request.whitespace_pattern.as_deref(),
+                &vocabulary,
+            )?;
+            let index = server.cached_index(key, || {
+                let regex = server
+                    .compiled_regex(request.schema.clone(), request.whitespace_pattern.clone())?;
+                server.build_index(&regex, &vocabulary)
+            })?;
+
+            let mut final_states: Vec<_> = index.final_states().iter().copied().collect();
+            final_states.sort();
+            let mut transitions: Vec<_> = index
+                .transitions()
+                .iter()
+                .flat_map(|(&state, next)| {
+                    next.iter().map(move |(&token_id, &next_state)| Transition {
+                        state,
+                        token_id,
+                        next_state,
+                    })
+                })
+                .collect();
+            transitions.sort_by_key(|t| (t.state, t.token_id));
+
+            Ok(BuildIndexResponse {
+                initial_state: index.initial_state(),
+                final_states,
+                transitions,
+            })
+        })
+        .await
+    }
+}
+
+/// Runs CPU-bound work off the async threads, reporting errors as invalid arguments.
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> Result<Response<T>, Status> {
+    match tokio::task::spawn_blocking(work).await {
+        Ok(Ok(response)) => Ok(Response::new(response)),
+        Ok(Err(e)) => Err(Status::invalid_argument(format!("{:#}", e))),
+        Err(e) => Err(Status::internal(e.to_string())),
+    }
+}
+
+/// Serves the gRPC service of `server` until the process is stopped.
+pub fn serve(addr: SocketAddr, server: Server) -> anyhow::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .max_blocking_threads(server.workers())
+        .build()?;
+    runtime.block_on(
+        tonic::transport::Server::builder()
+            .add_service(GuidanceServer::new(GuidanceService::with_server(server)))
+            .serve(addr),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let service = GuidanceService::new(4);
+        let schema = r#"{"type": "boolean"}"#.to_string();
+
+        let response = runtime
+            .block_on(service.compile(Request::new(CompileRequest {
+                schema: schema.clone(),
+                whitespace_pattern: None,
+                gbnf: true,
+            })))
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.regex, "(?:true|false)");
+        assert!(response.gbnf.is_some());
+
+        let response = runtime
+            .block_on(service.validate(Request::new(ValidateRequest {
+                schema: schema.clone(),
+                whitespace_pattern: None,
+                document: "trap".to_string(),
+            })))
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            (response.result(), response.offset),
+            (validate_response::Result::Mismatch, 2)
+        );
+
+        let vocabulary = vec![
+            Token {
+                token: b"true".to_vec(),
+                ids: vec![1],
+            },
+            Token {
+                token: b"false".to_vec(),
+                ids: vec![2],
+            },
+        ];
+        let response = runtime
+            .block_on(service.build_index(Request::new(BuildIndexRequest {
+                schema,
+                whitespace_pattern: None,
+                vocabulary,
+                eos_token_id: 0,
+            })))
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.final_states.len(), 1);
+        // two tokens from the start state, and EOS looping on the final state
+        assert_eq!(response.transitions.len(), 3);
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let service = GuidanceService::new(4);
+        let status = runtime
+            .block_on(service.compile(Request::new(CompileRequest {
+                schema: "{".to_string(),
+                whitespace_pattern: None,
+                gbnf: false,
+            })))
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_recursive_schema() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let service = GuidanceService::with_server(Server::new(4).with_limits(None, Some(1000)));
+        let schema = r##"{
+            "$defs": {"n": {"type": "object", "properties": {"next": {"$ref": "#/$defs/n"}}}},
+            "$ref": "#/$defs/n"
+        }"##;
+        let status = runtime
+            .block_on(service.validate(Request::new(ValidateRequest {
+                schema: schema.to_string(),
+                whitespace_pattern: None,
+                document: "{}".to_string(),
+            })))
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(
+            status.message().contains("recursive"),
+            "{}",
+            status.message()
+        );
+
+        let response = runtime
+            .block_on(service.compile(Request::new(CompileRequest {
+                schema: r#"{"type": "null"}"#.to_string(),
+                whitespace_pattern: None,
+                gbnf: false,
+            })))
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.regex, "null");
+    }
+}
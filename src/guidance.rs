@@ -1,10 +1,25 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
 use anyhow::{anyhow, Result};
-use jsonschema::JSONSchema;
 use regex::escape;
+use regex_syntax::hir::{Hir, HirKind};
 use serde_json::json;
 use serde_json::Value;
 
+use crate::automaton;
+use crate::draft::{self, Draft};
+use crate::error::{brief, SchemaError};
+use crate::exclusive::{self, Exclusivity, Intersection};
 use crate::handle_types;
+use crate::keywords;
+use crate::merge;
+use crate::pushdown::{self, Rule};
+use crate::range;
+use crate::simplify;
 use crate::types;
 
 #[derive(Debug, Copy, Clone)]
@@ -14,6 +29,8 @@ enum SchemaKeyword {
     AnyOf,
     OneOf,
     PrefixItems,
+    /// An `items` array, the `prefixItems` of drafts before 2020-12.
+    TupleItems,
     Enum,
     Const,
     Ref,
@@ -21,438 +38,2369 @@ enum SchemaKeyword {
     EmptyObject,
 }
 
-pub fn build_regex_from_schema(json: &str, whitespace_pattern: Option<&str>) -> Result<String> {
-    let json_value: Value = serde_json::from_str(json)?;
-    let _compiled_schema = JSONSchema::compile(&json_value)
-        .map_err(|e| anyhow!("Failed to compile JSON schema: {}", e))?;
-
-    to_regex(&json_value, whitespace_pattern, &json_value)
+/// Settings for [`build_regex_from_schema_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct RegexOptions {
+    /// Pattern allowed between JSON tokens, `None` for the default one.
+    pub whitespace_pattern: Option<String>,
+    /// Patterns for some positions between JSON tokens instead of
+    /// `whitespace_pattern`.
+    pub whitespace_positions: WhitespacePositions,
+    /// Emit capturing groups, like Outlines does, instead of `(?:...)`.
+    pub capture_groups: bool,
+    /// Wrap the values of object properties in named groups, like
+    /// `(?P<address__city>...)` for `/address/city`, so a match gives the
+    /// values without parsing the JSON.
+    ///
+    /// Only values with a single place in the documents are named: not those
+    /// in arrays, maps or `$ref` targets, which the regex repeats. Values of
+    /// objects without required properties, which appear in several
+    /// alternatives, and names taken already, as by another `anyOf` branch,
+    /// are left unnamed with a warning.
+    pub named_groups: bool,
+    /// Shrink the regex with [`crate::simplify::simplify`].
+    pub simplify: bool,
+    /// Fail instead of generating a regex longer than this many bytes.
+    pub max_regex_len: Option<usize>,
+    /// Fail instead of generating more than this many subschemas, counting
+    /// a subschema again each time it's reached through a `$ref`.
+    pub max_nodes: Option<usize>,
+    /// Fail on `pattern`s compiling to more than roughly this many automaton
+    /// states, like `(\w{100}){100}`, so one pattern can't blow up the
+    /// automaton of a batch.
+    pub max_pattern_complexity: Option<usize>,
+    /// Write object properties sorted by name.
+    ///
+    /// Otherwise they're written in the order of `serde_json`'s maps: sorted
+    /// by name too by default, or in the order of the schema with the
+    /// `preserve_order` feature, which is what Outlines does. Sorting keeps
+    /// the regexes the same even when another crate enables that feature.
+    pub sort_properties: bool,
+    /// Write every property of objects as required, whatever `required`
+    /// says, which also keeps the regex of objects small.
+    pub treat_all_properties_required: bool,
+    /// Read objects without `additionalProperties` as if it were `false`, so
+    /// objects without `properties` are empty, as OpenAI's strict mode has it.
+    pub closed_objects: bool,
+    /// Bound integers and numbers by their OpenAPI `format`: `int32` and
+    /// `int64` to their range, `float` and `double` to the digits their
+    /// largest values take.
+    pub numeric_formats: bool,
+    /// Lenient forms of numbers to accept too, like `007`, `+1` and `.5`.
+    pub number_syntax: types::NumberSyntax,
+    /// Write the values of enums sorted by their JSON text instead of in the
+    /// order of the schema, so enums listing the same values in another
+    /// order give the same regex.
+    pub sort_enums: bool,
+    /// The most branches the alternation of an enum has, for engines
+    /// choking on huge ones. Values starting alike are factored as one
+    /// branch, like `"a(b|c)"` for `"ab"` and `"ac"`, and what's still too
+    /// wide is split into nested groups of this many branches. Must be at
+    /// least 2.
+    pub max_alternation_width: Option<usize>,
+    /// Accept the numbers of enums in any form of the same value without an
+    /// exponent, like `1.0` for `1` or `20` for `2e1`, as well as written.
+    pub equivalent_numbers: bool,
+    /// Fail on keywords the regex can't enforce, like most `uniqueItems`,
+    /// instead of ignoring them with a warning.
+    pub strict: bool,
+    /// Warn about pairs of `anyOf` and `oneOf` branches accepting values that
+    /// start with the same long input, as a streaming matcher has to read all
+    /// of it before knowing which branch it's in.
+    pub report_ambiguity: bool,
+    /// Match the values of exactly one `oneOf` branch, as validators do,
+    /// instead of those of any branch, by subtracting the overlap of the
+    /// branches from their automata. Branches that can't be subtracted, like
+    /// those with named groups or too large automata, are left overlapping
+    /// with a warning.
+    pub exclusive_one_of: bool,
+    /// Allow the whitespace pattern before and after the top-level value,
+    /// as around a whole JSON document. Any schema can be at the top level,
+    /// scalars and arrays included.
+    pub padded: bool,
+    /// Wrap the regex in `^(?:...)$`, for engines that search for a match
+    /// instead of matching the whole output.
+    pub anchored: bool,
+    /// The values allowed where the schema doesn't constrain them, like `{}`.
+    pub unconstrained: UnconstrainedValues,
+    /// Read the schema as this draft instead of the one its `$schema`
+    /// declares: the schema is validated as that draft, and tuples are an
+    /// `items` array before 2020-12 and `prefixItems` from it on. Without
+    /// either, tuples can be both.
+    pub draft: Option<Draft>,
+    /// The engine [`crate::pushdown::build_guide`] guides with. The functions
+    /// building a regex ignore it.
+    pub backend: Backend,
 }
 
-pub fn to_regex(
-    json: &Value,
-    whitespace_pattern: Option<&str>,
-    full_schema: &Value,
-) -> Result<String> {
-    let whitespace_pattern = whitespace_pattern.unwrap_or(types::WHITESPACE);
-
-    match json {
-        Value::Object(obj) => {
-            let keyword = if obj.is_empty() {
-                SchemaKeyword::EmptyObject
-            } else {
-                [
-                    ("properties", SchemaKeyword::Properties),
-                    ("allOf", SchemaKeyword::AllOf),
-                    ("anyOf", SchemaKeyword::AnyOf),
-                    ("oneOf", SchemaKeyword::OneOf),
-                    ("prefixItems", SchemaKeyword::PrefixItems),
-                    ("enum", SchemaKeyword::Enum),
-                    ("const", SchemaKeyword::Const),
-                    ("$ref", SchemaKeyword::Ref),
-                    ("type", SchemaKeyword::Type),
-                ]
-                .iter()
-                .find_map(|&(key, schema_keyword)| {
-                    if obj.contains_key(key) {
-                        Some(schema_keyword)
-                    } else {
-                        None
-                    }
-                })
-                .ok_or_else(|| anyhow!("Unsupported JSON Schema structure {} \nMake sure it is valid to the JSON Schema specification and check if it's supported by Outlines.\nIf it should be supported, please open an issue.", json))?
-            };
+impl RegexOptions {
+    /// Options producing the same regexes as Outlines, but for `[]` where
+    /// values are unconstrained, which Outlines doesn't match.
+    pub fn legacy() -> Self {
+        Self::preset(Preset::OutlinesLegacy)
+    }
 
-            match keyword {
-                SchemaKeyword::Properties => {
-                    handle_properties(obj, whitespace_pattern, full_schema)
-                }
-                SchemaKeyword::AllOf => handle_all_of(obj, whitespace_pattern, full_schema),
-                SchemaKeyword::AnyOf => handle_any_of(obj, whitespace_pattern, full_schema),
-                SchemaKeyword::OneOf => handle_one_of(obj, whitespace_pattern, full_schema),
-                SchemaKeyword::PrefixItems => {
-                    handle_prefix_items(obj, whitespace_pattern, full_schema)
-                }
-                SchemaKeyword::Enum => handle_enum(obj, whitespace_pattern),
-                SchemaKeyword::Const => handle_const(obj, whitespace_pattern),
-                SchemaKeyword::Ref => handle_ref(obj, whitespace_pattern, full_schema),
-                SchemaKeyword::Type => handle_type(obj, whitespace_pattern, full_schema),
-                SchemaKeyword::EmptyObject => handle_empty_object(whitespace_pattern, full_schema),
-            }
+    /// The options `preset` stands for, to adjust further as needed.
+    pub fn preset(preset: Preset) -> Self {
+        match preset {
+            Preset::OpenAiStrict => Self {
+                whitespace_pattern: Some(String::new()),
+                treat_all_properties_required: true,
+                closed_objects: true,
+                ..Self::default()
+            },
+            Preset::OutlinesLegacy => Self {
+                capture_groups: true,
+                ..Self::default()
+            },
+            Preset::Pretty => Self {
+                whitespace_pattern: Some(PRETTY_WHITESPACE.to_string()),
+                padded: true,
+                ..Self::default()
+            },
         }
-        _ => Err(anyhow!("Invalid JSON Schema: expected an object")),
     }
 }
 
-fn handle_properties(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    let mut regex = String::from(r"\{");
-
-    let properties = obj
-        .get("properties")
-        .and_then(Value::as_object)
-        .ok_or_else(|| anyhow!("'properties' not found or not an object"))?;
-
-    let required_properties = obj
-        .get("required")
-        .and_then(Value::as_array)
-        .map(|arr| arr.iter().filter_map(Value::as_str).collect::<Vec<_>>())
-        .unwrap_or_default();
-
-    let is_required: Vec<bool> = properties
-        .keys()
-        .map(|item| required_properties.contains(&item.as_str()))
-        .collect();
+/// Whitespace patterns replacing [`RegexOptions::whitespace_pattern`] at some
+/// positions, like `": "` without a space after commas.
+///
+/// A property that may or may not be the first of its object, as optional
+/// ones come before it, takes either `inside_brackets` or `after_comma`
+/// before its key.
+#[derive(Debug, Clone, Default)]
+pub struct WhitespacePositions {
+    /// After the `:` of object members.
+    pub after_colon: Option<String>,
+    /// After the `,` between members and items.
+    pub after_comma: Option<String>,
+    /// After `{` and `[`, and before `}` and `]`.
+    pub inside_brackets: Option<String>,
+}
 
-    if is_required.iter().any(|&x| x) {
-        let last_required_pos = is_required
-            .iter()
-            .enumerate()
-            .filter(|&(_, &value)| value)
-            .map(|(i, _)| i)
-            .max()
-            .unwrap();
+impl WhitespacePositions {
+    /// Checks each pattern set, like [`validate_whitespace_pattern`].
+    pub fn validate(&self) -> Result<()> {
+        [&self.after_colon, &self.after_comma, &self.inside_brackets]
+            .into_iter()
+            .flatten()
+            .try_for_each(|pattern| validate_whitespace_pattern(pattern))
+    }
+}
 
-        for (i, (name, value)) in properties.iter().enumerate() {
-            let mut subregex = format!(
-                r#"{whitespace_pattern}"{}"{}:{}"#,
-                escape(name),
-                whitespace_pattern,
-                whitespace_pattern
-            );
-            subregex += &to_regex(value, Some(whitespace_pattern), full_schema)?;
+/// Whitespace of indented JSON: a space, or a line break and the indentation
+/// of the next line.
+const PRETTY_WHITESPACE: &str = r"[ ]?|\n[ ]{0,32}";
 
-            if i < last_required_pos {
-                subregex = format!("{}{},", subregex, whitespace_pattern);
-            } else if i > last_required_pos {
-                subregex = format!("{},{}", whitespace_pattern, subregex);
-            }
+/// The values allowed where the schema doesn't constrain them: `{}`, the
+/// values of objects without `additionalProperties` and the items of arrays
+/// without `items`.
+///
+/// Every value is allowed by default, with objects and arrays nested two
+/// levels deep, which is a large regex and a larger automaton.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnconstrainedValues {
+    pub strings: bool,
+    /// Numbers, integers included.
+    pub numbers: bool,
+    pub booleans: bool,
+    pub nulls: bool,
+    pub objects: bool,
+    pub arrays: bool,
+    /// Levels of objects and arrays below an unconstrained one, for those
+    /// without the `depth` keyword.
+    pub depth: u64,
+}
 
-            regex += &if is_required[i] {
-                subregex
-            } else {
-                format!("({})?", subregex)
-            };
+impl Default for UnconstrainedValues {
+    fn default() -> Self {
+        Self {
+            strings: true,
+            numbers: true,
+            booleans: true,
+            nulls: true,
+            objects: true,
+            arrays: true,
+            depth: 2,
         }
-    } else {
-        let mut property_subregexes = Vec::new();
-        for (name, value) in properties.iter().rev() {
-            let mut subregex = format!(
-                r#"{whitespace_pattern}"{}"{}:{}"#,
-                escape(name),
-                whitespace_pattern,
-                whitespace_pattern
-            );
+    }
+}
 
-            subregex += &to_regex(value, Some(whitespace_pattern), full_schema)?;
-            property_subregexes.push(subregex);
+impl UnconstrainedValues {
+    /// Strings, numbers, booleans and `null`, without objects and arrays.
+    pub fn scalars() -> Self {
+        Self {
+            objects: false,
+            arrays: false,
+            ..Self::default()
         }
+    }
 
-        let mut possible_patterns = Vec::new();
-        for i in 0..property_subregexes.len() {
-            let mut pattern = String::new();
-            for subregex in &property_subregexes[..i] {
-                pattern += &format!("({}{},)?", subregex, whitespace_pattern);
-            }
-            pattern += &property_subregexes[i];
-            for subregex in &property_subregexes[i + 1..] {
-                pattern += &format!("({},{})?", whitespace_pattern, subregex);
-            }
-            possible_patterns.push(pattern);
+    /// Whether values of `instance_type` are allowed.
+    pub fn allows(&self, instance_type: &str) -> bool {
+        match instance_type {
+            "string" => self.strings,
+            "number" | "integer" => self.numbers,
+            "boolean" => self.booleans,
+            "null" => self.nulls,
+            "object" => self.objects,
+            "array" => self.arrays,
+            _ => false,
         }
-
-        regex += &format!("({})?", possible_patterns.join("|"));
     }
+}
+
+/// The engine guiding generation, see [`RegexOptions::backend`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// The DFA of the regex of the schema, which fails on recursive `$ref`s
+    /// and nests unconstrained values [`UnconstrainedValues::depth`] levels.
+    #[default]
+    Regex,
+    /// A [`crate::pushdown::Grammar`], matching recursive `$ref`s and
+    /// unconstrained values at any depth, at the cost of walking a stack of
+    /// automata at each step instead of looking up an index.
+    Pushdown,
+}
+
+/// Options the output of common providers and libraries takes, see
+/// [`RegexOptions::preset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Like the strict structured outputs of OpenAI: every property
+    /// required, no properties besides the listed ones and no whitespace.
+    OpenAiStrict,
+    /// The regexes of Outlines, with their capture groups.
+    OutlinesLegacy,
+    /// Indented JSON, with line breaks between tokens and around the value.
+    Pretty,
+}
 
-    regex += &format!("{}\\}}", whitespace_pattern);
+pub fn build_regex_from_schema(json: &str, whitespace_pattern: Option<&str>) -> Result<String> {
+    build_regex_from_schema_with_options(
+        json,
+        &RegexOptions {
+            whitespace_pattern: whitespace_pattern.map(String::from),
+            ..RegexOptions::default()
+        },
+    )
+}
 
+pub fn build_regex_from_schema_with_options(json: &str, options: &RegexOptions) -> Result<String> {
+    let mut regex = String::new();
+    write_regex_from_schema_to(&mut regex, json, options)?;
     Ok(regex)
 }
 
-fn handle_all_of(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    match obj.get("allOf") {
-        Some(Value::Array(all_of)) => {
-            let subregexes: Result<Vec<String>> = all_of
-                .iter()
-                .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
-                .collect();
+/// Like [`build_regex_from_schema_with_options`], along with the parts of
+/// the schema the regex doesn't enforce. Those are errors instead with
+/// [`RegexOptions::strict`].
+pub fn build_regex_from_schema_with_warnings(
+    json: &str,
+    options: &RegexOptions,
+) -> Result<(String, Vec<SchemaError>)> {
+    let mut regex = String::new();
+    let generated = generate(&mut regex, json, options)?;
+    Ok((regex, generated.warnings))
+}
 
-            let subregexes = subregexes?;
-            let combined_regex = subregexes.join("");
+/// Like [`build_regex_from_schema_with_options`], but writes the regex into
+/// `out` as it is generated. On error, `out` holds part of a regex.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(schema_len = json.len()))
+)]
+pub fn write_regex_from_schema_to<W: Write>(
+    out: &mut W,
+    json: &str,
+    options: &RegexOptions,
+) -> Result<()> {
+    generate(out, json, options)?;
+    Ok(())
+}
 
-            Ok(format!(r"({})", combined_regex))
-        }
-        _ => Err(anyhow!("'allOf' must be an array")),
-    }
+/// The generator of `schema` with `options`, which leaves the whitespace
+/// pattern and what's around the top-level value to the caller.
+pub(crate) fn configured_generator<'a>(
+    whitespace_pattern: &'a str,
+    schema: &'a Value,
+    options: &'a RegexOptions,
+) -> Generator<'a> {
+    Generator::new(whitespace_pattern, schema)
+        .with_whitespace_positions(&options.whitespace_positions)
+        .with_limits(options.max_regex_len, options.max_nodes)
+        .with_max_pattern_complexity(options.max_pattern_complexity)
+        .with_named_groups(options.named_groups)
+        .with_sorted_properties(options.sort_properties)
+        .with_all_properties_required(options.treat_all_properties_required)
+        .with_closed_objects(options.closed_objects)
+        .with_numeric_formats(options.numeric_formats)
+        .with_number_syntax(options.number_syntax)
+        .with_sorted_enums(options.sort_enums)
+        .with_max_alternation_width(options.max_alternation_width)
+        .with_equivalent_numbers(options.equivalent_numbers)
+        .with_strict(options.strict)
+        .with_ambiguity_report(options.report_ambiguity)
+        .with_exclusive_one_of(options.exclusive_one_of)
+        .with_unconstrained_values(options.unconstrained)
+        .with_draft(options.draft)
 }
 
-fn handle_any_of(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    match obj.get("anyOf") {
-        Some(Value::Array(any_of)) => {
-            let subregexes: Result<Vec<String>> = any_of
-                .iter()
-                .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
-                .collect();
+/// Like [`build_regex_from_schema_with_options`], along with the JSON
+/// Pointer in the documents of the value each named group holds, by name.
+pub(crate) fn build_regex_with_group_pointers(
+    json: &str,
+    options: &RegexOptions,
+) -> Result<(String, HashMap<String, String>)> {
+    let mut regex = String::new();
+    let generated = generate(&mut regex, json, options)?;
+    Ok((regex, generated.group_pointers))
+}
 
-            let subregexes = subregexes?;
+/// What generating a regex finds out besides the regex.
+struct Generated {
+    warnings: Vec<SchemaError>,
+    group_pointers: HashMap<String, String>,
+}
+
+/// Writes the regex of `json` into `out`.
+fn generate<W: Write>(out: &mut W, json: &str, options: &RegexOptions) -> Result<Generated> {
+    let json_value = parse_with_options(json, options)?;
+    let whitespace_pattern = resolve_whitespace_pattern(options.whitespace_pattern.as_deref())?;
+    let generator = configured_generator(&whitespace_pattern, &json_value, options);
+
+    // what surrounds the top-level value
+    let padding = if options.padded {
+        whitespace_pattern.as_ref()
+    } else {
+        ""
+    };
+    let (prefix, suffix) = if options.anchored {
+        (format!("^(?:{}", padding), format!("{})$", padding))
+    } else {
+        (padding.to_string(), padding.to_string())
+    };
+    let affixes = (prefix.as_str(), suffix.as_str());
 
-            Ok(format!(r"({})", subregexes.join("|")))
+    if options.simplify {
+        // simplifying needs the whole regex
+        // and is anchored afterwards, as it would print anchors its own way
+        let mut regex = String::new();
+        generator.write_top_level(&mut regex, &json_value, (padding, padding))?;
+        if !options.capture_groups {
+            regex = non_capturing(&regex);
+        }
+        let regex = simplify::simplify(&regex)?;
+        if options.anchored {
+            write!(out, "^(?:{})$", regex)?;
+        } else {
+            out.write_str(&regex)?;
         }
-        _ => Err(anyhow!("'anyOf' must be an array")),
+    } else if options.capture_groups {
+        generator.write_limited(out, |out| {
+            generator.write_top_level(out, &json_value, affixes)
+        })?;
+    } else {
+        generator.write_limited(out, |out| {
+            let mut out = NonCapturing::new(out);
+            generator.write_top_level(&mut out, &json_value, affixes)?;
+            Ok(out.finish()?)
+        })?;
     }
+    Ok(Generated {
+        warnings: generator.warnings(),
+        group_pointers: generator.group_pointers(),
+    })
 }
 
-fn handle_one_of(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    match obj.get("oneOf") {
-        Some(Value::Array(one_of)) => {
-            let subregexes: Result<Vec<String>> = one_of
-                .iter()
-                .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
-                .collect();
+/// The schema of `json`, once it and `options` are checked.
+pub(crate) fn parse_with_options(json: &str, options: &RegexOptions) -> Result<Value> {
+    let json_value: Value = serde_json::from_str(json)?;
+    check_with_options(&json_value, options)?;
+    Ok(json_value)
+}
 
-            let subregexes = subregexes?;
+/// Checks `schema` against its meta-schema, and `options`.
+pub(crate) fn check_with_options(schema: &Value, options: &RegexOptions) -> Result<()> {
+    draft::validate_schema(schema, options.draft)?;
 
-            let xor_patterns: Vec<String> = subregexes
-                .into_iter()
-                .map(|subregex| format!(r"(?:{})", subregex))
-                .collect();
+    options.whitespace_positions.validate()?;
+    if options.max_alternation_width.is_some_and(|width| width < 2) {
+        return Err(anyhow!("max_alternation_width must be at least 2"));
+    }
+    Ok(())
+}
 
-            Ok(format!(r"({})", xor_patterns.join("|")))
-        }
-        _ => Err(anyhow!("'oneOf' must be an array")),
+/// The regex of a whole document written by a generator, with the groups,
+/// simplification and anchors `options` ask for.
+pub(crate) fn finish_regex(regex: &str, options: &RegexOptions) -> Result<String> {
+    let mut regex = if options.capture_groups {
+        regex.to_string()
+    } else {
+        non_capturing(regex)
+    };
+    if options.simplify {
+        regex = simplify::simplify(&regex)?;
     }
+    if options.anchored {
+        regex = format!("^(?:{})$", regex);
+    }
+    Ok(regex)
 }
 
-fn handle_prefix_items(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    match obj.get("prefixItems") {
-        Some(Value::Array(prefix_items)) => {
-            let element_patterns: Result<Vec<String>> = prefix_items
-                .iter()
-                .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
-                .collect();
+/// Rewrites every capturing group of `regex` as a non-capturing one.
+///
+/// The generator builds its patterns with plain `(...)` groups, as Outlines
+/// does; they cost engines group slots without ever being read.
+pub(crate) fn non_capturing(regex: &str) -> String {
+    let mut result = String::with_capacity(regex.len());
+    let mut out = NonCapturing::new(&mut result);
+    // writing into a String can't fail
+    let _ = out.write_str(regex).and_then(|_| out.finish());
+    result
+}
 
-            let element_patterns = element_patterns?;
+/// Writer turning the capturing groups of a regex written through it into
+/// non-capturing ones, one character at a time.
+struct NonCapturing<'a, W> {
+    out: &'a mut W,
+    /// Nesting depth of character classes, which can't hold groups.
+    class_depth: usize,
+    /// Whether the previous character was an unescaped `\`.
+    escaped: bool,
+    /// Right after `[` (1) or `[^` (2), where `^` and `]` are literals.
+    class_start: u8,
+    /// A `(` held back until the next character shows if it's a capture.
+    open_paren: bool,
+}
 
-            let comma_split_pattern = format!("{},{}", whitespace_pattern, whitespace_pattern);
-            let tuple_inner = element_patterns.join(&comma_split_pattern);
+impl<'a, W: Write> NonCapturing<'a, W> {
+    fn new(out: &'a mut W) -> Self {
+        Self {
+            out,
+            class_depth: 0,
+            escaped: false,
+            class_start: 0,
+            open_paren: false,
+        }
+    }
 
-            Ok(format!(
-                r"\[{whitespace_pattern}{tuple_inner}{whitespace_pattern}\]"
-            ))
+    fn finish(&mut self) -> fmt::Result {
+        if std::mem::take(&mut self.open_paren) {
+            self.out.write_char('(')?;
         }
-        _ => Err(anyhow!("'prefixItems' must be an array")),
+        Ok(())
     }
 }
 
-fn handle_enum(obj: &serde_json::Map<String, Value>, _whitespace_pattern: &str) -> Result<String> {
-    match obj.get("enum") {
-        Some(Value::Array(enum_values)) => {
-            let choices: Result<Vec<String>> = enum_values
-                .iter()
-                .map(|choice| match choice {
-                    Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {
-                        let json_string = serde_json::to_string(choice)?;
-                        Ok(regex::escape(&json_string))
-                    }
-                    _ => Err(anyhow!("Unsupported data type in enum: {:?}", choice)),
-                })
-                .collect();
+impl<W: Write> Write for NonCapturing<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.write_char(c)?;
+        }
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        if std::mem::take(&mut self.open_paren) {
+            self.out.write_str(if c == '?' { "(" } else { "(?:" })?;
+        }
+        if std::mem::take(&mut self.escaped) {
+            return self.out.write_char(c);
+        }
 
-            let choices = choices?;
-            Ok(format!(r"({})", choices.join("|")))
+        let class_start = std::mem::take(&mut self.class_start);
+        match c {
+            '\\' => self.escaped = true,
+            '^' if class_start == 1 => self.class_start = 2,
+            ']' if class_start > 0 => {}
+            '[' => {
+                self.class_depth += 1;
+                self.class_start = 1;
+            }
+            ']' if self.class_depth > 0 => self.class_depth -= 1,
+            '(' if self.class_depth == 0 => {
+                self.open_paren = true;
+                return Ok(());
+            }
+            _ => {}
         }
-        _ => Err(anyhow!("'enum' must be an array")),
+        self.out.write_char(c)
     }
 }
 
-fn handle_const(obj: &serde_json::Map<String, Value>, _whitespace_pattern: &str) -> Result<String> {
-    match obj.get("const") {
-        Some(const_value) => match const_value {
-            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {
-                let json_string = serde_json::to_string(const_value)?;
-                Ok(regex::escape(&json_string))
+/// Checks that a custom whitespace pattern can be embedded between JSON tokens.
+///
+/// The pattern must be a valid regex without capture groups, which would
+/// shift the group numbers of the generated regex, and without anchors or
+/// word boundaries.
+pub fn validate_whitespace_pattern(pattern: &str) -> Result<()> {
+    let hir = regex_syntax::Parser::new()
+        .parse(pattern)
+        .map_err(|e| anyhow!("Invalid whitespace pattern {:?}: {}", pattern, e))?;
+
+    fn check(hir: &Hir, pattern: &str) -> Result<()> {
+        match hir.kind() {
+            HirKind::Capture(_) => Err(anyhow!(
+                "Whitespace pattern {:?} contains a capture group, use (?:...) instead",
+                pattern
+            )),
+            HirKind::Look(_) => Err(anyhow!(
+                "Whitespace pattern {:?} contains an anchor or a word boundary",
+                pattern
+            )),
+            HirKind::Repetition(repetition) => check(&repetition.sub, pattern),
+            HirKind::Concat(subs) | HirKind::Alternation(subs) => {
+                subs.iter().try_for_each(|sub| check(sub, pattern))
             }
-            _ => Err(anyhow!("Unsupported data type in const: {:?}", const_value)),
-        },
-        None => Err(anyhow!("'const' key not found in object")),
+            HirKind::Empty | HirKind::Literal(_) | HirKind::Class(_) => Ok(()),
+        }
     }
+    check(&hir, pattern)
 }
 
-fn handle_ref(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
+/// The whitespace pattern to generate with: the default one, or the custom
+/// one validated and wrapped in a group so an alternation in it stays local.
+pub(crate) fn resolve_whitespace_pattern(pattern: Option<&str>) -> Result<Cow<'static, str>> {
+    match pattern {
+        None => Ok(Cow::Borrowed(types::WHITESPACE)),
+        Some("") => Ok(Cow::Borrowed("")),
+        Some(pattern) => {
+            validate_whitespace_pattern(pattern)?;
+            Ok(Cow::Owned(format!("(?:{})", pattern)))
+        }
+    }
+}
+
+/// Builds a regex for values of `T`, using the schema generated by `schemars`.
+#[cfg(feature = "schemars")]
+pub fn build_regex_for_type<T: schemars::JsonSchema>(
+    whitespace_pattern: Option<&str>,
 ) -> Result<String> {
-    let ref_path = obj["$ref"]
-        .as_str()
-        .ok_or_else(|| anyhow!("'$ref' must be a string"))?;
+    let schema = serde_json::to_string(&schemars::schema_for!(T))?;
+    build_regex_from_schema(&schema, whitespace_pattern)
+}
 
-    // TODO Only handle local references for now, maybe add support for remote references later
-    if !ref_path.starts_with("#/") {
-        return Err(anyhow!("Only local references are supported"));
-    }
+/// Builds a regex for a JSON array holding between `min_items` and `max_items`
+/// values that each match the schema.
+pub fn build_regex_for_array_of(
+    json: &str,
+    min_items: u64,
+    max_items: Option<u64>,
+    whitespace_pattern: Option<&str>,
+) -> Result<String> {
+    handle_types::check_bounds("min_items", "max_items", min_items, max_items)?;
+    let items_regex = build_regex_from_schema(json, whitespace_pattern)?;
+    let whitespace_pattern = resolve_whitespace_pattern(whitespace_pattern)?;
 
-    let path_parts: Vec<&str> = ref_path[2..].split('/').collect();
-    let referenced_schema = resolve_local_ref(full_schema, &path_parts)?;
+    if max_items == Some(0) {
+        return Ok(format!(r"\[{0}{0}\]", whitespace_pattern));
+    }
+    let items = handle_types::separated_items(
+        &format!("({})", items_regex),
+        &format!(",{}", whitespace_pattern),
+        min_items,
+        max_items,
+    );
 
-    to_regex(referenced_schema, Some(whitespace_pattern), full_schema)
+    Ok(format!(
+        r"\[{0}{1}{0}\]",
+        whitespace_pattern,
+        non_capturing(&items)
+    ))
 }
 
-fn resolve_local_ref<'a>(schema: &'a Value, path_parts: &[&str]) -> Result<&'a Value> {
-    let mut current = schema;
-    for &part in path_parts {
-        current = current
-            .get(part)
-            .ok_or_else(|| anyhow!("Invalid reference path: {}", part))?;
-    }
-    Ok(current)
+/// Builds a regex for JSON Lines: between `min_items` and `max_items` values
+/// matching the schema, one per line, with an optional trailing newline.
+pub fn build_regex_for_json_lines(
+    json: &str,
+    min_items: u64,
+    max_items: Option<u64>,
+    whitespace_pattern: Option<&str>,
+) -> Result<String> {
+    handle_types::check_bounds("min_items", "max_items", min_items, max_items)?;
+    let items_regex = build_regex_from_schema(json, whitespace_pattern)?;
+
+    let Some(num_repeats) = handle_types::get_num_items_pattern(Some(min_items), max_items) else {
+        return Ok(String::new());
+    };
+    let allow_empty = if min_items == 0 { "?" } else { "" };
+
+    Ok(format!(
+        r"(?:(?:{0})(?:\n(?:{0})){1}\n?){2}",
+        items_regex, num_repeats, allow_empty
+    ))
 }
 
-fn handle_type(
-    obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
+pub fn to_regex(
+    json: &Value,
+    whitespace_pattern: Option<&str>,
     full_schema: &Value,
 ) -> Result<String> {
-    let instance_type = obj["type"]
-        .as_str()
-        .ok_or_else(|| anyhow!("'type' must be a string"))?;
-    match instance_type {
-        "string" => handle_types::handle_string_type(obj),
-        "number" => handle_types::handle_number_type(obj),
-        "integer" => handle_types::handle_integer_type(obj),
-        "array" => handle_types::handle_array_type(obj, whitespace_pattern, full_schema),
-        "object" => handle_types::handle_object_type(obj, whitespace_pattern, full_schema),
-        "boolean" => handle_types::handle_boolean_type(),
-        "null" => handle_types::handle_null_type(),
-        _ => Err(anyhow!("Unsupported type: {}", instance_type)),
-    }
+    let mut regex = String::new();
+    write_regex_to(&mut regex, json, whitespace_pattern, full_schema)?;
+    Ok(regex)
 }
 
-pub fn handle_empty_object(whitespace_pattern: &str, full_schema: &Value) -> Result<String> {
-    // JSON Schema Spec: Empty object means unconstrained, any json type is legal
-    let types = vec![
-        json!({"type": "boolean"}),
-        json!({"type": "null"}),
-        json!({"type": "number"}),
-        json!({"type": "integer"}),
-        json!({"type": "string"}),
-        json!({"type": "array"}),
-        json!({"type": "object"}),
-    ];
+/// Writes the regex for `json` into `out`, the streaming form of [`to_regex`].
+pub fn write_regex_to<W: Write>(
+    out: &mut W,
+    json: &Value,
+    whitespace_pattern: Option<&str>,
+    full_schema: &Value,
+) -> Result<()> {
+    let whitespace_pattern = whitespace_pattern.unwrap_or(types::WHITESPACE);
+    Generator::new(whitespace_pattern, full_schema).write(out, json)
+}
 
-    let regexes: Result<Vec<String>> = types
-        .iter()
-        .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
-        .collect();
+/// What generating a regex needs besides the subschema at hand: the
+/// whitespace pattern, the root schema `$ref`s point into, and the limits
+/// the regex has to stay within.
+#[derive(Clone)]
+pub struct Generator<'a> {
+    pub whitespace_pattern: &'a str,
+    /// The whitespace patterns of some positions, `whitespace_pattern` by
+    /// default, see [`WhitespacePositions`].
+    pub(crate) after_colon: &'a str,
+    pub(crate) after_comma: &'a str,
+    pub(crate) inside_brackets: &'a str,
+    pub full_schema: &'a Value,
+    max_regex_len: Option<usize>,
+    max_nodes: Option<usize>,
+    pub(crate) max_pattern_complexity: Option<usize>,
+    pub(crate) sort_properties: bool,
+    pub(crate) all_properties_required: bool,
+    pub(crate) closed_objects: bool,
+    pub(crate) numeric_formats: bool,
+    pub(crate) number_syntax: types::NumberSyntax,
+    pub(crate) unconstrained: UnconstrainedValues,
+    /// The draft the schema is read as, `None` for the forms of all of them.
+    pub(crate) draft: Option<Draft>,
+    named_groups: bool,
+    sort_enums: bool,
+    max_alternation_width: Option<usize>,
+    equivalent_numbers: bool,
+    strict: bool,
+    report_ambiguity: bool,
+    exclusive_one_of: bool,
+    /// Subschemas generated so far, shared by the parallel workers.
+    nodes: Arc<AtomicUsize>,
+    /// What wasn't enforced so far, shared like `nodes`.
+    warnings: Arc<Mutex<Vec<SchemaError>>>,
+    /// Names of the groups written so far, with the JSON Pointer of their
+    /// value in the documents, shared like `nodes`.
+    group_names: Arc<Mutex<HashMap<String, String>>>,
+    /// The rules called so far when generating a [`crate::pushdown::Grammar`],
+    /// shared like `nodes`, `None` when generating a regex.
+    rules: Option<Arc<Mutex<Vec<Rule>>>>,
+    location: Location<'a>,
+}
 
-    let regexes = regexes?;
+/// Where the subschema being generated sits in the schema document.
+#[derive(Clone, Copy)]
+enum Location<'a> {
+    Root,
+    /// The target of a `$ref`, by its JSON Pointer, below the generator of
+    /// the subschema with the `$ref`.
+    Ref(&'a Generator<'a>, &'a str),
+    Child(&'a Generator<'a>, Segment<'a>),
+}
 
-    let wrapped_regexes: Vec<String> = regexes.into_iter().map(|r| format!("({})", r)).collect();
+/// One step of a JSON Pointer.
+#[derive(Clone, Copy)]
+pub enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
 
-    Ok(wrapped_regexes.join("|"))
+impl<'a> From<&'a str> for Segment<'a> {
+    fn from(key: &'a str) -> Self {
+        Segment::Key(key)
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::py_wrapper::python_build_regex_from_schema;
-    use regex::Regex;
-    use regex_syntax::Parser;
-    use serde_json::json;
+impl From<usize> for Segment<'_> {
+    fn from(index: usize) -> Self {
+        Segment::Index(index)
+    }
+}
 
-    use super::*;
+impl<'a> Generator<'a> {
+    pub fn new(whitespace_pattern: &'a str, full_schema: &'a Value) -> Self {
+        Self {
+            whitespace_pattern,
+            after_colon: whitespace_pattern,
+            after_comma: whitespace_pattern,
+            inside_brackets: whitespace_pattern,
+            full_schema,
+            max_regex_len: None,
+            max_nodes: None,
+            max_pattern_complexity: None,
+            sort_properties: false,
+            all_properties_required: false,
+            closed_objects: false,
+            numeric_formats: false,
+            number_syntax: types::NumberSyntax::default(),
+            unconstrained: UnconstrainedValues::default(),
+            draft: Draft::detect(full_schema),
+            named_groups: false,
+            sort_enums: false,
+            max_alternation_width: None,
+            equivalent_numbers: false,
+            strict: false,
+            report_ambiguity: false,
+            exclusive_one_of: false,
+            nodes: Arc::new(AtomicUsize::new(0)),
+            warnings: Arc::default(),
+            group_names: Arc::default(),
+            rules: None,
+            location: Location::Root,
+        }
+    }
 
-    fn test_regex(schema: &serde_json::Value) {
-        let schema_str = schema.to_string();
-        let rust_regex = build_regex_from_schema(&schema_str, None).unwrap();
-        let outlines_regex = python_build_regex_from_schema(&schema_str).unwrap();
+    /// Uses the patterns set in `positions` instead of the whitespace pattern
+    /// at their positions.
+    pub fn with_whitespace_positions(mut self, positions: &'a WhitespacePositions) -> Self {
+        if let Some(pattern) = &positions.after_colon {
+            self.after_colon = pattern;
+        }
+        if let Some(pattern) = &positions.after_comma {
+            self.after_comma = pattern;
+        }
+        if let Some(pattern) = &positions.inside_brackets {
+            self.inside_brackets = pattern;
+        }
+        self
+    }
 
-        // check if regexes even compile
-        Regex::new(&rust_regex).expect("Rust regex is invalid");
-        Regex::new(&outlines_regex).expect("Outlines regex is invalid");
+    /// Fails generation once the regex gets longer than `max_regex_len`
+    /// bytes, or once more than `max_nodes` subschemas were generated.
+    pub fn with_limits(mut self, max_regex_len: Option<usize>, max_nodes: Option<usize>) -> Self {
+        self.max_regex_len = max_regex_len;
+        self.max_nodes = max_nodes;
+        self
+    }
 
-        // compare to outlines
-        assert!(
-            compare_regexes(&rust_regex, &outlines_regex),
-            "Rust and Python outputs are not equivalent for schema: {}\nRust:    {}\nPython:  {}",
-            schema_str,
-            rust_regex,
-            outlines_regex
-        );
+    /// Fails on `pattern`s over `max_pattern_complexity`, see
+    /// [`RegexOptions::max_pattern_complexity`].
+    pub fn with_max_pattern_complexity(mut self, max_pattern_complexity: Option<usize>) -> Self {
+        self.max_pattern_complexity = max_pattern_complexity;
+        self
     }
 
-    fn compare_regexes(a: &str, b: &str) -> bool {
-        let parse_result_a = Parser::new().parse(a);
-        let parse_result_b = Parser::new().parse(b);
+    /// Wraps the values of properties in named groups, see
+    /// [`RegexOptions::named_groups`].
+    pub fn with_named_groups(mut self, named_groups: bool) -> Self {
+        self.named_groups = named_groups;
+        self
+    }
 
-        match (parse_result_a, parse_result_b) {
-            (Ok(hir_a), Ok(hir_b)) => hir_a == hir_b,
-            _ => false,
-        }
+    /// Writes the properties of objects sorted by name instead of in the
+    /// order of the schema map.
+    pub fn with_sorted_properties(mut self, sort_properties: bool) -> Self {
+        self.sort_properties = sort_properties;
+        self
     }
 
-    mod object_tests {
-        use super::*;
+    /// Writes every property of objects as required.
+    pub fn with_all_properties_required(mut self, all_properties_required: bool) -> Self {
+        self.all_properties_required = all_properties_required;
+        self
+    }
 
-        #[test]
-        fn test_object_type_with_name() {
-            let schema = json!({"type": "object", "properties": {"name": {"type": "string"}}});
-            test_regex(&schema);
-        }
-        #[test]
-        fn test_schema0_regex() {
-            let schema = json!({
-                "type": "object",
-                "properties": {
-                    "name": {
-                        "type": "string",
-                        "minLength": 2,
-                        "maxLength": 5
-                    },
-                },
-            });
+    /// Reads objects without `additionalProperties` as closed, see
+    /// [`RegexOptions::closed_objects`].
+    pub fn with_closed_objects(mut self, closed_objects: bool) -> Self {
+        self.closed_objects = closed_objects;
+        self
+    }
 
-            test_regex(&schema);
-        }
+    /// Writes the values of enums sorted by their JSON text instead of in the
+    /// order of the schema.
+    pub fn with_sorted_enums(mut self, sort_enums: bool) -> Self {
+        self.sort_enums = sort_enums;
+        self
+    }
 
-        #[test]
-        fn test_schema1_regex() {
-            let schema = json!({
-                "type": "object",
-                "properties": {
-                    "name": {
-                        "type": "string",
-                        "minLength": 2,
-                        "maxLength": 5
-                    },
-                },
-            });
-            test_regex(&schema);
-        }
+    /// Writes enums with at most `width` branches per alternation, see
+    /// [`RegexOptions::max_alternation_width`].
+    pub fn with_max_alternation_width(mut self, width: Option<usize>) -> Self {
+        self.max_alternation_width = width;
+        self
+    }
 
-        #[test]
-        fn test_schema2_regex() {
-            let schema = json!({
-                "type": "object",
-                "properties": {
+    /// Accepts the numbers of enums in their other forms, see
+    /// [`RegexOptions::equivalent_numbers`].
+    pub fn with_equivalent_numbers(mut self, equivalent_numbers: bool) -> Self {
+        self.equivalent_numbers = equivalent_numbers;
+        self
+    }
+
+    /// Fails on keywords the regex can't enforce instead of recording a
+    /// warning.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Warns about `anyOf` and `oneOf` branches a matcher can't tell apart
+    /// for long, see [`RegexOptions::report_ambiguity`].
+    pub fn with_ambiguity_report(mut self, report_ambiguity: bool) -> Self {
+        self.report_ambiguity = report_ambiguity;
+        self
+    }
+
+    /// Matches the values of exactly one `oneOf` branch, see
+    /// [`RegexOptions::exclusive_one_of`].
+    pub fn with_exclusive_one_of(mut self, exclusive_one_of: bool) -> Self {
+        self.exclusive_one_of = exclusive_one_of;
+        self
+    }
+
+    /// Bounds integers and numbers by their `format`, see
+    /// [`RegexOptions::numeric_formats`].
+    pub fn with_numeric_formats(mut self, numeric_formats: bool) -> Self {
+        self.numeric_formats = numeric_formats;
+        self
+    }
+
+    /// Accepts the lenient forms of numbers of `number_syntax` too.
+    pub fn with_number_syntax(mut self, number_syntax: types::NumberSyntax) -> Self {
+        self.number_syntax = number_syntax;
+        self
+    }
+
+    /// Allows these values where the schema doesn't constrain them.
+    pub fn with_unconstrained_values(mut self, unconstrained: UnconstrainedValues) -> Self {
+        self.unconstrained = unconstrained;
+        self
+    }
+
+    /// Reads the schema as `draft`, or as its `$schema` declares when `None`.
+    pub fn with_draft(mut self, draft: Option<Draft>) -> Self {
+        self.draft = draft.or(self.draft);
+        self
+    }
+
+    /// The generator for the subschema at `segment` below the current one.
+    pub fn at<'b>(&'b self, segment: impl Into<Segment<'b>>) -> Generator<'b> {
+        self.located(Location::Child(self, segment.into()))
+    }
+
+    /// Writes the recursive `$ref`s and unconstrained values as calls to the
+    /// `rules` of a grammar, see [`Generator::call`].
+    pub(crate) fn with_rules(mut self, rules: Arc<Mutex<Vec<Rule>>>) -> Self {
+        self.rules = Some(rules);
+        self
+    }
+
+    /// The pattern calling `rule` of the grammar being generated, `None` when
+    /// generating a regex.
+    pub(crate) fn call(&self, rule: Rule) -> Option<String> {
+        let mut rules = self
+            .rules
+            .as_ref()?
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let index = match rules.iter().position(|called| *called == rule) {
+            Some(index) => index,
+            None => {
+                rules.push(rule);
+                rules.len() - 1
+            }
+        };
+        // the root is rule 0
+        Some(pushdown::call_pattern(index + 1))
+    }
+
+    /// The regex of `rule`, whose calls are written as in the regex of the
+    /// schema.
+    pub(crate) fn rule_regex(&self, rule: &Rule) -> Result<String> {
+        match rule {
+            Rule::Ref(ref_path) => {
+                let path_parts: Vec<&str> = ref_path[2..].split('/').collect();
+                let target = resolve_local_ref(self.full_schema, &path_parts)?;
+                self.at_ref(ref_path).to_regex(target)
+            }
+            Rule::Unconstrained => self.to_regex(&json!({})),
+        }
+    }
+
+    /// The generator for the target of the `$ref` `ref_path`.
+    pub(crate) fn at_ref<'b>(&'b self, ref_path: &'b str) -> Generator<'b> {
+        self.located(Location::Ref(self, ref_path.trim_start_matches('#')))
+    }
+
+    /// Whether the target of the `$ref` `ref_path` is being generated
+    /// already, from a `$ref` of one of the enclosing subschemas.
+    fn in_ref(&self, ref_path: &str) -> bool {
+        let target = ref_path.trim_start_matches('#');
+        let mut location = self.location;
+        loop {
+            location = match location {
+                Location::Root => return false,
+                Location::Ref(_, pointer) if pointer == target => return true,
+                Location::Ref(parent, _) | Location::Child(parent, _) => parent.location,
+            };
+        }
+    }
+
+    fn located<'b>(&'b self, location: Location<'b>) -> Generator<'b> {
+        Generator {
+            whitespace_pattern: self.whitespace_pattern,
+            after_colon: self.after_colon,
+            after_comma: self.after_comma,
+            inside_brackets: self.inside_brackets,
+            full_schema: self.full_schema,
+            max_regex_len: self.max_regex_len,
+            max_nodes: self.max_nodes,
+            max_pattern_complexity: self.max_pattern_complexity,
+            sort_properties: self.sort_properties,
+            all_properties_required: self.all_properties_required,
+            closed_objects: self.closed_objects,
+            numeric_formats: self.numeric_formats,
+            number_syntax: self.number_syntax,
+            unconstrained: self.unconstrained,
+            draft: self.draft,
+            named_groups: self.named_groups,
+            sort_enums: self.sort_enums,
+            max_alternation_width: self.max_alternation_width,
+            equivalent_numbers: self.equivalent_numbers,
+            strict: self.strict,
+            report_ambiguity: self.report_ambiguity,
+            exclusive_one_of: self.exclusive_one_of,
+            nodes: Arc::clone(&self.nodes),
+            warnings: Arc::clone(&self.warnings),
+            group_names: Arc::clone(&self.group_names),
+            rules: self.rules.clone(),
+            location,
+        }
+    }
+
+    /// The JSON Pointer of the current subschema in the schema document.
+    pub fn pointer(&self) -> String {
+        match self.location {
+            Location::Root => String::new(),
+            Location::Ref(_, pointer) => pointer.to_string(),
+            Location::Child(parent, segment) => {
+                let mut pointer = parent.pointer();
+                pointer.push('/');
+                match segment {
+                    Segment::Key(key) => pointer += &key.replace('~', "~0").replace('/', "~1"),
+                    Segment::Index(index) => pointer += &index.to_string(),
+                }
+                pointer
+            }
+        }
+    }
+
+    /// The name of the group around the value of the current subschema, a
+    /// property, from the names of the properties leading to it: `a__b` for
+    /// `/properties/a/properties/b`. `None` without named groups, for values
+    /// with several places in the documents, or with a warning when the name
+    /// was taken already.
+    fn group_name(&self) -> Option<String> {
+        if !self.named_groups {
+            return None;
+        }
+        let names = self.property_path()?;
+        let document_pointer: String = names.iter().map(|name| format!("/{}", name)).collect();
+        let mut name: String = names
+            .join("__")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            name.insert(0, '_');
+        }
+
+        let mut group_names = self
+            .group_names
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if !group_names.contains_key(&name) {
+            group_names.insert(name.clone(), document_pointer);
+            Some(name)
+        } else {
+            drop(group_names);
+            self.warn(SchemaError::new(format!(
+                "the group name '{}' is taken already, the value is left unnamed",
+                name
+            )));
+            None
+        }
+    }
+
+    /// The names of the properties leading to the value of the current
+    /// subschema, as escaped in JSON Pointers, or `None` when the value has
+    /// several places in the documents, like in arrays, maps or definitions.
+    fn property_path(&self) -> Option<Vec<String>> {
+        let pointer = self.pointer();
+        let mut segments = pointer.split('/').skip(1);
+        let mut names = Vec::new();
+        while let Some(segment) = segments.next() {
+            match segment {
+                "properties" => names.extend(segments.next().map(String::from)),
+                "allOf" | "anyOf" | "oneOf" => {
+                    segments.next();
+                }
+                _ => return None,
+            }
+        }
+        Some(names)
+    }
+
+    /// The JSON Pointer in the documents of the value of each named group
+    /// written so far, by name.
+    fn group_pointers(&self) -> HashMap<String, String> {
+        self.group_names
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Whether the regex of numbers matches every integer, which it doesn't
+    /// when numbers need a fraction.
+    fn integers_are_numbers(&self) -> bool {
+        !self.number_syntax.force_decimal_for_number
+    }
+
+    /// Records that the regex doesn't enforce what `error` is about, or fails
+    /// with it in strict mode.
+    pub fn unenforced(&self, error: SchemaError) -> Result<()> {
+        if self.strict {
+            return Err(error.in_subschema(&self.pointer()).into());
+        }
+        self.warn(error);
+        Ok(())
+    }
+
+    /// Records `error` as a warning, even in strict mode.
+    pub fn warn(&self, error: SchemaError) {
+        let error = error.in_subschema(&self.pointer());
+        self.warnings
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(error);
+    }
+
+    /// The warnings recorded so far by this generator and the ones derived
+    /// from it, by pointer and without repeats.
+    pub fn warnings(&self) -> Vec<SchemaError> {
+        let mut warnings = self
+            .warnings
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        warnings.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+        warnings.dedup();
+        warnings
+    }
+
+    /// Writes the regex of `schema` into `out` between `prefix` and `suffix`.
+    fn write_top_level<W: Write>(
+        &self,
+        out: &mut W,
+        schema: &Value,
+        (prefix, suffix): (&str, &str),
+    ) -> Result<()> {
+        out.write_str(prefix)?;
+        self.write(out, schema)?;
+        out.write_str(suffix)?;
+        Ok(())
+    }
+
+    /// Writes the regex of `schema` into `out`.
+    pub fn write<W: Write>(&self, out: &mut W, schema: &Value) -> Result<()> {
+        let nodes = self.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(max_nodes) = self.max_nodes.filter(|&max_nodes| nodes > max_nodes) {
+            return Err(anyhow!(
+                "The schema expands to more than {} subschemas (max_nodes), check for deeply nested or repeatedly referenced definitions",
+                max_nodes
+            ));
+        }
+
+        let Value::Object(obj) = schema else {
+            return Err(SchemaError::new(format!(
+                "Invalid JSON Schema: expected an object, got {}",
+                brief(schema)
+            ))
+            .at(self.pointer())
+            .into());
+        };
+        // the first keyword set picks the handler, which may still narrow
+        // it down by the others: `enum` keeps the values of `type` and the
+        // value of `const`, and `const` has to be of `type`
+        let keyword = if obj.is_empty() {
+            SchemaKeyword::EmptyObject
+        } else {
+            [
+                ("properties", SchemaKeyword::Properties),
+                ("allOf", SchemaKeyword::AllOf),
+                ("anyOf", SchemaKeyword::AnyOf),
+                ("oneOf", SchemaKeyword::OneOf),
+                ("prefixItems", SchemaKeyword::PrefixItems),
+                ("items", SchemaKeyword::TupleItems),
+                ("enum", SchemaKeyword::Enum),
+                ("const", SchemaKeyword::Const),
+                ("$ref", SchemaKeyword::Ref),
+                ("type", SchemaKeyword::Type),
+            ]
+            .iter()
+            .find_map(|&(key, schema_keyword)| {
+                let present = match schema_keyword {
+                    SchemaKeyword::PrefixItems => {
+                        draft::has_prefix_items(self.draft) && obj.contains_key(key)
+                    }
+                    SchemaKeyword::TupleItems => {
+                        draft::has_tuple_items(self.draft) && obj.get(key).is_some_and(Value::is_array)
+                    }
+                    _ => obj.contains_key(key),
+                };
+                present.then_some(schema_keyword)
+            })
+            .ok_or_else(|| {
+                SchemaError::new(format!("Unsupported JSON Schema structure {}", schema)).with_hint(
+                    "Make sure it is valid to the JSON Schema specification and check if it's supported by Outlines.\nIf it should be supported, please open an issue.",
+                )
+            })?
+        };
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("schema", pointer = %self.pointer()).entered();
+        #[cfg(feature = "tracing")]
+        let (start, out) = (std::time::Instant::now(), &mut CountingWriter::new(out));
+
+        let result = match keyword {
+            SchemaKeyword::Properties => handle_properties(out, obj, self),
+            SchemaKeyword::AllOf => handle_all_of(out, obj, self),
+            SchemaKeyword::AnyOf => handle_any_of(out, obj, self),
+            SchemaKeyword::OneOf => handle_one_of(out, obj, self),
+            SchemaKeyword::PrefixItems => handle_prefix_items(out, obj, "prefixItems", self),
+            SchemaKeyword::TupleItems => handle_prefix_items(out, obj, "items", self),
+            SchemaKeyword::Enum => handle_enum(out, obj, self),
+            SchemaKeyword::Const => handle_const(out, obj),
+            SchemaKeyword::Ref => handle_ref(out, obj, self),
+            SchemaKeyword::Type => handle_type(out, obj, self),
+            SchemaKeyword::EmptyObject => handle_empty_object(out, self),
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            ?keyword,
+            bytes = out.written,
+            elapsed_us = start.elapsed().as_micros() as u64,
+            "generated subschema"
+        );
+        result.map_err(|error| self.locate(error))
+    }
+
+    /// `error` located in the current subschema, unless it's located in a
+    /// subschema already: the innermost one failing is the one it's about.
+    fn locate(&self, error: anyhow::Error) -> anyhow::Error {
+        match error.downcast::<SchemaError>() {
+            Ok(error) if error.pointer.is_some() => error.into(),
+            Ok(error) => error.in_subschema(&self.pointer()).into(),
+            Err(error) => SchemaError::new(format!("{:#}", error))
+                .at(self.pointer())
+                .into(),
+        }
+    }
+
+    /// Runs `f` on `schema`, or on the target of its `$ref`s, with the
+    /// generator located there, and locates its errors as [`Generator::write`]
+    /// does. For the formats writing their own patterns around subschemas.
+    pub(crate) fn resolve<R>(
+        &self,
+        schema: &Value,
+        f: impl FnOnce(&Generator, &Value) -> Result<R>,
+    ) -> Result<R> {
+        let Some(ref_path) = schema.get("$ref").and_then(Value::as_str) else {
+            return f(self, schema).map_err(|error| self.locate(error));
+        };
+        let target = self
+            .ref_target(ref_path)
+            .map_err(|error| self.locate(error))?;
+        self.at_ref(ref_path).resolve(target, f)
+    }
+
+    /// The target of the `$ref` `ref_path` of the current subschema.
+    fn ref_target(&self, ref_path: &str) -> Result<&'a Value> {
+        // TODO Only handle local references for now, maybe add support for remote references later
+        if !ref_path.starts_with("#/") {
+            return Err(SchemaError::new("Only local references are supported")
+                .with_hint(format!(
+                    "\"{}\" must point into this schema, e.g. \"#/$defs/name\"; inline remote schemas under \"$defs\"",
+                    ref_path
+                ))
+                .into());
+        }
+
+        // a regex can't match values nested without bound
+        if self.in_ref(ref_path) {
+            return Err(SchemaError::new(format!(
+                "the $ref to \"{}\" is recursive, which a regex can't match",
+                ref_path
+            ))
+            .with_keyword("$ref")
+            .with_hint("unroll the recursion to the depth the documents need, with one definition per level, the last one without the $ref, or guide with Backend::Pushdown")
+            .into());
+        }
+
+        let path_parts: Vec<&str> = ref_path[2..].split('/').collect();
+        resolve_local_ref(self.full_schema, &path_parts)
+    }
+
+    /// The regex of `schema` on its own, for fragments written more than once.
+    pub fn to_regex(&self, schema: &Value) -> Result<String> {
+        let mut regex = String::new();
+        self.write_limited(&mut regex, |out| self.write(out, schema))?;
+        Ok(regex)
+    }
+
+    /// Runs `write` on `out`, failing as soon as more than `max_regex_len`
+    /// bytes were written to it.
+    fn write_limited<W: Write>(
+        &self,
+        out: &mut W,
+        write: impl FnOnce(&mut LimitedWriter<'_, W>) -> Result<()>,
+    ) -> Result<()> {
+        let mut limited = LimitedWriter {
+            out,
+            remaining: self.max_regex_len.unwrap_or(usize::MAX),
+            exceeded: false,
+        };
+        let result = write(&mut limited);
+        if limited.exceeded {
+            return Err(anyhow!(
+                "The regex is longer than {} bytes (max_regex_len), the schema is too large to compile",
+                self.max_regex_len.unwrap_or(usize::MAX)
+            ));
+        }
+        result
+    }
+}
+
+/// Writer counting the bytes written through it, for tracing. It writes
+/// through `dyn Write` so nesting it doesn't create a new type per level.
+#[cfg(feature = "tracing")]
+struct CountingWriter<'a> {
+    out: &'a mut dyn Write,
+    written: usize,
+}
+
+#[cfg(feature = "tracing")]
+impl<'a> CountingWriter<'a> {
+    fn new<W: Write>(out: &'a mut W) -> Self {
+        Self { out, written: 0 }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Write for CountingWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.written += s.len();
+        self.out.write_str(s)
+    }
+}
+
+/// Writer failing once more bytes than allowed were written through it.
+struct LimitedWriter<'a, W> {
+    out: &'a mut W,
+    remaining: usize,
+    exceeded: bool,
+}
+
+impl<W: Write> Write for LimitedWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self.remaining.checked_sub(s.len()) {
+            Some(remaining) => {
+                self.remaining = remaining;
+                self.out.write_str(s)
+            }
+            None => {
+                self.exceeded = true;
+                Err(fmt::Error)
+            }
+        }
+    }
+}
+
+fn handle_properties<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<()> {
+    let whitespace_pattern = generator.whitespace_pattern;
+    out.write_str(r"\{")?;
+
+    let at_properties = generator.at("properties");
+    let mut properties: Vec<(&String, &Value)> =
+        keywords::required(keywords::get_object(obj, "properties")?, "properties")?
+            .iter()
+            .collect();
+    if generator.sort_properties {
+        properties.sort_by_key(|&(name, _)| name);
+    }
+    let values: Vec<(Generator, &Value)> = properties
+        .iter()
+        .map(|&(name, value)| (at_properties.at(name.as_str()), value))
+        .collect();
+
+    let required_properties = keywords::get_strings(obj, "required")?.unwrap_or_default();
+
+    let is_required: Vec<bool> = properties
+        .iter()
+        .map(|(name, _)| {
+            generator.all_properties_required || required_properties.contains(&name.as_str())
+        })
+        .collect();
+
+    let value_regexes = parallel_regexes(&values)?;
+    let key = |i: usize| -> Result<String> {
+        Ok(format!(
+            r#"{}{whitespace_pattern}:{}"#,
+            property_name(properties[i].0)?,
+            generator.after_colon
+        ))
+    };
+    // the whitespace before the key of property `i`, around property `present`
+    let (first, later) = (generator.inside_brackets, generator.after_comma);
+    let lead = |i: usize, present: usize| -> Cow<str> {
+        if i > present || is_required[..i].contains(&true) {
+            Cow::Borrowed(later)
+        } else if i == 0 || first == later {
+            Cow::Borrowed(first)
+        } else {
+            Cow::Owned(format!("({}|{})", first, later))
+        }
+    };
+
+    if let Some(last_required_pos) = is_required.iter().rposition(|&x| x) {
+        write_around(
+            out,
+            last_required_pos,
+            &is_required,
+            whitespace_pattern,
+            |out, i| {
+                out.write_str(&lead(i, last_required_pos))?;
+                out.write_str(&key(i)?)?;
+                let (at_value, value) = &values[i];
+                let group_name = at_value.group_name();
+                if let Some(name) = &group_name {
+                    write!(out, "(?P<{}>", name)?;
+                }
+                match &value_regexes {
+                    Some(value_regexes) => out.write_str(&value_regexes[i])?,
+                    None => at_value.write(out, value)?,
+                }
+                if group_name.is_some() {
+                    out.write_char(')')?;
+                }
+                Ok(())
+            },
+        )?;
+    } else {
+        // each property appears in several alternatives, so they're generated once up front
+        // and can't be named, as a name can only be used once
+        if generator.named_groups && !values.is_empty() && generator.property_path().is_some() {
+            generator.warn(SchemaError::new(
+                "the object has no required properties, its values are left unnamed",
+            ));
+        }
+        let mut property_subregexes = Vec::with_capacity(values.len());
+        for (i, (at_value, value)) in values.iter().enumerate() {
+            let mut subregex = key(i)?;
+            match &value_regexes {
+                Some(value_regexes) => subregex += &value_regexes[i],
+                None => subregex += &at_value.to_regex(value)?,
+            }
+            property_subregexes.push(subregex);
+        }
+
+        // one alternative for each property, with the others optional around it
+        out.write_char('(')?;
+        for present in 0..property_subregexes.len() {
+            if present > 0 {
+                out.write_char('|')?;
+            }
+            write_around(out, present, &is_required, whitespace_pattern, |out, i| {
+                out.write_str(&lead(i, present))?;
+                Ok(out.write_str(&property_subregexes[i])?)
+            })?;
+        }
+        out.write_str(")?")?;
+    }
+
+    write!(out, "{}\\}}", generator.inside_brackets)?;
+
+    Ok(())
+}
+
+/// Writes the properties of an object around `present`, one of them that's
+/// there: those before it end with a comma and those after start with one,
+/// so leaving out optional ones never leaves a comma dangling.
+fn write_around<W: Write>(
+    out: &mut W,
+    present: usize,
+    is_required: &[bool],
+    whitespace_pattern: &str,
+    mut write_property: impl FnMut(&mut W, usize) -> Result<()>,
+) -> Result<()> {
+    for (i, &required) in is_required.iter().enumerate() {
+        let optional = i != present && !required;
+        if optional {
+            out.write_char('(')?;
+        }
+        if i > present {
+            write!(out, "{},", whitespace_pattern)?;
+        }
+        write_property(out, i)?;
+        if i < present {
+            write!(out, "{},", whitespace_pattern)?;
+        }
+        if optional {
+            out.write_str(")?")?;
+        }
+    }
+    Ok(())
+}
+
+/// Regex matching the property `name` as a JSON string, quotes included.
+/// Quotes, backslashes and control characters in it are escaped the way JSON
+/// does before escaping it for the regex.
+pub(crate) fn property_name(name: &str) -> Result<String> {
+    Ok(escape(&serde_json::to_string(name)?))
+}
+
+/// Below this many siblings, generating them in parallel costs more than it saves.
+#[cfg(feature = "rayon")]
+const PARALLEL_SIBLINGS: usize = 16;
+
+/// The regexes of sibling subschemas, in order, when they're generated in
+/// parallel; `None` when they should be written one after the other instead,
+/// i.e. without the `rayon` feature or when there are only a few of them.
+#[cfg_attr(not(feature = "rayon"), allow(unused_variables))]
+fn parallel_regexes(subschemas: &[(Generator, &Value)]) -> Result<Option<Vec<String>>> {
+    #[cfg(feature = "rayon")]
+    if subschemas.len() >= PARALLEL_SIBLINGS {
+        use rayon::prelude::*;
+
+        return subschemas
+            .par_iter()
+            .map(|(generator, subschema)| generator.to_regex(subschema))
+            .collect::<Result<Vec<_>>>()
+            .map(Some);
+    }
+    Ok(None)
+}
+
+/// Pairs each subschema of an array with its generator, `generator` being
+/// the one of the array.
+fn indexed<'a>(
+    generator: &'a Generator,
+    subschemas: &'a [Value],
+) -> Vec<(Generator<'a>, &'a Value)> {
+    subschemas
+        .iter()
+        .enumerate()
+        .map(|(i, subschema)| (generator.at(i), subschema))
+        .collect()
+}
+
+/// Writes the regex of each subschema, separated by `separator`.
+fn write_joined<W: Write>(
+    out: &mut W,
+    subschemas: &[(Generator, &Value)],
+    separator: &str,
+) -> Result<()> {
+    if let Some(regexes) = parallel_regexes(subschemas)? {
+        for (i, regex) in regexes.iter().enumerate() {
+            if i > 0 {
+                out.write_str(separator)?;
+            }
+            out.write_str(regex)?;
+        }
+        return Ok(());
+    }
+
+    for (i, (generator, subschema)) in subschemas.iter().enumerate() {
+        if i > 0 {
+            out.write_str(separator)?;
+        }
+        generator.write(out, subschema)?;
+    }
+    Ok(())
+}
+
+/// Branches accepting values that start with this many bytes in common are
+/// reported as ambiguous.
+const AMBIGUOUS_PREFIX_LEN: usize = 16;
+
+/// Writes the branches of `keyword` like [`write_joined`], warning about the
+/// pairs of them starting with the same long input when asked to.
+fn write_branches<W: Write>(
+    out: &mut W,
+    keyword: &str,
+    generator: &Generator,
+    branches: &[(Generator, &Value)],
+    separator: &str,
+) -> Result<()> {
+    if !generator.report_ambiguity || branches.len() < 2 {
+        return write_joined(out, branches, separator);
+    }
+
+    let regexes = branch_regexes(branches)?;
+    report_ambiguity(keyword, generator, branches, &regexes)?;
+    out.write_str(&regexes.join(separator))?;
+    Ok(())
+}
+
+/// The regexes of `branches`, in order.
+fn branch_regexes(branches: &[(Generator, &Value)]) -> Result<Vec<String>> {
+    match parallel_regexes(branches)? {
+        Some(regexes) => Ok(regexes),
+        None => branches
+            .iter()
+            .map(|(generator, branch)| generator.to_regex(branch))
+            .collect(),
+    }
+}
+
+/// Warns about the pairs of `branches` of `keyword` whose `regexes` start
+/// with the same long input.
+fn report_ambiguity(
+    keyword: &str,
+    generator: &Generator,
+    branches: &[(Generator, &Value)],
+    regexes: &[String],
+) -> Result<()> {
+    let dfas = regexes
+        .iter()
+        .map(|regex| automaton::build_dfa(regex))
+        .collect::<Result<Vec<_>>>()?;
+    for (i, a) in dfas.iter().enumerate() {
+        for (j, b) in dfas.iter().enumerate().skip(i + 1) {
+            let prefix = automaton::shared_prefix(a, b, AMBIGUOUS_PREFIX_LEN)?;
+            if prefix.len() < AMBIGUOUS_PREFIX_LEN {
+                continue;
+            }
+            generator.warn(
+                SchemaError::new(format!(
+                    "the {} branches at {} and {} both accept values starting with `{}`, which a streaming matcher reads before telling them apart",
+                    keyword,
+                    branches[i].0.pointer(),
+                    branches[j].0.pointer(),
+                    String::from_utf8_lossy(&prefix)
+                ))
+                .with_keyword(keyword)
+                .with_hint("start the branches with something that sets them apart, like a first property with a different `const` in each"),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn handle_all_of<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<()> {
+    let all_of = keywords::required(keywords::get_array(obj, "allOf")?, "allOf")?;
+    // branches refining the same scalar are written as one schema, as
+    // writing them one after the other would match several values
+    let branches: Option<Vec<_>> = all_of.iter().map(Value::as_object).collect();
+    if let Some(branches) = branches {
+        let mut own = obj.clone();
+        own.remove("allOf");
+        if let Some(merged) = merge::merge_scalars(std::iter::once(&own).chain(branches))? {
+            return write_merged(out, generator, merged);
+        }
+    }
+
+    out.write_char('(')?;
+    write_joined(out, &indexed(&generator.at("allOf"), all_of), "")?;
+    out.write_char(')')?;
+    Ok(())
+}
+
+/// Writes the schema merged from the `allOf` branches, with the warnings and
+/// errors about its keywords pointing into the branch each one comes from.
+fn write_merged<W: Write>(out: &mut W, generator: &Generator, merged: merge::Merged) -> Result<()> {
+    let base = generator.pointer();
+    let relocate = |error: &mut SchemaError| {
+        for (keyword, &origin) in &merged.origins {
+            if origin > 0 && error.pointer.as_deref() == Some(&format!("{}/{}", base, keyword)) {
+                error.pointer = Some(format!("{}/allOf/{}/{}", base, origin - 1, keyword));
+            }
+        }
+    };
+
+    let warnings = || {
+        generator
+            .warnings
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    };
+    let reported = warnings().len();
+    let mut result = write_merged_scalars(out, generator, &merged.schema);
+    for warning in warnings().iter_mut().skip(reported) {
+        relocate(warning);
+    }
+    if let Some(error) = result.as_mut().err().and_then(|e| e.downcast_mut()) {
+        relocate(error);
+    }
+    result
+}
+
+/// Writes a merged schema, whose `pattern`s and length the regex of a string
+/// can't hold together, as the intersection of the regexes of each.
+fn write_merged_scalars<W: Write>(
+    out: &mut W,
+    generator: &Generator,
+    schema: &serde_json::Map<String, Value>,
+) -> Result<()> {
+    let patterns: Vec<Value> = match schema.get("pattern") {
+        Some(Value::Array(patterns)) => patterns.clone(),
+        Some(pattern) => vec![pattern.clone()],
+        None => Vec::new(),
+    };
+    let has_length = schema.contains_key("minLength") || schema.contains_key("maxLength");
+    if patterns.len() < 2 && !(has_length && patterns.len() == 1) {
+        return generator.write(out, &Value::Object(schema.clone()));
+    }
+
+    let mut unpatterned = schema.clone();
+    unpatterned.remove("pattern");
+    let mut regexes = Vec::new();
+    if has_length {
+        regexes.push(generator.to_regex(&Value::Object(unpatterned.clone()))?);
+    }
+    let mut unbounded = unpatterned.clone();
+    unbounded.remove("minLength");
+    unbounded.remove("maxLength");
+    for pattern in &patterns {
+        let mut patterned = unbounded.clone();
+        let pattern = match pattern.as_str() {
+            Some(pattern) => Value::from(anchored_pattern(pattern)),
+            None => pattern.clone(),
+        };
+        patterned.insert("pattern".to_string(), pattern);
+        regexes.push(generator.to_regex(&Value::Object(patterned))?);
+    }
+
+    match exclusive::intersection(&regexes)? {
+        Intersection::Regex(regex) => write!(out, "({})", regex)?,
+        Intersection::Empty => {
+            return Err(SchemaError::new(
+                "no string matches every pattern and length of the allOf branches",
+            )
+            .with_keyword("pattern")
+            .in_subschema(&generator.pointer())
+            .into())
+        }
+        Intersection::Infeasible(reason) => {
+            generator.unenforced(
+                SchemaError::new(format!(
+                    "the patterns of the allOf branches aren't enforced together: {}",
+                    reason
+                ))
+                .with_keyword("pattern"),
+            )?;
+            // the length, or else the first pattern
+            let mut fallback = unpatterned;
+            if !has_length {
+                fallback.insert("pattern".to_string(), patterns[0].clone());
+            }
+            generator.write(out, &Value::Object(fallback))?;
+        }
+    }
+    Ok(())
+}
+
+/// `pattern` anchored at both ends, which is how the regex of a pattern
+/// reads it, with any characters of a string for an end it doesn't anchor.
+fn anchored_pattern(pattern: &str) -> String {
+    let any = format!("{}*", types::STRING_INNER);
+    let start = pattern.strip_prefix('^');
+    let inner = start.unwrap_or(pattern);
+    let end = inner.strip_suffix('$').filter(|_| !inner.ends_with("\\$"));
+    format!(
+        "^{}(?:{}){}$",
+        if start.is_some() { "" } else { &any },
+        end.unwrap_or(inner),
+        if end.is_some() { "" } else { &any }
+    )
+}
+
+fn handle_any_of<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<()> {
+    let any_of = keywords::required(keywords::get_array(obj, "anyOf")?, "anyOf")?;
+    // a branch only accepting values of another one adds nothing to the
+    // regex but ambiguity, of which one of two equal branches is kept
+    let any_of_generator = generator.at("anyOf");
+    let branches: Vec<_> = indexed(&any_of_generator, any_of)
+        .into_iter()
+        .enumerate()
+        .filter(|(i, (_, branch))| {
+            !any_of.iter().enumerate().any(|(j, other)| {
+                let subsumed = |a, b| is_subsumed(a, b, generator.integers_are_numbers());
+                j != *i && subsumed(branch, other) && (j < *i || !subsumed(other, branch))
+            })
+        })
+        .map(|(_, branch)| branch)
+        .collect();
+    out.write_char('(')?;
+    write_branches(out, "anyOf", generator, &branches, "|")?;
+    out.write_char(')')?;
+    Ok(())
+}
+
+fn handle_one_of<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<()> {
+    let one_of = keywords::required(keywords::get_array(obj, "oneOf")?, "oneOf")?;
+    let one_of_generator = generator.at("oneOf");
+    let branches = indexed(&one_of_generator, one_of);
+    if generator.exclusive_one_of && branches.len() > 1 {
+        return write_exclusive_one_of(out, generator, &branches);
+    }
+    out.write_str("((?:")?;
+    write_branches(out, "oneOf", generator, &branches, ")|(?:")?;
+    out.write_str("))")?;
+    Ok(())
+}
+
+/// Writes the `oneOf` `branches` so that values matching several of them
+/// don't match, see [`RegexOptions::exclusive_one_of`].
+fn write_exclusive_one_of<W: Write>(
+    out: &mut W,
+    generator: &Generator,
+    branches: &[(Generator, &Value)],
+) -> Result<()> {
+    let regexes = branch_regexes(branches)?;
+    if generator.report_ambiguity {
+        report_ambiguity("oneOf", generator, branches, &regexes)?;
+    }
+
+    let exclusivity =
+        match generator.named_groups && regexes.iter().any(|regex| regex.contains("(?P<")) {
+            true => Exclusivity::Infeasible("the branches have named groups".to_string()),
+            false => exclusive::exclusivity(&regexes)?,
+        };
+    match exclusivity {
+        Exclusivity::Disjoint => {}
+        Exclusivity::Exclusive(regex) => {
+            write!(out, "({})", regex)?;
+            return Ok(());
+        }
+        Exclusivity::Unsatisfiable => {
+            return Err(
+                SchemaError::new("no value matches exactly one oneOf branch")
+                    .with_keyword("oneOf")
+                    .into(),
+            )
+        }
+        Exclusivity::Infeasible(reason) => generator.unenforced(
+            SchemaError::new(format!("oneOf branches may overlap: {}", reason))
+                .with_keyword("oneOf"),
+        )?,
+    }
+    write!(out, "((?:{}))", regexes.join(")|(?:"))?;
+    Ok(())
+}
+
+/// The items of a tuple, under `keyword`: `prefixItems`, or `items` before
+/// 2020-12.
+fn handle_prefix_items<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    keyword: &str,
+    generator: &Generator,
+) -> Result<()> {
+    let prefix_items = keywords::required(keywords::get_array(obj, keyword)?, keyword)?;
+    let inside_brackets = generator.inside_brackets;
+    let comma_split_pattern = format!("{},{}", generator.whitespace_pattern, generator.after_comma);
+    write!(out, r"\[{inside_brackets}")?;
+    write_joined(
+        out,
+        &indexed(&generator.at(keyword), prefix_items),
+        &comma_split_pattern,
+    )?;
+    write!(out, r"{inside_brackets}\]")?;
+    Ok(())
+}
+
+fn handle_enum<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<()> {
+    let enum_values = keywords::required(keywords::get_array(obj, "enum")?, "enum")?;
+    // `const` narrows `enum` down to its value
+    if let Some(const_value) = obj.get("const") {
+        if !enum_values.contains(const_value) {
+            return Err(SchemaError::new(format!(
+                "const {} isn't one of the enum values",
+                brief(const_value)
+            ))
+            .with_keyword("const")
+            .into());
+        }
+        return handle_const(out, obj);
+    }
+
+    // and `type` to the values of its types
+    let types = instance_types(obj)?;
+    let enum_values: Vec<&Value> = enum_values
+        .iter()
+        .filter(|value| types.as_ref().is_none_or(|types| has_type(value, types)))
+        .collect();
+    if enum_values.is_empty() {
+        return Err(SchemaError::new(format!(
+            "none of the enum values has type {}",
+            brief(&obj["type"])
+        ))
+        .with_keyword("enum")
+        .into());
+    }
+
+    let mut choices: Vec<String> = Vec::with_capacity(enum_values.len());
+    // regexes of the numbers in all their forms, with `equivalent_numbers`
+    let mut numbers: Vec<String> = Vec::new();
+    for choice in enum_values {
+        if let Some(number) = choice.as_number().filter(|_| generator.equivalent_numbers) {
+            if let Some(number) = range::equivalent_numbers(number) {
+                if !numbers.contains(&number) {
+                    numbers.push(number);
+                }
+                continue;
+            }
+        }
+        let choice = match choice {
+            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {
+                serde_json::to_string(choice)?
+            }
+            _ => return Err(anyhow!("Unsupported data type in enum: {:?}", choice)),
+        };
+        // merged schemas often repeat values
+        if !choices.contains(&choice) {
+            choices.push(choice);
+        }
+    }
+    if generator.sort_enums {
+        choices.sort_unstable();
+        numbers.sort_unstable();
+    }
+
+    let alternation = match generator.max_alternation_width {
+        Some(width) if choices.len() + numbers.len() > width => {
+            let choices: Vec<&str> = choices.iter().map(String::as_str).collect();
+            let mut branches = factored_branches(&choices, width);
+            branches.extend(numbers);
+            chunked(branches, width)
+        }
+        _ => {
+            let mut alternatives = numbers;
+            if !choices.is_empty() {
+                alternatives.insert(0, literal_alternation(&choices));
+            }
+            alternatives.join("|")
+        }
+    };
+    write!(out, r"({})", alternation)?;
+    Ok(())
+}
+
+/// The branches of a regex matching exactly one of the distinct `choices`,
+/// at most `width` of them per alternation where possible: the choices
+/// starting with the same character are one branch matching what they have
+/// in common once, like `a(b|c)` for `ab` and `ac`, and the rest of them is
+/// factored the same way.
+fn factored_branches(choices: &[&str], width: usize) -> Vec<String> {
+    if choices.len() <= width {
+        return choices.iter().map(|choice| escape(choice)).collect();
+    }
+
+    let mut groups: Vec<Vec<&str>> = Vec::new();
+    for choice in choices {
+        let first = choice.chars().next();
+        match groups
+            .iter_mut()
+            .find(|group| group[0].chars().next() == first)
+        {
+            Some(group) => group.push(choice),
+            None => groups.push(vec![choice]),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|group| {
+            if let [choice] = group[..] {
+                return escape(choice);
+            }
+            let prefix_len = group[1..]
+                .iter()
+                .map(|choice| common_len(group[0].chars(), choice.chars()))
+                .min()
+                .unwrap_or(0);
+            let rests: Vec<&str> = group.iter().map(|choice| &choice[prefix_len..]).collect();
+            format!(
+                "{}({})",
+                escape(&group[0][..prefix_len]),
+                chunked(factored_branches(&rests, width), width)
+            )
+        })
+        .collect()
+}
+
+/// `branches` as an alternation of at most `width` branches, with those
+/// beyond it split into nested groups of `width` branches.
+fn chunked(mut branches: Vec<String>, width: usize) -> String {
+    while branches.len() > width {
+        branches = branches
+            .chunks(width)
+            .map(|chunk| format!("({})", chunk.join("|")))
+            .collect();
+    }
+    branches.join("|")
+}
+
+/// Regex matching exactly one of the distinct `choices`. When the choices
+/// only differ in a single character, like `"a"`, `"b"` and `"c"`, the
+/// differing character is written as a class (`"[a-c]"`) instead of listing
+/// every choice.
+fn literal_alternation(choices: &[String]) -> String {
+    let unique: Vec<&str> = choices.iter().map(String::as_str).collect();
+    if unique.len() < 2 {
+        return unique.iter().map(|choice| escape(choice)).collect();
+    }
+
+    let first = unique[0];
+    let prefix_len = unique[1..]
+        .iter()
+        .map(|choice| common_len(first.chars(), choice.chars()))
+        .min()
+        .unwrap_or(0);
+    let rests: Vec<&str> = unique.iter().map(|choice| &choice[prefix_len..]).collect();
+    let suffix_len = rests[1..]
+        .iter()
+        .map(|rest| common_len(rests[0].chars().rev(), rest.chars().rev()))
+        .min()
+        .unwrap_or(0);
+    let (prefix, suffix) = (
+        &first[..prefix_len],
+        &rests[0][rests[0].len() - suffix_len..],
+    );
+
+    let middles: Vec<&str> = rests
+        .iter()
+        .map(|rest| &rest[..rest.len() - suffix.len()])
+        .collect();
+    if middles.iter().any(|middle| middle.chars().count() > 1) {
+        let choices: Vec<String> = unique.iter().map(|choice| escape(choice)).collect();
+        return choices.join("|");
+    }
+
+    let mut chars: Vec<char> = middles
+        .iter()
+        .filter_map(|middle| middle.chars().next())
+        .collect();
+    chars.sort_unstable();
+    let optional = if chars.len() < middles.len() { "?" } else { "" };
+    format!(
+        "{}{}{}{}",
+        escape(prefix),
+        char_class(&chars),
+        optional,
+        escape(suffix)
+    )
+}
+
+/// Length in bytes of the common start of `a` and `b`.
+fn common_len(a: impl Iterator<Item = char>, b: impl Iterator<Item = char>) -> usize {
+    a.zip(b)
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum()
+}
+
+/// A class matching any of the sorted, distinct `chars`, using ranges for runs.
+fn char_class(chars: &[char]) -> String {
+    let escape_char = |c: char| {
+        if regex_syntax::is_meta_character(c) {
+            format!(r"\{}", c)
+        } else {
+            c.to_string()
+        }
+    };
+    if let [c] = chars {
+        return escape_char(*c);
+    }
+
+    let mut class = String::from("[");
+    let mut i = 0;
+    while i < chars.len() {
+        let mut end = i;
+        while end + 1 < chars.len() && chars[end + 1] as u32 == chars[end] as u32 + 1 {
+            end += 1;
+        }
+        class += &escape_char(chars[i]);
+        if end > i + 1 {
+            class.push('-');
+        }
+        if end > i {
+            class += &escape_char(chars[end]);
+        }
+        i = end + 1;
+    }
+    class.push(']');
+    class
+}
+
+fn handle_const<W: Write>(out: &mut W, obj: &serde_json::Map<String, Value>) -> Result<()> {
+    if let (Some(const_value), Some(types)) = (obj.get("const"), instance_types(obj)?) {
+        if !has_type(const_value, &types) {
+            return Err(SchemaError::new(format!(
+                "const {} doesn't have type {}",
+                brief(const_value),
+                brief(&obj["type"])
+            ))
+            .with_keyword("const")
+            .into());
+        }
+    }
+
+    match obj.get("const") {
+        Some(const_value) => match const_value {
+            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {
+                let json_string = serde_json::to_string(const_value)?;
+                out.write_str(&regex::escape(&json_string))?;
+                Ok(())
+            }
+            _ => Err(anyhow!("Unsupported data type in const: {:?}", const_value)),
+        },
+        None => Err(anyhow!("'const' key not found in object")),
+    }
+}
+
+fn handle_ref<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<()> {
+    let ref_path = keywords::required(keywords::get_str(obj, "$ref")?, "$ref")?;
+    if generator.in_ref(ref_path) {
+        if let Some(call) = generator.call(Rule::Ref(ref_path.to_string())) {
+            out.write_str(&call)?;
+            return Ok(());
+        }
+    }
+    let referenced_schema = generator.ref_target(ref_path)?;
+    generator.at_ref(ref_path).write(out, referenced_schema)
+}
+
+fn resolve_local_ref<'a>(schema: &'a Value, path_parts: &[&str]) -> Result<&'a Value> {
+    let mut current = schema;
+    for &part in path_parts {
+        current = current
+            .get(part)
+            .ok_or_else(|| anyhow!("Invalid reference path: {}", part))?;
+    }
+    Ok(current)
+}
+
+/// Follows local `$ref`s until reaching a schema that isn't a reference.
+pub(crate) fn follow_refs<'a>(mut schema: &'a Value, full_schema: &'a Value) -> Result<&'a Value> {
+    let mut followed = Vec::new();
+    while let Some(ref_path) = schema.get("$ref").and_then(Value::as_str) {
+        if followed.contains(&ref_path) {
+            return Err(anyhow!("The $ref to \"{}\" refers to itself", ref_path));
+        }
+        followed.push(ref_path);
+        let path = ref_path
+            .strip_prefix("#/")
+            .ok_or_else(|| anyhow!("Only local references are supported"))?;
+        let path_parts: Vec<&str> = path.split('/').collect();
+        schema = resolve_local_ref(full_schema, &path_parts)?;
+    }
+    Ok(schema)
+}
+
+fn handle_type<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<()> {
+    let mut types = keywords::required(instance_types(obj)?, "type")?;
+    if let Value::Array(_) = obj["type"] {
+        // a list of types accepts a value of any of them, where integers
+        // are numbers too unless keywords of either type tell them apart
+        if types.contains(&"number")
+            && generator.integers_are_numbers()
+            && !obj
+                .keys()
+                .any(|key| SEPARATE_NUMBER_KEYWORDS.contains(&key.as_str()))
+        {
+            types.retain(|&instance_type| instance_type != "integer");
+        }
+        out.write_char('(')?;
+        for (i, instance_type) in types.iter().enumerate() {
+            if i > 0 {
+                out.write_char('|')?;
+            }
+            handle_instance_type(out, obj, instance_type, generator)?;
+        }
+        out.write_char(')')?;
+        return Ok(());
+    }
+
+    handle_instance_type(out, obj, types[0], generator)
+}
+
+/// The type names of `type`, a single one or a list of them, when it's set.
+fn instance_types(obj: &serde_json::Map<String, Value>) -> Result<Option<Vec<&str>>> {
+    let Some(types) = obj.get("type") else {
+        return Ok(None);
+    };
+    let types = match types {
+        Value::String(instance_type) => Some(vec![instance_type.as_str()]),
+        Value::Array(instance_types) => instance_types.iter().map(Value::as_str).collect(),
+        _ => None,
+    };
+    match types {
+        Some(types) => Ok(Some(types)),
+        None => Err(SchemaError::wrong_type(
+            "type",
+            "a string or an array of strings",
+            &obj["type"],
+        )
+        .with_hint("use a type name like \"string\", or a list of them like [\"string\", \"null\"]")
+        .into()),
+    }
+}
+
+/// Keywords bounding integers and numbers differently, so that a number
+/// schema may not accept every integer the same schema accepts.
+const SEPARATE_NUMBER_KEYWORDS: &[&str] = &[
+    "format",
+    "minDigits",
+    "maxDigits",
+    "minDigitsInteger",
+    "maxDigitsInteger",
+    "minDigitsFraction",
+    "maxDigitsFraction",
+    "minDigitsExponent",
+    "maxDigitsExponent",
+];
+
+/// Whether every value `schema` accepts is accepted by `other` too, as far
+/// as a quick look tells: both are the same, or `schema` is a scalar of a
+/// type `other` accepts any value of, integers being numbers when
+/// `integers_are_numbers`.
+fn is_subsumed(schema: &Value, other: &Value, integers_are_numbers: bool) -> bool {
+    if schema == other {
+        return true;
+    }
+    let (Some(schema), Some(other)) = (schema.as_object(), other.as_object()) else {
+        return false;
+    };
+    // the handlers of these may not keep to `type`
+    let picks_handler = [
+        "properties",
+        "allOf",
+        "anyOf",
+        "oneOf",
+        "prefixItems",
+        "$ref",
+    ];
+    if picks_handler
+        .iter()
+        .any(|&keyword| schema.contains_key(keyword))
+    {
+        return false;
+    }
+    let is_bare = other
+        .keys()
+        .all(|key| key == "type" || merge::ANNOTATIONS.contains(&key.as_str()));
+    let (Ok(Some(types)), Ok(Some(other_types))) = (instance_types(schema), instance_types(other))
+    else {
+        return false;
+    };
+    is_bare
+        && types.iter().all(|instance_type| {
+            ["integer", "number", "boolean", "null"].contains(instance_type)
+                && (other_types.contains(instance_type)
+                    || (*instance_type == "integer"
+                        && integers_are_numbers
+                        && other_types.contains(&"number")))
+        })
+}
+
+/// Whether `value` is an instance of any of `types`. Integers include
+/// numbers without a fraction, like `1.0`.
+fn has_type(value: &Value, types: &[&str]) -> bool {
+    types
+        .iter()
+        .any(|&instance_type| match (instance_type, value) {
+            ("string", Value::String(_))
+            | ("number", Value::Number(_))
+            | ("boolean", Value::Bool(_))
+            | ("null", Value::Null)
+            | ("array", Value::Array(_))
+            | ("object", Value::Object(_)) => true,
+            ("integer", Value::Number(number)) => {
+                number.is_i64()
+                    || number.is_u64()
+                    || number.as_f64().is_some_and(|n| n.fract() == 0.0)
+            }
+            _ => false,
+        })
+}
+
+/// Writes the regex of `obj` for a single type. The handlers never read
+/// `type` themselves, so a list of types reuses `obj` for each of them.
+fn handle_instance_type<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    instance_type: &str,
+    generator: &Generator,
+) -> Result<()> {
+    match instance_type {
+        "string" => handle_types::handle_string_type(out, obj, generator),
+        "number" => handle_types::handle_number_type(out, obj, generator),
+        "integer" => handle_types::handle_integer_type(out, obj, generator),
+        "array" => handle_types::handle_array_type(out, obj, generator),
+        "object" => handle_types::handle_object_type(out, obj, generator),
+        "boolean" => handle_types::handle_boolean_type(out),
+        "null" => handle_types::handle_null_type(out),
+        _ => Err(SchemaError::new(format!("Unsupported type: {}", instance_type))
+            .with_hint("the types are \"string\", \"number\", \"integer\", \"boolean\", \"null\", \"array\" and \"object\"")
+            .into()),
+    }
+}
+
+fn handle_empty_object<W: Write>(out: &mut W, generator: &Generator) -> Result<()> {
+    // JSON Schema Spec: Empty object means unconstrained, any json type is legal
+    let types: Vec<Value> = [
+        "boolean", "null", "number", "integer", "string", "array", "object",
+    ]
+    .into_iter()
+    .filter(|instance_type| generator.unconstrained.allows(instance_type))
+    .map(|instance_type| json!({ "type": instance_type }))
+    .collect();
+    if types.is_empty() {
+        return Err(SchemaError::new("no type is allowed for unconstrained values").into());
+    }
+
+    out.write_char('(')?;
+    // the types aren't part of the schema, so they're located at the empty object
+    let types: Vec<(Generator, &Value)> = types.iter().map(|t| (generator.clone(), t)).collect();
+    write_joined(out, &types, ")|(")?;
+    out.write_char(')')?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "python")]
+    use crate::py_wrapper::python_build_regex_from_schema;
+    use jsonschema::JSONSchema;
+    use regex::Regex;
+    #[cfg(feature = "python")]
+    use regex_syntax::Parser;
+    use serde_json::json;
+
+    use super::*;
+
+    fn is_full_match(regex: &str, text: &str) -> bool {
+        Regex::new(&format!("^(?:{})$", regex))
+            .unwrap()
+            .is_match(text)
+    }
+
+    #[cfg(feature = "python")]
+    fn test_regex(schema: &serde_json::Value) {
+        let schema_str = schema.to_string();
+        let rust_regex =
+            build_regex_from_schema_with_options(&schema_str, &RegexOptions::legacy()).unwrap();
+        let outlines_regex = python_build_regex_from_schema(&schema_str).unwrap();
+
+        // check if regexes even compile
+        Regex::new(&rust_regex).expect("Rust regex is invalid");
+        Regex::new(&outlines_regex).expect("Outlines regex is invalid");
+
+        // compare to outlines
+        assert!(
+            compare_regexes(&rust_regex, &outlines_regex),
+            "Rust and Python outputs are not equivalent for schema: {}\nRust:    {}\nPython:  {}",
+            schema_str,
+            rust_regex,
+            outlines_regex
+        );
+    }
+
+    #[cfg(feature = "python")]
+    fn compare_regexes(a: &str, b: &str) -> bool {
+        let parse_result_a = Parser::new().parse(a);
+        let parse_result_b = Parser::new().parse(b);
+
+        match (parse_result_a, parse_result_b) {
+            (Ok(hir_a), Ok(hir_b)) => hir_a == hir_b,
+            _ => false,
+        }
+    }
+
+    #[cfg(feature = "python")]
+    mod object_tests {
+        use super::*;
+
+        #[test]
+        fn test_object_type_with_name() {
+            let schema = json!({"type": "object", "properties": {"name": {"type": "string"}}});
+            test_regex(&schema);
+        }
+        #[test]
+        fn test_schema0_regex() {
+            let schema = json!({
+                "type": "object",
+                "properties": {
+                    "name": {
+                        "type": "string",
+                        "minLength": 2,
+                        "maxLength": 5
+                    },
+                },
+            });
+
+            test_regex(&schema);
+        }
+
+        #[test]
+        fn test_schema1_regex() {
+            let schema = json!({
+                "type": "object",
+                "properties": {
+                    "name": {
+                        "type": "string",
+                        "minLength": 2,
+                        "maxLength": 5
+                    },
+                },
+            });
+            test_regex(&schema);
+        }
+
+        #[test]
+        fn test_schema2_regex() {
+            let schema = json!({
+                "type": "object",
+                "properties": {
                     "name": {
                         "type": "string"
                     },
@@ -461,298 +2409,1748 @@ mod tests {
             test_regex(&schema);
         }
 
-        #[test]
-        fn test_schema3_regex() {
-            let schema = json!({
-                "type": "object",
-                "properties": {
-                    "flag": {"type": "boolean"},
-                },
-            });
-            test_regex(&schema);
+        #[test]
+        fn test_schema3_regex() {
+            let schema = json!({
+                "type": "object",
+                "properties": {
+                    "flag": {"type": "boolean"},
+                },
+            });
+            test_regex(&schema);
+        }
+
+        // TODO, this fails since the order of the properties is different between rust and python
+        // It's commented out for now but in the future would be nice to be able to test this
+        // #[test]
+        // fn test_schema4_regex() {
+        //     let schema = json!({
+        //         "type": "object",
+        //         "properties": {
+        //             "name": {"type": "string"},
+        //             "flag": {"type": "boolean"},
+        //         },
+        //     });
+        //     test_regex(&schema);
+        // }
+    }
+
+    #[cfg(feature = "python")]
+    mod array_tests {
+        use super::*;
+
+        #[test]
+        fn test_array_with_string() {
+            let schema_string = json!({"type": "array", "items": {"type": "string"}});
+            test_regex(&schema_string);
+        }
+
+        #[test]
+        fn test_array_with_number() {
+            let schema_number = json!({"type": "array", "items": {"type": "number"}});
+            test_regex(&schema_number);
+        }
+
+        #[test]
+        fn test_array_with_integer() {
+            let schema_integer = json!({"type": "array", "items": {"type": "integer"}});
+            test_regex(&schema_integer);
+        }
+
+        #[test]
+        fn test_array_with_boolean() {
+            let schema_boolean = json!({"type": "array", "items": {"type": "boolean"}});
+            test_regex(&schema_boolean);
+        }
+
+        #[test]
+        fn test_array_with_null() {
+            let schema_null = json!({"type": "array", "items": {"type": "null"}});
+            test_regex(&schema_null);
+        }
+    }
+
+    #[cfg(feature = "python")]
+    mod string_tests {
+        use super::*;
+
+        #[test]
+        fn test_string_type() {
+            let schema = json!({"type": "string", "minLength": 2, "maxLength": 5});
+            test_regex(&schema);
+        }
+
+        #[test]
+        fn test_string_type_with_length_constraints() {
+            let schema = json!({"type": "string", "minLength": 2, "maxLength": 5});
+            test_regex(&schema);
+        }
+
+        #[test]
+        fn test_string_type_with_pattern() {
+            let schema = json!({"type": "string", "pattern": "^[a-zA-Z0-9]+$"});
+            test_regex(&schema);
+        }
+
+        #[test]
+        fn test_string_type_with_date_time_format() {
+            let schema = json!({"type": "string", "format": "date-time"});
+            test_regex(&schema);
+        }
+
+        #[test]
+        fn test_string_type_with_date_format() {
+            let schema = json!({"type": "string", "format": "date"});
+            test_regex(&schema);
+        }
+
+        #[test]
+        fn test_string_type_with_time_format() {
+            let schema = json!({"type": "string", "format": "time"});
+            test_regex(&schema);
+        }
+
+        #[test]
+        fn test_string_type_with_uuid_format() {
+            let schema = json!({"type": "string", "format": "uuid"});
+            test_regex(&schema);
+        }
+
+        #[test]
+        fn test_string_type_with_pattern_and_length_constraints() {
+            let schema = json!({
+                "type": "string",
+                "pattern": "^[A-Z]+$",
+                "minLength": 3,
+                "maxLength": 10
+            });
+            test_regex(&schema);
+        }
+
+        #[test]
+        fn test_string_type_with_format_and_length_constraints() {
+            let schema = json!({
+                "type": "string",
+                "format": "date-time",
+                "minLength": 20,
+                "maxLength": 30
+            });
+            test_regex(&schema);
+        }
+    }
+    #[cfg(feature = "python")]
+    mod number_tests {
+        use super::*;
+
+        #[test]
+        fn test_number_type() {
+            let schema = json!({"type": "number"});
+            test_regex(&schema);
+        }
+        #[test]
+        fn test_number_with_int_bounds_type() {
+            let schema = json!({"type": "number", "minDigitsInteger": 12});
+            test_regex(&schema);
+        }
+        #[test]
+        fn test_number_with_fraction_bounds() {
+            let schema = json!({"type": "number", "minDigitsFraction": 2, "maxDigitsFraction": 4});
+            test_regex(&schema);
+        }
+        #[test]
+        fn test_number_with_exponent_bounds() {
+            let schema = json!({"type": "number", "minDigitsExponent": 1, "maxDigitsExponent": 3});
+            test_regex(&schema);
+        }
+    }
+
+    #[cfg(feature = "python")]
+    mod integer_tests {
+        use super::*;
+
+        #[test]
+        fn test_integer_type() {
+            let schema = json!({"type": "integer"});
+            test_regex(&schema);
+        }
+
+        #[test]
+        fn test_integer_with_low_bound() {
+            let schema = json!({"type": "integer", "minDigits": 1});
+            test_regex(&schema);
+        }
+
+        // TODO, this might be related to a bug in outlines, commented out for now
+        // #[test]
+        // fn test_integer_with_high_bound() {
+        //     let schema = json!({"type": "integer", "maxDigits": 10});
+        //     test_regex(&schema);
+        // }
+
+        #[test]
+        fn test_integer_with_low_and_high_bound() {
+            let schema = json!({"type": "integer", "minDigits": 1, "maxDigits": 10});
+            test_regex(&schema);
+        }
+    }
+
+    #[cfg(feature = "python")]
+    mod simple_tests {
+        use super::*;
+
+        #[test]
+        fn test_boolean_type() {
+            let schema = json!({"type": "boolean"});
+            test_regex(&schema);
+        }
+        #[test]
+        fn test_null_type() {
+            let schema = json!({"type": "null"});
+            test_regex(&schema);
+        }
+        #[test]
+        fn test_enum() {
+            let schema = json!({
+                "enum": ["red", "green", "blue", 42, true, null]
+            });
+            test_regex(&schema);
+        }
+        #[test]
+        fn test_const() {
+            let schema = json!({
+                "const": "hello world"
+            });
+            test_regex(&schema);
+        }
+        #[test]
+        fn test_prefix_items() {
+            let schema = json!({
+                "prefixItems": [
+                    { "type": "integer" },
+                    { "type": "string" },
+                    { "type": "boolean" }
+                ]
+            });
+            test_regex(&schema);
+        }
+    }
+
+    #[cfg(feature = "python")]
+    mod ref_tests {
+        use super::*;
+
+        #[test]
+        fn test_ref() {
+            let schema = json!({
+                "type": "object",
+                "properties": {
+                    "flag": {
+                        "$ref": "#/definitions/flag"
+                    }
+                },
+                "definitions": {
+                    "flag": {
+                        "type": "boolean"
+                    }
+                }
+            });
+            test_regex(&schema);
+        }
+        #[test]
+        fn test_multiple_refs() {
+            let schema = json!({
+                "type": "object",
+                "properties": {
+                    "manager": { "$ref": "#/definitions/employee" },
+                    "subordinate": { "$ref": "#/definitions/employee" }
+                },
+                "required": ["manager", "subordinate"],
+                "definitions": {
+                    "employee": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string" },
+                            "age": { "type": "integer" }
+                        },
+                        "required": ["name", "age"]
+                    }
+                }
+            });
+            test_regex(&schema);
+        }
+    }
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_nested_ref() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "employee": { "$ref": "#/definitions/employee" }
+            },
+            "definitions": {
+                "employee": {
+                    "type": "object",
+                    "properties": {
+                        "person": { "$ref": "#/definitions/person" },
+                        "position": { "type": "string" }
+                    },
+                    "required": ["person", "position"]
+                },
+                "person": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "age": { "type": "integer" }
+                    },
+                    "required": ["name", "age"]
+                }
+            }
+        });
+        test_regex(&schema);
+    }
+    mod array_of_tests {
+        use super::*;
+
+        #[test]
+        fn test_array_of_objects() {
+            let schema = json!({
+                "type": "object",
+                "properties": {"id": {"type": "integer"}},
+                "required": ["id"]
+            });
+            let regex =
+                build_regex_for_array_of(&schema.to_string(), 1, Some(2), Some("")).unwrap();
+
+            assert!(is_full_match(&regex, r#"[{"id":1}]"#));
+            assert!(is_full_match(&regex, r#"[{"id":1},{"id":2}]"#));
+            assert!(!is_full_match(&regex, "[]"));
+            assert!(!is_full_match(&regex, r#"[{"id":1},{"id":2},{"id":3}]"#));
+        }
+
+        #[test]
+        fn test_array_of_zero_items() {
+            let schema = json!({"type": "integer"});
+            let regex =
+                build_regex_for_array_of(&schema.to_string(), 0, Some(0), Some("")).unwrap();
+
+            assert!(is_full_match(&regex, "[]"));
+            assert!(!is_full_match(&regex, "[1]"));
+        }
+
+        #[test]
+        fn test_json_lines() {
+            let schema = json!({
+                "type": "object",
+                "properties": {"id": {"type": "integer"}},
+                "required": ["id"]
+            });
+            let regex = build_regex_for_json_lines(&schema.to_string(), 2, None, Some("")).unwrap();
+
+            assert!(is_full_match(&regex, "{\"id\":1}\n{\"id\":2}"));
+            assert!(is_full_match(
+                &regex,
+                "{\"id\":1}\n{\"id\":2}\n{\"id\":3}\n"
+            ));
+            assert!(!is_full_match(&regex, "{\"id\":1}"));
+            assert!(!is_full_match(&regex, "{\"id\":1}{\"id\":2}"));
+        }
+    }
+
+    #[test]
+    fn test_type_list() {
+        let schema = json!({"type": ["string", "null"], "maxLength": 2, "minLength": 0});
+        let regex = build_regex_from_schema(&schema.to_string(), None).unwrap();
+
+        assert!(is_full_match(&regex, r#""ab""#));
+        assert!(is_full_match(&regex, "null"));
+        assert!(!is_full_match(&regex, r#""abc""#));
+        assert!(!is_full_match(&regex, "1"));
+    }
+
+    #[test]
+    fn test_whitespace_pattern() {
+        let schema = json!({"type": "array", "items": {"type": "integer"}}).to_string();
+        let regex = build_regex_from_schema(&schema, Some(r"\n|\t")).unwrap();
+        assert!(is_full_match(&regex, "[\n1,\t2\t]"));
+        assert!(!is_full_match(&regex, "[\n1,\t\t2]"));
+
+        assert!(build_regex_from_schema(&schema, Some("[ ")).is_err());
+        let error = build_regex_from_schema(&schema, Some("( )*")).unwrap_err();
+        assert!(error.to_string().contains("capture group"));
+        assert!(validate_whitespace_pattern("^ ").is_err());
+        assert!(validate_whitespace_pattern("(?: |\n)*").is_ok());
+        assert!(matches!(
+            resolve_whitespace_pattern(None).unwrap(),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_enum_folding() {
+        let regex = |values: Value| {
+            let schema = json!({ "enum": values }).to_string();
+            build_regex_from_schema(&schema, None).unwrap()
+        };
+        assert_eq!(regex(json!(["a", "b", "c", "e"])), r#"(?:"[a-ce]")"#);
+        assert_eq!(regex(json!([1, 2, 3, 4])), "(?:[1-4])");
+        assert_eq!(regex(json!(["ab", "abc", "abd", "ab"])), r#"(?:"ab[cd]?")"#);
+        assert_eq!(regex(json!(["-", "."])), r#"(?:"[\-\.]")"#);
+        assert_eq!(regex(json!(["red", "blue", 1])), r#"(?:"red"|"blue"|1)"#);
+
+        let folded = regex(json!(["x", "é", "y"]));
+        assert!(is_full_match(&folded, r#""é""#));
+        assert!(!is_full_match(&folded, r#""z""#));
+    }
+
+    #[test]
+    fn test_enum_order() {
+        let regex = |values: Value, sort_enums| {
+            let options = RegexOptions {
+                sort_enums,
+                ..RegexOptions::default()
+            };
+            let schema = json!({ "enum": values }).to_string();
+            build_regex_from_schema_with_options(&schema, &options).unwrap()
+        };
+        let values = json!(["red", null, "blue", "red", 1, null]);
+        assert_eq!(regex(values.clone(), false), r#"(?:"red"|null|"blue"|1)"#);
+        assert_eq!(regex(values, true), r#"(?:"blue"|"red"|1|null)"#);
+        assert_eq!(
+            regex(json!([3, "a", 1]), true),
+            regex(json!([1, 3, "a", 3]), true)
+        );
+    }
+
+    #[test]
+    fn test_max_alternation_width() {
+        let build = |values: Value, width| {
+            let options = RegexOptions {
+                max_alternation_width: Some(width),
+                ..RegexOptions::default()
+            };
+            let schema = json!({ "enum": values }).to_string();
+            build_regex_from_schema_with_options(&schema, &options)
+        };
+        assert_eq!(
+            build(json!(["red", "rose", "blue", 1, 2, null]), 2).unwrap(),
+            r#"(?:(?:"(?:r(?:ed"|ose")|blue")|1)|(?:2|null))"#
+        );
+        assert_eq!(
+            build(json!(["red", "blue"]), 2).unwrap(),
+            r#"(?:"red"|"blue")"#
+        );
+
+        let values: Vec<String> = (0..100).map(|i| format!("value{}", i * 7)).collect();
+        let regex = build(json!(values), 4).unwrap();
+        for value in &values {
+            assert!(is_full_match(&regex, &format!("\"{}\"", value)));
+        }
+        assert!(!is_full_match(&regex, r#""value1""#));
+        assert!(!is_full_match(&regex, r#""value""#));
+
+        let error = build(json!(["a"]), 1).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "max_alternation_width must be at least 2"
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_siblings_keep_their_order() {
+        let consts: Vec<Value> = (0..40).map(|i| json!({ "const": i })).collect();
+        let schema = json!({ "anyOf": consts });
+        let expected: Vec<String> = (0..40).map(|i| i.to_string()).collect();
+        assert_eq!(
+            to_regex(&schema, None, &schema).unwrap(),
+            format!("({})", expected.join("|"))
+        );
+
+        let properties: serde_json::Map<String, Value> = (0..40)
+            .map(|i| (format!("p{:02}", i), json!({ "const": i })))
+            .collect();
+        let schema = json!({ "properties": properties, "required": ["p00"] });
+        let regex = build_regex_from_schema(&schema.to_string(), Some("")).unwrap();
+        assert!(regex.starts_with(r#"\{"p00":0(?:,"p01":1)?(?:,"p02":2)?"#));
+    }
+
+    #[test]
+    fn test_property_order() {
+        let schema =
+            r#"{"properties": {"b": {"const": 1}, "a": {"const": 2}}, "required": ["a", "b"]}"#;
+        let sorted = r#"\{"a":2,"b":1\}"#;
+        let schema_order = r#"\{"b":1,"a":2\}"#;
+
+        let regex = build_regex_from_schema(schema, Some("")).unwrap();
+        if cfg!(feature = "preserve_order") {
+            assert_eq!(regex, schema_order);
+        } else {
+            assert_eq!(regex, sorted);
+        }
+
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            sort_properties: true,
+            ..RegexOptions::default()
+        };
+        for _ in 0..3 {
+            assert_eq!(
+                build_regex_from_schema_with_options(schema, &options).unwrap(),
+                sorted
+            );
         }
+    }
 
-        // TODO, this fails since the order of the properties is different between rust and python
-        // It's commented out for now but in the future would be nice to be able to test this
-        // #[test]
-        // fn test_schema4_regex() {
-        //     let schema = json!({
-        //         "type": "object",
-        //         "properties": {
-        //             "name": {"type": "string"},
-        //             "flag": {"type": "boolean"},
-        //         },
-        //     });
-        //     test_regex(&schema);
-        // }
+    #[test]
+    fn test_openapi_string_formats() {
+        let build = |format: &str, strict| {
+            let schema = json!({"type": "string", "format": format}).to_string();
+            let options = RegexOptions {
+                strict,
+                ..RegexOptions::default()
+            };
+            build_regex_from_schema_with_warnings(&schema, &options)
+        };
+
+        let (byte, warnings) = build("byte", true).unwrap();
+        assert!(warnings.is_empty());
+        for text in [r#""""#, r#""aGVsbG8=""#, r#""aGk=""#, r#""aGV5""#] {
+            assert!(is_full_match(&byte, text), "{}", text);
+        }
+        for text in [r#""aGVsbG8""#, r#""a===""#, r#""aG!5""#] {
+            assert!(!is_full_match(&byte, text), "{}", text);
+        }
+
+        for format in ["binary", "password"] {
+            let (regex, warnings) = build(format, false).unwrap();
+            assert_eq!(regex, non_capturing(types::STRING));
+            assert_eq!(warnings[0].pointer.as_deref(), Some("/format"));
+            assert!(build(format, true).is_err());
+        }
     }
 
-    mod array_tests {
-        use super::*;
+    #[test]
+    fn test_rfc_string_formats() {
+        use crate::automaton::{validate, Validation};
 
-        #[test]
-        fn test_array_with_string() {
-            let schema_string = json!({"type": "array", "items": {"type": "string"}});
-            test_regex(&schema_string);
+        let cases = [
+            (
+                "iri",
+                &[
+                    r#""https://例え.jp/パス?q=1#frag""#,
+                    r#""urn:isbn:0451450523""#,
+                ][..],
+                &[r#""no scheme""#, r#""http://a b""#, r#""1http:x""#][..],
+            ),
+            (
+                "uri-template",
+                &[
+                    r#""/users/{id}""#,
+                    r#""http://example.com/search{?q,lang}""#,
+                    r#""{+path:6}/here{/list*}{#section}""#,
+                ][..],
+                &[
+                    r#""/users/{}""#,
+                    r#""/users/{id""#,
+                    r#""{a b}""#,
+                    r#""{x:0}""#,
+                ][..],
+            ),
+            (
+                "idn-hostname",
+                &[
+                    r#""bücher.example""#,
+                    r#""xn--bcher-kva.de""#,
+                    r#""例え.テスト""#,
+                ][..],
+                &[r#""-a.com""#, r#""a-.com""#, r#""a..com""#, r#""a_b.com""#][..],
+            ),
+        ];
+        for (format, valid, invalid) in cases {
+            let schema = json!({"type": "string", "format": format}).to_string();
+            let regex = build_regex_from_schema(&schema, None).unwrap();
+            for text in valid {
+                let validation = validate(&regex, text.as_bytes()).unwrap();
+                assert_eq!(validation, Validation::Match, "{} {}", format, text);
+            }
+            for text in invalid {
+                let validation = validate(&regex, text.as_bytes()).unwrap();
+                assert_ne!(validation, Validation::Match, "{} {}", format, text);
+            }
         }
+    }
 
-        #[test]
-        fn test_array_with_number() {
-            let schema_number = json!({"type": "array", "items": {"type": "number"}});
-            test_regex(&schema_number);
+    #[test]
+    fn test_numeric_formats() {
+        let options = RegexOptions {
+            numeric_formats: true,
+            ..RegexOptions::default()
+        };
+        let build = |schema: Value| {
+            build_regex_from_schema_with_options(&schema.to_string(), &options).unwrap()
+        };
+
+        let int32 = build(json!({"type": "integer", "format": "int32"}));
+        for text in ["0", "-0", "7", "2147483647", "-2147483648", "1999999999"] {
+            assert!(is_full_match(&int32, text), "{}", text);
         }
+        for text in [
+            "2147483648",
+            "-2147483649",
+            "2150000000",
+            "10000000000",
+            "01",
+        ] {
+            assert!(!is_full_match(&int32, text), "{}", text);
+        }
+        let int64 = build(json!({"type": "integer", "format": "int64"}));
+        assert!(is_full_match(&int64, "-9223372036854775808"));
+        assert!(!is_full_match(&int64, "9223372036854775808"));
 
-        #[test]
-        fn test_array_with_integer() {
-            let schema_integer = json!({"type": "array", "items": {"type": "integer"}});
-            test_regex(&schema_integer);
+        let float = build(json!({"type": "number", "format": "float"}));
+        assert!(is_full_match(&float, "-3.4e+38"));
+        assert!(!is_full_match(&float, "1e100"));
+        assert!(is_full_match(
+            &build(json!({"type": "number", "format": "double"})),
+            "1.7E+308"
+        ));
+
+        // without the option, formats stay annotations
+        let schema = json!({"type": "integer", "format": "int32"}).to_string();
+        let regex = build_regex_from_schema(&schema, None).unwrap();
+        assert!(is_full_match(&regex, "99999999999"));
+    }
+
+    #[test]
+    fn test_pointer() {
+        let schema = json!({});
+        let generator = Generator::new("", &schema);
+        assert_eq!(generator.pointer(), "");
+
+        let properties = generator.at("properties");
+        let property = properties.at("a/b~c");
+        assert_eq!(
+            property.at("anyOf").at(1).pointer(),
+            "/properties/a~1b~0c/anyOf/1"
+        );
+        // a reference continues from its target
+        let target = property.at_ref("#/$defs/item");
+        assert_eq!(target.at("items").pointer(), "/$defs/item/items");
+    }
+
+    #[test]
+    fn test_wrong_typed_keywords() {
+        let cases = [
+            (
+                json!({"type": 5}),
+                "keyword 'type' expected a string or an array of strings, got 5 at /type",
+            ),
+            (
+                json!({"type": ["string", 3]}),
+                r#"keyword 'type' expected a string or an array of strings, got ["string",3] at /type"#,
+            ),
+            (
+                json!({"properties": {"a": {"type": "string", "minLength": "3"}}}),
+                r#"keyword 'minLength' expected a non-negative integer, got "3" at /properties/a/minLength"#,
+            ),
+            (
+                json!({"properties": {}, "required": [1]}),
+                "keyword 'required' expected an array of strings, got [1] at /required",
+            ),
+            (
+                json!({"anyOf": {"type": "null"}}),
+                r#"keyword 'anyOf' expected an array, got {"type":"null"} at /anyOf"#,
+            ),
+            (
+                json!({"type": "array", "items": 3}),
+                "Invalid JSON Schema: expected an object, got 3 at /items",
+            ),
+        ];
+        // the metaschema would catch most of these, so they go around it
+        for (schema, message) in cases {
+            let error = to_regex(&schema, None, &schema).unwrap_err();
+            assert_eq!(error.to_string(), message);
         }
+    }
 
-        #[test]
-        fn test_array_with_boolean() {
-            let schema_boolean = json!({"type": "array", "items": {"type": "boolean"}});
-            test_regex(&schema_boolean);
+    #[test]
+    fn test_size_limits() {
+        let build = |schema: &Value, max_regex_len, max_nodes| {
+            let options = RegexOptions {
+                max_regex_len,
+                max_nodes,
+                ..RegexOptions::default()
+            };
+            build_regex_from_schema_with_options(&schema.to_string(), &options)
+        };
+
+        let schema = json!({"type": "boolean"});
+        assert_eq!(build(&schema, Some(14), None).unwrap(), "(?:true|false)");
+        let error = build(&schema, Some(13), None).unwrap_err();
+        assert!(error.to_string().contains("max_regex_len"), "{}", error);
+
+        // fragments generated up front are held to the limit too
+        let schema = json!({"type": "array", "items": {"enum": ["red", "green", "blue"]}});
+        let error = build(&schema, Some(10), None).unwrap_err();
+        assert!(error.to_string().contains("max_regex_len"), "{}", error);
+
+        // every level references the next one twice, doubling the work
+        let schema = json!({
+            "$defs": {
+                "a": {"prefixItems": [{"$ref": "#/$defs/b"}, {"$ref": "#/$defs/b"}]},
+                "b": {"prefixItems": [{"$ref": "#/$defs/c"}, {"$ref": "#/$defs/c"}]},
+                "c": {"type": "integer"}
+            },
+            "$ref": "#/$defs/a"
+        });
+        assert!(build(&schema, None, Some(14)).is_ok());
+        let error = build(&schema, None, Some(13)).unwrap_err();
+        assert!(error.to_string().contains("max_nodes"), "{}", error);
+    }
+
+    #[test]
+    fn test_optional_properties() {
+        let names = ["a", "b", "c", "d"];
+        // every mix of required and optional properties
+        for required_mask in 0..1 << names.len() {
+            let required: Vec<&str> = (0..names.len())
+                .filter(|i| required_mask & (1 << i) != 0)
+                .map(|i| names[i])
+                .collect();
+            let properties: serde_json::Map<String, Value> = names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.to_string(), json!({ "const": i })))
+                .collect();
+            let schema = json!({"type": "object", "properties": properties, "required": required});
+            let regex = build_regex_from_schema(&schema.to_string(), Some(" ?")).unwrap();
+            let validator = JSONSchema::compile(&schema).unwrap();
+
+            // against every subset of the properties, in schema order
+            for present_mask in 0..1 << names.len() {
+                let present: Vec<usize> = (0..names.len())
+                    .filter(|i| present_mask & (1 << i) != 0)
+                    .collect();
+                let members: Vec<String> = present
+                    .iter()
+                    .map(|&i| format!(r#""{}": {}"#, names[i], i))
+                    .collect();
+                let instance = format!("{{{}}}", members.join(", "));
+                let value: Value = serde_json::from_str(&instance).unwrap();
+                assert_eq!(
+                    is_full_match(&regex, &instance),
+                    validator.is_valid(&value),
+                    "{} with {}",
+                    schema,
+                    instance
+                );
+            }
         }
+    }
 
-        #[test]
-        fn test_array_with_null() {
-            let schema_null = json!({"type": "array", "items": {"type": "null"}});
-            test_regex(&schema_null);
+    #[test]
+    fn test_all_properties_required() {
+        let schema = json!({
+            "properties": {"a": {"const": 1}, "b": {"const": 2}},
+            "required": ["a"]
+        })
+        .to_string();
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            treat_all_properties_required: true,
+            ..RegexOptions::default()
+        };
+        let regex = build_regex_from_schema_with_options(&schema, &options).unwrap();
+        assert_eq!(regex, r#"\{"a":1,"b":2\}"#);
+    }
+
+    #[test]
+    fn test_property_names() {
+        for name in [r#"a"b"#, "π", r"a\b", "a.b*", "tab\t"] {
+            let schema = json!({"properties": {name: {"const": 1}}, "required": [name]});
+            let regex = build_regex_from_schema(&schema.to_string(), Some("")).unwrap();
+            let instance = json!({name: 1}).to_string();
+            assert!(is_full_match(&regex, &instance), "{} {}", regex, instance);
+            assert!(!is_full_match(&regex, r#"{"ab":1}"#), "{}", regex);
+        }
+    }
+
+    #[test]
+    fn test_keyword_combinations() {
+        let regex = |schema: Value| to_regex(&schema, None, &schema);
+
+        assert_eq!(
+            regex(json!({"type": "string", "enum": ["a", 1, null, "b"]})).unwrap(),
+            r#"("[ab]")"#
+        );
+        assert_eq!(
+            regex(json!({"type": ["integer", "null"], "enum": [1, 2.0, 2.5, "3", null]})).unwrap(),
+            r"(1|2\.0|null)"
+        );
+        assert_eq!(
+            regex(json!({"enum": ["a", "b"], "const": "b"})).unwrap(),
+            r#""b""#
+        );
+        assert_eq!(regex(json!({"type": "integer", "const": 3})).unwrap(), "3");
+
+        let errors = [
+            (
+                json!({"type": "string", "enum": [1, 2]}),
+                r#"none of the enum values has type "string" at /enum"#,
+            ),
+            (
+                json!({"enum": ["a", "b"], "const": "c"}),
+                r#"const "c" isn't one of the enum values at /const"#,
+            ),
+            (
+                json!({"type": "boolean", "const": "true"}),
+                r#"const "true" doesn't have type "boolean" at /const"#,
+            ),
+        ];
+        for (schema, message) in errors {
+            assert_eq!(regex(schema).unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_top_level_value() {
+        let build = |padded, anchored| {
+            let options = RegexOptions {
+                whitespace_pattern: Some(" *".to_string()),
+                padded,
+                anchored,
+                ..RegexOptions::default()
+            };
+            let schema = json!({"type": "array", "items": {"enum": ["a", "b"]}, "maxItems": 2});
+            build_regex_from_schema_with_options(&schema.to_string(), &options).unwrap()
+        };
+
+        let bare = build(false, false);
+        assert!(is_full_match(&bare, r#"["a", "b"]"#));
+        assert!(!is_full_match(&bare, r#" ["a"] "#));
+
+        let padded = build(true, false);
+        assert!(is_full_match(&padded, r#"  ["a", "b"] "#));
+        assert!(is_full_match(&padded, "[]"));
+
+        let anchored = build(true, true);
+        assert!(anchored.starts_with("^(?:") && anchored.ends_with(")$"));
+        let anchored = Regex::new(&anchored).unwrap();
+        assert!(anchored.is_match(r#" ["b"]"#));
+        assert!(!anchored.is_match(r#"x["b"]"#));
+
+        let simplified = RegexOptions {
+            simplify: true,
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let regex = build_regex_from_schema_with_options(r#"{"const": 1}"#, &simplified).unwrap();
+        assert_eq!(regex, "^(?:1)$");
+    }
+
+    #[test]
+    fn test_items_bounds() {
+        let bounds = [
+            (None, None),
+            (Some(0), None),
+            (Some(1), None),
+            (Some(3), None),
+            (None, Some(0)),
+            (Some(0), Some(0)),
+            (None, Some(1)),
+            (Some(1), Some(1)),
+            (Some(2), Some(2)),
+            (Some(1), Some(3)),
+            (Some(0), Some(2)),
+        ];
+        for (min_items, max_items) in bounds {
+            let mut schema = json!({"type": "array", "items": {"type": "integer"}});
+            if let Some(min_items) = min_items {
+                schema["minItems"] = json!(min_items);
+            }
+            if let Some(max_items) = max_items {
+                schema["maxItems"] = json!(max_items);
+            }
+            let regex = build_regex_from_schema(&schema.to_string(), Some("")).unwrap();
+            let validator = JSONSchema::compile(&schema).unwrap();
+
+            for len in 0..5 {
+                let array = Value::Array((0..len).map(|i| json!(i)).collect());
+                assert_eq!(
+                    is_full_match(&regex, &array.to_string()),
+                    validator.is_valid(&array),
+                    "{} with {}",
+                    schema,
+                    array
+                );
+            }
+        }
+
+        // a single item isn't written twice
+        let schema = json!({"type": "array", "items": {"const": 7}, "minItems": 1, "maxItems": 1});
+        assert_eq!(
+            build_regex_from_schema(&schema.to_string(), Some("")).unwrap(),
+            r"\[(?:7)\]"
+        );
+        let error = build_regex_from_schema(
+            &json!({"type": "array", "minItems": 3, "maxItems": 2}).to_string(),
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "maxItems must be greater than or equal to minItems at /maxItems"
+        );
+    }
+
+    #[test]
+    fn test_unique_items() {
+        let build = |schema: Value, strict| {
+            let options = RegexOptions {
+                whitespace_pattern: Some(String::new()),
+                strict,
+                ..RegexOptions::default()
+            };
+            build_regex_from_schema_with_warnings(&schema.to_string(), &options)
+        };
+
+        let schema = json!({
+            "type": "array",
+            "items": {"enum": ["a", "b", "c", "a"]},
+            "uniqueItems": true,
+            "minItems": 1
+        });
+        let (regex, warnings) = build(schema, true).unwrap();
+        assert!(warnings.is_empty());
+        for text in [r#"["a"]"#, r#"["c","a"]"#, r#"["b","c","a"]"#] {
+            assert!(is_full_match(&regex, text), "{}", text);
+        }
+        for text in ["[]", r#"["a","a"]"#, r#"["a","b","a"]"#] {
+            assert!(!is_full_match(&regex, text), "{}", text);
+        }
+
+        let schema = json!({
+            "properties": {"tags": {"type": "array", "items": {"type": "string"}, "uniqueItems": true}}
+        });
+        let (_, warnings) = build(schema.clone(), false).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].pointer.as_deref(),
+            Some("/properties/tags/uniqueItems")
+        );
+        let error = build(schema, true).unwrap_err();
+        assert_eq!(SchemaError::find(&error), Some(&warnings[0]));
+
+        let schema =
+            json!({"type": "array", "items": {"const": 1}, "uniqueItems": true, "minItems": 2});
+        assert!(build(schema, false).is_err());
+
+        // the values of enums are left out when the rest of items rejects them
+        let schema = json!({
+            "type": "array",
+            "items": {"type": "string", "enum": ["a", 1]},
+            "uniqueItems": true
+        });
+        let (regex, _) = build(schema, true).unwrap();
+        assert!(is_full_match(&regex, r#"["a"]"#));
+        assert!(!is_full_match(&regex, "[1]"));
+
+        // numbers are compared by value
+        let schema = json!({"type": "array", "items": {"enum": [1, 1.0, 2]}, "uniqueItems": true});
+        let (regex, _) = build(schema, true).unwrap();
+        for text in ["[1]", "[1.0,2]", "[2,1]"] {
+            assert!(is_full_match(&regex, text), "{}", text);
+        }
+        for text in ["[1,1.0]", "[1.0,1]", "[1,2,1.0]"] {
+            assert!(!is_full_match(&regex, text), "{}", text);
+        }
+
+        let schema = json!({"type": "array", "items": {"type": "boolean"}, "uniqueItems": true});
+        let (regex, warnings) = build(schema, true).unwrap();
+        assert!(warnings.is_empty());
+        assert!(is_full_match(&regex, "[true,false]"));
+        assert!(!is_full_match(&regex, "[true,true]"));
+
+        let schema = json!({
+            "type": "array",
+            "items": {"type": ["null", "boolean"]},
+            "uniqueItems": true
+        });
+        let (regex, _) = build(schema, true).unwrap();
+        assert!(is_full_match(&regex, "[false,null,true]"));
+        assert!(!is_full_match(&regex, "[null,null]"));
+    }
+
+    #[test]
+    fn test_numeric_bounds() {
+        let build = |schema: Value| build_regex_from_schema(&schema.to_string(), None);
+
+        let regex = build(json!({"type": "number", "minimum": 0, "maximum": 1})).unwrap();
+        for text in ["0", "0.25", "1", "1.000", "0.999999"] {
+            assert!(is_full_match(&regex, text), "{}", text);
+        }
+        for text in ["-0.5", "1.5", "1.0001", "2", "1e-1"] {
+            assert!(!is_full_match(&regex, text), "{}", text);
+        }
+
+        let regex =
+            build(json!({"type": "integer", "exclusiveMinimum": -3, "maximum": 250})).unwrap();
+        for text in ["-2", "0", "99", "250"] {
+            assert!(is_full_match(&regex, text), "{}", text);
+        }
+        for text in ["-3", "251", "1000", "1.5"] {
+            assert!(!is_full_match(&regex, text), "{}", text);
+        }
+        // draft 4 makes the minimum exclusive with a boolean
+        let regex = build(json!({
+            "$schema": "http://json-schema.org/draft-04/schema#",
+            "type": "number", "minimum": 0.5, "exclusiveMinimum": true
+        }))
+        .unwrap();
+        assert!(!is_full_match(&regex, "0.5"));
+        assert!(is_full_match(&regex, "0.51"));
+
+        // with the integer formats, the tighter of both bounds is used
+        let options = RegexOptions {
+            numeric_formats: true,
+            ..RegexOptions::default()
+        };
+        let schema = json!({"type": "integer", "format": "int32", "minimum": 0});
+        let regex = build_regex_from_schema_with_options(&schema.to_string(), &options).unwrap();
+        assert!(is_full_match(&regex, "2147483647"));
+        assert!(!is_full_match(&regex, "2147483648"));
+        assert!(!is_full_match(&regex, "-1"));
+
+        let cases = [
+            (
+                json!({"type": "number", "minimum": 2, "maximum": 1}),
+                "maximum must be greater than or equal to the minimum at /maximum",
+            ),
+            (
+                json!({"type": "integer", "minimum": 2.2, "maximum": 2.8}),
+                "no integer is within the minimum and maximum at the root of the schema",
+            ),
+            (
+                json!({"type": "number", "minimum": 0, "maxDigitsInteger": 3}),
+                "'maxDigitsInteger' can't be combined with a minimum or maximum at /maxDigitsInteger",
+            ),
+        ];
+        for (schema, message) in cases {
+            assert_eq!(build(schema).unwrap_err().to_string(), message);
+        }
+
+        let schema = json!({"type": "integer", "multipleOf": 5}).to_string();
+        let (_, warnings) =
+            build_regex_from_schema_with_warnings(&schema, &RegexOptions::default()).unwrap();
+        assert_eq!(warnings[0].pointer.as_deref(), Some("/multipleOf"));
+    }
+
+    #[test]
+    fn test_number_syntax() {
+        let options = RegexOptions {
+            number_syntax: types::NumberSyntax {
+                leading_zeros: true,
+                plus_sign: true,
+                bare_fractions: true,
+                ..types::NumberSyntax::default()
+            },
+            ..RegexOptions::default()
+        };
+        let build = |schema: Value| {
+            build_regex_from_schema_with_options(&schema.to_string(), &options).unwrap()
+        };
+
+        let number = build(json!({"type": "number"}));
+        for text in ["007", "+1", ".5", "-.5e+3", "+00.25", "1.5"] {
+            assert!(is_full_match(&number, text), "{}", text);
+        }
+        for text in [".", "+", "1.", "+-1", ".e1"] {
+            assert!(!is_full_match(&number, text), "{}", text);
+        }
+        let integer = build(json!({"type": "integer", "maxDigits": 2}));
+        for text in ["+7", "0042", "-099"] {
+            assert!(is_full_match(&integer, text), "{}", text);
+        }
+        for text in [".5", "100", "+"] {
+            assert!(!is_full_match(&integer, text), "{}", text);
+        }
+
+        // without them, only the forms of JSON
+        let schema = json!({"type": "number"}).to_string();
+        let regex = build_regex_from_schema(&schema, None).unwrap();
+        for text in ["007", "+1", ".5"] {
+            assert!(!is_full_match(&regex, text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_all_of_scalars() {
+        let build = |schema: Value| {
+            build_regex_from_schema_with_warnings(&schema.to_string(), &RegexOptions::default())
+        };
+
+        let schema = json!({
+            "allOf": [{"type": "integer"}, {"minimum": 1}, {"type": "number", "maximum": 12}]
+        });
+        let (regex, warnings) = build(schema).unwrap();
+        assert!(warnings.is_empty());
+        for text in ["1", "7", "12"] {
+            assert!(is_full_match(&regex, text), "{}", text);
+        }
+        for text in ["0", "13", "1.5", "17"] {
+            assert!(!is_full_match(&regex, text), "{}", text);
+        }
+
+        // a pattern and a length are intersected, and so are patterns
+        let schema = json!({
+            "allOf": [{"type": "string", "pattern": "^[a-z]+$"}, {"maxLength": 3}]
+        });
+        let (regex, warnings) = build(schema).unwrap();
+        assert!(warnings.is_empty());
+        for text in [r#""a""#, r#""abc""#] {
+            assert!(is_full_match(&regex, text), "{}", text);
+        }
+        for text in [r#""""#, r#""π""#, r#""abcd""#, r#""A""#] {
+            assert!(!is_full_match(&regex, text), "{}", text);
+        }
+        let schema = json!({
+            "type": "string",
+            "allOf": [{"pattern": "^a[a-z]*$"}, {"pattern": "^[a-z]*b$"}, {"maxLength": 4}]
+        });
+        let (regex, _) = build(schema).unwrap();
+        for text in [r#""ab""#, r#""axyb""#] {
+            assert!(is_full_match(&regex, text), "{}", text);
+        }
+        for text in [r#""a""#, r#""b""#, r#""axyzb""#] {
+            assert!(!is_full_match(&regex, text), "{}", text);
+        }
+
+        // a pattern without `$` goes on with anything
+        let schema = json!({"allOf": [{"type": "string"}, {"maxLength": 10}, {"pattern": "^a"}]});
+        let (regex, warnings) = build(schema).unwrap();
+        assert!(warnings.is_empty());
+        for text in [r#""a""#, r#""abc""#, r#""a123456789""#] {
+            assert!(is_full_match(&regex, text), "{}", text);
+        }
+        for text in [r#""""#, r#""bc""#, r#""a1234567890""#] {
+            assert!(!is_full_match(&regex, text), "{}", text);
+        }
+
+        // otherwise the length wins over the pattern, which is reported in
+        // its branch, or fails under strict
+        let schema = json!({"allOf": [{"type": "string"}, {"maxLength": 1000}, {"pattern": "^a"}]});
+        let (regex, warnings) = build(schema.clone()).unwrap();
+        assert!(is_full_match(&regex, r#""bc""#));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].pointer.as_deref(), Some("/allOf/2/pattern"));
+        let options = RegexOptions {
+            strict: true,
+            ..RegexOptions::default()
+        };
+        let error =
+            build_regex_from_schema_with_options(&schema.to_string(), &options).unwrap_err();
+        assert_eq!(SchemaError::find(&error), Some(&warnings[0]));
+
+        let schema = json!({"type": "string", "allOf": [{"pattern": "^a+$"}, {"pattern": "^b+$"}]});
+        let error = build(schema).unwrap_err();
+        assert_eq!(
+            SchemaError::find(&error).unwrap().pointer.as_deref(),
+            Some("/allOf/0/pattern")
+        );
+
+        let schema = json!({"type": "string", "allOf": [{"type": "integer"}]});
+        assert_eq!(
+            build(schema).unwrap_err().to_string(),
+            "the allOf branches have no type in common at /allOf"
+        );
+    }
+
+    #[test]
+    fn test_subsumed_branches() {
+        let build = |schema: Value| build_regex_from_schema(&schema.to_string(), None).unwrap();
+        let number = build(json!({"type": "number"}));
+
+        let any_of = json!({"anyOf": [{"type": "integer"}, {"type": "number", "title": "n"}]});
+        assert_eq!(build(any_of), format!("(?:{})", number));
+        let any_of = json!({"anyOf": [
+            {"type": "integer", "minimum": 3},
+            {"type": "number"},
+            {"type": "number"}
+        ]});
+        assert_eq!(build(any_of), format!("(?:{})", number));
+        assert_eq!(
+            build(json!({"type": ["integer", "number"]})),
+            format!("(?:{})", number)
+        );
+
+        // number doesn't take in what it constrains differently
+        let any_of = json!({"anyOf": [{"type": "integer"}, {"type": "number", "minimum": 1}]});
+        assert!(is_full_match(&build(any_of), "-5"));
+        let types = json!({"type": ["integer", "number"], "minDigitsFraction": 2});
+        assert!(is_full_match(&build(types), "-5"));
+        let any_of = json!({"anyOf": [{"type": "integer"}, {"type": "string"}]});
+        assert!(is_full_match(&build(any_of), "5"));
+    }
+
+    #[test]
+    fn test_report_ambiguity() {
+        let options = RegexOptions {
+            report_ambiguity: true,
+            strict: true,
+            ..RegexOptions::default()
+        };
+        let warnings = |schema: Value| {
+            let (regex, warnings) =
+                build_regex_from_schema_with_warnings(&schema.to_string(), &options).unwrap();
+            let plain = build_regex_from_schema(&schema.to_string(), None).unwrap();
+            assert_eq!(regex, plain);
+            warnings
+        };
+
+        let kind = |name: &str, value: Value| {
+            json!({
+                "type": "object",
+                "properties": {"kind": {"const": name}, "value": value},
+                "required": ["kind", "value"]
+            })
+        };
+        let schema = json!({"anyOf": [
+            kind("point", json!({"type": "integer"})),
+            kind("point", json!({"type": "string"})),
+            kind("line", json!({"type": "integer"}))
+        ]});
+        let found = warnings(schema);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].pointer.as_deref(), Some("/anyOf"));
+        assert_eq!(
+            found[0].message,
+            r#"the anyOf branches at /anyOf/0 and /anyOf/1 both accept values starting with `{ "kind" : "poin`, which a streaming matcher reads before telling them apart"#
+        );
+
+        let schema = json!({"oneOf": [
+            {"type": "string", "pattern": "^user-[a-z]+$"},
+            {"type": "string", "pattern": "^user-[a-z]+-admin$"}
+        ]});
+        assert_eq!(warnings(schema)[0].pointer.as_deref(), Some("/oneOf"));
+        assert!(warnings(json!({"anyOf": [{"type": "string"}, {"type": "integer"}]})).is_empty());
+    }
+
+    #[test]
+    fn test_presets() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"id": {"type": "integer"}, "tags": {"type": "object"}},
+            "required": ["id"]
+        })
+        .to_string();
+        let regex = |preset| {
+            build_regex_from_schema_with_options(&schema, &RegexOptions::preset(preset)).unwrap()
+        };
+
+        let strict = regex(Preset::OpenAiStrict);
+        assert!(is_full_match(&strict, r#"{"id":1,"tags":{}}"#));
+        assert!(!is_full_match(&strict, r#"{"id":1}"#));
+        assert!(!is_full_match(&strict, r#"{"id":1,"tags":{"a":1}}"#));
+        assert!(!is_full_match(&strict, r#"{"id": 1,"tags":{}}"#));
+
+        let pretty = regex(Preset::Pretty);
+        let text = "{\n  \"id\": 1,\n  \"tags\": {\n    \"a\": true\n  }\n}\n";
+        assert!(is_full_match(&pretty, text));
+        assert!(is_full_match(&pretty, r#"{"id": 1}"#));
+
+        assert!(regex(Preset::OutlinesLegacy).contains("(-)?"));
+    }
+
+    #[test]
+    fn test_closed_objects() {
+        let closed = json!({"type": "object", "additionalProperties": false}).to_string();
+        let regex = build_regex_from_schema(&closed, None).unwrap();
+        assert!(is_full_match(&regex, "{}"));
+        assert!(!is_full_match(&regex, r#"{"a":1}"#));
+    }
+
+    #[test]
+    fn test_named_groups() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "address": {
+                    "type": "object",
+                    "properties": {"zip code": {"type": "integer"}},
+                    "required": ["zip code"]
+                },
+                "name": {"type": "string"},
+                "tags": {"type": "object", "properties": {"a": {"type": "integer"}}}
+            },
+            "required": ["name", "address", "tags"]
+        })
+        .to_string();
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            named_groups: true,
+            ..RegexOptions::default()
+        };
+        let (regex, warnings) = build_regex_from_schema_with_warnings(&schema, &options).unwrap();
+
+        let captures = Regex::new(&format!("^{}$", regex))
+            .unwrap()
+            .captures(r#"{"address":{"zip code":75001},"name":"Ann","tags":{"a":1}}"#)
+            .unwrap();
+        assert_eq!(&captures["name"], r#""Ann""#);
+        assert_eq!(&captures["address"], r#"{"zip code":75001}"#);
+        assert_eq!(&captures["address__zip_code"], "75001");
+        assert_eq!(&captures["tags"], r#"{"a":1}"#);
+        assert!(captures.name("tags__a").is_none());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].pointer.as_deref(), Some("/properties/tags"));
+
+        // a name is only used once
+        let schema = json!({"anyOf": [
+            {"type": "object", "properties": {"a": {"type": "integer"}}, "required": ["a"]},
+            {"type": "object", "properties": {"a": {"type": "string"}}, "required": ["a"]}
+        ]})
+        .to_string();
+        let (regex, warnings) = build_regex_from_schema_with_warnings(&schema, &options).unwrap();
+        assert!(Regex::new(&regex).is_ok());
+        assert_eq!(
+            warnings[0].pointer.as_deref(),
+            Some("/anyOf/1/properties/a")
+        );
+    }
+
+    #[test]
+    fn test_required_without_properties() {
+        for schema in [
+            json!({"type": "object", "required": ["a", "b"]}),
+            json!({"type": "object", "required": ["b", "a"], "additionalProperties": {"type": "integer"}}),
+            json!({"type": "object", "required": ["a"], "maxProperties": 2}),
+            json!({"type": "object", "required": ["a"], "minProperties": 3}),
+        ] {
+            let regex = build_regex_from_schema(&schema.to_string(), Some(" ?")).unwrap();
+            let validator = JSONSchema::compile(&schema).unwrap();
+
+            // every subset of the keys, the required ones first
+            let names = ["a", "b", "c", "d"];
+            for present_mask in 0..1 << names.len() {
+                let members: Vec<String> = (0..names.len())
+                    .filter(|i| present_mask & (1 << i) != 0)
+                    .map(|i| format!(r#""{}": {}"#, names[i], i))
+                    .collect();
+                let instance = format!("{{{}}}", members.join(", "));
+                let value: Value = serde_json::from_str(&instance).unwrap();
+                assert_eq!(
+                    is_full_match(&regex, &instance),
+                    validator.is_valid(&value),
+                    "{} with {}",
+                    schema,
+                    instance
+                );
+            }
+        }
+
+        let schema = json!({"type": "object", "required": ["a"]}).to_string();
+        let regex = build_regex_from_schema(&schema, None).unwrap();
+        assert!(is_full_match(&regex, r#"{"a": "x", "z": [1]}"#));
+        assert!(!is_full_match(&regex, r#"{"z": [1]}"#));
+
+        let closed = json!({"type": "object", "required": ["a"], "additionalProperties": false});
+        assert!(build_regex_from_schema(&closed.to_string(), None).is_err());
+    }
+
+    #[test]
+    fn test_equivalent_numbers() {
+        let schema = json!({"enum": [1, 2e1, -0.5, 0, "a"]}).to_string();
+        let options = RegexOptions {
+            equivalent_numbers: true,
+            ..RegexOptions::default()
+        };
+        let regex = build_regex_from_schema_with_options(&schema, &options).unwrap();
+        for text in [
+            "1", "1.0", "1.00", "20", "20.0", "-0.5", "-0.50", "0", "-0", "0.0", r#""a""#,
+        ] {
+            assert!(is_full_match(&regex, text), "{}", text);
+        }
+        for text in ["1.", "01", "2", "-1", "0.5", "20.01", "2e1"] {
+            assert!(!is_full_match(&regex, text), "{}", text);
+        }
+
+        // written as is otherwise
+        let regex = build_regex_from_schema(&schema, None).unwrap();
+        assert!(is_full_match(&regex, "20.0"));
+        assert!(!is_full_match(&regex, "20"));
+    }
+
+    #[test]
+    fn test_force_decimal_for_number() {
+        let options = RegexOptions {
+            number_syntax: types::NumberSyntax {
+                force_decimal_for_number: true,
+                ..types::NumberSyntax::default()
+            },
+            ..RegexOptions::default()
+        };
+        let build = |schema: Value| {
+            build_regex_from_schema_with_options(&schema.to_string(), &options).unwrap()
+        };
+
+        for schema in [
+            json!({"type": "number"}),
+            json!({"type": "number", "minimum": -1, "maximum": 3}),
+        ] {
+            let regex = build(schema);
+            for text in ["0.0", "2.5", "-1.0", "3.00"] {
+                assert!(is_full_match(&regex, text), "{} {}", regex, text);
+            }
+            for text in ["0", "2", "-1", "3", "2."] {
+                assert!(!is_full_match(&regex, text), "{} {}", regex, text);
+            }
+        }
+
+        let integer = build(json!({"type": "integer", "minimum": 1}));
+        assert!(is_full_match(&integer, "5"));
+        assert!(!is_full_match(&integer, "5.0"));
+
+        // integers aren't numbers then, so neither shape drops them
+        for schema in [
+            json!({"anyOf": [{"type": "integer"}, {"type": "number"}]}),
+            json!({"type": ["integer", "number"]}),
+        ] {
+            let regex = build(schema);
+            for text in ["5", "5.0", "-2.5"] {
+                assert!(is_full_match(&regex, text), "{} {}", regex, text);
+            }
         }
+
+        // the spec's forms by default
+        let regex = build_regex_from_schema(&json!({"type": "number"}).to_string(), None).unwrap();
+        assert!(is_full_match(&regex, "5"));
     }
 
-    mod string_tests {
-        use super::*;
+    #[test]
+    fn test_whitespace_positions() {
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            whitespace_positions: WhitespacePositions {
+                after_colon: Some(" ".to_string()),
+                inside_brackets: Some(" ?".to_string()),
+                ..WhitespacePositions::default()
+            },
+            ..RegexOptions::default()
+        };
+        let build = |schema: Value| {
+            build_regex_from_schema_with_options(&schema.to_string(), &options).unwrap()
+        };
 
-        #[test]
-        fn test_string_type() {
-            let schema = json!({"type": "string", "minLength": 2, "maxLength": 5});
-            test_regex(&schema);
+        let regex = build(json!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "integer"},
+                "b": {"type": "array", "items": {"type": "integer"}},
+                "c": {"type": "object", "additionalProperties": {"type": "integer"}}
+            },
+            "required": ["b"]
+        }));
+        for text in [
+            r#"{"b": []}"#,
+            r#"{ "a": 1,"b": [ 1,2 ] }"#,
+            r#"{"b": [1],"c": { "x": 1,"y": 2 }}"#,
+        ] {
+            assert!(is_full_match(&regex, text), "{} {}", regex, text);
         }
-
-        #[test]
-        fn test_string_type_with_length_constraints() {
-            let schema = json!({"type": "string", "minLength": 2, "maxLength": 5});
-            test_regex(&schema);
+        for text in [r#"{"b":[]}"#, r#"{"a":1,"b": []}"#, r#"{"b": [1, 2]}"#] {
+            assert!(!is_full_match(&regex, text), "{} {}", regex, text);
         }
 
-        #[test]
-        fn test_string_type_with_pattern() {
-            let schema = json!({"type": "string", "pattern": "^[a-zA-Z0-9]+$"});
-            test_regex(&schema);
+        // an optional first property either opens the object or follows one
+        let regex = build(json!({
+            "type": "object",
+            "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}}
+        }));
+        for text in ["{}", r#"{"b": 2}"#, r#"{ "b": 2 }"#, r#"{"a": 1, "b": 2}"#] {
+            assert!(is_full_match(&regex, text), "{} {}", regex, text);
         }
 
-        #[test]
-        fn test_string_type_with_date_time_format() {
-            let schema = json!({"type": "string", "format": "date-time"});
-            test_regex(&schema);
-        }
+        let options = RegexOptions {
+            whitespace_positions: WhitespacePositions {
+                after_comma: Some("(".to_string()),
+                ..WhitespacePositions::default()
+            },
+            ..RegexOptions::default()
+        };
+        assert!(build_regex_from_schema_with_options("{}", &options).is_err());
+    }
 
-        #[test]
-        fn test_string_type_with_date_format() {
-            let schema = json!({"type": "string", "format": "date"});
-            test_regex(&schema);
-        }
+    #[test]
+    fn test_draft_semantics() {
+        let build = |schema: Value, draft: Option<Draft>| {
+            let options = RegexOptions {
+                whitespace_pattern: Some(String::new()),
+                draft,
+                ..RegexOptions::default()
+            };
+            build_regex_from_schema_with_options(&schema.to_string(), &options)
+        };
+        let draft_04 = "http://json-schema.org/draft-04/schema#";
+        let draft_07 = "http://json-schema.org/draft-07/schema#";
+        let draft_2020 = "https://json-schema.org/draft/2020-12/schema";
 
-        #[test]
-        fn test_string_type_with_time_format() {
-            let schema = json!({"type": "string", "format": "time"});
-            test_regex(&schema);
+        // the exclusive bounds of draft 4 are booleans, numbers after it, as
+        // without `$schema`, where the schema is validated as draft 7
+        let boolean = json!({"type": "integer", "minimum": 1, "exclusiveMinimum": true});
+        let number = json!({"type": "integer", "exclusiveMinimum": 1});
+        for (draft, boolean_ok, number_ok) in [
+            (Some(draft_04), true, false),
+            (Some(draft_07), false, true),
+            (None, false, true),
+        ] {
+            for (mut schema, ok) in [(boolean.clone(), boolean_ok), (number.clone(), number_ok)] {
+                if let Some(draft) = draft {
+                    schema["$schema"] = json!(draft);
+                }
+                let regex = build(schema.clone(), None);
+                assert_eq!(regex.is_ok(), ok, "{}", schema);
+                if let Ok(regex) = regex {
+                    assert!(!is_full_match(&regex, "1"));
+                    assert!(is_full_match(&regex, "2"));
+                }
+            }
         }
 
-        #[test]
-        fn test_string_type_with_uuid_format() {
-            let schema = json!({"type": "string", "format": "uuid"});
-            test_regex(&schema);
-        }
+        // tuples are an `items` array before 2020-12, and `prefixItems` after
+        let tuple = json!({"type": "array", "items": [{"type": "integer"}, {"type": "boolean"}]});
+        let prefix = json!({"type": "array", "prefixItems": [{"type": "integer"}]});
+        let mut old_tuple = tuple.clone();
+        old_tuple["$schema"] = json!(draft_07);
+        let regex = build(old_tuple, None).unwrap();
+        assert!(is_full_match(&regex, "[1,true]"));
+        assert!(!is_full_match(&regex, "[1]"));
+        let regex = build(tuple.clone(), None).unwrap();
+        assert!(is_full_match(&regex, "[1,true]"));
+        assert!(build(tuple.clone(), Some(Draft::Draft2020_12)).is_err());
 
-        #[test]
-        fn test_string_type_with_pattern_and_length_constraints() {
-            let schema = json!({
-                "type": "string",
-                "pattern": "^[A-Z]+$",
-                "minLength": 3,
-                "maxLength": 10
-            });
-            test_regex(&schema);
-        }
+        let mut old_prefix = prefix.clone();
+        old_prefix["$schema"] = json!(draft_07);
+        let regex = build(old_prefix, None).unwrap();
+        assert!(is_full_match(&regex, "[true,null]"));
+        let regex = build(prefix.clone(), None).unwrap();
+        assert!(!is_full_match(&regex, "[true,null]"));
 
-        #[test]
-        fn test_string_type_with_format_and_length_constraints() {
-            let schema = json!({
-                "type": "string",
-                "format": "date-time",
-                "minLength": 20,
-                "maxLength": 30
-            });
-            test_regex(&schema);
-        }
+        // the draft option wins over `$schema`
+        let mut schema = boolean.clone();
+        schema["$schema"] = json!(draft_2020);
+        assert!(build(schema.clone(), None).is_err());
+        assert!(build(schema, Some(Draft::Draft4)).is_ok());
     }
-    mod number_tests {
-        use super::*;
 
-        #[test]
-        fn test_number_type() {
-            let schema = json!({"type": "number"});
-            test_regex(&schema);
-        }
-        #[test]
-        fn test_number_with_int_bounds_type() {
-            let schema = json!({"type": "number", "minDigitsInteger": 12});
-            test_regex(&schema);
-        }
-        #[test]
-        fn test_number_with_fraction_bounds() {
-            let schema = json!({"type": "number", "minDigitsFraction": 2, "maxDigitsFraction": 4});
-            test_regex(&schema);
+    #[test]
+    fn test_unconstrained_values() {
+        let build = |schema: Value, unconstrained: UnconstrainedValues| {
+            let options = RegexOptions {
+                whitespace_pattern: Some(String::new()),
+                unconstrained,
+                ..RegexOptions::default()
+            };
+            build_regex_from_schema_with_options(&schema.to_string(), &options)
+        };
+
+        let regex = build(json!({}), UnconstrainedValues::scalars()).unwrap();
+        for text in ["1", "-2.5", r#""a""#, "true", "null"] {
+            assert!(is_full_match(&regex, text), "{}", text);
         }
-        #[test]
-        fn test_number_with_exponent_bounds() {
-            let schema = json!({"type": "number", "minDigitsExponent": 1, "maxDigitsExponent": 3});
-            test_regex(&schema);
+        for text in ["[]", "{}", r#"{"a":1}"#] {
+            assert!(!is_full_match(&regex, text), "{}", text);
         }
-    }
 
-    mod integer_tests {
-        use super::*;
+        // the values of objects and the items of arrays follow them too
+        let only_strings = UnconstrainedValues {
+            numbers: false,
+            booleans: false,
+            nulls: false,
+            ..UnconstrainedValues::default()
+        };
+        let regex = build(json!({"type": "object"}), only_strings).unwrap();
+        assert!(is_full_match(&regex, r#"{"a":"b","c":["d",{"e":"f"}]}"#));
+        assert!(!is_full_match(&regex, r#"{"a":1}"#));
+        let regex = build(json!({"type": "array"}), only_strings).unwrap();
+        assert!(is_full_match(&regex, r#"["a",[]]"#));
+        assert!(!is_full_match(&regex, "[1]"));
 
-        #[test]
-        fn test_integer_type() {
-            let schema = json!({"type": "integer"});
-            test_regex(&schema);
-        }
+        // no objects or arrays below `depth` levels
+        let shallow = UnconstrainedValues {
+            depth: 1,
+            ..UnconstrainedValues::default()
+        };
+        let regex = build(json!({"type": "array"}), shallow).unwrap();
+        assert!(is_full_match(&regex, r#"[1,{"a":2}]"#));
+        assert!(!is_full_match(&regex, r#"[1,{"a":[2]}]"#));
 
-        #[test]
-        fn test_integer_with_low_bound() {
-            let schema = json!({"type": "integer", "minDigits": 1});
-            test_regex(&schema);
-        }
+        let containers = UnconstrainedValues {
+            strings: false,
+            numbers: false,
+            booleans: false,
+            nulls: false,
+            objects: true,
+            arrays: true,
+            depth: 0,
+        };
+        let regex = build(json!({"type": "array"}), containers).unwrap();
+        assert!(is_full_match(&regex, "[]"));
+        assert!(build(json!({"type": "array", "minItems": 1}), containers).is_err());
+        let none = UnconstrainedValues {
+            objects: false,
+            arrays: false,
+            ..containers
+        };
+        assert!(build(json!({}), none).is_err());
+    }
 
-        // TODO, this might be related to a bug in outlines, commented out for now
-        // #[test]
-        // fn test_integer_with_high_bound() {
-        //     let schema = json!({"type": "integer", "maxDigits": 10});
-        //     test_regex(&schema);
-        // }
+    #[test]
+    fn test_exclusive_one_of() {
+        let build = |schema: Value| {
+            let options = RegexOptions {
+                exclusive_one_of: true,
+                ..RegexOptions::default()
+            };
+            build_regex_from_schema_with_warnings(&schema.to_string(), &options)
+        };
 
-        #[test]
-        fn test_integer_with_low_and_high_bound() {
-            let schema = json!({"type": "integer", "minDigits": 1, "maxDigits": 10});
-            test_regex(&schema);
+        let (regex, warnings) = build(json!({"oneOf": [
+            {"type": "integer", "minimum": 0, "maximum": 10},
+            {"type": "integer", "minimum": 5, "maximum": 20}
+        ]}))
+        .unwrap();
+        assert!(warnings.is_empty());
+        for value in 0..=25 {
+            let exclusive = (0..5).contains(&value) || (11..=20).contains(&value);
+            assert_eq!(
+                is_full_match(&regex, &value.to_string()),
+                exclusive,
+                "{}",
+                value
+            );
         }
-    }
 
-    mod simple_tests {
-        use super::*;
+        // disjoint branches are written as they are
+        let disjoint = json!({"oneOf": [{"type": "boolean"}, {"type": "null"}]});
+        assert_eq!(
+            build(disjoint.clone()).unwrap().0,
+            build_regex_from_schema(&disjoint.to_string(), None).unwrap()
+        );
 
-        #[test]
-        fn test_boolean_type() {
-            let schema = json!({"type": "boolean"});
-            test_regex(&schema);
+        // strings are read a character at a time
+        for schema in [
+            json!({"oneOf": [{"type": "string"}, {"const": "a"}]}),
+            json!({"oneOf": [{"type": "string"}, {"type": "string", "maxLength": 1}]}),
+        ] {
+            let (regex, warnings) = build(schema).unwrap();
+            assert!(warnings.is_empty());
+            for text in [r#""ab""#, r#""πé""#, r#""😀😀""#] {
+                assert!(is_full_match(&regex, text), "{}", text);
+            }
+            assert!(!is_full_match(&regex, r#""a""#));
         }
-        #[test]
-        fn test_null_type() {
-            let schema = json!({"type": "null"});
-            test_regex(&schema);
+        let (regex, _) = build(json!({"oneOf": [
+            {"type": "string"},
+            {"type": "string", "maxLength": 1}
+        ]}))
+        .unwrap();
+        for text in [r#""""#, r#""π""#, r#""😀""#] {
+            assert!(!is_full_match(&regex, text), "{}", text);
         }
-        #[test]
-        fn test_enum() {
-            let schema = json!({
-                "enum": ["red", "green", "blue", 42, true, null]
-            });
-            test_regex(&schema);
+
+        let error = build(json!({"oneOf": [{"const": 1}, {"enum": [1]}]})).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no value matches exactly one oneOf branch at /oneOf"
+        );
+    }
+
+    #[test]
+    fn test_length_counts_code_points() {
+        use crate::automaton::{validate, Validation};
+
+        let schema = json!({"type": "string", "minLength": 2, "maxLength": 2}).to_string();
+        let regex = build_regex_from_schema(&schema, None).unwrap();
+        // the DFA runs on bytes but its classes match whole UTF-8 sequences
+        for text in [r#""ab""#, r#""éü""#, r#""漢字""#, r#""😀!""#, r#""\"a""#] {
+            assert_eq!(
+                validate(&regex, text.as_bytes()).unwrap(),
+                Validation::Match,
+                "{}",
+                text
+            );
         }
-        #[test]
-        fn test_const() {
-            let schema = json!({
-                "const": "hello world"
-            });
-            test_regex(&schema);
+        for text in [r#""é""#, r#""😀""#, r#""abc""#] {
+            assert_ne!(
+                validate(&regex, text.as_bytes()).unwrap(),
+                Validation::Match,
+                "{}",
+                text
+            );
         }
-        #[test]
-        fn test_prefix_items() {
-            let schema = json!({
-                "prefixItems": [
-                    { "type": "integer" },
-                    { "type": "string" },
-                    { "type": "boolean" }
-                ]
-            });
-            test_regex(&schema);
+    }
+
+    #[test]
+    fn test_non_capturing_groups() {
+        let schema = json!({"type": "array", "items": {"type": "boolean"}}).to_string();
+        let regex = build_regex_from_schema(&schema, None).unwrap();
+        assert_eq!(Regex::new(&regex).unwrap().captures_len(), 1);
+
+        let legacy =
+            build_regex_from_schema_with_options(&schema, &RegexOptions::legacy()).unwrap();
+        assert_eq!(non_capturing(&legacy), regex);
+
+        assert_eq!(
+            non_capturing(r"(a\(|[(\]])(?P<b>c)[^]()]"),
+            r"(?:a\(|[(\]])(?P<b>c)[^]()]"
+        );
+
+        // a `(` at the end of one write is resolved by the next one
+        let mut streamed = String::new();
+        let mut out = NonCapturing::new(&mut streamed);
+        for chunk in ["(", "?:a)(", "b)("] {
+            out.write_str(chunk).unwrap();
         }
+        out.finish().unwrap();
+        assert_eq!(streamed, "(?:a)(?:b)(");
     }
 
-    mod ref_tests {
+    #[cfg(feature = "schemars")]
+    mod schemars_tests {
+        use schemars::JsonSchema;
+
         use super::*;
 
-        #[test]
-        fn test_ref() {
-            let schema = json!({
-                "type": "object",
-                "properties": {
-                    "flag": {
-                        "$ref": "#/definitions/flag"
-                    }
-                },
-                "definitions": {
-                    "flag": {
-                        "type": "boolean"
-                    }
-                }
-            });
-            test_regex(&schema);
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        struct Address {
+            city: String,
+        }
+
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        struct User {
+            age: u32,
+            address: Address,
+            nickname: Option<String>,
         }
+
         #[test]
-        fn test_multiple_refs() {
-            let schema = json!({
-                "type": "object",
-                "properties": {
-                    "manager": { "$ref": "#/definitions/employee" },
-                    "subordinate": { "$ref": "#/definitions/employee" }
-                },
-                "required": ["manager", "subordinate"],
-                "definitions": {
-                    "employee": {
-                        "type": "object",
-                        "properties": {
-                            "name": { "type": "string" },
-                            "age": { "type": "integer" }
-                        },
-                        "required": ["name", "age"]
-                    }
-                }
-            });
-            test_regex(&schema);
+        fn test_build_regex_for_type() {
+            let regex = build_regex_for_type::<User>(Some("")).unwrap();
+
+            assert!(is_full_match(
+                &regex,
+                r#"{"address":{"city":"Oslo"},"age":36}"#
+            ));
+            assert!(is_full_match(
+                &regex,
+                r#"{"address":{"city":"Oslo"},"age":36,"nickname":null}"#
+            ));
+            assert!(!is_full_match(&regex, r#"{"address":{},"age":36}"#));
         }
     }
-    #[test]
-    fn test_nested_ref() {
-        let schema = json!({
-            "type": "object",
-            "properties": {
-                "employee": { "$ref": "#/definitions/employee" }
-            },
-            "definitions": {
-                "employee": {
-                    "type": "object",
-                    "properties": {
-                        "person": { "$ref": "#/definitions/person" },
-                        "position": { "type": "string" }
-                    },
-                    "required": ["person", "position"]
-                },
-                "person": {
-                    "type": "object",
-                    "properties": {
-                        "name": { "type": "string" },
-                        "age": { "type": "integer" }
-                    },
-                    "required": ["name", "age"]
-                }
-            }
-        });
-        test_regex(&schema);
-    }
+
     #[test]
     fn test_invalid_ref_path() {
         let schema = json!({
@@ -779,5 +4177,44 @@ mod tests {
         assert!(err
             .to_string()
             .contains("Invalid reference path: nonexistent"));
+        assert_eq!(
+            SchemaError::find(&err).unwrap().pointer.as_deref(),
+            Some("/properties/person")
+        );
+    }
+
+    #[test]
+    fn test_recursive_ref() {
+        // a linked list, nested without bound
+        let schema = json!({
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "properties": {
+                        "value": {"type": "integer"},
+                        "next": {"$ref": "#/$defs/node"}
+                    }
+                }
+            },
+            "$ref": "#/$defs/node"
+        });
+        let error = build_regex_from_schema(&schema.to_string(), None).unwrap_err();
+        let error = SchemaError::find(&error).unwrap();
+        assert_eq!(
+            error.message,
+            r##"the $ref to "#/$defs/node" is recursive, which a regex can't match"##
+        );
+        assert_eq!(
+            error.pointer.as_deref(),
+            Some("/$defs/node/properties/next/$ref")
+        );
+
+        let schema = json!({
+            "$defs": {"a": {"$ref": "#/$defs/b"}, "b": {"$ref": "#/$defs/a"}},
+            "$ref": "#/$defs/a"
+        });
+        assert!(build_regex_from_schema(&schema.to_string(), None).is_err());
+        let full_schema = schema.clone();
+        assert!(follow_refs(&schema, &full_schema).is_err());
     }
 }
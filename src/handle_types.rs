@@ -1,69 +1,110 @@
+use std::borrow::Cow;
+use std::fmt::Write;
 use std::num::NonZeroU64;
 
 use anyhow::{anyhow, Ok, Result};
 use serde_json::json;
 use serde_json::Value;
 
-use crate::guidance::to_regex;
+use crate::error::SchemaError;
+use crate::guidance::{property_name, Generator};
+use crate::keywords;
+use crate::pushdown::Rule;
+use crate::range;
+use crate::sandbox;
 use crate::types;
 
-pub fn handle_boolean_type() -> Result<String> {
+/// Arrays with `uniqueItems` are only written out when their items allow
+/// at most this many of them, as every order of the values is listed.
+const MAX_UNIQUE_ARRAYS: usize = 1000;
+
+pub fn handle_boolean_type<W: Write>(out: &mut W) -> Result<()> {
     let format_type = types::JsonType::Boolean;
-    Ok(format_type.to_regex().to_string())
+    out.write_str(format_type.to_regex())?;
+    Ok(())
 }
 
-pub fn handle_null_type() -> Result<String> {
+pub fn handle_null_type<W: Write>(out: &mut W) -> Result<()> {
     let format_type = types::JsonType::Null;
-    Ok(format_type.to_regex().to_string())
+    out.write_str(format_type.to_regex())?;
+    Ok(())
 }
 
-pub fn handle_string_type(obj: &serde_json::Map<String, Value>) -> Result<String> {
-    if obj.contains_key("maxLength") || obj.contains_key("minLength") {
-        let max_items = obj.get("maxLength");
-        let min_items = obj.get("minLength");
-
-        match (min_items, max_items) {
-            (Some(min), Some(max)) if min.as_f64() > max.as_f64() => {
-                return Err(anyhow::anyhow!(
-                    "maxLength must be greater than or equal to minLength"
-                ));
+pub fn handle_string_type<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<()> {
+    let min_length = keywords::get_u64(obj, "minLength")?;
+    let max_length = keywords::get_u64(obj, "maxLength")?;
+    if min_length.is_some() || max_length.is_some() {
+        check_bounds(
+            "minLength",
+            "maxLength",
+            min_length.unwrap_or(0),
+            max_length,
+        )?;
+        // a length and a pattern can't both be written into the regex
+        for keyword in ["pattern", "format"] {
+            if obj.contains_key(keyword) {
+                generator.unenforced(
+                    SchemaError::new(format!("{} isn't enforced along with a length", keyword))
+                        .with_keyword(keyword),
+                )?;
             }
-            _ => {}
         }
 
-        let formatted_max = max_items
-            .and_then(Value::as_u64)
-            .map_or("".to_string(), |n| format!("{}", n));
-        let formatted_min = min_items
-            .and_then(Value::as_u64)
-            .map_or("".to_string(), |n| format!("{}", n));
-
-        Ok(format!(
-            r#""{}{{{},{}}}""#,
+        // `{,n}` isn't valid in every engine, so the minimum is always written
+        write!(
+            out,
+            r#""{}{{{},"#,
             types::STRING_INNER,
-            formatted_min,
-            formatted_max,
-        ))
-    } else if let Some(pattern) = obj.get("pattern").and_then(Value::as_str) {
-        if pattern.starts_with('^') && pattern.ends_with('$') {
-            Ok(format!(r#"("{}")"#, &pattern[1..pattern.len() - 1]))
-        } else {
-            Ok(format!(r#"("{}")"#, pattern))
+            min_length.unwrap_or(0)
+        )?;
+        if let Some(max) = max_length {
+            write!(out, "{}", max)?;
         }
-    } else if let Some(format) = obj.get("format").and_then(Value::as_str) {
+        out.write_str(r#"}""#)?;
+    } else if let Some(pattern) = keywords::get_str(obj, "pattern")? {
+        let pattern = if pattern.starts_with('^') && pattern.ends_with('$') {
+            &pattern[1..pattern.len() - 1]
+        } else {
+            pattern
+        };
+        write!(
+            out,
+            r#"("{}")"#,
+            sandbox::checked_pattern(pattern, generator)?
+        )?;
+    } else if let Some(format) = keywords::get_str(obj, "format")? {
         match types::FormatType::from_str(format) {
-            Some(format_type) => Ok(format_type.to_regex().to_string()),
-            None => Err(anyhow::anyhow!(
-                "Format {} is not supported by Outlines",
-                format
-            )),
+            Some(format_type) => out.write_str(format_type.to_regex())?,
+            // OpenAPI formats that only hint at how the string is used
+            None if format == "binary" || format == "password" => {
+                generator.unenforced(
+                    SchemaError::new(format!("format '{}' is generated as any string", format))
+                        .with_keyword("format"),
+                )?;
+                out.write_str(types::JsonType::String.to_regex())?;
+            }
+            None => {
+                return Err(anyhow::anyhow!(
+                    "Format {} is not supported by Outlines",
+                    format
+                ))
+            }
         }
     } else {
-        Ok(types::JsonType::String.to_regex().to_string())
+        out.write_str(types::JsonType::String.to_regex())?;
     }
+    Ok(())
 }
 
-pub fn handle_number_type(obj: &serde_json::Map<String, Value>) -> Result<String> {
+pub fn handle_number_type<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<()> {
     let bounds = [
         "minDigitsInteger",
         "maxDigitsInteger",
@@ -73,204 +114,582 @@ pub fn handle_number_type(obj: &serde_json::Map<String, Value>) -> Result<String
         "maxDigitsExponent",
     ];
 
+    // the digits needed to write the largest value of the format, which
+    // still lets through a few larger ones
+    let format_digits = match keywords::get_str(obj, "format")? {
+        Some("float") if generator.numeric_formats => Some((39, 2)),
+        Some("double") if generator.numeric_formats => Some((309, 3)),
+        _ => None,
+    };
+
     let has_bounds = bounds.iter().any(|&key| obj.contains_key(key));
+    let (mut lower, mut upper) = range::bounds(obj)?;
+    check_multiple_of(obj, generator)?;
 
-    if has_bounds {
+    if lower.is_some() || upper.is_some() {
+        if has_bounds {
+            return Err(not_combined(&bounds, obj).into());
+        }
+        if let Some((integer_digits, _)) = format_digits {
+            // the digits of the format, as bounds
+            let bound = range::Decimal::parse(&format!("1e{}", integer_digits));
+            upper = range::tighter_upper(upper, bound.clone().map(range::Bound::exclusive));
+            let bound = bound.map(|bound| range::Bound::exclusive(bound.negated()));
+            lower = range::tighter_lower(lower, bound);
+        }
+        out.write_str(&within_bounds(lower, upper, false, generator)?)?;
+    } else if has_bounds {
         let (min_digits_integer, max_digits_integer) = validate_quantifiers(
-            obj.get("minDigitsInteger").and_then(Value::as_u64),
-            obj.get("maxDigitsInteger").and_then(Value::as_u64),
+            keywords::get_u64(obj, "minDigitsInteger")?,
+            keywords::get_u64(obj, "maxDigitsInteger")?,
             1,
         )?;
 
         let (min_digits_fraction, max_digits_fraction) = validate_quantifiers(
-            obj.get("minDigitsFraction").and_then(Value::as_u64),
-            obj.get("maxDigitsFraction").and_then(Value::as_u64),
+            keywords::get_u64(obj, "minDigitsFraction")?,
+            keywords::get_u64(obj, "maxDigitsFraction")?,
             0,
         )?;
 
         let (min_digits_exponent, max_digits_exponent) = validate_quantifiers(
-            obj.get("minDigitsExponent").and_then(Value::as_u64),
-            obj.get("maxDigitsExponent").and_then(Value::as_u64),
+            keywords::get_u64(obj, "minDigitsExponent")?,
+            keywords::get_u64(obj, "maxDigitsExponent")?,
             0,
         )?;
 
-        let integers_quantifier = match (min_digits_integer, max_digits_integer) {
-            (Some(min), Some(max)) => format!("{{{},{}}}", min, max),
-            (Some(min), None) => format!("{{{},}}", min),
-            (None, Some(max)) => format!("{{1,{}}}", max),
-            (None, None) => "*".to_string(),
-        };
-        let fraction_quantifier = match (min_digits_fraction, max_digits_fraction) {
-            (Some(min), Some(max)) => format!("{{{},{}}}", min, max),
-            (Some(min), None) => format!("{{{},}}", min),
-            (None, Some(max)) => format!("{{0,{}}}", max),
-            (None, None) => "+".to_string(),
-        };
-
-        let exponent_quantifier = match (min_digits_exponent, max_digits_exponent) {
-            (Some(min), Some(max)) => format!("{{{},{}}}", min, max),
-            (Some(min), None) => format!("{{{},}}", min),
-            (None, Some(max)) => format!("{{0,{}}}", max),
-            (None, None) => "+".to_string(),
-        };
-
-        Ok(format!(
-            r"((-)?(0|[1-9][0-9]{}))(\.[0-9]{})?([eE][+-][0-9]{})?",
-            integers_quantifier, fraction_quantifier, exponent_quantifier
-        ))
+        let integers_quantifier = quantifier(min_digits_integer, max_digits_integer, 1, "*");
+        let fraction_quantifier = quantifier(min_digits_fraction, max_digits_fraction, 0, "+");
+        let exponent_quantifier = quantifier(min_digits_exponent, max_digits_exponent, 0, "+");
+
+        out.write_str(&generator.number_syntax.number_regex(
+            &format!("[0-9]{}", integers_quantifier),
+            Some(&format!("[0-9]{}", fraction_quantifier)),
+            &format!("([eE][+-][0-9]{})?", exponent_quantifier),
+        ))?;
+    } else if let Some((integer_digits, exponent_digits)) = format_digits {
+        out.write_str(&generator.number_syntax.number_regex(
+            &format!("[0-9]{{0,{}}}", integer_digits - 1),
+            Some("[0-9]+"),
+            &format!("([eE][+-][0-9]{{1,{}}})?", exponent_digits),
+        ))?;
     } else {
-        let format_type = types::JsonType::Number;
-        Ok(format_type.to_regex().to_string())
+        out.write_str(&generator.number_syntax.number_regex(
+            "[0-9]*",
+            Some("[0-9]+"),
+            "([eE][+-][0-9]+)?",
+        ))?;
     }
+    Ok(())
 }
-pub fn handle_integer_type(obj: &serde_json::Map<String, Value>) -> Result<String> {
+pub fn handle_integer_type<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<()> {
+    let format_range = match keywords::get_str(obj, "format")? {
+        Some("int32") if generator.numeric_formats => Some(i32::MAX as u64),
+        Some("int64") if generator.numeric_formats => Some(i64::MAX as u64),
+        _ => None,
+    };
+    let (mut lower, mut upper) = range::bounds(obj)?;
+    let has_bounds = lower.is_some() || upper.is_some();
+    if let Some(max) = format_range {
+        // the minimum is one past `-max`, in two's complement
+        let bound = |value: String| range::Decimal::parse(&value).map(range::Bound::inclusive);
+        lower = range::tighter_lower(lower, bound(format!("-{}", max as u128 + 1)));
+        upper = range::tighter_upper(upper, bound(max.to_string()));
+    }
+    check_multiple_of(obj, generator)?;
+
     if obj.contains_key("minDigits") || obj.contains_key("maxDigits") {
+        if has_bounds {
+            return Err(not_combined(&["minDigits", "maxDigits"], obj).into());
+        }
         let (min_digits, max_digits) = validate_quantifiers(
-            obj.get("minDigits").and_then(Value::as_u64),
-            obj.get("maxDigits").and_then(Value::as_u64),
+            keywords::get_u64(obj, "minDigits")?,
+            keywords::get_u64(obj, "maxDigits")?,
             1,
         )?;
 
-        let quantifier = match (min_digits, max_digits) {
-            (Some(min), Some(max)) => format!("{{{},{}}}", min, max),
-            (Some(min), None) => format!("{{{},}}", min),
-            (None, Some(max)) => format!("{{0,{}}}", max),
-            (None, None) => "*".to_string(),
-        };
-
-        Ok(format!(r"(-)?(0|[1-9][0-9]{})", quantifier))
+        out.write_str(&generator.number_syntax.number_regex(
+            &format!("[0-9]{}", quantifier(min_digits, max_digits, 0, "*")),
+            None,
+            "",
+        ))?;
+    } else if lower.is_some() || upper.is_some() {
+        out.write_str(&within_bounds(lower, upper, true, generator)?)?;
     } else {
-        let format_type = types::JsonType::Integer;
-        Ok(format_type.to_regex().to_string())
+        out.write_str(&generator.number_syntax.number_regex("[0-9]*", None, ""))?;
     }
+    Ok(())
 }
-pub fn handle_object_type(
+pub fn handle_object_type<W: Write>(
+    out: &mut W,
     obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    let min_properties = obj.get("minProperties").and_then(|v| v.as_u64());
-    let max_properties = obj.get("maxProperties").and_then(|v| v.as_u64());
+    generator: &Generator,
+) -> Result<()> {
+    let whitespace_pattern = generator.whitespace_pattern;
+    let min_properties = keywords::get_u64(obj, "minProperties")?.unwrap_or(0);
+    let max_properties = keywords::get_u64(obj, "maxProperties")?;
+    check_bounds(
+        "minProperties",
+        "maxProperties",
+        min_properties,
+        max_properties,
+    )?;
+
+    let mut required = keywords::get_strings(obj, "required")?.unwrap_or_default();
+    required.sort_unstable();
+    required.dedup();
 
-    let num_repeats = get_num_items_pattern(min_properties, max_properties);
-
-    if num_repeats.is_none() {
-        return Ok(format!(r"\{{{}}}", whitespace_pattern));
+    let additional_properties = obj.get("additionalProperties");
+    if !required.is_empty() && additional_properties == Some(&Value::Bool(false)) {
+        return Err(SchemaError::new(
+            "the object has required properties but no properties can be there",
+        )
+        .with_keyword("required")
+        .into());
     }
-
-    let num_repeats = num_repeats.unwrap();
-    let allow_empty = if min_properties.unwrap_or(0) == 0 {
-        "?"
-    } else {
-        ""
+    check_bounds(
+        "required",
+        "maxProperties",
+        required.len() as u64,
+        max_properties,
+    )?;
+    let closed = match additional_properties {
+        None => generator.closed_objects,
+        Some(additional_properties) => *additional_properties == Value::Bool(false),
     };
+    if required.is_empty() && (max_properties == Some(0) || closed) {
+        write!(out, r"\{{{}}}", generator.inside_brackets)?;
+        return Ok(());
+    }
 
-    let additional_properties = obj.get("additionalProperties");
-
-    let value_pattern =
-        if additional_properties.is_none() || additional_properties == Some(&Value::Bool(true)) {
-            // Handle unconstrained object case
-            let mut legal_types = vec![
-                json!({"type": "string"}),
-                json!({"type": "number"}),
-                json!({"type": "boolean"}),
-                json!({"type": "null"}),
-            ];
-
-            let depth = obj.get("depth").and_then(|v| v.as_u64()).unwrap_or(2);
-            if depth > 0 {
-                legal_types.push(json!({"type": "object", "depth": depth - 1}));
-                legal_types.push(json!({"type": "array", "depth": depth - 1}));
+    let value_pattern = match additional_properties {
+        None | Some(Value::Bool(true)) => match unconstrained_call(obj, generator)? {
+            Some(call) => Ok(call),
+            None => {
+                // Handle unconstrained object case
+                let legal_types = unconstrained_types(
+                    obj,
+                    &["string", "number", "boolean", "null", "object", "array"],
+                    generator,
+                )?;
+                if legal_types.is_empty() {
+                    if !required.is_empty() {
+                        return Err(SchemaError::new(
+                            "the object has required properties but no type is allowed for their values",
+                        )
+                        .with_keyword("required")
+                        .into());
+                    }
+                    write!(out, r"\{{{}}}", generator.inside_brackets)?;
+                    return Ok(());
+                }
+
+                let any_of = json!({"anyOf": legal_types});
+                generator.to_regex(&any_of)
             }
-
-            let any_of = json!({"anyOf": legal_types});
-            to_regex(&any_of, Some(whitespace_pattern), full_schema)
-        } else {
-            to_regex(
-                additional_properties.unwrap(),
-                Some(whitespace_pattern),
-                full_schema,
-            )
-        };
-
-    // TODO handle the unwrap
-    let value_pattern = value_pattern.unwrap();
+        },
+        Some(additional_properties) => generator
+            .at("additionalProperties")
+            .to_regex(additional_properties),
+    }?;
+
+    if !required.is_empty() {
+        return handle_required_entries(
+            out,
+            &required,
+            &value_pattern,
+            (min_properties, max_properties),
+            closed,
+            generator,
+        );
+    }
 
     let key_value_pattern = format!(
-        "{}{whitespace_pattern}:{whitespace_pattern}{value_pattern}",
-        types::STRING
+        "{}{whitespace_pattern}:{}{value_pattern}",
+        types::STRING,
+        generator.after_colon
     );
-    let key_value_successor_pattern =
-        format!("{whitespace_pattern},{whitespace_pattern}{key_value_pattern}");
-    let multiple_key_value_pattern = format!(
-        "({key_value_pattern}({key_value_successor_pattern}){{{num_repeats}}}){allow_empty}"
+    let multiple_key_value_pattern = separated_items(
+        &key_value_pattern,
+        &format!("{whitespace_pattern},{}", generator.after_comma),
+        min_properties,
+        max_properties,
     );
 
-    let res = format!(
-        r"\{{{}{}{}}}",
-        whitespace_pattern, multiple_key_value_pattern, whitespace_pattern
-    );
-    Ok(res)
+    write!(
+        out,
+        r"\{{{0}{1}{0}}}",
+        generator.inside_brackets, multiple_key_value_pattern
+    )?;
+    Ok(())
+}
+
+/// Writes an object with `required` but no `properties`: the required keys
+/// first, in order, then as many other entries as the bounds allow, none when
+/// the object is `closed`. Every value takes `value_pattern`.
+fn handle_required_entries<W: Write>(
+    out: &mut W,
+    required: &[&str],
+    value_pattern: &str,
+    (min_properties, max_properties): (u64, Option<u64>),
+    closed: bool,
+    generator: &Generator,
+) -> Result<()> {
+    let (whitespace_pattern, after_colon) = (generator.whitespace_pattern, generator.after_colon);
+    let entry = |key: &str| format!("{key}{whitespace_pattern}:{after_colon}{value_pattern}");
+    let separator = format!("{whitespace_pattern},{}", generator.after_comma);
+
+    write!(out, r"\{{{}", generator.inside_brackets)?;
+    for (i, name) in required.iter().enumerate() {
+        if i > 0 {
+            out.write_str(&separator)?;
+        }
+        out.write_str(&entry(&property_name(name)?))?;
+    }
+    let count = required.len() as u64;
+    let min = min_properties.saturating_sub(count);
+    let max = max_properties.map(|max| max - count);
+    if !closed && max != Some(0) {
+        let other = format!("({}{})", separator, entry(types::STRING));
+        match max {
+            Some(max) => write!(out, "{}{{{},{}}}", other, min, max)?,
+            None => write!(out, "{}{{{},}}", other, min)?,
+        }
+    }
+    write!(out, r"{}\}}", generator.inside_brackets)?;
+    Ok(())
 }
 
-pub fn handle_array_type(
+/// The schemas of the unconstrained values below `obj`, an object or array
+/// without a schema for them, of the allowed `types` in order. Objects and
+/// arrays are only allowed above the `depth` of `obj`.
+fn unconstrained_types(
     obj: &serde_json::Map<String, Value>,
-    whitespace_pattern: &str,
-    full_schema: &Value,
-) -> Result<String> {
-    let num_repeats = get_num_items_pattern(
-        obj.get("minItems").and_then(Value::as_u64),
-        obj.get("maxItems").and_then(Value::as_u64),
-    )
-    .unwrap_or_else(|| String::from(""));
-
-    if num_repeats.is_empty() {
-        return Ok(format!(r"\[{0}{0}\]", whitespace_pattern));
+    types: &[&str],
+    generator: &Generator,
+) -> Result<Vec<Value>> {
+    let depth = keywords::get_u64(obj, "depth")?.unwrap_or(generator.unconstrained.depth);
+    Ok(types
+        .iter()
+        .filter(|&&instance_type| generator.unconstrained.allows(instance_type))
+        .filter_map(|&instance_type| match instance_type {
+            "object" | "array" => {
+                (depth > 0).then(|| json!({"type": instance_type, "depth": depth - 1}))
+            }
+            _ => Some(json!({ "type": instance_type })),
+        })
+        .collect())
+}
+
+/// The call to the rule of unconstrained values nested at any depth, when
+/// generating a grammar for `obj`, an object or array without a schema for
+/// them and without a `depth` for them.
+fn unconstrained_call(
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<Option<String>> {
+    let allows_any = ["string", "number", "boolean", "null", "object", "array"]
+        .iter()
+        .any(|instance_type| generator.unconstrained.allows(instance_type));
+    if !allows_any || keywords::get_u64(obj, "depth")?.is_some() {
+        return Ok(None);
     }
+    Ok(generator.call(Rule::Unconstrained))
+}
 
-    let allow_empty = if obj.get("minItems").and_then(Value::as_u64).unwrap_or(0) == 0 {
-        "?"
+pub fn handle_array_type<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<()> {
+    if keywords::get_bool(obj, "uniqueItems")? == Some(true)
+        && handle_unique_items(out, obj, generator)?
+    {
+        return Ok(());
+    }
+
+    let inside_brackets = generator.inside_brackets;
+    let min_items = keywords::get_u64(obj, "minItems")?.unwrap_or(0);
+    let max_items = keywords::get_u64(obj, "maxItems")?;
+    check_bounds("minItems", "maxItems", min_items, max_items)?;
+
+    if max_items == Some(0) {
+        write!(out, r"\[{0}{0}\]", inside_brackets)?;
+        return Ok(());
+    }
+
+    // the items regex appears twice, so it's generated once up front
+    let mut items_regex = String::new();
+    if let Some(items) = obj.get("items") {
+        items_regex = generator.at("items").to_regex(items)?;
+    } else if let Some(call) = unconstrained_call(obj, generator)? {
+        items_regex = call;
     } else {
-        ""
+        let legal_types = unconstrained_types(
+            obj,
+            &[
+                "boolean", "null", "number", "integer", "string", "object", "array",
+            ],
+            generator,
+        )?;
+        if legal_types.is_empty() {
+            if min_items > 0 {
+                return Err(SchemaError::new(
+                    "minItems is above 0 but no type is allowed for the items",
+                )
+                .with_keyword("minItems")
+                .into());
+            }
+            write!(out, r"\[{0}{0}\]", inside_brackets)?;
+            return Ok(());
+        }
+
+        for (i, legal_type) in legal_types.iter().enumerate() {
+            if i > 0 {
+                items_regex.push('|');
+            }
+            items_regex += &generator.to_regex(legal_type)?;
+        }
+    }
+
+    write!(
+        out,
+        r"\[{0}{1}{0}\]",
+        inside_brackets,
+        separated_items(
+            &format!("({})", items_regex),
+            &format!(",{}", generator.after_comma),
+            min_items,
+            max_items
+        )
+    )?;
+    Ok(())
+}
+
+/// Writes the regex of an array with `uniqueItems` and returns `true` when
+/// its items only take a few values, which are then listed in every order
+/// they can come in. Otherwise the keyword is left to the caller to ignore.
+fn handle_unique_items<W: Write>(
+    out: &mut W,
+    obj: &serde_json::Map<String, Value>,
+    generator: &Generator,
+) -> Result<bool> {
+    let min_items = keywords::get_u64(obj, "minItems")?.unwrap_or(0);
+    let max_items = keywords::get_u64(obj, "maxItems")?;
+    check_bounds("minItems", "maxItems", min_items, max_items)?;
+    // arrays of a single item are unique anyway
+    if max_items.is_some_and(|max| max <= 1) {
+        return Ok(false);
+    }
+
+    let Some(values) = obj.get("items").and_then(item_values) else {
+        generator.unenforced(
+            SchemaError::new("uniqueItems isn't enforced")
+                .with_keyword("uniqueItems")
+                .with_hint(
+                    "only items limited to a few values with \"enum\" or \"const\", or to booleans and null, are kept unique",
+                ),
+        )?;
+        return Ok(false);
     };
+    // counted on every listed value, before the regex of the items schema,
+    // which leaves some of them out, is generated
+    let count = distinct_values(values.iter().cloned()).len();
+    let min_items = min_items as usize;
+    if unique_arrays(
+        count,
+        min_items,
+        max_items.map_or(count, |max| (max as usize).min(count)),
+    ) > MAX_UNIQUE_ARRAYS
+    {
+        generator.unenforced(
+            SchemaError::new(format!(
+                "uniqueItems isn't enforced, the {} values of items make more than {} arrays",
+                count, MAX_UNIQUE_ARRAYS
+            ))
+            .with_keyword("uniqueItems")
+            .with_hint("lower maxItems or the number of values"),
+        )?;
+        return Ok(false);
+    }
 
-    if let Some(items) = obj.get("items") {
-        let items_regex = to_regex(items, Some(whitespace_pattern), full_schema)?;
-        Ok(format!(
-            r"\[{0}(({1})(,{0}({1})){2}){3}{0}\]",
-            whitespace_pattern, items_regex, num_repeats, allow_empty
+    // the values the rest of the items schema rejects, like those of another
+    // `type`, are left out
+    let items = &obj["items"];
+    let items_regex = generator.at("items").to_regex(items)?;
+    let items_regex = regex::Regex::new(&format!("^(?:{})$", items_regex))?;
+    let values = distinct_values(
+        values
+            .into_iter()
+            .filter(|value| items_regex.is_match(&value.to_string())),
+    );
+    let max_items = max_items.map_or(values.len(), |max| (max as usize).min(values.len()));
+    if min_items > values.len() {
+        return Err(SchemaError::new(format!(
+            "minItems is {} but only arrays of up to {} unique items are allowed",
+            min_items, max_items
         ))
-    } else {
-        let mut legal_types = vec![
-            json!({"type": "boolean"}),
-            json!({"type": "null"}),
-            json!({"type": "number"}),
-            json!({"type": "integer"}),
-            json!({"type": "string"}),
-        ];
-
-        let depth = obj.get("depth").and_then(Value::as_u64).unwrap_or(2);
-        if depth > 0 {
-            legal_types.push(json!({"type": "object", "depth": depth - 1}));
-            legal_types.push(json!({"type": "array", "depth": depth - 1}));
+        .with_keyword("minItems")
+        .into());
+    }
+
+    let separator = format!("{},{}", generator.whitespace_pattern, generator.after_comma);
+    write!(
+        out,
+        r"\[{0}{1}{0}\]",
+        generator.inside_brackets,
+        unique_items(&values, 0, min_items, max_items, &separator)
+    )?;
+    Ok(true)
+}
+
+/// The values the `items` schema lists with `enum` or `const`, or those of
+/// its `type` when it only takes booleans and `null`.
+fn item_values(items: &Value) -> Option<Vec<Value>> {
+    let values = match (items.get("enum"), items.get("const")) {
+        (Some(Value::Array(values)), _) => values.clone(),
+        (None, Some(value)) => vec![value.clone()],
+        _ => {
+            let types = match items.get("type") {
+                Some(Value::String(instance_type)) => vec![instance_type.as_str()],
+                Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+                _ => return None,
+            };
+            let mut values = Vec::new();
+            for instance_type in types {
+                match instance_type {
+                    "boolean" => values.extend([json!(true), json!(false)]),
+                    "null" => values.push(Value::Null),
+                    _ => return None,
+                }
+            }
+            values
         }
+    };
+    if values
+        .iter()
+        .any(|value| value.is_array() || value.is_object())
+    {
+        return None;
+    }
+    Some(values)
+}
 
-        let regexes: Result<Vec<String>> = legal_types
-            .iter()
-            .map(|t| to_regex(t, Some(whitespace_pattern), full_schema))
-            .collect();
+/// The regexes of `values`, as escaped JSON, one per distinct value: numbers
+/// like `1` and `1.0` are the same one, written either way.
+fn distinct_values(values: impl IntoIterator<Item = Value>) -> Vec<String> {
+    let mut distinct: Vec<(Value, Vec<String>)> = Vec::new();
+    for value in values {
+        let text = regex::escape(&value.to_string());
+        match distinct
+            .iter_mut()
+            .find(|(other, _)| same_value(other, &value))
+        {
+            Some((_, texts)) if !texts.contains(&text) => texts.push(text),
+            Some(_) => {}
+            None => distinct.push((value, vec![text])),
+        }
+    }
+    distinct
+        .into_iter()
+        .map(|(_, texts)| match texts.as_slice() {
+            [text] => text.clone(),
+            _ => format!("({})", texts.join("|")),
+        })
+        .collect()
+}
 
-        let regexes = regexes?;
-        let regexes_joined = regexes.join("|");
+/// Whether `a` and `b` are equal as JSON Schema compares values, numbers by
+/// their value whatever their form.
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a == b,
+            _ => a.as_f64() == b.as_f64(),
+        },
+        _ => a == b,
+    }
+}
+
+/// Number of arrays of `min` to `max` distinct items out of `values` values.
+fn unique_arrays(values: usize, min: usize, max: usize) -> usize {
+    let mut total: usize = 0;
+    let mut arrangements: usize = 1;
+    for len in 0..=max {
+        if len >= min {
+            total = total.saturating_add(arrangements);
+        }
+        arrangements = arrangements.saturating_mul(values - len.min(values));
+    }
+    total
+}
+
+/// Regex for the rest of an array of distinct `values`, once `written` items
+/// came before them.
+fn unique_items(
+    values: &[String],
+    written: usize,
+    min: usize,
+    max: usize,
+    separator: &str,
+) -> String {
+    if written == max {
+        return String::new();
+    }
+    let branches: Vec<String> = (0..values.len())
+        .map(|i| {
+            let mut rest = values.to_vec();
+            let value = rest.remove(i);
+            let separator_before = if written == 0 { "" } else { separator };
+            let tail = unique_items(&rest, written + 1, min, max, separator);
+            format!("{}{}{}", separator_before, value, tail)
+        })
+        .collect();
+    let optional = if written >= min { "?" } else { "" };
+    format!("({}){}", branches.join("|"), optional)
+}
 
-        Ok(format!(
-            r"\[{0}(({1})(,{0}({1})){2}){3}{0}\]",
-            whitespace_pattern, regexes_joined, num_repeats, allow_empty
+// HELPER FUNCTIONS
+
+/// Fails when the bound of `max_keyword` is below the one of `min_keyword`,
+/// which no value could satisfy.
+pub(crate) fn check_bounds(
+    min_keyword: &str,
+    max_keyword: &str,
+    min: u64,
+    max: Option<u64>,
+) -> Result<()> {
+    if max.is_some_and(|max| max < min) {
+        return Err(SchemaError::new(format!(
+            "{} must be greater than or equal to {}",
+            max_keyword, min_keyword
         ))
+        .with_keyword(max_keyword)
+        .into());
     }
+    Ok(())
 }
 
-/// HELPER FUNCTIONS
+/// Regex for between `min` and `max` matches of `item`, with `separator`
+/// between them. `item` is repeated as is, so it needs its own group if it
+/// has alternatives. Zero items have to be handled by the caller.
+pub(crate) fn separated_items(item: &str, separator: &str, min: u64, max: Option<u64>) -> String {
+    let rest = match (min.saturating_sub(1), max.map(|max| max.saturating_sub(1))) {
+        // a single item
+        (_, Some(0)) => String::new(),
+        (min, None) => format!("({}{}){{{},}}", separator, item, min),
+        (min, Some(max)) if min == max => format!("({}{}){{{}}}", separator, item, min),
+        (min, Some(max)) => format!("({}{}){{{},{}}}", separator, item, min, max),
+    };
+    match (rest.is_empty(), min) {
+        (true, 1) => item.to_string(),
+        (_, 0) => format!("({}{})?", item, rest),
+        _ => format!("({}{})", item, rest),
+    }
+}
 
 fn validate_quantifiers(
     min_bound: Option<u64>,
@@ -291,7 +710,68 @@ fn validate_quantifiers(
     Ok((min_bound.flatten(), max_bound.flatten()))
 }
 
-fn get_num_items_pattern(min_items: Option<u64>, max_items: Option<u64>) -> Option<String> {
+/// Regex for the numbers within `lower` and `upper`, or an error when
+/// there are none.
+fn within_bounds(
+    lower: Option<range::Bound>,
+    upper: Option<range::Bound>,
+    integer: bool,
+    generator: &Generator,
+) -> Result<String> {
+    let syntax = &generator.number_syntax;
+    range::range_regex(lower.as_ref(), upper.as_ref(), integer, syntax).ok_or_else(|| {
+        let kind = if integer { "integer" } else { "number" };
+        SchemaError::new(format!("no {} is within the minimum and maximum", kind)).into()
+    })
+}
+
+/// The error for digit counts set along with bounds, which can't both be
+/// enforced by the same regex.
+fn not_combined(digits_keywords: &[&str], obj: &serde_json::Map<String, Value>) -> SchemaError {
+    let keyword = digits_keywords
+        .iter()
+        .copied()
+        .find(|keyword| obj.contains_key(*keyword))
+        .unwrap_or(digits_keywords[0]);
+    SchemaError::new(format!(
+        "'{}' can't be combined with a minimum or maximum",
+        keyword
+    ))
+    .with_keyword(keyword)
+    .with_hint("bound the value with either the digits or the minimum and maximum")
+}
+
+/// `multipleOf` can't be written as a regex in general, so it's left to
+/// the validator.
+fn check_multiple_of(obj: &serde_json::Map<String, Value>, generator: &Generator) -> Result<()> {
+    if obj.contains_key("multipleOf") {
+        generator.unenforced(
+            SchemaError::new("multipleOf isn't enforced by the regex").with_keyword("multipleOf"),
+        )?;
+    }
+    Ok(())
+}
+
+/// Quantifier for between `min` and `max` repetitions, starting from
+/// `default_min` when only `max` is set and `unbounded` when neither is.
+fn quantifier(
+    min: Option<NonZeroU64>,
+    max: Option<NonZeroU64>,
+    default_min: u64,
+    unbounded: &'static str,
+) -> Cow<'static, str> {
+    match (min, max) {
+        (Some(min), Some(max)) => Cow::Owned(format!("{{{},{}}}", min, max)),
+        (Some(min), None) => Cow::Owned(format!("{{{},}}", min)),
+        (None, Some(max)) => Cow::Owned(format!("{{{},{}}}", default_min, max)),
+        (None, None) => Cow::Borrowed(unbounded),
+    }
+}
+
+pub(crate) fn get_num_items_pattern(
+    min_items: Option<u64>,
+    max_items: Option<u64>,
+) -> Option<String> {
     let min_items = min_items.unwrap_or(0);
 
     match max_items {
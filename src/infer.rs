@@ -0,0 +1,290 @@
+//! Schemas for simple regexes, the other way around from the generator.
+//!
+//! Meant for moving endpoints constrained by a regex over to a schema, and for
+//! checking that a regex the generator wrote reads back as its schema.
+
+use anyhow::{anyhow, Result};
+use regex_syntax::hir::{Class, Hir, HirKind, Look, Repetition};
+use serde_json::{json, Value};
+
+use crate::patterns;
+
+/// Alternations spelling out more values than this aren't read as an enum.
+const MAX_VALUES: usize = 256;
+
+/// A schema accepting what `regex` matches, when it has one of the simple
+/// forms this knows of, `None` otherwise:
+///
+/// - the regexes the generator writes for types and string formats, like
+///   [`patterns::INTEGER`],
+/// - literals and alternations of them, like `^(yes|no)$`, read as a `const`
+///   or an `enum` of JSON values when all of them are JSON, like `"a"|"b"`,
+///   and of strings otherwise,
+/// - runs of digits, like `[0-9]{2,4}`, which are strings as they can start
+///   with zeros, and `[1-9][0-9]{0,2}` or `0|[1-9][0-9]*`, which are integers.
+///
+/// Anchors around the whole regex and capture groups are ignored.
+pub fn infer_schema_from_regex(regex: &str) -> Result<Option<Value>> {
+    let hir = unanchored(normalized(regex)?);
+    Ok(known_pattern(&hir)
+        .or_else(|| enumeration(&hir))
+        .or_else(|| digits(&hir)))
+}
+
+/// The syntax tree of `regex` without capture groups.
+fn normalized(regex: &str) -> Result<Hir> {
+    let hir = regex_syntax::Parser::new()
+        .parse(regex)
+        .map_err(|e| anyhow!("Failed to parse regex: {}", e))?;
+    Ok(without_captures(hir))
+}
+
+fn without_captures(hir: Hir) -> Hir {
+    match hir.into_kind() {
+        HirKind::Capture(capture) => without_captures(*capture.sub),
+        HirKind::Concat(subs) => Hir::concat(subs.into_iter().map(without_captures).collect()),
+        HirKind::Alternation(subs) => {
+            Hir::alternation(subs.into_iter().map(without_captures).collect())
+        }
+        HirKind::Repetition(repetition) => Hir::repetition(Repetition {
+            sub: Box::new(without_captures(*repetition.sub)),
+            ..repetition
+        }),
+        HirKind::Empty => Hir::empty(),
+        HirKind::Literal(literal) => Hir::literal(literal.0),
+        HirKind::Class(class) => Hir::class(class),
+        HirKind::Look(look) => Hir::look(look),
+    }
+}
+
+/// `hir` without the `^` and `$` around it.
+fn unanchored(hir: Hir) -> Hir {
+    let HirKind::Concat(subs) = hir.kind() else {
+        return hir;
+    };
+    let is_look = |hir: &Hir, look: Look| matches!(hir.kind(), HirKind::Look(l) if *l == look);
+    let start = usize::from(subs.first().is_some_and(|hir| is_look(hir, Look::Start)));
+    let end = subs.len() - usize::from(subs.last().is_some_and(|hir| is_look(hir, Look::End)));
+    Hir::concat(subs[start..end.max(start)].to_vec())
+}
+
+fn known_pattern(hir: &Hir) -> Option<Value> {
+    let types = [
+        (patterns::STRING, json!({"type": "string"})),
+        (patterns::INTEGER, json!({"type": "integer"})),
+        (patterns::NUMBER, json!({"type": "number"})),
+        (patterns::BOOLEAN, json!({"type": "boolean"})),
+        (patterns::NULL, json!({"type": "null"})),
+    ];
+    let formats = [
+        (patterns::DATE_TIME, "date-time"),
+        (patterns::DATE, "date"),
+        (patterns::TIME, "time"),
+        (patterns::UUID, "uuid"),
+        (patterns::IRI, "iri"),
+        (patterns::URI_TEMPLATE, "uri-template"),
+        (patterns::IDN_HOSTNAME, "idn-hostname"),
+        (patterns::BYTE, "byte"),
+    ]
+    .map(|(pattern, format)| (pattern, json!({"type": "string", "format": format})));
+    types
+        .into_iter()
+        .chain(formats)
+        .find(|(pattern, _)| normalized(pattern).is_ok_and(|known| known == *hir))
+        .map(|(_, schema)| schema)
+}
+
+/// A `const` or an `enum` of the texts `hir` matches, when there are few.
+fn enumeration(hir: &Hir) -> Option<Value> {
+    let mut found = texts(hir)?;
+    dedup(&mut found);
+    let json: Option<Vec<Value>> = found
+        .iter()
+        .map(|text| serde_json::from_str(text).ok())
+        .collect();
+    let mut values = json.unwrap_or_else(|| found.into_iter().map(Value::from).collect());
+    dedup(&mut values);
+    Some(match <[Value; 1]>::try_from(values) {
+        Ok([value]) => json!({"const": value}),
+        Err(values) => json!({"enum": values}),
+    })
+}
+
+/// Removes the repeated values of `values`, keeping their first place.
+fn dedup<T: PartialEq + Clone>(values: &mut Vec<T>) {
+    let mut kept: Vec<T> = Vec::with_capacity(values.len());
+    for value in values.drain(..) {
+        if !kept.contains(&value) {
+            kept.push(value);
+        }
+    }
+    *values = kept;
+}
+
+/// Every text `hir` matches, when it matches few of them.
+fn texts(hir: &Hir) -> Option<Vec<String>> {
+    match hir.kind() {
+        HirKind::Empty => Some(vec![String::new()]),
+        HirKind::Literal(literal) => Some(vec![String::from_utf8(literal.0.to_vec()).ok()?]),
+        HirKind::Class(Class::Unicode(class)) => {
+            let chars: Vec<char> = class
+                .ranges()
+                .iter()
+                .flat_map(|range| range.start()..=range.end())
+                .take(MAX_VALUES + 1)
+                .collect();
+            (chars.len() <= MAX_VALUES).then(|| chars.into_iter().map(String::from).collect())
+        }
+        HirKind::Alternation(subs) => {
+            let mut found = Vec::new();
+            for sub in subs {
+                found.extend(texts(sub)?);
+                if found.len() > MAX_VALUES {
+                    return None;
+                }
+            }
+            Some(found)
+        }
+        HirKind::Concat(subs) => {
+            let mut found = vec![String::new()];
+            for sub in subs {
+                let ends = texts(sub)?;
+                if found.len() * ends.len() > MAX_VALUES {
+                    return None;
+                }
+                found = found
+                    .iter()
+                    .flat_map(|start| ends.iter().map(move |end| format!("{}{}", start, end)))
+                    .collect();
+            }
+            Some(found)
+        }
+        // only optional parts, like `-?`
+        HirKind::Repetition(repetition) if repetition.min == 0 && repetition.max == Some(1) => {
+            let mut found = vec![String::new()];
+            found.extend(texts(&repetition.sub)?);
+            Some(found)
+        }
+        HirKind::Class(Class::Bytes(_)) | HirKind::Repetition(_) | HirKind::Look(_) => None,
+        HirKind::Capture(capture) => texts(&capture.sub),
+    }
+}
+
+/// A schema for a run of digits, bounded by how many there can be.
+fn digits(hir: &Hir) -> Option<Value> {
+    if let Some((min, max)) = digit_run(hir) {
+        let mut schema = json!({
+            "type": "string",
+            "pattern": match max {
+                Some(max) => format!("^[0-9]{{{},{}}}$", min, max),
+                None => format!("^[0-9]{{{},}}$", min),
+            },
+            "minLength": min,
+        });
+        if let Some(max) = max {
+            schema["maxLength"] = json!(max);
+        }
+        return Some(schema);
+    }
+
+    // integers without leading zeros, from 0 or from their smallest length
+    let (zero, run) = match hir.kind() {
+        HirKind::Alternation(subs) if subs.len() == 2 && is_literal(&subs[0], "0") => {
+            (true, &subs[1])
+        }
+        _ => (false, hir),
+    };
+    let HirKind::Concat(subs) = run.kind() else {
+        return None;
+    };
+    let [first, rest] = subs.as_slice() else {
+        return None;
+    };
+    if !is_class(first, "[1-9]") {
+        return None;
+    }
+    let (min, max) = digit_run(rest)?;
+    let minimum = if zero { 0 } else { 10u64.checked_pow(min)? };
+    let mut schema = json!({"type": "integer", "minimum": minimum});
+    if let Some(max) = max {
+        schema["maximum"] = json!(10u64.checked_pow(max + 1)? - 1);
+    }
+    Some(schema)
+}
+
+/// The least and most digits of a repeated `[0-9]` or `\d`.
+fn digit_run(hir: &Hir) -> Option<(u32, Option<u32>)> {
+    match hir.kind() {
+        HirKind::Repetition(repetition)
+            if is_class(&repetition.sub, "[0-9]") || is_class(&repetition.sub, r"\d") =>
+        {
+            Some((repetition.min, repetition.max))
+        }
+        _ if is_class(hir, "[0-9]") || is_class(hir, r"\d") => Some((1, Some(1))),
+        _ => None,
+    }
+}
+
+fn is_class(hir: &Hir, class: &str) -> bool {
+    matches!(hir.kind(), HirKind::Class(_)) && normalized(class).is_ok_and(|class| class == *hir)
+}
+
+fn is_literal(hir: &Hir, text: &str) -> bool {
+    matches!(hir.kind(), HirKind::Literal(literal) if *literal.0 == *text.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guidance::build_regex_from_schema;
+
+    fn infer(regex: &str) -> Option<Value> {
+        infer_schema_from_regex(regex).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for schema in [
+            json!({"type": "string"}),
+            json!({"type": "integer"}),
+            json!({"type": "number"}),
+            json!({"type": "boolean"}),
+            json!({"type": "null"}),
+            json!({"type": "string", "format": "date-time"}),
+            json!({"type": "string", "format": "uuid"}),
+            json!({"const": "fixed"}),
+            json!({"const": 42}),
+            json!({"enum": ["red", "green", 3, null]}),
+        ] {
+            let regex = build_regex_from_schema(&schema.to_string(), None).unwrap();
+            assert_eq!(infer(&regex), Some(schema.clone()), "{}", regex);
+        }
+    }
+
+    #[test]
+    fn test_legacy_patterns() {
+        assert_eq!(infer("^(yes|no)$"), Some(json!({"enum": ["yes", "no"]})));
+        assert_eq!(
+            infer(r"^v-?[12]$"),
+            Some(json!({"enum": ["v1", "v2", "v-1", "v-2"]}))
+        );
+        assert_eq!(infer("ok"), Some(json!({"const": "ok"})));
+        assert_eq!(
+            infer(r"^\d{3,5}$"),
+            Some(
+                json!({"type": "string", "pattern": "^[0-9]{3,5}$", "minLength": 3, "maxLength": 5})
+            )
+        );
+        assert_eq!(
+            infer("^[1-9][0-9]{0,2}$"),
+            Some(json!({"type": "integer", "minimum": 1, "maximum": 999}))
+        );
+        assert_eq!(
+            infer("0|[1-9][0-9]{0,3}"),
+            Some(json!({"type": "integer", "minimum": 0, "maximum": 9999}))
+        );
+        assert_eq!(infer("[a-z]+@[a-z]+"), None);
+        assert_eq!(infer("x[0-9]{4}"), None);
+        assert!(infer_schema_from_regex("(").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! Typed access to the keywords of a schema object.
+//!
+//! Each accessor returns `None` when the keyword isn't set, and an error
+//! naming the keyword when it's set to a value of the wrong type, instead of
+//! ignoring that value.
+
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+use crate::error::SchemaError;
+
+/// The value of `keyword` read by `read`, which returns `None` for values of
+/// the wrong type, described by `expected`.
+fn get<'a, T>(
+    obj: &'a Map<String, Value>,
+    keyword: &str,
+    expected: &str,
+    read: impl FnOnce(&'a Value) -> Option<T>,
+) -> Result<Option<T>> {
+    match obj.get(keyword) {
+        None => Ok(None),
+        Some(value) => match read(value) {
+            Some(value) => Ok(Some(value)),
+            None => Err(SchemaError::wrong_type(keyword, expected, value).into()),
+        },
+    }
+}
+
+/// A count like `minLength`, which may be written as `3.0` too.
+pub fn get_u64(obj: &Map<String, Value>, keyword: &str) -> Result<Option<u64>> {
+    get(obj, keyword, "a non-negative integer", |value| {
+        value.as_u64().or_else(|| {
+            value
+                .as_f64()
+                .filter(|n| *n >= 0.0 && n.fract() == 0.0 && *n <= u64::MAX as f64)
+                .map(|n| n as u64)
+        })
+    })
+}
+
+pub fn get_bool(obj: &Map<String, Value>, keyword: &str) -> Result<Option<bool>> {
+    get(obj, keyword, "a boolean", Value::as_bool)
+}
+
+pub fn get_str<'a>(obj: &'a Map<String, Value>, keyword: &str) -> Result<Option<&'a str>> {
+    get(obj, keyword, "a string", Value::as_str)
+}
+
+pub fn get_array<'a>(obj: &'a Map<String, Value>, keyword: &str) -> Result<Option<&'a Vec<Value>>> {
+    get(obj, keyword, "an array", Value::as_array)
+}
+
+pub fn get_object<'a>(
+    obj: &'a Map<String, Value>,
+    keyword: &str,
+) -> Result<Option<&'a Map<String, Value>>> {
+    get(obj, keyword, "an object", Value::as_object)
+}
+
+/// A list of names like `required`.
+pub fn get_strings<'a>(obj: &'a Map<String, Value>, keyword: &str) -> Result<Option<Vec<&'a str>>> {
+    get(obj, keyword, "an array of strings", |value| {
+        value.as_array()?.iter().map(Value::as_str).collect()
+    })
+}
+
+/// A keyword the caller only got to because it's set.
+pub fn required<T>(value: Option<T>, keyword: &str) -> Result<T> {
+    value.ok_or_else(|| SchemaError::new(format!("keyword '{}' is missing", keyword)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_accessors() {
+        let obj = json!({"minLength": 3.0, "maxLength": -1, "required": ["a", 1], "enum": []});
+        let obj = obj.as_object().unwrap();
+
+        assert_eq!(get_u64(obj, "minLength").unwrap(), Some(3));
+        assert_eq!(get_u64(obj, "minItems").unwrap(), None);
+        assert!(get_array(obj, "enum").unwrap().unwrap().is_empty());
+
+        let error = get_u64(obj, "maxLength").unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<SchemaError>().unwrap(),
+            &SchemaError::wrong_type("maxLength", "a non-negative integer", &json!(-1))
+        );
+        assert_eq!(
+            error.to_string(),
+            "keyword 'maxLength' expected a non-negative integer, got -1"
+        );
+        assert!(get_strings(obj, "required").is_err());
+    }
+}
@@ -1,4 +1,54 @@
+pub mod automaton;
+pub mod avro;
+#[cfg(feature = "candle")]
+pub mod candle;
+pub mod compat;
+pub mod csv;
+pub mod derive;
+#[cfg(feature = "miette")]
+pub mod diagnostic;
+pub mod dialect;
+pub mod diff;
+pub mod draft;
+pub mod error;
+mod exclusive;
+pub mod explain;
+pub mod extract;
+pub mod gbnf;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod guidance;
-pub mod handle_types;
+mod handle_types;
+pub mod infer;
+mod keywords;
+pub mod lint;
+mod merge;
+pub mod openapi;
+pub mod patch;
+pub mod patterns;
+pub mod prelude;
+pub mod program;
+pub mod project;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod pushdown;
+#[cfg(feature = "python")]
 pub mod py_wrapper;
+#[cfg(feature = "python")]
+pub mod python;
+mod range;
+pub mod sample;
+pub mod sampler;
+mod sandbox;
+pub mod server;
+mod shared;
+pub mod simplify;
+pub mod skeleton;
+pub mod snapshots;
+pub mod toml;
+pub mod tools;
 pub mod types;
+pub mod typescript;
+pub mod union;
+pub mod xml;
+pub mod yaml;
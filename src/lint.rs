@@ -0,0 +1,370 @@
+//! Checks of schemas for what makes constrained decoding slow or unreliable,
+//! to gate the schemas others submit before compiling them.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use regex_syntax::hir::{Hir, HirKind};
+use regex_syntax::Parser;
+use serde_json::{Map, Value};
+
+use crate::error::SchemaError;
+use crate::guidance::{build_regex_from_schema_with_warnings, RegexOptions};
+
+/// Objects with more optional properties than this get a warning, as the
+/// regex of an object grows with the square of their count.
+const MANY_OPTIONAL_PROPERTIES: usize = 12;
+/// Objects with more optional properties than this get an error.
+const TOO_MANY_OPTIONAL_PROPERTIES: usize = 48;
+/// Formats whose strings have a bounded length.
+const BOUNDED_FORMATS: &[&str] = &["date", "time", "date-time", "uuid", "ipv4", "ipv6"];
+
+/// How much a [`Lint`] degrades constrained decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Worth knowing, like a string the model may write at any length where
+    /// the schema doesn't always ask for one.
+    Info,
+    /// Makes decoding slower or lets through what the schema rejects.
+    Warning,
+    /// Makes the regex too large to use, or the schema doesn't compile.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A construct of a schema degrading constrained decoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub severity: Severity,
+    /// JSON Pointer to the subschema or keyword, `""` for the root.
+    pub pointer: String,
+    /// The check that found it, like `unbounded-string`.
+    pub rule: &'static str,
+    pub message: String,
+}
+
+/// Lints `schema`, by pointer:
+///
+/// - `unbounded-string`: strings without `maxLength`, `enum`, `const`,
+///   `pattern` or a bounded `format`, as warnings where every document has
+///   them (required properties, items, the root) and as infos elsewhere.
+/// - `many-optional-properties`: objects with many optional properties.
+/// - `unbounded-pattern`: `pattern`s with unbounded repetitions, like `a+`.
+/// - `ambiguous-union`: `anyOf` and `oneOf` branches a streaming matcher
+///   can't tell apart for long, see [`RegexOptions::report_ambiguity`].
+/// - `unenforced-keyword`: keywords the regex ignores.
+/// - `invalid-schema`: the schema doesn't compile, as an error.
+pub fn lint(schema: &Value) -> Vec<Lint> {
+    let mut linter = Linter {
+        lints: Vec::new(),
+        hot_refs: HashSet::new(),
+    };
+    linter.walk(schema, String::new(), true);
+
+    // definitions are linted as hot when a hot subschema refers to them
+    let definitions: Vec<(String, &Value)> = ["$defs", "definitions"]
+        .into_iter()
+        .filter_map(|keyword| Some((keyword, schema.get(keyword)?.as_object()?)))
+        .flat_map(|(keyword, definitions)| {
+            definitions
+                .iter()
+                .map(move |(name, value)| (format!("/{}/{}", keyword, escape_token(name)), value))
+        })
+        .collect();
+    let mut walked = HashSet::new();
+    while let Some((pointer, value)) = definitions
+        .iter()
+        .find(|(pointer, _)| !walked.contains(pointer) && linter.hot_refs.contains(pointer))
+    {
+        walked.insert(pointer.clone());
+        linter.walk(value, pointer.clone(), true);
+    }
+    for (pointer, value) in &definitions {
+        if !walked.contains(pointer) {
+            linter.walk(value, pointer.clone(), false);
+        }
+    }
+
+    let options = RegexOptions {
+        report_ambiguity: true,
+        ..RegexOptions::default()
+    };
+    match build_regex_from_schema_with_warnings(&schema.to_string(), &options) {
+        Ok((_, warnings)) => {
+            for warning in warnings {
+                let ambiguous = matches!(warning.keyword.as_deref(), Some("anyOf" | "oneOf"));
+                linter.lints.push(Lint {
+                    severity: Severity::Warning,
+                    pointer: warning.pointer.unwrap_or_default(),
+                    rule: match ambiguous {
+                        true => "ambiguous-union",
+                        false => "unenforced-keyword",
+                    },
+                    message: warning.message,
+                });
+            }
+        }
+        Err(error) => linter.lints.push(Lint {
+            severity: Severity::Error,
+            pointer: SchemaError::find(&error)
+                .and_then(|error| error.pointer.clone())
+                .unwrap_or_default(),
+            rule: "invalid-schema",
+            message: format!("{:#}", error),
+        }),
+    }
+
+    let mut lints = linter.lints;
+    lints.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+    lints
+}
+
+struct Linter {
+    lints: Vec<Lint>,
+    /// The definitions referred to by hot subschemas, by JSON Pointer.
+    hot_refs: HashSet<String>,
+}
+
+impl Linter {
+    /// Lints the subschema `node` at `pointer`, which is hot when every
+    /// document where its parent is has it too.
+    fn walk(&mut self, node: &Value, pointer: String, hot: bool) {
+        let Some(obj) = node.as_object() else {
+            return;
+        };
+        if let Some(reference) = obj.get("$ref").and_then(Value::as_str) {
+            if hot {
+                if let Some(target) = reference.strip_prefix('#') {
+                    self.hot_refs.insert(target.to_string());
+                }
+            }
+        }
+
+        self.lint_string(obj, &pointer, hot);
+        self.lint_pattern(obj, &pointer);
+        self.lint_properties(obj, &pointer, hot);
+
+        for keyword in ["allOf", "anyOf", "oneOf"] {
+            if let Some(subschemas) = obj.get(keyword).and_then(Value::as_array) {
+                // a branch of a union is only in some of the documents
+                let hot = hot && (keyword == "allOf" || subschemas.len() == 1);
+                for (i, value) in subschemas.iter().enumerate() {
+                    self.walk(value, format!("{}/{}/{}", pointer, keyword, i), hot);
+                }
+            }
+        }
+        if let Some(subschemas) = obj.get("prefixItems").and_then(Value::as_array) {
+            for (i, value) in subschemas.iter().enumerate() {
+                self.walk(value, format!("{}/prefixItems/{}", pointer, i), true);
+            }
+        }
+        match obj.get("items") {
+            Some(Value::Array(subschemas)) => {
+                for (i, value) in subschemas.iter().enumerate() {
+                    self.walk(value, format!("{}/items/{}", pointer, i), true);
+                }
+            }
+            Some(value) => self.walk(value, format!("{}/items", pointer), true),
+            None => {}
+        }
+        if let Some(value) = obj.get("additionalProperties") {
+            self.walk(value, format!("{}/additionalProperties", pointer), false);
+        }
+    }
+
+    fn lint_string(&mut self, obj: &Map<String, Value>, pointer: &str, hot: bool) {
+        let is_string = match obj.get("type") {
+            Some(Value::String(name)) => name == "string",
+            Some(Value::Array(names)) => names.iter().any(|name| name == "string"),
+            _ => false,
+        };
+        let bounded = ["maxLength", "enum", "const", "pattern"]
+            .iter()
+            .any(|keyword| obj.contains_key(*keyword))
+            || obj
+                .get("format")
+                .and_then(Value::as_str)
+                .is_some_and(|format| BOUNDED_FORMATS.contains(&format));
+        if !is_string || bounded {
+            return;
+        }
+        self.lints.push(Lint {
+            severity: if hot {
+                Severity::Warning
+            } else {
+                Severity::Info
+            },
+            pointer: pointer.to_string(),
+            rule: "unbounded-string",
+            message: "string without maxLength, which the model may write at any length"
+                .to_string(),
+        });
+    }
+
+    fn lint_pattern(&mut self, obj: &Map<String, Value>, pointer: &str) {
+        let Some(pattern) = obj.get("pattern").and_then(Value::as_str) else {
+            return;
+        };
+        // invalid patterns fail to compile, which is linted on its own
+        let Ok(hir) = Parser::new().parse(pattern) else {
+            return;
+        };
+        if has_unbounded_repetition(&hir) {
+            self.lints.push(Lint {
+                severity: Severity::Warning,
+                pointer: format!("{}/pattern", pointer),
+                rule: "unbounded-pattern",
+                message: format!(
+                    "pattern '{}' repeats without bound, which the model may do at any length",
+                    pattern
+                ),
+            });
+        }
+    }
+
+    fn lint_properties(&mut self, obj: &Map<String, Value>, pointer: &str, hot: bool) {
+        let Some(properties) = obj.get("properties").and_then(Value::as_object) else {
+            return;
+        };
+        let required: HashSet<&str> = obj
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+
+        let optional = properties
+            .keys()
+            .filter(|name| !required.contains(name.as_str()))
+            .count();
+        if optional > MANY_OPTIONAL_PROPERTIES {
+            self.lints.push(Lint {
+                severity: if optional > TOO_MANY_OPTIONAL_PROPERTIES {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                },
+                pointer: pointer.to_string(),
+                rule: "many-optional-properties",
+                message: format!(
+                    "{} optional properties, which the regex has to allow in every combination",
+                    optional
+                ),
+            });
+        }
+
+        for (name, value) in properties {
+            let hot = hot && required.contains(name.as_str());
+            self.walk(
+                value,
+                format!("{}/properties/{}", pointer, escape_token(name)),
+                hot,
+            );
+        }
+    }
+}
+
+fn has_unbounded_repetition(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Repetition(repetition) => {
+            repetition.max.is_none() || has_unbounded_repetition(&repetition.sub)
+        }
+        HirKind::Capture(capture) => has_unbounded_repetition(&capture.sub),
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => {
+            subs.iter().any(has_unbounded_repetition)
+        }
+        _ => false,
+    }
+}
+
+fn escape_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn rules(lints: &[Lint]) -> Vec<(Severity, &str, &str)> {
+        lints
+            .iter()
+            .map(|lint| (lint.severity, lint.rule, lint.pointer.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let optional: Map<String, Value> = (0..20)
+            .map(|i| (format!("flag{}", i), json!({"type": "boolean"})))
+            .collect();
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "note": {"type": "string"},
+                "day": {"type": "string", "format": "date"},
+                "code": {"type": "string", "pattern": "^[A-Z]{2,3}-[0-9]+$"},
+                "id": {"type": "string", "pattern": "^[a-f0-9]{8}$"},
+                "home": {"$ref": "#/$defs/Address"},
+                "flags": {"type": "object", "properties": optional},
+                "pet": {"anyOf": [
+                    {"type": "object", "properties": {"a_name": {"type": "string", "maxLength": 30}, "b_age": {"type": "integer"}}, "required": ["a_name", "b_age"]},
+                    {"type": "object", "properties": {"a_name": {"type": "string", "maxLength": 30}, "b_legs": {"type": "integer"}}, "required": ["a_name", "b_legs"]}
+                ]}
+            },
+            "required": ["name", "day", "code", "id", "home", "pet"],
+            "$defs": {
+                "Address": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"]
+                }
+            }
+        });
+        assert_eq!(
+            rules(&lint(&schema)),
+            [
+                (
+                    Severity::Warning,
+                    "unbounded-string",
+                    "/$defs/Address/properties/city"
+                ),
+                (
+                    Severity::Warning,
+                    "unbounded-pattern",
+                    "/properties/code/pattern"
+                ),
+                (
+                    Severity::Warning,
+                    "many-optional-properties",
+                    "/properties/flags"
+                ),
+                (Severity::Warning, "unbounded-string", "/properties/name"),
+                (Severity::Info, "unbounded-string", "/properties/note"),
+                (
+                    Severity::Warning,
+                    "ambiguous-union",
+                    "/properties/pet/anyOf"
+                ),
+            ]
+        );
+
+        let lints = lint(&json!({"type": "object", "properties": {"a": {"minLength": -1}}}));
+        assert_eq!(
+            rules(&lints),
+            [(Severity::Error, "invalid-schema", "/properties/a/minLength")]
+        );
+    }
+}
@@ -1,83 +1,1231 @@
-use regex::Regex;
-use serde_json::json;
-use std::env;
-
-mod guidance;
-mod handle_types;
-mod py_wrapper;
-mod types;
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() != 2 {
-        println!(
-            "Usage: {} <multiple|limit|simplebool|simple|empty>",
-            args[0]
-        );
-        return;
-    }
-
-    let schema_type = &args[1];
-
-    let json_input = match schema_type.as_str() {
-        "multiple" => json!({
-            "type": "object",
-            "properties": {
-                "name": {"type": "string"},
-                "flag": {"type": "boolean"},
-            },
-        }),
-        "limit" => json!({
-            "type": "object",
-            "properties": {
-                "name": {"type": "string", "minLength": 2, "maxLength": 5},
-            },
-        }),
-        "simple" => json!({
-            "type": "object",
-            "properties": {
-                "name": {"type": "string"},
-            },
-        }),
-        "simplebool" => json!({
-            "type": "object",
-            "properties": {
-                "flag": {"type": "boolean"},
-            },
-        }),
-        "empty" => json!({
-            "type": "object",
-        }),
-        "number" => json!({
-            "type": "number",
-            "minDigitsInteger": 12,
-        }),
-        _ => {
-            println!("Invalid argument. Use 'simple' or 'empty'.");
-            return;
-        }
-    }
-    .to_string();
-
-    let regex = guidance::build_regex_from_schema(&json_input, None).unwrap();
-    let regex_pattern = Regex::new(&regex).unwrap();
-
-    println!("{}", regex_pattern.as_str());
-
-    match schema_type.as_str() {
-        "simple" => {
-            let reference_regex =
-                std::fs::read_to_string("reference/regex_for_simple_json.txt").unwrap();
-            println!("regex length: {}", regex_pattern.as_str().len());
-            println!("reference regex length: {}", reference_regex.len());
-        }
-        "empty" => {
-            let reference_regex =
-                std::fs::read_to_string("reference/regex_for_empty_json.txt").unwrap();
-            println!("regex length: {}", regex_pattern.as_str().len());
-            println!("reference regex length: {}", reference_regex.len());
-        }
-        _ => {}
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use guidance_rs::automaton::{self, Validation};
+use guidance_rs::error::SchemaError;
+use guidance_rs::server::Server;
+use guidance_rs::types::NumberSyntax;
+use guidance_rs::{diff, draft, explain, gbnf, guidance, lint, sample, skeleton, snapshots};
+use serde_json::{json, Value};
+
+static WATCH_INTERVAL: Duration = Duration::from_millis(300);
+
+#[derive(Parser)]
+#[command(
+    name = "guidance-rs",
+    about = "Compile JSON schemas into regexes for guided generation"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Print results and errors as JSON
+    #[arg(long, global = true)]
+    json: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compile a JSON schema into a regex
+    Compile(Box<CompileArgs>),
+    /// Check whether a document matches the regex of a schema
+    Validate(ValidateArgs),
+    /// Print random documents permitted by the regex of a schema
+    Sample(SampleArgs),
+    /// Show the regex fragment generated for each node of a schema
+    Explain(ExplainArgs),
+    /// Check whether two schemas or regexes match the same documents
+    Diff(DiffArgs),
+    /// Report the size of the regex and automata generated for a schema
+    Stats(StatsArgs),
+    /// Flag what in a schema degrades constrained decoding
+    Lint(LintArgs),
+    /// Serve the compile, validate and index endpoints over HTTP
+    Serve(ServeArgs),
+    /// Regenerate the snapshots of the regexes of the schema corpus
+    GenSnapshots(GenSnapshotsArgs),
+    /// Compare the regex of a schema with the one outlines generates
+    #[cfg(feature = "python")]
+    CompareOutlines(CompareOutlinesArgs),
+}
+
+#[derive(Args)]
+struct CompileArgs {
+    /// Path to the JSON schema, or `-` for stdin
+    #[arg(default_value = "-")]
+    schema: PathBuf,
+
+    /// Compile every `*.json` schema in this directory instead
+    #[arg(long, conflicts_with = "schema", requires = "out_dir")]
+    dir: Option<PathBuf>,
+
+    /// Where to write one output file per schema of `--dir`
+    #[arg(long, requires = "dir")]
+    out_dir: Option<PathBuf>,
+
+    /// Start from the options of a provider or library, which the other flags add to
+    #[arg(long, value_enum)]
+    preset: Option<Preset>,
+
+    /// Regex for the whitespace allowed between JSON tokens
+    #[arg(long)]
+    whitespace_pattern: Option<String>,
+
+    /// Regex for the whitespace after the colons of objects instead
+    #[arg(long)]
+    whitespace_after_colon: Option<String>,
+
+    /// Regex for the whitespace after the commas of objects and arrays instead
+    #[arg(long)]
+    whitespace_after_comma: Option<String>,
+
+    /// Regex for the whitespace inside the brackets of objects and arrays instead
+    #[arg(long)]
+    whitespace_inside_brackets: Option<String>,
+
+    /// Recompile whenever the schema file changes, until interrupted
+    #[arg(long, conflicts_with = "dir")]
+    watch: bool,
+
+    /// Anchor the regex to the start and end of the input
+    #[arg(long)]
+    anchor: bool,
+
+    /// Allow whitespace before and after the top-level value
+    #[arg(long)]
+    padded: bool,
+
+    /// Emit capturing groups, matching the regexes of Outlines
+    #[arg(long)]
+    capture_groups: bool,
+
+    /// Wrap the values of properties in groups named after them
+    #[arg(long)]
+    named_groups: bool,
+
+    /// Shrink the regex without changing what it matches
+    #[arg(long)]
+    simplify: bool,
+
+    /// Write object properties sorted by name, whatever the schema order
+    #[arg(long)]
+    sort_properties: bool,
+
+    /// Write every property of objects, even the optional ones
+    #[arg(long)]
+    treat_all_properties_required: bool,
+
+    /// Allow no properties but the listed ones in objects without additionalProperties
+    #[arg(long)]
+    closed_objects: bool,
+
+    /// Write enum values sorted, whatever the schema order
+    #[arg(long)]
+    sort_enums: bool,
+
+    /// Most branches per alternation of an enum, splitting larger ones
+    #[arg(long)]
+    max_alternation_width: Option<usize>,
+
+    /// Accept enum numbers in any form of their value, like 1.0 for 1
+    #[arg(long)]
+    equivalent_numbers: bool,
+
+    /// Fail on keywords the regex can't enforce instead of warning about them
+    #[arg(long)]
+    strict: bool,
+
+    /// Warn about anyOf and oneOf branches accepting values with a long common start
+    #[arg(long)]
+    report_ambiguity: bool,
+
+    /// Match the values of exactly one oneOf branch, as validators do
+    #[arg(long)]
+    exclusive_one_of: bool,
+
+    /// Types of the values allowed where the schema doesn't constrain them, like `{}`
+    #[arg(long, value_enum, value_delimiter = ',')]
+    unconstrained_types: Option<Vec<InstanceType>>,
+
+    /// Levels of objects and arrays allowed below an unconstrained one
+    #[arg(long)]
+    unconstrained_depth: Option<u64>,
+
+    /// Read the schema as this draft instead of the one its `$schema` declares
+    #[arg(long, value_enum)]
+    draft: Option<Draft>,
+
+    /// Bound integers and numbers by their OpenAPI format (int32, int64, float, double)
+    #[arg(long)]
+    numeric_formats: bool,
+
+    /// Accept integer parts with leading zeros, like 007
+    #[arg(long)]
+    allow_leading_zeros: bool,
+
+    /// Accept numbers with a + sign
+    #[arg(long)]
+    allow_plus_sign: bool,
+
+    /// Accept fractions without an integer part, like .5
+    #[arg(long)]
+    allow_bare_fractions: bool,
+
+    /// Require a fraction on numbers, like 5.0 instead of 5
+    #[arg(long)]
+    force_decimal_for_number: bool,
+
+    /// Fail when the regex would be longer than this many bytes
+    #[arg(long)]
+    max_regex_len: Option<usize>,
+
+    /// Fail when the schema expands to more than this many subschemas
+    #[arg(long)]
+    max_nodes: Option<usize>,
+
+    /// Fail on string patterns compiling to more than roughly this many
+    /// automaton states
+    #[arg(long)]
+    max_pattern_complexity: Option<usize>,
+
+    #[arg(long, value_enum, default_value_t = Output::Regex)]
+    output: Output,
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    /// Path to the JSON schema
+    #[arg(long)]
+    schema: PathBuf,
+
+    /// Path to the document to check, or `-` for stdin
+    #[arg(default_value = "-")]
+    document: PathBuf,
+
+    /// Regex for the whitespace allowed between JSON tokens
+    #[arg(long)]
+    whitespace_pattern: Option<String>,
+
+    /// Keep the final newline of the document instead of ignoring it
+    #[arg(long)]
+    exact: bool,
+}
+
+#[derive(Args)]
+struct SampleArgs {
+    /// Path to the JSON schema, or `-` for stdin
+    #[arg(long, default_value = "-")]
+    schema: PathBuf,
+
+    /// Number of documents to print
+    #[arg(short, default_value_t = 5)]
+    n: usize,
+
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Regex for the whitespace allowed between JSON tokens
+    #[arg(long)]
+    whitespace_pattern: Option<String>,
+
+    /// Print the examples and defaults of the schema instead of random documents
+    #[arg(long)]
+    examples: bool,
+
+    /// Write object properties sorted by name, like `compile --sort-properties`
+    #[arg(long)]
+    sort_properties: bool,
+}
+
+#[derive(Args)]
+struct ExplainArgs {
+    /// Path to the JSON schema, or `-` for stdin
+    #[arg(default_value = "-")]
+    schema: PathBuf,
+
+    /// Regex for the whitespace allowed between JSON tokens
+    #[arg(long)]
+    whitespace_pattern: Option<String>,
+}
+
+/// Each input is a JSON schema when it holds a JSON object, and a regex
+/// otherwise, so generated regexes can be compared with reference files.
+#[derive(Args)]
+struct DiffArgs {
+    left: PathBuf,
+    right: PathBuf,
+
+    /// Regex for the whitespace allowed between JSON tokens
+    #[arg(long)]
+    whitespace_pattern: Option<String>,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    /// Path to the JSON schema, or `-` for stdin
+    #[arg(default_value = "-")]
+    schema: PathBuf,
+
+    /// Regex for the whitespace allowed between JSON tokens
+    #[arg(long)]
+    whitespace_pattern: Option<String>,
+
+    /// Also build the DFA used for guided generation, which can be slow, and
+    /// print its state, final state and transition counts and memory usage
+    #[arg(long)]
+    dfa: bool,
+
+    /// Fail once the DFA takes more than this many bytes, listing the parts
+    /// of the schema with the largest DFAs
+    #[arg(long, requires = "dfa")]
+    max_dfa_memory: Option<usize>,
+}
+
+#[derive(Args)]
+struct LintArgs {
+    /// Path to the JSON schema, or `-` for stdin
+    #[arg(default_value = "-")]
+    schema: PathBuf,
+
+    /// Exit with 1 when a lint is at least this severe
+    #[arg(long, value_enum, default_value = "error")]
+    deny: Severity,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+
+    /// Number of compiled regexes, and of indexes, kept in memory
+    #[arg(long, default_value_t = 256)]
+    cache_size: usize,
+
+    /// Threads answering connections, one per CPU by default
+    #[arg(long)]
+    workers: Option<usize>,
+
+    /// Reject schemas whose regex would be longer than this many bytes
+    #[arg(long, default_value_t = guidance_rs::server::DEFAULT_MAX_REGEX_LEN)]
+    max_regex_len: usize,
+
+    /// Reject schemas expanding to more than this many subschemas
+    #[arg(long, default_value_t = guidance_rs::server::DEFAULT_MAX_NODES)]
+    max_nodes: usize,
+
+    /// Reject indexes whose DFA takes more than this many bytes
+    #[arg(long, default_value_t = guidance_rs::server::DEFAULT_MAX_DFA_BYTES)]
+    max_dfa_bytes: usize,
+
+    /// Close connections that take longer than this many seconds to send a
+    /// request or read a response, 0 for never
+    #[arg(long, default_value_t = guidance_rs::server::DEFAULT_TIMEOUT.as_secs())]
+    timeout_secs: u64,
+
+    /// Serve the gRPC service of proto/guidance.proto instead of HTTP
+    #[cfg(feature = "grpc")]
+    #[arg(long)]
+    grpc: bool,
+}
+
+#[derive(Args)]
+struct GenSnapshotsArgs {
+    /// Directory holding the `schemas` corpus and the snapshots
+    #[arg(long, default_value = "snapshots")]
+    dir: PathBuf,
+
+    /// Only report the snapshots that are out of date
+    #[arg(long)]
+    check: bool,
+}
+
+#[cfg(feature = "python")]
+#[derive(Args)]
+struct CompareOutlinesArgs {
+    /// Path to the JSON schema, or `-` for stdin
+    #[arg(default_value = "-")]
+    schema: PathBuf,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Output {
+    Regex,
+    /// GBNF grammar for llama.cpp
+    Gbnf,
+    /// The parsed regex syntax tree
+    Hir,
+    /// Size of the DFA used for guided generation
+    DfaStats,
+    /// The fixed text with placeholders for the values, then the regex of
+    /// each value by JSON Pointer, without whitespace
+    Skeleton,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Preset {
+    /// OpenAI strict structured outputs: all properties required, closed objects, no whitespace
+    OpenaiStrict,
+    /// The regexes of Outlines
+    OutlinesLegacy,
+    /// Indented JSON
+    Pretty,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InstanceType {
+    String,
+    /// Numbers, integers included
+    Number,
+    Boolean,
+    Null,
+    Object,
+    Array,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl From<Severity> for lint::Severity {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Info => lint::Severity::Info,
+            Severity::Warning => lint::Severity::Warning,
+            Severity::Error => lint::Severity::Error,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Draft {
+    #[value(name = "4")]
+    Draft4,
+    #[value(name = "6")]
+    Draft6,
+    #[value(name = "7")]
+    Draft7,
+    #[value(name = "2019-09")]
+    Draft2019_09,
+    #[value(name = "2020-12")]
+    Draft2020_12,
+}
+
+impl From<Draft> for draft::Draft {
+    fn from(draft: Draft) -> Self {
+        match draft {
+            Draft::Draft4 => draft::Draft::Draft4,
+            Draft::Draft6 => draft::Draft::Draft6,
+            Draft::Draft7 => draft::Draft::Draft7,
+            Draft::Draft2019_09 => draft::Draft::Draft2019_09,
+            Draft::Draft2020_12 => draft::Draft::Draft2020_12,
+        }
+    }
+}
+
+impl From<Preset> for guidance::Preset {
+    fn from(preset: Preset) -> Self {
+        match preset {
+            Preset::OpenaiStrict => guidance::Preset::OpenAiStrict,
+            Preset::OutlinesLegacy => guidance::Preset::OutlinesLegacy,
+            Preset::Pretty => guidance::Preset::Pretty,
+        }
+    }
+}
+
+impl Output {
+    /// File extension used when compiling a directory.
+    fn extension(self) -> &'static str {
+        match self {
+            Output::Regex => "regex",
+            Output::Gbnf => "gbnf",
+            Output::Hir => "hir",
+            Output::DfaStats => "txt",
+            Output::Skeleton => "skeleton",
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let json = cli.json;
+    let result = match cli.command {
+        Command::Compile(args) => compile(&args, json),
+        Command::Validate(args) => validate(&args, json),
+        Command::Sample(args) => sample(&args, json),
+        Command::Explain(args) => explain(&args, json),
+        Command::Diff(args) => diff(&args, json),
+        Command::Stats(args) => stats(&args, json),
+        Command::Lint(args) => lint(&args, json),
+        Command::Serve(args) => serve(&args),
+        Command::GenSnapshots(args) => gen_snapshots(&args, json),
+        #[cfg(feature = "python")]
+        Command::CompareOutlines(args) => compare_outlines(&args, json),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e) if json => {
+            let mut error = json!({"error": format!("{:#}", e)});
+            if let Some(schema_error) = SchemaError::find(&e) {
+                error["pointer"] = json!(schema_error.pointer);
+                error["hint"] = json!(schema_error.hint);
+            }
+            print_json(&error);
+            ExitCode::from(2)
+        }
+        Err(e) => {
+            #[cfg(feature = "miette")]
+            if let Some(diagnostic) = e.downcast_ref::<guidance_rs::diagnostic::SchemaDiagnostic>()
+            {
+                eprint!("{}", diagnostic.render());
+                return ExitCode::from(2);
+            }
+            eprintln!("error: {:#}", e);
+            if let Some(hint) = SchemaError::find(&e).and_then(|e| e.hint.as_ref()) {
+                eprintln!("hint: {}", hint);
+            }
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn compile(args: &CompileArgs, json: bool) -> Result<ExitCode> {
+    if let (Some(dir), Some(out_dir)) = (&args.dir, &args.out_dir) {
+        return compile_dir(args, dir, out_dir, json);
+    }
+    if args.watch {
+        return watch(args, json);
+    }
+
+    let schema = read_input(&args.schema)?;
+    let (text, value, warnings) = match compile_schema(&schema, args) {
+        Err(e) if !json => return Err(diagnose(e, &args.schema, schema)),
+        result => result?,
+    };
+    if json {
+        print_json(&value);
+    } else {
+        print_warnings(&warnings);
+        print!("{}", text);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints what the regex doesn't enforce to stderr.
+fn print_warnings(warnings: &[SchemaError]) {
+    for warning in warnings {
+        eprintln!("warning: {}", warning);
+        if let Some(hint) = &warning.hint {
+            eprintln!("hint: {}", hint);
+        }
+    }
+}
+
+/// The values allowed where the schema doesn't constrain them, all of them
+/// unless `--unconstrained-types` lists some.
+fn unconstrained_values(args: &CompileArgs) -> guidance::UnconstrainedValues {
+    let mut unconstrained = guidance::UnconstrainedValues::default();
+    if let Some(types) = &args.unconstrained_types {
+        unconstrained = guidance::UnconstrainedValues {
+            strings: types.contains(&InstanceType::String),
+            numbers: types.contains(&InstanceType::Number),
+            booleans: types.contains(&InstanceType::Boolean),
+            nulls: types.contains(&InstanceType::Null),
+            objects: types.contains(&InstanceType::Object),
+            arrays: types.contains(&InstanceType::Array),
+            ..unconstrained
+        };
+    }
+    if let Some(depth) = args.unconstrained_depth {
+        unconstrained.depth = depth;
+    }
+    unconstrained
+}
+
+/// The compiled schema in the requested output format, as text ending with
+/// a newline and as JSON holding the regex next to the output, along with
+/// the warnings, which the JSON holds too.
+fn compile_schema(schema: &str, args: &CompileArgs) -> Result<(String, Value, Vec<SchemaError>)> {
+    let preset = args
+        .preset
+        .map(|preset| guidance::RegexOptions::preset(preset.into()))
+        .unwrap_or_default();
+    let options = guidance::RegexOptions {
+        whitespace_pattern: args
+            .whitespace_pattern
+            .clone()
+            .or(preset.whitespace_pattern),
+        whitespace_positions: guidance::WhitespacePositions {
+            after_colon: args.whitespace_after_colon.clone(),
+            after_comma: args.whitespace_after_comma.clone(),
+            inside_brackets: args.whitespace_inside_brackets.clone(),
+        },
+        capture_groups: args.capture_groups || preset.capture_groups,
+        named_groups: args.named_groups,
+        simplify: args.simplify,
+        max_regex_len: args.max_regex_len,
+        max_nodes: args.max_nodes,
+        max_pattern_complexity: args.max_pattern_complexity,
+        sort_properties: args.sort_properties,
+        treat_all_properties_required: args.treat_all_properties_required
+            || preset.treat_all_properties_required,
+        closed_objects: args.closed_objects || preset.closed_objects,
+        numeric_formats: args.numeric_formats,
+        number_syntax: NumberSyntax {
+            leading_zeros: args.allow_leading_zeros,
+            plus_sign: args.allow_plus_sign,
+            bare_fractions: args.allow_bare_fractions,
+            force_decimal_for_number: args.force_decimal_for_number,
+        },
+        sort_enums: args.sort_enums,
+        max_alternation_width: args.max_alternation_width,
+        equivalent_numbers: args.equivalent_numbers,
+        strict: args.strict,
+        report_ambiguity: args.report_ambiguity,
+        exclusive_one_of: args.exclusive_one_of,
+        padded: args.padded || preset.padded,
+        anchored: args.anchor,
+        unconstrained: unconstrained_values(args),
+        draft: args.draft.map(Draft::into),
+        backend: preset.backend,
+    };
+    let (regex, warnings) = guidance::build_regex_from_schema_with_warnings(schema, &options)?;
+
+    let (text, mut value) = match args.output {
+        Output::Regex => (format!("{}\n", regex), json!({"regex": regex})),
+        Output::Gbnf => {
+            let grammar = gbnf::regex_to_gbnf(&regex)?;
+            (grammar.clone(), json!({"regex": regex, "gbnf": grammar}))
+        }
+        Output::Hir => {
+            let hir = format!("{:#?}", regex_syntax::Parser::new().parse(&regex)?);
+            (format!("{}\n", hir), json!({"regex": regex, "hir": hir}))
+        }
+        Output::DfaStats => {
+            let stats = automaton::dfa_stats(&regex)?;
+            let text = format!(
+                "states: {}\nfinal states: {}\ntransitions: {}\nmemory usage: {} bytes\n",
+                stats.states, stats.final_states, stats.transitions, stats.memory_usage
+            );
+            let value = json!({
+                "regex": regex,
+                "dfa_stats": {
+                    "states": stats.states,
+                    "final_states": stats.final_states,
+                    "transitions": stats.transitions,
+                    "memory_usage": stats.memory_usage,
+                }
+            });
+            (text, value)
+        }
+        Output::Skeleton => {
+            let skeleton = skeleton::skeleton(schema, &options)?;
+            let mut text = format!("{}\n", skeleton.template());
+            for (pointer, regex) in skeleton.values() {
+                text.push_str(&format!("{}\t{}\n", pointer, regex));
+            }
+            let values: serde_json::Map<String, Value> = skeleton
+                .values()
+                .map(|(pointer, regex)| (pointer.to_string(), Value::from(regex)))
+                .collect();
+            let value = json!({
+                "regex": skeleton.regex(),
+                "template": skeleton.template(),
+                "values": values,
+            });
+            (text, value)
+        }
+    };
+    if !warnings.is_empty() {
+        value["warnings"] = warnings
+            .iter()
+            .map(|warning| json!({"warning": warning.message, "pointer": warning.pointer, "hint": warning.hint}))
+            .collect();
+    }
+    Ok((text, value, warnings))
+}
+
+/// Polls the schema file and prints the output and regex stats after each change.
+fn watch(args: &CompileArgs, json: bool) -> Result<ExitCode> {
+    if args.schema == Path::new("-") {
+        return Err(anyhow!("--watch needs a schema file, not stdin"));
+    }
+
+    let mut last_modified = None;
+    loop {
+        let modified = fs::metadata(&args.schema)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if modified.is_some() && modified != last_modified {
+            last_modified = modified;
+            let compiled = read_input(&args.schema).and_then(|schema| {
+                let (text, value, warnings) = compile_schema(&schema, args)?;
+                let stats = automaton::regex_stats(value["regex"].as_str().unwrap_or_default())?;
+                Ok((text, value, warnings, stats))
+            });
+
+            match compiled {
+                Ok((_, mut value, _, stats)) if json => {
+                    value["stats"] = json!({
+                        "length": stats.length,
+                        "alternations": stats.alternations,
+                        "nfa_states": stats.nfa_states,
+                        "nfa_memory_usage": stats.nfa_memory_usage,
+                    });
+                    println!("{}", value);
+                }
+                Ok((text, _, warnings, stats)) => {
+                    print_warnings(&warnings);
+                    print!("{}", text);
+                    println!(
+                        "-- {} bytes, {} alternations, {} NFA states",
+                        stats.length, stats.alternations, stats.nfa_states
+                    );
+                }
+                Err(e) if json => println!("{}", json!({"error": format!("{:#}", e)})),
+                Err(e) => eprintln!("error: {:#}", e),
+            }
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Compiles every `*.json` file of `dir` on all cores, then prints a summary.
+///
+/// Exits with 1 when any schema fails to compile; the others are still written.
+fn compile_dir(args: &CompileArgs, dir: &Path, out_dir: &Path, json: bool) -> Result<ExitCode> {
+    let mut schemas = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            schemas.push(path);
+        }
+    }
+    schemas.sort();
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("Failed to create {}", out_dir.display()))?;
+
+    let compile_one = |path: &PathBuf| -> Result<(PathBuf, usize, Duration)> {
+        let schema = read_input(path)?;
+        let start = Instant::now();
+        let (output, ..) = compile_schema(&schema, args)?;
+        let elapsed = start.elapsed();
+
+        let out_path = out_dir
+            .join(path.file_stem().unwrap_or_default())
+            .with_extension(args.output.extension());
+        fs::write(&out_path, &output)
+            .with_context(|| format!("Failed to write {}", out_path.display()))?;
+        Ok((out_path, output.len(), elapsed))
+    };
+
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut results: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|worker| {
+                let schemas = &schemas;
+                scope.spawn(move || {
+                    schemas
+                        .iter()
+                        .enumerate()
+                        .skip(worker)
+                        .step_by(workers)
+                        .map(|(i, path)| (i, compile_one(path)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("compile worker panicked"))
+            .collect()
+    });
+    results.sort_by_key(|&(i, _)| i);
+
+    let failed = results.iter().any(|(_, result)| result.is_err());
+    let code = if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    };
+
+    if json {
+        let summary: Vec<_> = results
+            .iter()
+            .map(|(i, result)| match result {
+                Ok((out_path, size, elapsed)) => json!({
+                    "schema": schemas[*i],
+                    "output": out_path,
+                    "size": size,
+                    "time_ms": elapsed.as_secs_f64() * 1000.0,
+                }),
+                Err(e) => json!({"schema": schemas[*i], "error": format!("{:#}", e)}),
+            })
+            .collect();
+        print_json(&json!({"schemas": summary}));
+        return Ok(code);
+    }
+
+    let names: Vec<_> = schemas
+        .iter()
+        .map(|path| path.file_name().unwrap_or_default().to_string_lossy())
+        .collect();
+    let width = names
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+    println!("{:<width$}  {:>10}  {:>10}", "schema", "size", "time");
+    for (i, result) in results {
+        match result {
+            Ok((_, size, elapsed)) => println!(
+                "{:<width$}  {:>8} B  {:>7.1} ms",
+                names[i],
+                size,
+                elapsed.as_secs_f64() * 1000.0
+            ),
+            Err(e) => println!("{:<width$}  error: {:#}", names[i], e),
+        }
+    }
+    Ok(code)
+}
+
+/// Exits with 1 when the document doesn't match, reporting where it fails.
+fn validate(args: &ValidateArgs, json: bool) -> Result<ExitCode> {
+    let schema = read_input(&args.schema)?;
+    let regex = guidance::build_regex_from_schema(&schema, args.whitespace_pattern.as_deref())?;
+
+    let mut document = read_input(&args.document)?;
+    if !args.exact {
+        let trimmed = document.strip_suffix('\n').unwrap_or(&document);
+        document = trimmed.strip_suffix('\r').unwrap_or(trimmed).to_string();
+    }
+
+    let (text, value) = match automaton::validate(&regex, document.as_bytes())? {
+        Validation::Match => ("ok".to_string(), json!({"result": "match"})),
+        Validation::Mismatch(mismatch) => {
+            let offset = mismatch.offset;
+            let (line, column) = line_and_column(&document, offset);
+            let valid_prefix = String::from_utf8_lossy(&document.as_bytes()[..offset]);
+            let expected = if mismatch.expected.is_empty() {
+                "the end of the document".to_string()
+            } else {
+                format!("one of {}", mismatch.expected)
+            };
+            let text = format!(
+                "mismatch at byte {} (line {}, column {}): got '{}', expected {}\nvalid up to: {}",
+                offset,
+                line,
+                column,
+                mismatch.byte.escape_ascii(),
+                expected,
+                valid_prefix
+            );
+            let value = json!({
+                "result": "mismatch",
+                "offset": offset,
+                "line": line,
+                "column": column,
+                "byte": mismatch.byte,
+                "expected": mismatch.expected.to_string(),
+                "valid_prefix": valid_prefix,
+            });
+            (text, value)
+        }
+        Validation::Incomplete => (
+            "incomplete: the document ends before the schema is satisfied".to_string(),
+            json!({"result": "incomplete"}),
+        ),
+    };
+
+    if json {
+        print_json(&value);
+    } else {
+        println!("{}", text);
+    }
+    Ok(if value["result"] == "match" {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn sample(args: &SampleArgs, json: bool) -> Result<ExitCode> {
+    let schema = read_input(&args.schema)?;
+    let options = guidance::RegexOptions {
+        whitespace_pattern: args.whitespace_pattern.clone(),
+        sort_properties: args.sort_properties,
+        ..guidance::RegexOptions::default()
+    };
+    let samples = if args.examples {
+        sample::examples(&schema, &options)?
+    } else {
+        let regex = guidance::build_regex_from_schema_with_options(&schema, &options)?;
+        sample::samples(&regex, args.n, args.seed)?
+    };
+    if json {
+        print_json(&json!({"samples": samples}));
+    } else {
+        for document in samples {
+            println!("{}", document);
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn explain(args: &ExplainArgs, json: bool) -> Result<ExitCode> {
+    let schema = read_input(&args.schema)?;
+    let fragments = explain::explain(&schema, args.whitespace_pattern.as_deref())?;
+    if json {
+        let fragments: Vec<_> = fragments
+            .iter()
+            .map(|f| {
+                let spans: Vec<_> = f.spans.iter().map(|span| [span.start, span.end]).collect();
+                json!({"pointer": f.pointer, "depth": f.depth, "regex": f.regex, "spans": spans})
+            })
+            .collect();
+        print_json(&json!({"fragments": fragments}));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    for fragment in fragments {
+        let pointer = if fragment.pointer.is_empty() {
+            "#"
+        } else {
+            &fragment.pointer
+        };
+        println!("{}{}", "  ".repeat(fragment.depth), pointer);
+        println!("{}  {}", "  ".repeat(fragment.depth), fragment.regex);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Exits with 1 when the inputs don't match the same documents.
+fn diff(args: &DiffArgs, json: bool) -> Result<ExitCode> {
+    let left = schema_or_regex(&args.left, args.whitespace_pattern.as_deref())?;
+    let right = schema_or_regex(&args.right, args.whitespace_pattern.as_deref())?;
+    let result = diff::diff(&left, &right)?;
+    let code = if result.is_equivalent() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    };
+
+    if json {
+        let fragments: Vec<_> = result
+            .fragments
+            .iter()
+            .map(|(left, right)| json!({"left": left, "right": right}))
+            .collect();
+        let counterexample = result.counterexample.as_ref().map(|c| {
+            json!({
+                "input": c.input,
+                "matched_by": if c.matched_by_left { &args.left } else { &args.right },
+            })
+        });
+        print_json(&json!({
+            "equivalent": result.is_equivalent(),
+            "counterexample": counterexample,
+            "fragments": fragments,
+        }));
+        return Ok(code);
+    }
+
+    for (left, right) in &result.fragments {
+        println!("- {}", left);
+        println!("+ {}", right);
+    }
+    match result.counterexample {
+        None => println!("equivalent"),
+        Some(counterexample) => {
+            let side = if counterexample.matched_by_left {
+                args.left.display()
+            } else {
+                args.right.display()
+            };
+            println!(
+                "not equivalent: only {} matches {:?}",
+                side, counterexample.input
+            );
+        }
+    }
+    Ok(code)
+}
+
+fn stats(args: &StatsArgs, json: bool) -> Result<ExitCode> {
+    let schema = read_input(&args.schema)?;
+    let regex = guidance::build_regex_from_schema(&schema, args.whitespace_pattern.as_deref())?;
+    let stats = automaton::regex_stats(&regex)?;
+    let options = guidance::RegexOptions {
+        whitespace_pattern: args.whitespace_pattern.clone(),
+        ..guidance::RegexOptions::default()
+    };
+    let dfa_options = automaton::DfaBuildOptions {
+        max_memory_bytes: args.max_dfa_memory,
+    };
+    let dfa_stats = args
+        .dfa
+        .then(|| automaton::schema_dfa_stats(&schema, &options, &dfa_options))
+        .transpose()?;
+
+    if json {
+        let dfa_stats = dfa_stats.map(|stats| {
+            json!({
+                "states": stats.states,
+                "final_states": stats.final_states,
+                "transitions": stats.transitions,
+                "memory_usage": stats.memory_usage,
+            })
+        });
+        print_json(&json!({
+            "length": stats.length,
+            "alternations": stats.alternations,
+            "nfa_states": stats.nfa_states,
+            "nfa_memory_usage": stats.nfa_memory_usage,
+            "dfa_stats": dfa_stats,
+        }));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("regex length: {} bytes", stats.length);
+    println!("alternations: {}", stats.alternations);
+    println!("NFA states: {}", stats.nfa_states);
+    println!("NFA memory usage: {} bytes", stats.nfa_memory_usage);
+    if let Some(stats) = dfa_stats {
+        println!("DFA states: {}", stats.states);
+        println!("DFA final states: {}", stats.final_states);
+        println!("DFA transitions: {}", stats.transitions);
+        println!("DFA memory usage: {} bytes", stats.memory_usage);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Exits with 1 when a lint is at least as severe as `--deny`.
+fn lint(args: &LintArgs, json: bool) -> Result<ExitCode> {
+    let schema: Value = serde_json::from_str(&read_input(&args.schema)?)?;
+    let lints = lint::lint(&schema);
+    let deny = lint::Severity::from(args.deny);
+    let denied = lints.iter().any(|lint| lint.severity >= deny);
+
+    if json {
+        let lints: Vec<_> = lints
+            .iter()
+            .map(|lint| {
+                json!({
+                    "severity": lint.severity.to_string(),
+                    "pointer": lint.pointer,
+                    "rule": lint.rule,
+                    "message": lint.message,
+                })
+            })
+            .collect();
+        print_json(&json!({"lints": lints, "denied": denied}));
+    } else {
+        for lint in &lints {
+            let pointer = if lint.pointer.is_empty() {
+                "#"
+            } else {
+                &lint.pointer
+            };
+            println!(
+                "{} [{}] {}: {}",
+                lint.severity, lint.rule, pointer, lint.message
+            );
+        }
+    }
+    Ok(if denied {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Exits with 1 when outlines generates a regex matching other documents.
+#[cfg(feature = "python")]
+fn compare_outlines(args: &CompareOutlinesArgs, json: bool) -> Result<ExitCode> {
+    let schema = read_input(&args.schema)?;
+    // outlines uses its own default whitespace pattern, so use ours as well
+    let regex =
+        guidance::build_regex_from_schema_with_options(&schema, &guidance::RegexOptions::legacy())?;
+    let outlines_regex = guidance_rs::py_wrapper::python_build_regex_from_schema(&schema)
+        .map_err(|e| anyhow!("Failed to run outlines: {}", e))?;
+    let result = diff::diff(&regex, &outlines_regex)?;
+
+    if json {
+        print_json(&json!({
+            "regex": regex,
+            "outlines_regex": outlines_regex,
+            "identical": result.fragments.is_empty(),
+            "equivalent": result.is_equivalent(),
+            "counterexample": result.counterexample.as_ref().map(|c| &c.input),
+        }));
+    } else {
+        println!("guidance-rs: {}", regex);
+        println!("outlines:    {}", outlines_regex);
+        match &result.counterexample {
+            None if result.fragments.is_empty() => println!("identical"),
+            None => println!("equivalent"),
+            Some(counterexample) => {
+                let side = if counterexample.matched_by_left {
+                    "guidance-rs"
+                } else {
+                    "outlines"
+                };
+                println!(
+                    "not equivalent: only {} matches {:?}",
+                    side, counterexample.input
+                );
+            }
+        }
+    }
+
+    Ok(if result.is_equivalent() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn serve(args: &ServeArgs) -> Result<ExitCode> {
+    eprintln!("listening on {}:{}", args.host, args.port);
+    let mut server = Server::new(args.cache_size)
+        .with_limits(Some(args.max_regex_len), Some(args.max_nodes))
+        .with_max_dfa_bytes(Some(args.max_dfa_bytes))
+        .with_timeout(Some(Duration::from_secs(args.timeout_secs)));
+    if let Some(workers) = args.workers {
+        server = server.with_workers(workers);
+    }
+    #[cfg(feature = "grpc")]
+    if args.grpc {
+        let addr = format!("{}:{}", args.host, args.port)
+            .parse()
+            .context("Invalid host for the gRPC server")?;
+        guidance_rs::grpc::serve(addr, server)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    server.serve((args.host.as_str(), args.port))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// With `--check`, exits with 1 when a snapshot is out of date.
+fn gen_snapshots(args: &GenSnapshotsArgs, json: bool) -> Result<ExitCode> {
+    if !args.check {
+        let written = snapshots::generate(&args.dir)?;
+        if json {
+            print_json(&json!({"written": written}));
+        } else {
+            for path in written {
+                println!("wrote {}", path.display());
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mismatches = snapshots::check(&args.dir)?;
+    if json {
+        let mismatches: Vec<_> = mismatches
+            .iter()
+            .map(|m| {
+                json!({
+                    "schema": m.schema,
+                    "counterexample": m.counterexample.as_ref().map(|c| &c.input),
+                })
+            })
+            .collect();
+        print_json(&json!({"mismatches": mismatches}));
+    } else {
+        for mismatch in &mismatches {
+            match &mismatch.counterexample {
+                None => println!("{}: missing snapshot", mismatch.schema.display()),
+                Some(counterexample) => println!(
+                    "{}: only the {} matches {:?}",
+                    mismatch.schema.display(),
+                    if counterexample.matched_by_left {
+                        "schema"
+                    } else {
+                        "snapshot"
+                    },
+                    counterexample.input
+                ),
+            }
+        }
+    }
+
+    Ok(if mismatches.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn schema_or_regex(path: &Path, whitespace_pattern: Option<&str>) -> Result<String> {
+    let input = read_input(path)?;
+    match serde_json::from_str::<Value>(&input) {
+        Ok(schema) if schema.is_object() => {
+            guidance::build_regex_from_schema(&input, whitespace_pattern)
+        }
+        _ => Ok(input.trim_end_matches(['\r', '\n']).to_string()),
+    }
+}
+
+fn print_json(value: &Value) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("JSON values always serialize")
+    );
+}
+
+/// 1-based line and column of a byte offset.
+fn line_and_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text.as_bytes()[..offset];
+    let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+    let line_start = before
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    (line, offset - line_start + 1)
+}
+
+/// Reads a file, or stdin when the path is `-`.
+/// Attaches the schema to errors about a part of it, to show that part.
+#[cfg(feature = "miette")]
+fn diagnose(error: anyhow::Error, path: &Path, schema: String) -> anyhow::Error {
+    match error.downcast::<SchemaError>() {
+        Ok(error) => {
+            guidance_rs::diagnostic::SchemaDiagnostic::new(error, path.to_string_lossy(), schema)
+                .into()
+        }
+        Err(error) => error,
+    }
+}
+
+#[cfg(not(feature = "miette"))]
+fn diagnose(error: anyhow::Error, _path: &Path, _schema: String) -> anyhow::Error {
+    error
+}
+
+fn read_input(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        let mut input = String::new();
+        io::stdin()
+            .read_to_string(&mut input)
+            .context("Failed to read stdin")?;
+        Ok(input)
+    } else {
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
     }
 }
@@ -0,0 +1,322 @@
+//! Merging of schemas that refine a single value, for `allOf`.
+//!
+//! `allOf: [{"type": "string"}, {"maxLength": 10}]` accepts what both
+//! branches accept, which one schema with the keywords of both describes.
+//! Only keywords refining scalars are merged; a branch with any other, like
+//! `properties` or `$ref`, leaves `allOf` to be generated branch by branch.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+use crate::error::SchemaError;
+use crate::keywords;
+use crate::range::Decimal;
+
+/// Counts where the largest of the branches applies.
+const LOWER_COUNTS: &[&str] = &[
+    "minLength",
+    "minItems",
+    "minProperties",
+    "minDigits",
+    "minDigitsInteger",
+    "minDigitsFraction",
+    "minDigitsExponent",
+];
+
+/// Counts where the smallest of the branches applies.
+const UPPER_COUNTS: &[&str] = &[
+    "maxLength",
+    "maxItems",
+    "maxProperties",
+    "maxDigits",
+    "maxDigitsInteger",
+    "maxDigitsFraction",
+    "maxDigitsExponent",
+];
+
+/// Keywords the branches have to agree on, as their values can't be
+/// combined into one.
+const SHARED: &[&str] = &["const", "format", "multipleOf"];
+
+/// Keywords that don't change what's accepted.
+pub(crate) const ANNOTATIONS: &[&str] = &[
+    "title",
+    "description",
+    "default",
+    "examples",
+    "$comment",
+    "deprecated",
+    "readOnly",
+    "writeOnly",
+];
+
+/// One schema accepting the values every one of `schemas` accepts, with the
+/// schema each of its keywords comes from.
+pub struct Merged {
+    /// The keywords of all the schemas, but for different `pattern`s, which
+    /// are kept as an array of them for the caller to intersect.
+    pub schema: Map<String, Value>,
+    /// The index in `schemas` of the schema each keyword of `schema` takes
+    /// its value from, the first one setting it when several do.
+    pub origins: HashMap<String, usize>,
+}
+
+/// One schema accepting the values every one of `schemas` accepts, or
+/// `None` when one of them isn't made only of scalar refinements.
+pub fn merge_scalars<'a>(
+    schemas: impl IntoIterator<Item = &'a Map<String, Value>>,
+) -> Result<Option<Merged>> {
+    let mut merged = Map::new();
+    let mut origins = HashMap::new();
+    for (i, schema) in schemas.into_iter().enumerate() {
+        for (keyword, value) in schema {
+            let keyword = keyword.as_str();
+            if ANNOTATIONS.contains(&keyword) {
+                continue;
+            }
+            if !is_scalar_refinement(keyword, value) {
+                return Ok(None);
+            }
+            let Some(current) = merged.get(keyword) else {
+                merged.insert(keyword.to_string(), value.clone());
+                origins.insert(keyword.to_string(), i);
+                continue;
+            };
+
+            let combined = match keyword {
+                "type" => intersect_types(current, value)?,
+                "enum" => intersect_enums(current, value)?,
+                "pattern" => collect_patterns(current, value)?,
+                "minimum" | "exclusiveMinimum" => pick_number(current, value, Ordering::Greater)?,
+                "maximum" | "exclusiveMaximum" => pick_number(current, value, Ordering::Less)?,
+                _ if LOWER_COUNTS.contains(&keyword) => {
+                    pick_count(keyword, current, value, u64::max)?
+                }
+                _ if UPPER_COUNTS.contains(&keyword) => {
+                    pick_count(keyword, current, value, u64::min)?
+                }
+                _ if SHARED.contains(&keyword) => {
+                    if current != value {
+                        return Err(SchemaError::new(format!(
+                            "the allOf branches set '{}' to different values, which one regex can't enforce together",
+                            keyword
+                        ))
+                        .with_keyword("allOf")
+                        .into());
+                    }
+                    continue;
+                }
+                _ => unreachable!("not a scalar refinement: {}", keyword),
+            };
+            // the bound of this schema is the one kept
+            if combined == *value && combined != *current {
+                origins.insert(keyword.to_string(), i);
+            }
+            merged.insert(keyword.to_string(), combined);
+        }
+    }
+    Ok(Some(Merged {
+        schema: merged,
+        origins,
+    }))
+}
+
+fn is_scalar_refinement(keyword: &str, value: &Value) -> bool {
+    match keyword {
+        "type" | "enum" | "minimum" | "maximum" | "pattern" => true,
+        // the draft 4 booleans only mean something next to their bound
+        "exclusiveMinimum" | "exclusiveMaximum" => value.is_number(),
+        _ => {
+            LOWER_COUNTS.contains(&keyword)
+                || UPPER_COUNTS.contains(&keyword)
+                || SHARED.contains(&keyword)
+        }
+    }
+}
+
+/// The types of both `type` values, where integers are numbers too.
+fn intersect_types(a: &Value, b: &Value) -> Result<Value> {
+    let (a, b) = (type_names(a)?, type_names(b)?);
+    let mut types: Vec<&str> = Vec::new();
+    for &name in &a {
+        let common = if b.contains(&name) {
+            name
+        } else if (name == "number" && b.contains(&"integer"))
+            || (name == "integer" && b.contains(&"number"))
+        {
+            "integer"
+        } else {
+            continue;
+        };
+        if !types.contains(&common) {
+            types.push(common);
+        }
+    }
+
+    match types.as_slice() {
+        [] => Err(
+            SchemaError::new("the allOf branches have no type in common")
+                .with_keyword("allOf")
+                .into(),
+        ),
+        [name] => Ok(Value::from(*name)),
+        _ => Ok(Value::from(types)),
+    }
+}
+
+fn type_names(value: &Value) -> Result<Vec<&str>> {
+    let names = match value {
+        Value::String(name) => Some(vec![name.as_str()]),
+        Value::Array(names) => names.iter().map(Value::as_str).collect(),
+        _ => None,
+    };
+    names.ok_or_else(|| {
+        SchemaError::wrong_type("type", "a string or an array of strings", value).into()
+    })
+}
+
+/// The values of the first `enum` the second one has too.
+fn intersect_enums(a: &Value, b: &Value) -> Result<Value> {
+    let values = |value: &Value| {
+        value
+            .as_array()
+            .cloned()
+            .ok_or_else(|| SchemaError::wrong_type("enum", "an array", value))
+    };
+    let (a, b) = (values(a)?, values(b)?);
+    let common: Vec<Value> = a.into_iter().filter(|value| b.contains(value)).collect();
+    if common.is_empty() {
+        return Err(
+            SchemaError::new("the allOf branches have no enum value in common")
+                .with_keyword("allOf")
+                .into(),
+        );
+    }
+    Ok(Value::Array(common))
+}
+
+/// The patterns of `a`, a pattern or an array of those, and of `b`, each
+/// once.
+fn collect_patterns(a: &Value, b: &Value) -> Result<Value> {
+    let pattern = |value: &Value| {
+        value
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| SchemaError::wrong_type("pattern", "a string", value))
+    };
+    let mut patterns = match a {
+        Value::Array(patterns) => patterns.clone(),
+        _ => vec![Value::from(pattern(a)?)],
+    };
+    let b = Value::from(pattern(b)?);
+    if !patterns.contains(&b) {
+        patterns.push(b);
+    }
+    Ok(match <[Value; 1]>::try_from(patterns) {
+        Ok([pattern]) => pattern,
+        Err(patterns) => Value::Array(patterns),
+    })
+}
+
+/// The bound of `a` and `b` that's `ordering` compared to the other.
+fn pick_number(a: &Value, b: &Value, ordering: Ordering) -> Result<Value> {
+    let decimal = |value: &Value| {
+        value
+            .as_number()
+            .and_then(|number| Decimal::parse(&number.to_string()))
+            .ok_or_else(|| SchemaError::new(format!("expected a number, got {}", value)))
+    };
+    Ok(if decimal(b)?.cmp(&decimal(a)?) == ordering {
+        b.clone()
+    } else {
+        a.clone()
+    })
+}
+
+fn pick_count(keyword: &str, a: &Value, b: &Value, pick: fn(u64, u64) -> u64) -> Result<Value> {
+    let count = |value: &Value| {
+        let obj = Map::from_iter([(keyword.to_string(), value.clone())]);
+        keywords::required(keywords::get_u64(&obj, keyword)?, keyword)
+    };
+    Ok(Value::from(pick(count(a)?, count(b)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn merge(schemas: Value) -> Result<Option<Value>> {
+        let schemas = schemas.as_array().unwrap().clone();
+        let objects = schemas.iter().map(|schema| schema.as_object().unwrap());
+        Ok(merge_scalars(objects)?.map(|merged| Value::Object(merged.schema)))
+    }
+
+    #[test]
+    fn test_merge_scalars() {
+        let merged = merge(json!([
+            {"type": "string", "title": "code"},
+            {"maxLength": 10, "minLength": 1},
+            {"pattern": "^a", "maxLength": 8}
+        ]));
+        assert_eq!(
+            merged.unwrap(),
+            Some(json!({"type": "string", "minLength": 1, "maxLength": 8, "pattern": "^a"}))
+        );
+
+        let merged = merge(json!([
+            {"type": ["number", "null"], "minimum": 0, "exclusiveMaximum": 10.5},
+            {"type": "integer", "minimum": 2.5, "exclusiveMaximum": 20},
+            {"enum": [1, 3, 5, 7]},
+            {"enum": [7, 5, 2]}
+        ]));
+        assert_eq!(
+            merged.unwrap(),
+            Some(
+                json!({"type": "integer", "minimum": 2.5, "exclusiveMaximum": 10.5, "enum": [5, 7]})
+            )
+        );
+
+        // different patterns are all kept, each keyword with its schema
+        let schemas = [
+            json!({"pattern": "^a", "maxLength": 8}),
+            json!({"pattern": "b$", "maxLength": 4}),
+            json!({"pattern": "^a", "maxLength": 6}),
+        ];
+        let merged = merge_scalars(schemas.iter().map(|schema| schema.as_object().unwrap()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            Value::Object(merged.schema),
+            json!({"pattern": ["^a", "b$"], "maxLength": 4})
+        );
+        assert_eq!(merged.origins["pattern"], 0);
+        assert_eq!(merged.origins["maxLength"], 1);
+
+        // structure is left to allOf
+        let merged = merge(json!([{"type": "object"}, {"properties": {"a": {}}}]));
+        assert_eq!(merged.unwrap(), None);
+        let merged = merge(json!([{"minimum": 1, "exclusiveMinimum": true}]));
+        assert_eq!(merged.unwrap(), None);
+
+        for (schemas, message) in [
+            (
+                json!([{"type": "string"}, {"type": "integer"}]),
+                "the allOf branches have no type in common",
+            ),
+            (
+                json!([{"format": "date"}, {"format": "email"}]),
+                "the allOf branches set 'format' to different values, which one regex can't enforce together",
+            ),
+            (
+                json!([{"enum": [1]}, {"enum": [2]}]),
+                "the allOf branches have no enum value in common",
+            ),
+        ] {
+            assert_eq!(merge(schemas).unwrap_err().to_string(), message);
+        }
+    }
+}
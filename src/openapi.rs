@@ -0,0 +1,184 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+
+use crate::guidance::{
+    check_with_options, configured_generator, finish_regex, resolve_whitespace_pattern,
+    RegexOptions,
+};
+
+/// Builds the regex for a schema inside an OpenAPI 3.x document.
+///
+/// `pointer` is a local reference such as `#/components/schemas/Pet`. Refs
+/// between components are resolved against the document, and the OpenAPI 3.0
+/// `nullable` keyword is turned into an alternative with `null`.
+pub fn from_openapi(
+    document: &Value,
+    pointer: &str,
+    whitespace_pattern: Option<&str>,
+) -> Result<String> {
+    let options = RegexOptions {
+        whitespace_pattern: whitespace_pattern.map(str::to_string),
+        ..RegexOptions::default()
+    };
+    from_openapi_with_options(document, pointer, &options)
+}
+
+/// Like [`from_openapi`], with the schema generated with `options`. Errors
+/// point into the document.
+pub fn from_openapi_with_options(
+    document: &Value,
+    pointer: &str,
+    options: &RegexOptions,
+) -> Result<String> {
+    let version = document
+        .get("openapi")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("Not an OpenAPI document: 'openapi' version not found"))?;
+    if !version.starts_with("3.") {
+        return Err(anyhow!("Only OpenAPI 3.x is supported, got {}", version));
+    }
+
+    let path = pointer
+        .strip_prefix('#')
+        .ok_or_else(|| anyhow!("Only local references are supported"))?;
+
+    let document = resolve_nullable(document);
+    let schema = document
+        .pointer(path)
+        .ok_or_else(|| anyhow!("No schema found at {}", pointer))?;
+    check_with_options(schema, options)?;
+
+    let whitespace_pattern = resolve_whitespace_pattern(options.whitespace_pattern.as_deref())?;
+    let generator = configured_generator(&whitespace_pattern, &document, options);
+    let regex = generator.at_ref(pointer).to_regex(schema)?;
+    finish_regex(&regex, options)
+}
+
+/// Same as [`from_openapi`] for a document stored as JSON on disk.
+pub fn from_openapi_file(
+    path: impl AsRef<Path>,
+    pointer: &str,
+    whitespace_pattern: Option<&str>,
+) -> Result<String> {
+    let path = path.as_ref();
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let document: Value = serde_json::from_str(&content)?;
+    from_openapi(&document, pointer, whitespace_pattern)
+}
+
+/// Rewrites `"nullable": true` schemas into `anyOf` with a `null` branch.
+fn resolve_nullable(value: &Value) -> Value {
+    match value {
+        Value::Object(obj) => {
+            let resolved: serde_json::Map<String, Value> = obj
+                .iter()
+                .filter(|(key, value)| !(key.as_str() == "nullable" && value.is_boolean()))
+                .map(|(key, value)| (key.clone(), resolve_nullable(value)))
+                .collect();
+
+            if obj.get("nullable") == Some(&Value::Bool(true)) {
+                json!({"anyOf": [resolved, {"type": "null"}]})
+            } else {
+                Value::Object(resolved)
+            }
+        }
+        Value::Array(values) => Value::Array(values.iter().map(resolve_nullable).collect()),
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+    use crate::error::SchemaError;
+
+    fn petstore() -> Value {
+        json!({
+            "openapi": "3.0.3",
+            "info": {"title": "Petstore", "version": "1.0.0"},
+            "paths": {},
+            "components": {
+                "schemas": {
+                    "Owner": {
+                        "type": "object",
+                        "properties": {"name": {"type": "string"}},
+                        "required": ["name"]
+                    },
+                    "Pet": {
+                        "type": "object",
+                        "properties": {
+                            "id": {"type": "integer"},
+                            "owner": {"$ref": "#/components/schemas/Owner"},
+                            "tag": {"type": "string", "nullable": true}
+                        },
+                        "required": ["id", "owner", "tag"]
+                    }
+                }
+            }
+        })
+    }
+
+    fn is_full_match(regex: &str, text: &str) -> bool {
+        Regex::new(&format!("^(?:{})$", regex))
+            .unwrap()
+            .is_match(text)
+    }
+
+    #[test]
+    fn test_component_with_refs_and_nullable() {
+        let regex = from_openapi(&petstore(), "#/components/schemas/Pet", Some("")).unwrap();
+
+        assert!(is_full_match(
+            &regex,
+            r#"{"id":1,"owner":{"name":"Ada"},"tag":"cat"}"#
+        ));
+        assert!(is_full_match(
+            &regex,
+            r#"{"id":1,"owner":{"name":"Ada"},"tag":null}"#
+        ));
+        assert!(!is_full_match(
+            &regex,
+            r#"{"id":1,"owner":null,"tag":null}"#
+        ));
+    }
+
+    #[test]
+    fn test_options() {
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            closed_objects: true,
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let regex =
+            from_openapi_with_options(&petstore(), "#/components/schemas/Owner", &options).unwrap();
+        assert!(regex.starts_with('^'));
+        let regex = Regex::new(&regex).unwrap();
+        assert!(regex.is_match(r#"{"name":"Ada"}"#));
+        assert!(!regex.is_match(r#"{"name":"Ada","age":36}"#));
+    }
+
+    #[test]
+    fn test_errors_point_into_the_document() {
+        let mut document = petstore();
+        document["components"]["schemas"]["Owner"]["properties"]["name"]["format"] = json!("nope");
+        let error = from_openapi(&document, "#/components/schemas/Pet", None).unwrap_err();
+        assert_eq!(
+            SchemaError::find(&error).unwrap().pointer.as_deref(),
+            Some("/components/schemas/Owner/properties/name")
+        );
+    }
+
+    #[test]
+    fn test_invalid_documents() {
+        assert!(from_openapi(&petstore(), "#/components/schemas/Cat", None).is_err());
+        assert!(from_openapi(&json!({"swagger": "2.0"}), "#/definitions/Pet", None).is_err());
+        assert!(from_openapi(&json!({"openapi": "2.0"}), "#/definitions/Pet", None).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! JSON Merge Patch (RFC 7396), to tighten a base schema per request before
+//! compiling it, like pinning an enum to one value or lowering `maxItems`.
+
+use serde_json::{Map, Value};
+
+/// `schema` with `overrides` applied as a JSON Merge Patch: the members of
+/// an object in `overrides` replace those of `schema`, objects merging
+/// recursively, and `null` removes a member. Any other value replaces the
+/// whole schema.
+pub fn merge_overrides(schema: &Value, overrides: &Value) -> Value {
+    let mut merged = schema.clone();
+    apply(&mut merged, overrides);
+    merged
+}
+
+fn apply(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!("the target was made an object");
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            apply(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_merge_overrides() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "color": {"enum": ["red", "green", "blue"]},
+                "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 10},
+                "note": {"type": "string"}
+            },
+            "required": ["color"]
+        });
+        let overrides = json!({
+            "properties": {
+                "color": {"enum": ["green"]},
+                "tags": {"maxItems": 2},
+                "note": null
+            },
+            "required": ["color", "tags"]
+        });
+        assert_eq!(
+            merge_overrides(&schema, &overrides),
+            json!({
+                "type": "object",
+                "properties": {
+                    "color": {"enum": ["green"]},
+                    "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 2}
+                },
+                "required": ["color", "tags"]
+            })
+        );
+
+        // the examples of the RFC
+        for (target, patch, merged) in [
+            (json!({"a": "b"}), json!({"a": "c"}), json!({"a": "c"})),
+            (
+                json!({"a": "b"}),
+                json!({"b": "c"}),
+                json!({"a": "b", "b": "c"}),
+            ),
+            (json!({"a": "b"}), json!({"a": null}), json!({})),
+            (json!({"a": ["b"]}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "c"}), json!({"a": ["b"]}), json!({"a": ["b"]})),
+            (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+            (json!({"a": "b"}), json!(["c"]), json!(["c"])),
+            (
+                json!({"e": null}),
+                json!({"a": 1}),
+                json!({"e": null, "a": 1}),
+            ),
+            (
+                json!([1, 2]),
+                json!({"a": "b", "c": null}),
+                json!({"a": "b"}),
+            ),
+            (
+                json!({}),
+                json!({"a": {"bb": {"ccc": null}}}),
+                json!({"a": {"bb": {}}}),
+            ),
+        ] {
+            assert_eq!(merge_overrides(&target, &patch), merged, "{}", patch);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! The regexes the generator writes for JSON values and string formats.
+//!
+//! Each pattern is a `&'static str` to compose into larger regexes, which is
+//! what the generator does, and a [`LazyLock<Regex>`] compiled from it that
+//! matches whole strings, like `^(?:...)$`. The patterns use plain capturing
+//! groups, as Outlines does; the generator turns them into `(?:...)` unless
+//! asked for capture groups.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// One character of the content of a JSON string: `\"`, `\\`, or any
+/// character which isn't a control one.
+///
+/// One match is one code point of the decoded string, so repeating it counts
+/// lengths the way JSON Schema does, as long as the engine matches characters
+/// rather than bytes.
+pub const STRING_INNER: &str = r#"([^"\\\x00-\x1F\x7F-\x9F]|\\["\\])"#;
+/// A JSON string, quotes included.
+pub const STRING: &str = r#""([^"\\\x00-\x1F\x7F-\x9F]|\\["\\])*""#;
+/// A JSON number without a fraction or an exponent.
+pub const INTEGER: &str = r#"(-)?(0|[1-9][0-9]*)"#;
+/// A JSON number.
+pub const NUMBER: &str = r#"((-)?(0|[1-9][0-9]*))(\.[0-9]+)?([eE][+-][0-9]+)?"#;
+pub const BOOLEAN: &str = r#"(true|false)"#;
+pub const NULL: &str = r#"null"#;
+/// What's allowed between JSON tokens by default.
+pub const WHITESPACE: &str = r#"[ ]?"#;
+
+/// A `date-time` string, quotes included.
+pub const DATE_TIME: &str = r#""(-?(?:[1-9][0-9]*)?[0-9]{4})-(1[0-2]|0[1-9])-(3[01]|0[1-9]|[12][0-9])T(2[0-3]|[01][0-9]):([0-5][0-9]):([0-5][0-9])(\.[0-9]{3})?(Z)?""#;
+/// A `date` string, quotes included.
+pub const DATE: &str = r#""(?:\d{4})-(?:0[1-9]|1[0-2])-(?:0[1-9]|[1-2][0-9]|3[0-1])""#;
+/// A `time` string, quotes included.
+pub const TIME: &str = r#""(2[0-3]|[01][0-9]):([0-5][0-9]):([0-5][0-9])(\\.[0-9]+)?(Z)?""#;
+/// A `uuid` string in lowercase, quotes included.
+pub const UUID: &str = r#""[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}""#;
+/// An `iri` string, quotes included: a scheme, then any characters of an IRI
+/// (RFC 3987) in any order.
+pub const IRI: &str = r#""[a-zA-Z][a-zA-Z0-9+.\-]*:(?:[a-zA-Z0-9\-._~!$&'()*+,;=:@/?#\[\]\x{A0}-\x{10FFFF}]|%[0-9a-fA-F]{2})*""#;
+/// A `uri-template` string, quotes included: literals and expressions of RFC
+/// 6570, up to level 4.
+pub const URI_TEMPLATE: &str = r#""(?:[!#$&(-;=?-\[\]_a-z~\x{A0}-\x{10FFFF}]|%[0-9a-fA-F]{2}|\{[+#./;?&=,!@|]?(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2})(?:\.?(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2}))*(?::[1-9][0-9]{0,3}|\*)?(?:,(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2})(?:\.?(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2}))*(?::[1-9][0-9]{0,3}|\*)?)*\})*""#;
+/// An `idn-hostname` string, quotes included: dot-separated labels of letters,
+/// marks, digits and inner hyphens. The 63 character limit of labels isn't
+/// enforced, as it would blow up the DFA.
+pub const IDN_HOSTNAME: &str = r#""[\p{L}\p{N}](?:[\p{L}\p{M}\p{N}\-]*[\p{L}\p{M}\p{N}])?(?:\.[\p{L}\p{N}](?:[\p{L}\p{M}\p{N}\-]*[\p{L}\p{M}\p{N}])?)*""#;
+/// A `byte` string of OpenAPI, quotes included: base64 with padding.
+pub const BYTE: &str = r#""(?:[A-Za-z0-9+/]{4})*(?:[A-Za-z0-9+/]{2}==|[A-Za-z0-9+/]{3}=)?""#;
+
+/// [`STRING`], matching whole strings.
+pub static STRING_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(STRING));
+/// [`INTEGER`], matching whole strings.
+pub static INTEGER_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(INTEGER));
+/// [`NUMBER`], matching whole strings.
+pub static NUMBER_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(NUMBER));
+/// [`BOOLEAN`], matching whole strings.
+pub static BOOLEAN_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(BOOLEAN));
+/// [`NULL`], matching whole strings.
+pub static NULL_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(NULL));
+/// [`DATE_TIME`], matching whole strings.
+pub static DATE_TIME_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(DATE_TIME));
+/// [`DATE`], matching whole strings.
+pub static DATE_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(DATE));
+/// [`TIME`], matching whole strings.
+pub static TIME_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(TIME));
+/// [`UUID`], matching whole strings.
+pub static UUID_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(UUID));
+/// [`IRI`], matching whole strings.
+pub static IRI_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(IRI));
+/// [`URI_TEMPLATE`], matching whole strings.
+pub static URI_TEMPLATE_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(URI_TEMPLATE));
+/// [`IDN_HOSTNAME`], matching whole strings.
+pub static IDN_HOSTNAME_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(IDN_HOSTNAME));
+/// [`BYTE`], matching whole strings.
+pub static BYTE_REGEX: LazyLock<Regex> = LazyLock::new(|| whole(BYTE));
+
+fn whole(pattern: &str) -> Regex {
+    Regex::new(&format!("^(?:{})$", pattern)).expect("the patterns are valid regexes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns() {
+        for (regex, matching, other) in [
+            (&STRING_REGEX, r#""a \"b\"""#, r#""a"b""#),
+            (&INTEGER_REGEX, "-12", "012"),
+            (&NUMBER_REGEX, "1.5e+3", "1."),
+            (&BOOLEAN_REGEX, "true", "True"),
+            (&NULL_REGEX, "null", "nil"),
+            (
+                &DATE_TIME_REGEX,
+                r#""2024-02-29T12:30:00Z""#,
+                r#""2024-02-29""#,
+            ),
+            (&DATE_REGEX, r#""2024-02-29""#, r#""2024-13-01""#),
+            (&TIME_REGEX, r#""23:59:59""#, r#""24:00:00""#),
+            (
+                &UUID_REGEX,
+                r#""123e4567-e89b-12d3-a456-426614174000""#,
+                r#""123e4567""#,
+            ),
+            (&IRI_REGEX, r#""https://例え.jp/a?b#c""#, r#""no scheme""#),
+            (
+                &URI_TEMPLATE_REGEX,
+                r#""/users{/id}{?q,page}""#,
+                r#""/users{id""#,
+            ),
+            (&IDN_HOSTNAME_REGEX, r#""bücher.example""#, r#""-bücher""#),
+            (&BYTE_REGEX, r#""aGk=""#, r#""aGk""#),
+        ] {
+            assert!(regex.is_match(matching), "{}", matching);
+            assert!(!regex.is_match(other), "{}", other);
+        }
+    }
+}
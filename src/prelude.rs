@@ -0,0 +1,24 @@
+//! What most users of the crate need, in one import.
+//!
+//! ```
+//! use guidance_rs::prelude::*;
+//!
+//! let options = RegexOptions {
+//!     strict: true,
+//!     ..RegexOptions::legacy()
+//! };
+//! let regex = build_regex_from_schema_with_options(r#"{"type": "boolean"}"#, &options).unwrap();
+//! assert_eq!(regex, JsonType::Boolean.to_regex());
+//! ```
+//!
+//! [`RegexOptions`] holds the settings of the `build_regex_*` functions, and
+//! [`Generator`] builds regexes with the same settings through its `with_*`
+//! methods. Errors about the schema are [`SchemaError`]s.
+
+pub use crate::error::SchemaError;
+pub use crate::guidance::{
+    build_regex_from_schema, build_regex_from_schema_with_options,
+    build_regex_from_schema_with_warnings, to_regex, write_regex_from_schema_to, Generator, Preset,
+    RegexOptions,
+};
+pub use crate::types::{FormatType, JsonType, NumberSyntax};
@@ -0,0 +1,242 @@
+use anyhow::{anyhow, Result};
+use regex::escape;
+
+use crate::compat::{Guide, Index, Vocabulary};
+use crate::guidance::{build_regex_from_schema_with_options, RegexOptions};
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Json(String, RegexOptions),
+    Regex(String),
+    Choice(Vec<String>),
+}
+
+/// A sequence of literal text and constrained segments driven by a single Guide.
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    segments: Vec<Segment>,
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends literal text that must appear verbatim.
+    pub fn text(mut self, text: &str) -> Self {
+        self.segments.push(Segment::Text(text.to_string()));
+        self
+    }
+
+    /// Appends a value constrained by a JSON schema, generated with
+    /// `options` but for `anchored`, as the value sits inside the program.
+    pub fn json(mut self, schema: &str, options: &RegexOptions) -> Self {
+        let options = RegexOptions {
+            anchored: false,
+            ..options.clone()
+        };
+        self.segments
+            .push(Segment::Json(schema.to_string(), options));
+        self
+    }
+
+    /// Appends text constrained by a raw regular expression.
+    pub fn matching(mut self, pattern: &str) -> Self {
+        self.segments.push(Segment::Regex(pattern.to_string()));
+        self
+    }
+
+    /// Appends exactly one of `options`, matched verbatim.
+    pub fn choice(mut self, options: &[&str]) -> Self {
+        self.segments.push(Segment::Choice(
+            options.iter().map(|option| option.to_string()).collect(),
+        ));
+        self
+    }
+
+    /// Concatenates all segments into a single regular expression.
+    pub fn build_regex(&self) -> Result<String> {
+        let mut regex = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => regex += &escape(text),
+                Segment::Json(schema, options) => {
+                    let json = build_regex_from_schema_with_options(schema, options)?;
+                    regex += &format!("(?:{})", json)
+                }
+                Segment::Regex(pattern) => regex += &format!("(?:{})", pattern),
+                Segment::Choice(options) => {
+                    if options.is_empty() {
+                        return Err(anyhow!("'choice' needs at least one option"));
+                    }
+                    let options: Vec<String> = options.iter().map(|o| escape(o)).collect();
+                    regex += &format!("(?:{})", options.join("|"));
+                }
+            }
+        }
+        Ok(regex)
+    }
+
+    pub fn guide(&self, vocabulary: &Vocabulary) -> Result<Guide> {
+        let index = Index::new(&self.build_regex()?, vocabulary)?;
+        Ok(Guide::new(index))
+    }
+}
+
+/// Guide constrained to exactly one of `options`, like guidance's `select`.
+pub fn choice(options: &[&str], vocabulary: &Vocabulary) -> Result<Guide> {
+    Program::new().choice(options).guide(vocabulary)
+}
+
+/// Guide constrained to a raw regular expression, like guidance's `gen(regex=...)`.
+pub fn from_regex(pattern: &str, vocabulary: &Vocabulary) -> Result<Guide> {
+    Program::new().matching(pattern).guide(vocabulary)
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+
+    fn is_full_match(regex: &str, text: &str) -> bool {
+        Regex::new(&format!("^(?:{})$", regex))
+            .unwrap()
+            .is_match(text)
+    }
+
+    #[test]
+    fn test_text_is_escaped() {
+        let regex = Program::new().text("a.b (c)").build_regex().unwrap();
+        assert!(is_full_match(&regex, "a.b (c)"));
+        assert!(!is_full_match(&regex, "axb (c)"));
+    }
+
+    #[test]
+    fn test_interleaved_segments() {
+        let regex = Program::new()
+            .text("Answer: ")
+            .json(
+                r#"{"type": "object", "properties": {"flag": {"type": "boolean"}}, "required": ["flag"]}"#,
+                &RegexOptions::default(),
+            )
+            .text("\nDone")
+            .build_regex()
+            .unwrap();
+
+        assert!(is_full_match(&regex, "Answer: {\"flag\":true}\nDone"));
+        assert!(!is_full_match(&regex, "Answer: {\"flag\":1}\nDone"));
+        assert!(!is_full_match(&regex, "Answer: {\"flag\":true}"));
+    }
+
+    #[test]
+    fn test_regex_segment_is_grouped() {
+        let regex = Program::new()
+            .matching("a|b")
+            .text("!")
+            .build_regex()
+            .unwrap();
+        assert!(is_full_match(&regex, "a!"));
+        assert!(!is_full_match(&regex, "a"));
+    }
+
+    #[test]
+    fn test_json_options() {
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            closed_objects: true,
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let regex = Program::new()
+            .text("> ")
+            .json(
+                r#"{"type": "object", "properties": {"n": {"type": "integer"}}}"#,
+                &options,
+            )
+            .build_regex()
+            .unwrap();
+        assert!(is_full_match(&regex, r#"> {"n":1}"#));
+        assert!(!is_full_match(&regex, r#"> {"n": 1}"#));
+        assert!(!is_full_match(&regex, r#"> {"m":1}"#));
+    }
+
+    #[test]
+    fn test_invalid_schema_is_reported() {
+        let program = Program::new()
+            .text("x")
+            .json("{not json", &RegexOptions::default());
+        assert!(program.build_regex().is_err());
+    }
+
+    #[test]
+    fn test_guide_over_program() {
+        let mut vocabulary = Vocabulary::new(0);
+        for (id, token) in ["A: ", "true", "false", "!"].iter().enumerate() {
+            vocabulary.try_insert(*token, id as u32 + 1).unwrap();
+        }
+
+        let mut guide = Program::new()
+            .text("A: ")
+            .json(r#"{"type": "boolean"}"#, &RegexOptions::default())
+            .text("!")
+            .guide(&vocabulary)
+            .unwrap();
+
+        assert_eq!(guide.get_tokens().unwrap(), vec![1]);
+        assert_eq!(guide.advance(1).unwrap(), vec![2, 3]);
+        assert_eq!(guide.advance(3).unwrap(), vec![4]);
+        guide.advance(4).unwrap();
+        assert!(guide.is_finished());
+    }
+
+    fn digits_vocabulary() -> Vocabulary {
+        let mut vocabulary = Vocabulary::new(0);
+        for (id, token) in ["0", "1", "2", "12", "-", "yes", "no", "y", "es", "maybe"]
+            .iter()
+            .enumerate()
+        {
+            vocabulary.try_insert(*token, id as u32 + 1).unwrap();
+        }
+        vocabulary
+    }
+
+    #[test]
+    fn test_choice_escapes_options() {
+        let regex = Program::new().choice(&["a+b", "c"]).build_regex().unwrap();
+        assert!(is_full_match(&regex, "a+b"));
+        assert!(!is_full_match(&regex, "aab"));
+        assert!(is_full_match(&regex, "c"));
+    }
+
+    #[test]
+    fn test_empty_choice() {
+        assert!(Program::new().choice(&[]).build_regex().is_err());
+    }
+
+    #[test]
+    fn test_choice_guide() {
+        let vocabulary = digits_vocabulary();
+        let mut guide = choice(&["yes", "no", "maybe"], &vocabulary).unwrap();
+
+        // "yes" can be spelled as a single token or as "y" + "es"
+        assert_eq!(guide.get_tokens().unwrap(), vec![6, 7, 8, 10]);
+        guide.advance(8).unwrap();
+        assert_eq!(guide.get_tokens().unwrap(), vec![9]);
+        guide.advance(9).unwrap();
+        assert!(guide.is_finished());
+    }
+
+    #[test]
+    fn test_regex_guide() {
+        let vocabulary = digits_vocabulary();
+        let mut guide = from_regex(r"[0-9]{2}-[0-9]", &vocabulary).unwrap();
+
+        guide.advance(4).unwrap();
+        assert_eq!(guide.get_tokens().unwrap(), vec![5]);
+        guide.advance(5).unwrap();
+        guide.advance(1).unwrap();
+        assert!(guide.is_finished());
+    }
+}
@@ -0,0 +1,229 @@
+//! Schemas cut down to some of their properties, for follow-up turns asking
+//! the model to fill only a few fields of a large schema.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+use crate::guidance::{build_regex_from_schema_with_options, follow_refs, RegexOptions};
+
+/// Keywords counting the properties of the whole object, which the projected
+/// one doesn't have all of.
+const COUNTING_KEYWORDS: &[&str] = &["minProperties", "maxProperties"];
+
+/// The properties selected under an object, and whether each is kept whole.
+#[derive(Debug, Default)]
+struct Selection {
+    whole: bool,
+    properties: BTreeMap<String, Selection>,
+}
+
+/// The schema with only the properties at `pointers`, like
+/// `["/properties/name", "/properties/address/properties/city"]`.
+///
+/// A property is kept whole, and the objects on the way to it keep only the
+/// selected properties, with those of `required` that are left. `$ref`s on
+/// the way are replaced by their targets, and `$defs` stay for the others.
+pub fn project(schema: &Value, pointers: &[&str]) -> Result<Value> {
+    if pointers.is_empty() {
+        return Err(anyhow!("At least one pointer is required"));
+    }
+
+    let mut selection = Selection::default();
+    for pointer in pointers {
+        let mut segments = pointer.split('/');
+        if segments.next() != Some("") {
+            return Err(anyhow!("Invalid JSON Pointer: '{}'", pointer));
+        }
+        let mut selected = &mut selection;
+        let mut depth = 0;
+        loop {
+            match (segments.next(), segments.next()) {
+                (None, _) if depth > 0 => break,
+                (Some("properties"), Some(name)) => {
+                    let name = name.replace("~1", "/").replace("~0", "~");
+                    selected = selected.properties.entry(name).or_default();
+                    depth += 1;
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Only pointers to properties can be projected, not '{}'",
+                        pointer
+                    ))
+                }
+            }
+        }
+        selected.whole = true;
+    }
+
+    let mut projected = project_object(schema, schema, &selection, "")?;
+    // the root may have been a `$ref`, whose target lacks the definitions
+    for keyword in ["$defs", "definitions"] {
+        if let (Some(definitions), Value::Object(obj)) = (schema.get(keyword), &mut projected) {
+            obj.entry(keyword).or_insert_with(|| definitions.clone());
+        }
+    }
+    Ok(projected)
+}
+
+/// The regex of the schema `json` projected on `pointers`, see [`project`].
+pub fn build_projected_regex(
+    json: &str,
+    pointers: &[&str],
+    options: &RegexOptions,
+) -> Result<String> {
+    let schema: Value = serde_json::from_str(json)?;
+    let projected = project(&schema, pointers)?;
+    build_regex_from_schema_with_options(&projected.to_string(), options)
+}
+
+fn project_object(
+    schema: &Value,
+    full_schema: &Value,
+    selection: &Selection,
+    pointer: &str,
+) -> Result<Value> {
+    let obj = follow_refs(schema, full_schema)?
+        .as_object()
+        .ok_or_else(|| anyhow!("The schema at '{}' is not an object", pointer))?;
+    let properties = obj
+        .get("properties")
+        .and_then(Value::as_object)
+        .ok_or_else(|| anyhow!("The schema at '{}' has no properties", pointer))?;
+
+    let mut kept = Map::new();
+    for (name, selected) in &selection.properties {
+        let at = format!(
+            "{}/properties/{}",
+            pointer,
+            name.replace('~', "~0").replace('/', "~1")
+        );
+        let value = properties
+            .get(name)
+            .ok_or_else(|| anyhow!("The schema has no property at '{}'", at))?;
+        let value = match selected.whole {
+            true => value.clone(),
+            false => project_object(value, full_schema, selected, &at)?,
+        };
+        kept.insert(name.clone(), value);
+    }
+    // the order of the schema, which the regex may follow
+    let kept: Map<String, Value> = properties
+        .keys()
+        .filter_map(|name| Some((name.clone(), kept.remove(name)?)))
+        .collect();
+
+    let mut projected = obj.clone();
+    if let Some(required) = obj.get("required").and_then(Value::as_array) {
+        let required: Vec<Value> = required
+            .iter()
+            .filter(|name| name.as_str().is_some_and(|name| kept.contains_key(name)))
+            .cloned()
+            .collect();
+        projected.insert("required".to_string(), Value::Array(required));
+    }
+    projected.insert("properties".to_string(), Value::Object(kept));
+    for keyword in COUNTING_KEYWORDS {
+        projected.remove(*keyword);
+    }
+    Ok(Value::Object(projected))
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_project() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "age": {"type": "integer"},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "address": {"$ref": "#/$defs/Address"}
+            },
+            "required": ["name", "age", "address"],
+            "minProperties": 3,
+            "$defs": {
+                "Address": {
+                    "type": "object",
+                    "properties": {
+                        "city": {"type": "string"},
+                        "zip": {"type": "string"}
+                    },
+                    "required": ["city", "zip"]
+                }
+            }
+        });
+
+        let projected = project(
+            &schema,
+            &[
+                "/properties/tags",
+                "/properties/address/properties/city",
+                "/properties/name",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            projected,
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "tags": {"type": "array", "items": {"type": "string"}},
+                    "address": {
+                        "type": "object",
+                        "properties": {"city": {"type": "string"}},
+                        "required": ["city"]
+                    }
+                },
+                "required": ["name", "address"],
+                "$defs": schema["$defs"]
+            })
+        );
+
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let regex =
+            build_projected_regex(&schema.to_string(), &["/properties/age"], &options).unwrap();
+        let regex = Regex::new(&regex).unwrap();
+        assert!(regex.is_match(r#"{"age":41}"#));
+        assert!(!regex.is_match(r#"{"age":41,"name":"Ann"}"#));
+    }
+
+    #[test]
+    fn test_project_errors() {
+        let schema = json!({"type": "object", "properties": {"a": {"type": "integer"}}});
+        for (pointers, error) in [
+            (vec![], "At least one pointer is required"),
+            (
+                vec![""],
+                "Only pointers to properties can be projected, not ''",
+            ),
+            (
+                vec!["/items/0"],
+                "Only pointers to properties can be projected, not '/items/0'",
+            ),
+            (
+                vec!["/properties/b"],
+                "The schema has no property at '/properties/b'",
+            ),
+            (
+                vec!["/properties/a/properties/b"],
+                "The schema at '/properties/a' has no properties",
+            ),
+        ] {
+            let result = project(&schema, &pointers);
+            assert_eq!(result.unwrap_err().to_string(), error);
+        }
+    }
+}
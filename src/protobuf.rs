@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use serde_json::{json, Map, Value};
+
+use crate::guidance::{
+    check_with_options, configured_generator, finish_regex, resolve_whitespace_pattern,
+    RegexOptions,
+};
+
+static SIGNED_INT64: &str = r"-?(0|[1-9][0-9]*)";
+static UNSIGNED_INT64: &str = r"(0|[1-9][0-9]*)";
+static BASE64: &str = r"([A-Za-z0-9+/]{4})*([A-Za-z0-9+/]{2}==|[A-Za-z0-9+/]{3}=)?";
+static DURATION: &str = r"-?[0-9]+(\.[0-9]{1,9})?s";
+
+/// Builds a regex for the proto3 JSON form of `message`.
+///
+/// `descriptor_set` is an encoded `FileDescriptorSet`, as written by
+/// `protoc --descriptor_set_out`, and `message` a fully-qualified name such
+/// as `shop.v1.Order`.
+pub fn build_regex_from_descriptor_set(
+    descriptor_set: &[u8],
+    message: &str,
+    whitespace_pattern: Option<&str>,
+) -> Result<String> {
+    let options = RegexOptions {
+        whitespace_pattern: whitespace_pattern.map(str::to_string),
+        ..RegexOptions::default()
+    };
+    build_regex_from_descriptor_set_with_options(descriptor_set, message, &options)
+}
+
+/// Like [`build_regex_from_descriptor_set`], with the schema of the message
+/// generated with `options`.
+pub fn build_regex_from_descriptor_set_with_options(
+    descriptor_set: &[u8],
+    message: &str,
+    options: &RegexOptions,
+) -> Result<String> {
+    let descriptor_set = FileDescriptorSet::decode(descriptor_set)
+        .map_err(|e| anyhow!("Failed to decode FileDescriptorSet: {}", e))?;
+    let schema = descriptor_set_to_schema(&descriptor_set, message)?;
+    check_with_options(&schema, options)?;
+
+    let whitespace_pattern = resolve_whitespace_pattern(options.whitespace_pattern.as_deref())?;
+    let generator = configured_generator(&whitespace_pattern, &schema, options);
+    finish_regex(&generator.to_regex(&schema)?, options)
+}
+
+/// Maps `message` to a JSON schema following the proto3 JSON mapping.
+///
+/// Fields use their JSON names and are all optional, since proto3 omits
+/// fields holding default values. 64-bit integers are strings, `bytes` are
+/// base64 and enums are written by name. Members of a `oneof` are not checked
+/// for exclusivity, and recursive messages are rejected.
+pub fn descriptor_set_to_schema(
+    descriptor_set: &FileDescriptorSet,
+    message: &str,
+) -> Result<Value> {
+    let mut types = TypeIndex::default();
+    for file in &descriptor_set.file {
+        let prefix = match file.package() {
+            "" => String::new(),
+            package => format!(".{}", package),
+        };
+        types.add(&prefix, &file.message_type, &file.enum_type);
+    }
+
+    let name = format!(".{}", message.trim_start_matches('.'));
+    types.message_schema(&name, &mut Vec::new())
+}
+
+#[derive(Default)]
+struct TypeIndex<'a> {
+    messages: HashMap<String, &'a DescriptorProto>,
+    enums: HashMap<String, &'a EnumDescriptorProto>,
+}
+
+impl<'a> TypeIndex<'a> {
+    fn add(
+        &mut self,
+        prefix: &str,
+        messages: &'a [DescriptorProto],
+        enums: &'a [EnumDescriptorProto],
+    ) {
+        for message in messages {
+            let name = format!("{}.{}", prefix, message.name());
+            self.add(&name, &message.nested_type, &message.enum_type);
+            self.messages.insert(name, message);
+        }
+        for enumeration in enums {
+            self.enums
+                .insert(format!("{}.{}", prefix, enumeration.name()), enumeration);
+        }
+    }
+
+    fn message_schema(&self, name: &str, stack: &mut Vec<String>) -> Result<Value> {
+        if let Some(schema) = well_known_type(name) {
+            return Ok(schema);
+        }
+        if stack.iter().any(|seen| seen == name) {
+            return Err(anyhow!(
+                "Recursive message {} can't be mapped to a regex",
+                name
+            ));
+        }
+        let message = self
+            .messages
+            .get(name)
+            .ok_or_else(|| anyhow!("Message {} not found in the descriptor set", name))?;
+
+        stack.push(name.to_string());
+        let mut properties = Map::new();
+        for field in &message.field {
+            properties.insert(json_name(field), self.field_schema(field, stack)?);
+        }
+        stack.pop();
+
+        Ok(json!({"type": "object", "properties": properties}))
+    }
+
+    fn field_schema(&self, field: &FieldDescriptorProto, stack: &mut Vec<String>) -> Result<Value> {
+        if field.label() == Label::Repeated {
+            if let Some(entry) = self.map_entry(field) {
+                let value = entry
+                    .field
+                    .iter()
+                    .find(|f| f.name() == "value")
+                    .ok_or_else(|| anyhow!("Map entry {} has no value", field.type_name()))?;
+                return Ok(json!({
+                    "type": "object",
+                    "additionalProperties": self.value_schema(value, stack)?
+                }));
+            }
+            return Ok(json!({"type": "array", "items": self.value_schema(field, stack)?}));
+        }
+        self.value_schema(field, stack)
+    }
+
+    fn value_schema(&self, field: &FieldDescriptorProto, stack: &mut Vec<String>) -> Result<Value> {
+        Ok(match field.r#type() {
+            Type::Double | Type::Float => json!({"type": "number"}),
+            Type::Int32 | Type::Sint32 | Type::Sfixed32 | Type::Uint32 | Type::Fixed32 => {
+                json!({"type": "integer"})
+            }
+            Type::Int64 | Type::Sint64 | Type::Sfixed64 => {
+                json!({"type": "string", "pattern": SIGNED_INT64})
+            }
+            Type::Uint64 | Type::Fixed64 => json!({"type": "string", "pattern": UNSIGNED_INT64}),
+            Type::Bool => json!({"type": "boolean"}),
+            Type::String => json!({"type": "string"}),
+            Type::Bytes => json!({"type": "string", "pattern": BASE64}),
+            Type::Enum => {
+                let enumeration = self.enums.get(field.type_name()).ok_or_else(|| {
+                    anyhow!("Enum {} not found in the descriptor set", field.type_name())
+                })?;
+                let names: Vec<&str> = enumeration.value.iter().map(|v| v.name()).collect();
+                json!({"enum": names})
+            }
+            Type::Message => self.message_schema(field.type_name(), stack)?,
+            Type::Group => return Err(anyhow!("Groups have no proto3 JSON mapping")),
+        })
+    }
+
+    fn map_entry(&self, field: &FieldDescriptorProto) -> Option<&'a DescriptorProto> {
+        if field.r#type() != Type::Message {
+            return None;
+        }
+        self.messages
+            .get(field.type_name())
+            .copied()
+            .filter(|message| message.options.as_ref().is_some_and(|o| o.map_entry()))
+    }
+}
+
+/// The special JSON forms of `google.protobuf` types.
+fn well_known_type(name: &str) -> Option<Value> {
+    let wrapped = match name.strip_prefix(".google.protobuf.")? {
+        "Timestamp" => return Some(json!({"type": "string", "format": "date-time"})),
+        "Duration" => return Some(json!({"type": "string", "pattern": DURATION})),
+        "Empty" => return Some(json!({"type": "object", "maxProperties": 0})),
+        "Struct" => return Some(json!({"type": "object"})),
+        "Value" => return Some(json!({})),
+        "DoubleValue" | "FloatValue" => json!({"type": "number"}),
+        "Int32Value" | "UInt32Value" => json!({"type": "integer"}),
+        "Int64Value" => json!({"type": "string", "pattern": SIGNED_INT64}),
+        "UInt64Value" => json!({"type": "string", "pattern": UNSIGNED_INT64}),
+        "BoolValue" => json!({"type": "boolean"}),
+        "StringValue" => json!({"type": "string"}),
+        "BytesValue" => json!({"type": "string", "pattern": BASE64}),
+        _ => return None,
+    };
+    // wrappers exist to tell a default value from a missing one
+    Some(json!({"anyOf": [wrapped, {"type": "null"}]}))
+}
+
+/// The JSON name protoc records, or the lowerCamelCase form of the field name.
+fn json_name(field: &FieldDescriptorProto) -> String {
+    if let Some(name) = &field.json_name {
+        return name.clone();
+    }
+
+    let mut name = String::new();
+    let mut upper = false;
+    for c in field.name().chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            name.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use prost_types::{FileDescriptorProto, MessageOptions};
+    use regex::Regex;
+
+    use super::*;
+
+    fn field(
+        name: &str,
+        number: i32,
+        r#type: Type,
+        type_name: Option<&str>,
+    ) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(r#type as i32),
+            type_name: type_name.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn descriptor_set() -> FileDescriptorSet {
+        let mut tags = field("tags", 3, Type::Message, Some(".shop.Order.TagsEntry"));
+        tags.label = Some(Label::Repeated as i32);
+        let mut item_ids = field("item_ids", 4, Type::Int64, None);
+        item_ids.label = Some(Label::Repeated as i32);
+
+        let order = DescriptorProto {
+            name: Some("Order".to_string()),
+            field: vec![
+                field("order_id", 1, Type::Uint32, None),
+                field("status", 2, Type::Enum, Some(".shop.Status")),
+                tags,
+                item_ids,
+            ],
+            nested_type: vec![DescriptorProto {
+                name: Some("TagsEntry".to_string()),
+                field: vec![
+                    field("key", 1, Type::String, None),
+                    field("value", 2, Type::Bool, None),
+                ],
+                options: Some(MessageOptions {
+                    map_entry: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let status = EnumDescriptorProto {
+            name: Some("Status".to_string()),
+            value: vec![
+                prost_types::EnumValueDescriptorProto {
+                    name: Some("PENDING".to_string()),
+                    number: Some(0),
+                    ..Default::default()
+                },
+                prost_types::EnumValueDescriptorProto {
+                    name: Some("SHIPPED".to_string()),
+                    number: Some(1),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("shop.proto".to_string()),
+                package: Some("shop".to_string()),
+                message_type: vec![order],
+                enum_type: vec![status],
+                syntax: Some("proto3".to_string()),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = descriptor_set_to_schema(&descriptor_set(), "shop.Order").unwrap();
+        assert_eq!(
+            schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "orderId": {"type": "integer"},
+                    "status": {"enum": ["PENDING", "SHIPPED"]},
+                    "tags": {"type": "object", "additionalProperties": {"type": "boolean"}},
+                    "itemIds": {"type": "array", "items": {"type": "string", "pattern": SIGNED_INT64}}
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_regex_from_encoded_set() {
+        let bytes = descriptor_set().encode_to_vec();
+        let regex = build_regex_from_descriptor_set(&bytes, "shop.Order", Some("")).unwrap();
+        let regex = Regex::new(&format!("^(?:{})$", regex)).unwrap();
+
+        assert!(regex.is_match("{}"));
+        assert!(regex.is_match(r#"{"itemIds":["-12","3"]}"#));
+        assert!(regex.is_match(r#"{"status":"SHIPPED"}"#));
+        assert!(regex.is_match(r#"{"tags":{"gift":true}}"#));
+        assert!(!regex.is_match(r#"{"status":"LOST"}"#));
+        assert!(!regex.is_match(r#"{"itemIds":[12]}"#));
+    }
+
+    #[test]
+    fn test_options() {
+        let bytes = descriptor_set().encode_to_vec();
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            sort_properties: true,
+            treat_all_properties_required: true,
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let regex =
+            build_regex_from_descriptor_set_with_options(&bytes, "shop.Order", &options).unwrap();
+        assert!(regex.starts_with('^'));
+        let regex = Regex::new(&regex).unwrap();
+
+        assert!(regex.is_match(r#"{"itemIds":["1"],"orderId":2,"status":"PENDING","tags":{}}"#));
+        assert!(!regex.is_match("{}"));
+    }
+
+    #[test]
+    fn test_unknown_message() {
+        assert!(descriptor_set_to_schema(&descriptor_set(), "shop.Cart").is_err());
+    }
+}
@@ -0,0 +1,525 @@
+//! A pushdown automaton for the schemas a regex can't match: recursive
+//! `$ref`s, and unconstrained values nested at any depth.
+//!
+//! The schema is split into rules, each a regex: the one of the schema
+//! itself, one for each `$ref` target reached again from inside itself, and
+//! one for the unconstrained values. Where a regex would hold another rule,
+//! it holds a call to it instead: a `0xFF` byte, which no UTF-8 text holds,
+//! and the two bytes of the number of the rule. The automaton keeps a stack
+//! of the rules being matched, each at a state of the DFA of its regex, and
+//! follows every way calls and the ends of rules can go, as several branches
+//! can match the same text.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use regex_automata::dfa::{dense, Automaton, StartKind};
+use regex_automata::util::primitives::StateID;
+use regex_automata::util::syntax;
+use regex_automata::MatchKind;
+
+use crate::automaton::{start_state, walk};
+use crate::compat::{CompiledSchema, Guide, Token, TokenId, Vocabulary};
+use crate::guidance::{
+    configured_generator, non_capturing, parse_with_options, resolve_whitespace_pattern, Backend,
+    RegexOptions,
+};
+
+/// What a rule called from the regexes of a grammar matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Rule {
+    /// The target of a recursive `$ref`.
+    Ref(String),
+    /// Any value allowed where the schema doesn't constrain them.
+    Unconstrained,
+}
+
+/// The byte starting a call.
+const CALL: u8 = 0xFF;
+
+/// Rules a grammar can have, numbered by two bytes.
+const MAX_RULES: usize = 1 << 16;
+
+/// The pattern calling the rule numbered `index`.
+pub(crate) fn call_pattern(index: usize) -> String {
+    format!(r"(?-u:\xFF\x{:02X}\x{:02X})", index >> 8, index & 0xFF)
+}
+
+/// The rules being matched, innermost last, each at a state of its DFA.
+type Stack = Vec<(usize, StateID)>;
+
+/// The rules of a schema, see the [module docs](self). Cloning it is cheap.
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    regexes: Arc<[String]>,
+    dfas: Arc<[dense::DFA<Vec<u32>>]>,
+    starts: Arc<[StateID]>,
+}
+
+impl Grammar {
+    /// The grammar of `json`, whose rules are generated with `options` like
+    /// the regex of a schema.
+    pub fn new(json: &str, options: &RegexOptions) -> Result<Self> {
+        let json_value = parse_with_options(json, options)?;
+        let whitespace_pattern = resolve_whitespace_pattern(options.whitespace_pattern.as_deref())?;
+        let rules = Arc::new(Mutex::new(Vec::new()));
+        let generator = configured_generator(&whitespace_pattern, &json_value, options)
+            .with_rules(Arc::clone(&rules));
+
+        let mut regexes = vec![generator.to_regex(&json_value)?];
+        if options.padded {
+            regexes[0] = format!("{0}{1}{0}", whitespace_pattern, regexes[0]);
+        }
+        // the rules call more rules as they're generated
+        loop {
+            let rule = rules
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .get(regexes.len() - 1)
+                .cloned();
+            let Some(rule) = rule else {
+                break;
+            };
+            if regexes.len() == MAX_RULES {
+                return Err(anyhow!("The grammar takes more than {} rules", MAX_RULES));
+            }
+            regexes.push(generator.rule_regex(&rule)?);
+        }
+
+        if !options.capture_groups {
+            regexes = regexes.iter().map(|regex| non_capturing(regex)).collect();
+        }
+        Self::from_regexes(regexes)
+    }
+
+    fn from_regexes(regexes: Vec<String>) -> Result<Self> {
+        let dfas = regexes
+            .iter()
+            .map(|regex| build_rule_dfa(regex))
+            .collect::<Result<Vec<_>>>()?;
+        let starts = dfas.iter().map(start_state).collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            regexes: regexes.into(),
+            dfas: dfas.into(),
+            starts: starts.into(),
+        })
+    }
+
+    /// The regexes of the rules, the one of the schema first, with their
+    /// calls to each other.
+    pub fn rules(&self) -> &[String] {
+        &self.regexes
+    }
+
+    /// Whether `input` is a whole document of the grammar.
+    pub fn is_match(&self, input: &[u8]) -> bool {
+        let mut stacks = self.initial();
+        for &byte in input {
+            stacks = self.step(&stacks, byte);
+            if stacks.is_empty() {
+                return false;
+            }
+        }
+        self.accepts(&stacks)
+    }
+
+    fn initial(&self) -> Vec<Stack> {
+        self.closure(vec![vec![(0, self.starts[0])]])
+    }
+
+    fn is_final(&self, (rule, state): (usize, StateID)) -> bool {
+        let dfa = &self.dfas[rule];
+        dfa.is_match_state(dfa.next_eoi_state(state))
+    }
+
+    /// Whether the document can end with the rules of `stacks`.
+    fn accepts(&self, stacks: &[Stack]) -> bool {
+        stacks
+            .iter()
+            .any(|stack| stack.len() == 1 && self.is_final(stack[0]))
+    }
+
+    /// The stacks after reading `byte`, none when no document goes on with it.
+    fn step(&self, stacks: &[Stack], byte: u8) -> Vec<Stack> {
+        if byte == CALL {
+            return Vec::new();
+        }
+        let next = stacks
+            .iter()
+            .filter_map(|stack| {
+                let (&(rule, state), callers) = stack.split_last()?;
+                let next = walk(&self.dfas[rule], state, &[byte])?;
+                let mut stack = callers.to_vec();
+                stack.push((rule, next));
+                Some(stack)
+            })
+            .collect();
+        self.closure(next)
+    }
+
+    /// The calls from `state` of `rule`, with the state each returns to.
+    fn calls(&self, rule: usize, state: StateID) -> Vec<(usize, StateID)> {
+        let dfa = &self.dfas[rule];
+        let mut calls = Vec::new();
+        let Some(call) = walk(dfa, state, &[CALL]) else {
+            return calls;
+        };
+        for high in 0..=u8::MAX {
+            let Some(half) = walk(dfa, call, &[high]) else {
+                continue;
+            };
+            for low in 0..=u8::MAX {
+                if let Some(returned) = walk(dfa, half, &[low]) {
+                    calls.push((usize::from(high) << 8 | usize::from(low), returned));
+                }
+            }
+        }
+        calls
+    }
+
+    /// `stacks` and the ones they lead to without reading a byte, by calling
+    /// rules and returning from matched ones. A rule isn't called again
+    /// before reading a byte: left recursion would go on forever, and
+    /// matches nothing the rule doesn't match without it.
+    fn closure(&self, stacks: Vec<Stack>) -> Vec<Stack> {
+        let mut seen = HashSet::new();
+        let mut closed = Vec::new();
+        // each stack with the length below which its rules were there before
+        let mut queue: Vec<(Stack, usize)> = stacks
+            .into_iter()
+            .map(|stack| {
+                let len = stack.len();
+                (stack, len)
+            })
+            .collect();
+        while let Some((stack, low)) = queue.pop() {
+            if !seen.insert(stack.clone()) {
+                continue;
+            }
+            let (&(rule, state), callers) = stack.split_last().expect("stacks aren't empty");
+            for (callee, returned) in self.calls(rule, state) {
+                if stack[low..].iter().any(|&(entered, _)| entered == callee) {
+                    continue;
+                }
+                let mut called = callers.to_vec();
+                called.push((rule, returned));
+                called.push((callee, self.starts[callee]));
+                queue.push((called, low));
+            }
+            if !callers.is_empty() && self.is_final((rule, state)) {
+                queue.push((callers.to_vec(), low.min(callers.len())));
+            }
+            closed.push(stack);
+        }
+        closed
+    }
+}
+
+/// The anchored DFA of a rule, whose calls aren't UTF-8.
+fn build_rule_dfa(regex: &str) -> Result<dense::DFA<Vec<u32>>> {
+    dense::Builder::new()
+        .configure(
+            dense::Config::new()
+                .match_kind(MatchKind::All)
+                .start_kind(StartKind::Anchored),
+        )
+        .syntax(syntax::Config::new().utf8(false))
+        .build(&format!("(?:{})$", regex))
+        .map_err(|e| anyhow!("Failed to build DFA for regex: {}", e))
+}
+
+/// The tokens of a vocabulary by their bytes, so tokens starting alike are
+/// walked together.
+#[derive(Debug)]
+struct TokenTrie {
+    nodes: Vec<TrieNode>,
+    tokens: HashMap<TokenId, Token>,
+    eos_token_id: TokenId,
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: Vec<(u8, usize)>,
+    /// The tokens ending here.
+    ids: Vec<TokenId>,
+}
+
+impl TokenTrie {
+    fn new(vocabulary: &Vocabulary) -> Self {
+        let mut trie = Self {
+            nodes: vec![TrieNode::default()],
+            tokens: HashMap::new(),
+            eos_token_id: vocabulary.eos_token_id(),
+        };
+        for (token, ids) in vocabulary.tokens() {
+            let mut node = 0;
+            for &byte in token {
+                let child = trie.nodes[node]
+                    .children
+                    .iter()
+                    .find(|&&(child_byte, _)| child_byte == byte)
+                    .map(|&(_, child)| child);
+                node = child.unwrap_or_else(|| {
+                    trie.nodes.push(TrieNode::default());
+                    let child = trie.nodes.len() - 1;
+                    trie.nodes[node].children.push((byte, child));
+                    child
+                });
+            }
+            trie.nodes[node].ids.extend(ids);
+            for &id in ids {
+                trie.tokens.insert(id, token.clone());
+            }
+        }
+        trie
+    }
+}
+
+/// Walks a [`Grammar`] one token at a time, like [`Guide`] walks an index.
+///
+/// A grammar has no bound on its states to index in advance, so the allowed
+/// tokens are found at each step by walking the tokens of the vocabulary.
+#[derive(Debug, Clone)]
+pub struct PushdownGuide {
+    grammar: Grammar,
+    vocabulary: Arc<TokenTrie>,
+    stacks: Vec<Stack>,
+}
+
+impl PushdownGuide {
+    pub fn new(grammar: &Grammar, vocabulary: &Vocabulary) -> Self {
+        Self {
+            grammar: grammar.clone(),
+            vocabulary: Arc::new(TokenTrie::new(vocabulary)),
+            stacks: grammar.initial(),
+        }
+    }
+
+    pub fn get_tokens(&self) -> Result<Vec<TokenId>> {
+        let mut tokens = Vec::new();
+        self.allowed_tokens(0, &self.stacks, &mut tokens);
+        if self.is_finished() {
+            tokens.push(self.vocabulary.eos_token_id);
+        }
+        if tokens.is_empty() {
+            return Err(anyhow!("No allowed tokens available"));
+        }
+        tokens.sort_unstable();
+        tokens.dedup();
+        Ok(tokens)
+    }
+
+    /// Adds the tokens going on from `node` of the trie that `stacks` allow.
+    fn allowed_tokens(&self, node: usize, stacks: &[Stack], tokens: &mut Vec<TokenId>) {
+        for &(byte, child) in &self.vocabulary.nodes[node].children {
+            let next = self.grammar.step(stacks, byte);
+            if next.is_empty() {
+                continue;
+            }
+            tokens.extend(&self.vocabulary.nodes[child].ids);
+            self.allowed_tokens(child, &next, tokens);
+        }
+    }
+
+    /// Consumes `token_id` and returns the tokens allowed after it.
+    pub fn advance(&mut self, token_id: TokenId) -> Result<Vec<TokenId>> {
+        if token_id == self.vocabulary.eos_token_id && self.is_finished() {
+            return self.get_tokens();
+        }
+        let token = self
+            .vocabulary
+            .tokens
+            .get(&token_id)
+            .ok_or_else(|| anyhow!("Token {} is not in the vocabulary", token_id))?;
+        let mut stacks = self.stacks.clone();
+        for &byte in token {
+            stacks = self.grammar.step(&stacks, byte);
+            if stacks.is_empty() {
+                return Err(anyhow!("Token {} is not allowed here", token_id));
+            }
+        }
+        self.stacks = stacks;
+        self.get_tokens()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.grammar.accepts(&self.stacks)
+    }
+}
+
+/// A guide of the backend [`RegexOptions::backend`] picks.
+#[derive(Debug, Clone)]
+pub enum SchemaGuide {
+    Regex(Guide),
+    Pushdown(PushdownGuide),
+}
+
+impl SchemaGuide {
+    pub fn get_tokens(&self) -> Result<Vec<TokenId>> {
+        match self {
+            SchemaGuide::Regex(guide) => guide.get_tokens(),
+            SchemaGuide::Pushdown(guide) => guide.get_tokens(),
+        }
+    }
+
+    pub fn advance(&mut self, token_id: TokenId) -> Result<Vec<TokenId>> {
+        match self {
+            SchemaGuide::Regex(guide) => guide.advance(token_id),
+            SchemaGuide::Pushdown(guide) => guide.advance(token_id),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        match self {
+            SchemaGuide::Regex(guide) => guide.is_finished(),
+            SchemaGuide::Pushdown(guide) => guide.is_finished(),
+        }
+    }
+}
+
+/// The guide of `schema` for `vocabulary`, with the backend of `options`.
+pub fn build_guide(
+    schema: &str,
+    options: &RegexOptions,
+    vocabulary: &Vocabulary,
+) -> Result<SchemaGuide> {
+    match options.backend {
+        Backend::Regex => {
+            let compiled = CompiledSchema::new(schema, options, vocabulary)?;
+            Ok(SchemaGuide::Regex(Guide::new_from(&compiled)))
+        }
+        Backend::Pushdown => {
+            let grammar = Grammar::new(schema, options)?;
+            Ok(SchemaGuide::Pushdown(PushdownGuide::new(
+                &grammar, vocabulary,
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::error::SchemaError;
+    use crate::guidance::build_regex_from_schema_with_options;
+
+    fn grammar(schema: &serde_json::Value) -> Grammar {
+        Grammar::new(&schema.to_string(), &RegexOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_recursive_ref() {
+        let schema = json!({
+            "$ref": "#/$defs/node",
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "properties": {
+                        "children": {"type": "array", "items": {"$ref": "#/$defs/node"}},
+                        "value": {"type": "integer"}
+                    },
+                    "required": ["value"]
+                }
+            }
+        });
+        let error =
+            build_regex_from_schema_with_options(&schema.to_string(), &RegexOptions::default())
+                .unwrap_err();
+        assert!(SchemaError::find(&error)
+            .unwrap()
+            .message
+            .contains("recursive"));
+
+        let grammar = grammar(&schema);
+        assert_eq!(grammar.rules().len(), 2);
+        let mut deep = r#"{"value":1}"#.to_string();
+        for value in 0..20 {
+            deep = format!(
+                r#"{{"children":[{},{{"value":0}}],"value":{}}}"#,
+                deep, value
+            );
+        }
+        assert!(grammar.is_match(deep.as_bytes()));
+        assert!(grammar.is_match(br#"{"children":[],"value":1}"#));
+        assert!(!grammar.is_match(br#"{"children":[{}],"value":1}"#));
+        assert!(!grammar.is_match(br#"{"children":[{"value":2}],"value":1"#));
+    }
+
+    #[test]
+    fn test_mutual_and_left_recursion() {
+        let grammar = grammar(&json!({
+            "$ref": "#/$defs/a",
+            "$defs": {
+                "a": {"anyOf": [{"$ref": "#/$defs/a"}, {"type": "array", "items": {"$ref": "#/$defs/b"}}]},
+                "b": {"anyOf": [{"type": "integer"}, {"$ref": "#/$defs/a"}]}
+            }
+        }));
+        assert!(grammar.is_match(b"[1,[2,[]],[[[3]]]]"));
+        assert!(!grammar.is_match(b"1"));
+        assert!(!grammar.is_match(b"[[1]"));
+    }
+
+    #[test]
+    fn test_unconstrained_values_at_any_depth() {
+        let deep = format!("{}1{}", "[".repeat(50), "]".repeat(50));
+        let nested = format!(r#"{{"a":{}}}"#, deep);
+        let regex = build_regex_from_schema_with_options("{}", &RegexOptions::default()).unwrap();
+        assert!(!regex::Regex::new(&format!("^(?:{})$", regex))
+            .unwrap()
+            .is_match(&deep));
+
+        let grammar = grammar(&json!({}));
+        assert!(grammar.is_match(deep.as_bytes()));
+        assert!(grammar.is_match(nested.as_bytes()));
+        assert!(grammar.is_match(br#"{"a":[true,null,{"b":"c"}],"d":-1.5}"#));
+        assert!(!grammar.is_match(br#"{"a":[1,]}"#));
+
+        // `depth` still limits them
+        let grammar = self::grammar(&json!({"type": "array", "depth": 1}));
+        assert!(grammar.is_match(b"[[1]]"));
+        assert!(!grammar.is_match(b"[[[1]]]"));
+    }
+
+    #[test]
+    fn test_guide() {
+        let mut vocabulary = Vocabulary::new(0);
+        for (id, token) in ["[", "]", "1", ",", "[[", "]]", "x"].iter().enumerate() {
+            vocabulary.try_insert(*token, id as TokenId + 1).unwrap();
+        }
+        let schema = json!({
+            "$ref": "#/$defs/list",
+            "$defs": {"list": {"type": "array", "items": {"anyOf": [{"const": 1}, {"$ref": "#/$defs/list"}]}}}
+        });
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            backend: Backend::Pushdown,
+            ..RegexOptions::default()
+        };
+        let mut guide = build_guide(&schema.to_string(), &options, &vocabulary).unwrap();
+        assert!(matches!(guide, SchemaGuide::Pushdown(_)));
+        assert_eq!(guide.get_tokens().unwrap(), vec![1, 5]);
+
+        // [[ [ 1 ] , 1 ]]
+        for token in [5, 1, 3, 2, 4] {
+            assert!(!guide.is_finished());
+            guide.advance(token).unwrap();
+        }
+        assert_eq!(guide.get_tokens().unwrap(), vec![1, 3, 5]);
+        assert!(guide.advance(7).is_err());
+        guide.advance(3).unwrap();
+        assert_eq!(guide.get_tokens().unwrap(), vec![2, 4, 6]);
+        assert_eq!(guide.advance(6).unwrap(), vec![0]);
+        assert!(guide.is_finished());
+
+        let options = RegexOptions {
+            backend: Backend::Regex,
+            ..options
+        };
+        assert!(build_guide(&schema.to_string(), &options, &vocabulary).is_err());
+        let guide = build_guide(r#"{"type": "array"}"#, &options, &vocabulary).unwrap();
+        assert!(matches!(guide, SchemaGuide::Regex(_)));
+    }
+}
@@ -1,9 +1,7 @@
-#[cfg(test)]
 use pyo3::types::PyAnyMethods;
-#[cfg(test)]
 use pyo3::{types::PyModule, PyResult, Python};
 
-#[cfg(test)]
+/// Builds the regex for a schema with the installed outlines package.
 pub fn python_build_regex_from_schema(schema: &str) -> PyResult<String> {
     // TODO this now requires system vide installation of outlines
     Python::with_gil(|py| {
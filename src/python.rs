@@ -0,0 +1,257 @@
+//! Python bindings, built into a `guidance_rs` module with
+//! `maturin build --features python`: the regex of a schema and
+//! `GuidanceLogitsProcessor`, a logits processor for transformers and vLLM.
+
+// the pyo3 macros convert the errors of functions returning `PyResult`
+#![allow(clippy::useless_conversion)]
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::compat::{Index, StateId, TokenId, Vocabulary};
+use crate::guidance::{build_regex_from_schema_with_options, RegexOptions};
+
+fn value_error(error: anyhow::Error) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+/// The regex of a schema, with the default options and `whitespace_pattern`.
+#[pyfunction]
+#[pyo3(signature = (schema, whitespace_pattern=None))]
+fn build_regex_from_schema(schema: &str, whitespace_pattern: Option<String>) -> PyResult<String> {
+    let options = RegexOptions {
+        whitespace_pattern,
+        ..RegexOptions::default()
+    };
+    build_regex_from_schema_with_options(schema, &options).map_err(value_error)
+}
+
+/// Masks the scores of the tokens that can't go on with a match of a schema
+/// or a regex, following the `__call__(input_ids, scores)` protocol:
+///
+/// - with transformers, `input_ids` and `scores` are batches, and the tokens of
+///   `input_ids` the first call gets are the prompt,
+/// - with vLLM, they're those of one sequence, `input_ids` the tokens it
+///   generated.
+///
+/// A processor is for one generation, `reset()` starts another one.
+#[pyclass]
+pub struct GuidanceLogitsProcessor {
+    index: Index,
+    eos_token_id: TokenId,
+    /// The state of each sequence, empty before the first call.
+    states: Vec<StateId>,
+    /// Tokens of each sequence already read.
+    seen: Vec<usize>,
+}
+
+#[pymethods]
+impl GuidanceLogitsProcessor {
+    /// Takes a Hugging Face tokenizer and one of `schema` or `regex`.
+    #[new]
+    #[pyo3(signature = (tokenizer, schema=None, regex=None))]
+    fn py_new(
+        tokenizer: &Bound<'_, PyAny>,
+        schema: Option<&str>,
+        regex: Option<&str>,
+    ) -> PyResult<Self> {
+        let regex = match (schema, regex) {
+            (Some(schema), None) => build_regex_from_schema(schema, None)?,
+            (None, Some(regex)) => regex.to_string(),
+            _ => return Err(PyValueError::new_err("expected one of schema or regex")),
+        };
+        let vocabulary = vocabulary(tokenizer)?;
+        Self::new(&regex, &vocabulary).map_err(value_error)
+    }
+
+    fn __call__<'py>(
+        &mut self,
+        input_ids: &Bound<'py, PyAny>,
+        scores: Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let input_ids = match input_ids.hasattr("tolist")? {
+            true => input_ids.call_method0("tolist")?,
+            false => input_ids.clone(),
+        };
+        let batched = scores.getattr("ndim")?.extract::<usize>()? > 1;
+        let sequences: Vec<Vec<TokenId>> = match batched {
+            true => input_ids.extract()?,
+            false => vec![input_ids.extract()?],
+        };
+
+        let allowed = self.allowed_tokens(&sequences).map_err(value_error)?;
+        let vocab_size: usize = scores.getattr("shape")?.get_item(-1)?.extract()?;
+        for (i, tokens) in allowed.into_iter().enumerate() {
+            let row = match batched {
+                true => scores.get_item(i)?,
+                false => scores.clone(),
+            };
+            let mask = row.call_method1("new_full", ((vocab_size,), f32::NEG_INFINITY))?;
+            mask.set_item(tokens, 0.0)?;
+            row.call_method1("add_", (mask,))?;
+        }
+        Ok(scores)
+    }
+
+    fn reset(&mut self) {
+        self.states.clear();
+        self.seen.clear();
+    }
+}
+
+impl GuidanceLogitsProcessor {
+    pub fn new(regex: &str, vocabulary: &Vocabulary) -> Result<Self> {
+        Ok(Self {
+            index: Index::new(regex, vocabulary)?,
+            eos_token_id: vocabulary.eos_token_id(),
+            states: Vec::new(),
+            seen: Vec::new(),
+        })
+    }
+
+    /// Reads the tokens each sequence has gained since the last call, and
+    /// returns the tokens allowed next in each of them.
+    pub fn allowed_tokens(&mut self, sequences: &[Vec<TokenId>]) -> Result<Vec<Vec<TokenId>>> {
+        if self.states.is_empty() {
+            self.states = vec![self.index.initial_state(); sequences.len()];
+            self.seen = sequences.iter().map(Vec::len).collect();
+        } else if sequences.len() != self.states.len() {
+            return Err(anyhow!(
+                "Expected {} sequences, like the first call, got {}",
+                self.states.len(),
+                sequences.len()
+            ));
+        }
+
+        let mut allowed = Vec::with_capacity(sequences.len());
+        for (i, tokens) in sequences.iter().enumerate() {
+            for &token_id in tokens.get(self.seen[i]..).unwrap_or_default() {
+                let state = self.states[i];
+                self.states[i] = match self.index.next_state(&state, &token_id) {
+                    Some(next) => next,
+                    None if token_id == self.eos_token_id && self.index.is_final_state(&state) => {
+                        state
+                    }
+                    None => {
+                        return Err(anyhow!(
+                            "Token {} is not allowed in state {}",
+                            token_id,
+                            state
+                        ))
+                    }
+                };
+            }
+            self.seen[i] = tokens.len();
+            allowed.push(
+                self.index
+                    .allowed_tokens(&self.states[i])
+                    .unwrap_or_default(),
+            );
+        }
+        Ok(allowed)
+    }
+}
+
+/// The vocabulary of a Hugging Face tokenizer, without its special tokens.
+fn vocabulary(tokenizer: &Bound<'_, PyAny>) -> PyResult<Vocabulary> {
+    let eos_token_id: TokenId = tokenizer.getattr("eos_token_id")?.extract()?;
+    let special: HashSet<TokenId> = tokenizer.getattr("all_special_ids")?.extract()?;
+    let tokens = tokenizer.call_method0("get_vocab")?;
+    let tokens = tokens.downcast::<PyDict>()?;
+
+    let mut vocabulary = Vocabulary::new(eos_token_id);
+    for (token, id) in tokens.iter() {
+        let id: TokenId = id.extract()?;
+        if special.contains(&id) || id == eos_token_id {
+            continue;
+        }
+        let token: String = token.extract()?;
+        vocabulary
+            .try_insert(token_bytes(&token), id)
+            .map_err(value_error)?;
+    }
+    Ok(vocabulary)
+}
+
+/// The bytes of a token as written in a Hugging Face vocabulary: byte-level
+/// BPE maps each byte to a printable char, like `Ġ` for a space, and
+/// SentencePiece writes spaces as `▁` and bytes it has no token for as
+/// `<0x0A>`.
+fn token_bytes(token: &str) -> Vec<u8> {
+    if let Some(byte) = token
+        .strip_prefix("<0x")
+        .and_then(|hex| hex.strip_suffix('>'))
+        .filter(|hex| hex.len() == 2)
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+    {
+        return vec![byte];
+    }
+    if let Some(bytes) = token.chars().map(byte_level_byte).collect() {
+        return bytes;
+    }
+    token.replace('▁', " ").into_bytes()
+}
+
+/// The byte a char of byte-level BPE stands for: printable Latin-1 chars for
+/// themselves and the others shifted to `Ā` (U+0100) and after, in order.
+fn byte_level_byte(c: char) -> Option<u8> {
+    let printable = |byte: u8| matches!(byte, b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF);
+    match u32::from(c) {
+        code @ 0..=0xFF => u8::try_from(code).ok().filter(|&byte| printable(byte)),
+        code @ 0x100..=0x143 => (0..=u8::MAX)
+            .filter(|&byte| !printable(byte))
+            .nth(usize::try_from(code - 0x100).ok()?),
+        _ => None,
+    }
+}
+
+#[pymodule]
+fn guidance_rs(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(build_regex_from_schema, module)?)?;
+    module.add_class::<GuidanceLogitsProcessor>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bytes() {
+        assert_eq!(token_bytes("hello"), b"hello");
+        assert_eq!(token_bytes("Ġworld"), b" world");
+        assert_eq!(token_bytes("Ċ"), b"\n");
+        assert_eq!(token_bytes("Ã©"), "é".as_bytes());
+        assert_eq!(token_bytes("▁world"), b" world");
+        assert_eq!(token_bytes("<0x0A>"), b"\n");
+    }
+
+    #[test]
+    fn test_allowed_tokens() {
+        let mut vocabulary = Vocabulary::new(0);
+        for (id, token) in ["a", "b", "1", "ab"].iter().enumerate() {
+            vocabulary.try_insert(*token, id as TokenId + 1).unwrap();
+        }
+        let mut processor = GuidanceLogitsProcessor::new("a[b]?1", &vocabulary).unwrap();
+
+        // the first call holds the prompts, of any tokens
+        let allowed = processor.allowed_tokens(&[vec![2, 3], vec![3]]).unwrap();
+        assert_eq!(allowed, [vec![1, 4], vec![1, 4]]);
+        let allowed = processor
+            .allowed_tokens(&[vec![2, 3, 4], vec![3, 1]])
+            .unwrap();
+        assert_eq!(allowed, [vec![3], vec![2, 3]]);
+        let allowed = processor
+            .allowed_tokens(&[vec![2, 3, 4, 3], vec![3, 1, 2]])
+            .unwrap();
+        assert_eq!(allowed, [vec![0], vec![3]]);
+
+        assert!(processor.allowed_tokens(&[vec![2, 3, 4, 3, 1]]).is_err());
+        processor.reset();
+        assert!(processor.allowed_tokens(&[vec![7]]).is_ok());
+    }
+}
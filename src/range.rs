@@ -0,0 +1,761 @@
+//! Regexes for the numbers within bounds, from `minimum`, `maximum` and
+//! their exclusive forms.
+//!
+//! Bounds are read as decimals, digit by digit, so that they're compared
+//! and written out exactly whatever their size and precision. The numbers
+//! matched are written without an exponent.
+
+use std::cmp::Ordering;
+
+use anyhow::Result;
+use serde_json::{Map, Number, Value};
+
+use crate::error::SchemaError;
+use crate::types::NumberSyntax;
+
+/// A decimal number, as its digits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decimal {
+    negative: bool,
+    /// Without leading zeros, `[0]` below one.
+    integer: Vec<u8>,
+    /// Without trailing zeros.
+    fraction: Vec<u8>,
+}
+
+impl Decimal {
+    /// Reads a JSON number, `None` when it isn't one.
+    pub fn parse(number: &str) -> Option<Self> {
+        let (negative, number) = match number.strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, number),
+        };
+        let (mantissa, exponent) = match number.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().ok()?),
+            None => (number, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits = integer
+            .bytes()
+            .chain(fraction.bytes())
+            .map(|byte| byte.is_ascii_digit().then(|| byte - b'0'))
+            .collect::<Option<Vec<u8>>>()?;
+        if integer.is_empty() || mantissa.ends_with('.') {
+            return None;
+        }
+
+        // where the decimal point goes in `digits`
+        let point = integer.len() as i64 + exponent as i64;
+        let (integer, fraction) = if point <= 0 {
+            let mut fraction = vec![0; (-point) as usize];
+            fraction.extend(digits);
+            (Vec::new(), fraction)
+        } else if point as usize >= digits.len() {
+            let mut integer = digits;
+            integer.resize(point as usize, 0);
+            (integer, Vec::new())
+        } else {
+            let (integer, fraction) = digits.split_at(point as usize);
+            (integer.to_vec(), fraction.to_vec())
+        };
+        Some(Self::new(negative, integer, fraction))
+    }
+
+    fn new(negative: bool, integer: Vec<u8>, mut fraction: Vec<u8>) -> Self {
+        let mut integer: Vec<u8> = integer
+            .into_iter()
+            .skip_while(|&digit| digit == 0)
+            .collect();
+        if integer.is_empty() {
+            integer.push(0);
+        }
+        while fraction.last() == Some(&0) {
+            fraction.pop();
+        }
+        let negative = negative && !(integer == [0] && fraction.is_empty());
+        Self {
+            negative,
+            integer,
+            fraction,
+        }
+    }
+
+    fn from_number(number: &Number) -> Option<Self> {
+        Self::parse(&number.to_string())
+    }
+
+    fn zero() -> Self {
+        Self::new(false, Vec::new(), Vec::new())
+    }
+
+    pub fn negated(&self) -> Self {
+        Self::new(!self.negative, self.integer.clone(), self.fraction.clone())
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let magnitude = compare_integers(&self.integer, &other.integer)
+            .then_with(|| self.fraction.cmp(&other.fraction));
+        match (self.negative, other.negative) {
+            (false, false) => magnitude,
+            (true, true) => magnitude.reverse(),
+            (negative, _) => {
+                if negative {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            }
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A lower or upper bound.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bound {
+    pub value: Decimal,
+    pub exclusive: bool,
+}
+
+impl Bound {
+    pub fn inclusive(value: Decimal) -> Self {
+        Self {
+            value,
+            exclusive: false,
+        }
+    }
+
+    pub fn exclusive(value: Decimal) -> Self {
+        Self {
+            value,
+            exclusive: true,
+        }
+    }
+
+    fn negated(&self) -> Self {
+        Self {
+            value: self.value.negated(),
+            exclusive: self.exclusive,
+        }
+    }
+}
+
+/// The tighter of two lower bounds.
+pub fn tighter_lower(a: Option<Bound>, b: Option<Bound>) -> Option<Bound> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(match a.value.cmp(&b.value) {
+            Ordering::Greater => a,
+            Ordering::Less => b,
+            Ordering::Equal if a.exclusive => a,
+            Ordering::Equal => b,
+        }),
+        (a, b) => a.or(b),
+    }
+}
+
+/// The tighter of two upper bounds.
+pub fn tighter_upper(a: Option<Bound>, b: Option<Bound>) -> Option<Bound> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(match a.value.cmp(&b.value) {
+            Ordering::Less => a,
+            Ordering::Greater => b,
+            Ordering::Equal if a.exclusive => a,
+            Ordering::Equal => b,
+        }),
+        (a, b) => a.or(b),
+    }
+}
+
+/// The lower and upper bound set on `obj`, taking the tighter one when
+/// both the inclusive and exclusive keyword are set.
+///
+/// `exclusiveMinimum` and `exclusiveMaximum` may be numbers, or booleans
+/// making `minimum` and `maximum` exclusive as in draft 4.
+pub fn bounds(obj: &Map<String, Value>) -> Result<(Option<Bound>, Option<Bound>)> {
+    let lower = bound(obj, "minimum", "exclusiveMinimum")?;
+    let upper = bound(obj, "maximum", "exclusiveMaximum")?;
+    let lower = tighter_lower(lower.0, lower.1);
+    let upper = tighter_upper(upper.0, upper.1);
+
+    if let (Some(lower), Some(upper)) = (&lower, &upper) {
+        if upper.value < lower.value {
+            let keyword = if obj.contains_key("maximum") {
+                "maximum"
+            } else {
+                "exclusiveMaximum"
+            };
+            return Err(SchemaError::new(format!(
+                "{} must be greater than or equal to the minimum",
+                keyword
+            ))
+            .with_keyword(keyword)
+            .into());
+        }
+    }
+    Ok((lower, upper))
+}
+
+/// The bounds set by `keyword` and its exclusive form.
+fn bound(
+    obj: &Map<String, Value>,
+    keyword: &str,
+    exclusive_keyword: &str,
+) -> Result<(Option<Bound>, Option<Bound>)> {
+    let number = |keyword: &str| match obj.get(keyword) {
+        None => Ok(None),
+        Some(value) => value
+            .as_number()
+            .and_then(Decimal::from_number)
+            .map(Some)
+            .ok_or_else(|| SchemaError::wrong_type(keyword, "a number", value)),
+    };
+
+    match obj.get(exclusive_keyword) {
+        Some(Value::Bool(exclusive)) => {
+            let bound = number(keyword)?.map(|value| Bound {
+                value,
+                exclusive: *exclusive,
+            });
+            Ok((bound, None))
+        }
+        _ => Ok((
+            number(keyword)?.map(Bound::inclusive),
+            number(exclusive_keyword)?.map(Bound::exclusive),
+        )),
+    }
+}
+
+/// Numbers with more digits than this are only accepted as written, by
+/// [`equivalent_numbers`].
+const MAX_EQUIVALENT_DIGITS: usize = 64;
+
+/// Regex matching `number` written without an exponent and with any trailing
+/// zeros, like `20(\.0+)?` for `2e1`, and as JSON writes it when that has an
+/// exponent. `None` when it has too many digits to write out.
+pub(crate) fn equivalent_numbers(number: &Number) -> Option<String> {
+    let decimal = Decimal::from_number(number)?;
+    if decimal.integer.len() + decimal.fraction.len() > MAX_EQUIVALENT_DIGITS {
+        return None;
+    }
+    let digits = |digits: &[u8]| -> String {
+        digits
+            .iter()
+            .map(|&digit| char::from(b'0' + digit))
+            .collect()
+    };
+    // `-0` is zero too
+    let sign = match (decimal.negative, decimal == Decimal::zero()) {
+        (true, _) => "-",
+        (false, true) => "-?",
+        (false, false) => "",
+    };
+    let mut regex = format!("{}{}", sign, digits(&decimal.integer));
+    if decimal.fraction.is_empty() {
+        regex += r"(\.0+)?";
+    } else {
+        regex += &format!(r"\.{}0*", digits(&decimal.fraction));
+    }
+
+    let written = number.to_string();
+    if written.contains(['e', 'E']) {
+        regex = format!("{}|{}", regex::escape(&written), regex);
+    }
+    Some(regex)
+}
+
+/// Regex matching the numbers within `lower` and `upper`, only the
+/// integers when `integer` is set, written as `syntax` allows. `None` when
+/// there are none.
+pub fn range_regex(
+    lower: Option<&Bound>,
+    upper: Option<&Bound>,
+    integer: bool,
+    syntax: &NumberSyntax,
+) -> Option<String> {
+    let zero = Decimal::zero();
+    let mut branches = Vec::new();
+
+    if upper.is_none_or(|upper| upper.value >= zero) {
+        let low = match lower {
+            Some(lower) if lower.value >= zero => lower.clone(),
+            _ => Bound::inclusive(zero.clone()),
+        };
+        let magnitudes = magnitudes(&low, upper, integer, syntax);
+        if !magnitudes.is_empty() {
+            branches.push(format!("{}{}", syntax.positive_prefix(), group(magnitudes)));
+        }
+    }
+    // the negative numbers, by their magnitude, along with `-0`
+    if lower.is_none_or(|lower| lower.value <= zero) {
+        let low = match upper {
+            Some(upper) if upper.value <= zero => upper.negated(),
+            _ => Bound::inclusive(zero),
+        };
+        let high = lower.map(Bound::negated);
+        let magnitudes = magnitudes(&low, high.as_ref(), integer, syntax);
+        if !magnitudes.is_empty() {
+            branches.push(format!("-{}{}", syntax.zeros(), group(magnitudes)));
+        }
+    }
+
+    (!branches.is_empty()).then(|| group(branches))
+}
+
+/// The numbers from zero up within `low` and `high`, by integer part.
+fn magnitudes(
+    low: &Bound,
+    high: Option<&Bound>,
+    integer: bool,
+    syntax: &NumberSyntax,
+) -> Vec<String> {
+    let any_fraction = match (integer, syntax.force_decimal_for_number) {
+        (true, _) => "",
+        (false, true) => r"(\.[0-9]+)",
+        (false, false) => r"(\.[0-9]+)?",
+    };
+    let mut branches = Vec::new();
+    let with_fraction = |digits: &[u8], low: Option<&Bound>, high: Option<&Bound>| {
+        let (fraction, whole) = fraction(low, high, integer);
+        // written with a fraction of zeros instead
+        let whole = whole && (integer || !syntax.force_decimal_for_number);
+        let digits = literal(digits);
+        match fraction {
+            // `.5` for `0.5`
+            Some(fraction) if syntax.bare_fractions && digits == "0" => Some(if whole {
+                format!("(0|0?{})", fraction)
+            } else {
+                format!("0?{}", fraction)
+            }),
+            Some(fraction) if whole => Some(format!("{}({})?", digits, fraction)),
+            Some(fraction) => Some(format!("{}{}", digits, fraction)),
+            None => whole.then_some(digits),
+        }
+    };
+
+    match high {
+        Some(high) => match compare_integers(&low.value.integer, &high.value.integer) {
+            Ordering::Greater => {}
+            Ordering::Equal => {
+                branches.extend(with_fraction(&low.value.integer, Some(low), Some(high)))
+            }
+            Ordering::Less => {
+                branches.extend(with_fraction(&low.value.integer, Some(low), None));
+                let from = increment(&low.value.integer);
+                let to = decrement(&high.value.integer);
+                if compare_integers(&from, &to) != Ordering::Greater {
+                    branches.push(format!(
+                        "{}{}",
+                        group(integers(&from, Some(&to))),
+                        any_fraction
+                    ));
+                }
+                branches.extend(with_fraction(&high.value.integer, None, Some(high)));
+            }
+        },
+        None => {
+            branches.extend(with_fraction(&low.value.integer, Some(low), None));
+            let from = increment(&low.value.integer);
+            branches.push(format!("{}{}", group(integers(&from, None)), any_fraction));
+        }
+    }
+    branches
+}
+
+/// The fraction of the numbers with the integer part of the bounds given,
+/// as a regex for a `.` and the digits, `None` when no fraction is within
+/// them, and whether the integer part without a fraction is.
+fn fraction(low: Option<&Bound>, high: Option<&Bound>, integer: bool) -> (Option<String>, bool) {
+    let whole = low.is_none_or(|low| low.value.fraction.is_empty() && !low.exclusive)
+        && high.is_none_or(|high| !(high.value.fraction.is_empty() && high.exclusive));
+    if integer {
+        return (None, whole);
+    }
+
+    let digits = fraction_digits(
+        low.map(|low| (&low.value.fraction[..], low.exclusive)),
+        high.map(|high| (&high.value.fraction[..], high.exclusive)),
+        0,
+    );
+    (digits.map(|digits| format!(r"\.{}", digits)), whole)
+}
+
+/// Regex for the fraction digits from `pos` on, with the ones before it
+/// equal to those of the bounds still given. `None` when there are none.
+fn fraction_digits(
+    mut low: Option<(&[u8], bool)>,
+    high: Option<(&[u8], bool)>,
+    pos: usize,
+) -> Option<String> {
+    let started = pos > 0;
+    if low.is_some_and(|(digits, exclusive)| pos >= digits.len() && !exclusive) {
+        low = None;
+    }
+    match (low, high) {
+        (None, None) => return Some(if started { "[0-9]*" } else { "[0-9]+" }.to_string()),
+        // only zeros are left, making the number equal to `high`
+        (_, Some((digits, exclusive))) if pos >= digits.len() => {
+            return (low.is_none() && !exclusive)
+                .then(|| if started { "0*" } else { "0+" }.to_string());
+        }
+        // equal to `low` so far, so a digit above zero must follow
+        (Some((digits, _)), None) if pos >= digits.len() => {
+            return Some("0*[1-9][0-9]*".to_string())
+        }
+        _ => {}
+    }
+
+    let first = low.map_or(0, |(digits, _)| digits.get(pos).copied().unwrap_or(0));
+    let last = high.map_or(9, |(digits, _)| digits[pos]);
+    let mut branches = Vec::new();
+    if low.is_some() && high.is_some() && first == last {
+        if let Some(rest) = fraction_digits(low, high, pos + 1) {
+            branches.push(format!("{}{}", first, rest));
+        }
+    } else {
+        if low.is_some() {
+            if let Some(rest) = fraction_digits(low, None, pos + 1) {
+                branches.push(format!("{}{}", first, rest));
+            }
+        }
+        let from = first + low.is_some() as u8;
+        if let Some(to) = last
+            .checked_sub(high.is_some() as u8)
+            .filter(|&to| from <= to)
+        {
+            branches.push(format!("{}[0-9]*", digit_range(from, to)));
+        }
+        if high.is_some() {
+            if let Some(rest) = fraction_digits(None, high, pos + 1) {
+                branches.push(format!("{}{}", last, rest));
+            }
+        }
+    }
+
+    // stopping here is above `low` only once it's left behind
+    let can_stop = started && low.is_none();
+    match (branches.len(), can_stop) {
+        (0, false) => None,
+        (0, true) => Some(String::new()),
+        (1, false) => branches.pop(),
+        (_, false) => Some(format!("({})", branches.join("|"))),
+        (_, true) => Some(format!("({})?", branches.join("|"))),
+    }
+}
+
+/// Branches matching the integers from `from` to `to`, or up from `from`
+/// without `to`, written without leading zeros.
+fn integers(from: &[u8], to: Option<&[u8]>) -> Vec<String> {
+    let mut branches = Vec::new();
+    let mut from = from.to_vec();
+    if from == [0] {
+        branches.push("0".to_string());
+        if to == Some(&[0]) {
+            return branches;
+        }
+        from = vec![1];
+    }
+
+    let longest = to.map_or(from.len(), <[u8]>::len);
+    for len in from.len()..=longest {
+        let low = if len == from.len() {
+            from.clone()
+        } else {
+            let mut low = vec![1];
+            low.resize(len, 0);
+            low
+        };
+        let high = match to {
+            Some(to) if to.len() == len => to.to_vec(),
+            _ => vec![9; len],
+        };
+        branches.extend(same_length(&low, &high));
+    }
+    if to.is_none() {
+        branches.push(format!("[1-9][0-9]{{{},}}", from.len()));
+    }
+    branches
+}
+
+/// Branches matching the digit strings from `low` to `high`, of the same
+/// length.
+fn same_length(low: &[u8], high: &[u8]) -> Vec<String> {
+    let Some((&first, low_rest)) = low.split_first() else {
+        return vec![String::new()];
+    };
+    let (&last, high_rest) = high.split_first().expect("the same length as low");
+    if first == last {
+        return vec![format!(
+            "{}{}",
+            first,
+            group(same_length(low_rest, high_rest))
+        )];
+    }
+
+    let mut branches = Vec::new();
+    // a bound with only zeros or nines after its first digit is a full range
+    let from = if low_rest.iter().all(|&digit| digit == 0) {
+        first
+    } else {
+        let nines = vec![9; low_rest.len()];
+        branches.push(format!("{}{}", first, group(same_length(low_rest, &nines))));
+        first + 1
+    };
+    let high_full = high_rest.iter().all(|&digit| digit == 9);
+    let to = if high_full { last } else { last - 1 };
+    if from <= to {
+        let rest = match high_rest.len() {
+            0 => String::new(),
+            1 => "[0-9]".to_string(),
+            n => format!("[0-9]{{{}}}", n),
+        };
+        branches.push(format!("{}{}", digit_range(from, to), rest));
+    }
+    if !high_full {
+        let zeros = vec![0; high_rest.len()];
+        branches.push(format!("{}{}", last, group(same_length(&zeros, high_rest))));
+    }
+    branches
+}
+
+fn group(mut branches: Vec<String>) -> String {
+    match branches.len() {
+        1 => branches.pop().unwrap_or_default(),
+        _ => format!("({})", branches.join("|")),
+    }
+}
+
+fn digit_range(from: u8, to: u8) -> String {
+    if from == to {
+        from.to_string()
+    } else {
+        format!("[{}-{}]", from, to)
+    }
+}
+
+fn literal(digits: &[u8]) -> String {
+    digits.iter().map(|digit| digit.to_string()).collect()
+}
+
+fn compare_integers(a: &[u8], b: &[u8]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+fn increment(digits: &[u8]) -> Vec<u8> {
+    let mut digits = digits.to_vec();
+    for digit in digits.iter_mut().rev() {
+        if *digit == 9 {
+            *digit = 0;
+        } else {
+            *digit += 1;
+            return digits;
+        }
+    }
+    digits.insert(0, 1);
+    digits
+}
+
+/// One less than `digits`, which is above zero.
+fn decrement(digits: &[u8]) -> Vec<u8> {
+    let mut digits = digits.to_vec();
+    for digit in digits.iter_mut().rev() {
+        if *digit == 0 {
+            *digit = 9;
+        } else {
+            *digit -= 1;
+            break;
+        }
+    }
+    Decimal::new(false, digits, Vec::new()).integer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    type TestBound = Option<(&'static str, bool)>;
+
+    /// Checks the regex for each pair of `bounds` against `numbers`, given
+    /// as text along with their value.
+    fn check_ranges(bounds: &[TestBound], numbers: &[(String, f64)], integer: bool) {
+        let bound = |(value, exclusive): (&str, bool)| Bound {
+            value: Decimal::parse(value).unwrap(),
+            exclusive,
+        };
+        let value = |bound: TestBound| bound.map(|(value, _)| value.parse::<f64>().unwrap());
+
+        for &lower in bounds {
+            for &upper in bounds {
+                let (low, high) = (value(lower), value(upper));
+                if low.zip(high).is_some_and(|(low, high)| high < low) {
+                    continue;
+                }
+                let within = |n: f64| {
+                    lower.is_none_or(|(_, exclusive)| {
+                        let low = low.unwrap();
+                        if exclusive {
+                            n > low
+                        } else {
+                            n >= low
+                        }
+                    }) && upper.is_none_or(|(_, exclusive)| {
+                        let high = high.unwrap();
+                        if exclusive {
+                            n < high
+                        } else {
+                            n <= high
+                        }
+                    })
+                };
+
+                let regex = range_regex(
+                    lower.map(bound).as_ref(),
+                    upper.map(bound).as_ref(),
+                    integer,
+                    &NumberSyntax::default(),
+                );
+                let Some(regex) = regex else {
+                    assert!(
+                        numbers.iter().all(|(_, n)| !within(*n)),
+                        "{:?} {:?}",
+                        lower,
+                        upper
+                    );
+                    continue;
+                };
+                let full = Regex::new(&format!("^({})$", regex)).unwrap();
+                for (text, n) in numbers {
+                    assert_eq!(
+                        full.is_match(text),
+                        within(*n),
+                        "{} in {:?} {:?}: {}",
+                        text,
+                        lower,
+                        upper,
+                        regex
+                    );
+                }
+                for text in ["01", "1e0", "1.", "+1"] {
+                    assert!(!full.is_match(text), "{} in {}", text, regex);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decimal() {
+        let parse = |number| Decimal::parse(number).unwrap();
+        assert_eq!(parse("-0.0"), parse("0"));
+        assert_eq!(parse("1.5e2"), parse("150"));
+        assert_eq!(parse("25e-3"), parse("0.025"));
+        assert!(parse("-1.5") < parse("-1.25"));
+        assert!(parse("0.5") < parse("0.51"));
+        assert!(parse("9.99") < parse("10"));
+        assert_eq!(Decimal::parse("1."), None);
+        assert_eq!(Decimal::parse(".5"), None);
+    }
+
+    #[test]
+    fn test_integer_ranges() {
+        let bounds = [
+            None,
+            Some(("-105", false)),
+            Some(("-10", true)),
+            Some(("-1.5", false)),
+            Some(("0", false)),
+            Some(("0", true)),
+            Some(("7", false)),
+            Some(("19.5", false)),
+            Some(("100", true)),
+            Some(("999", false)),
+        ];
+        let numbers: Vec<_> = (-1200..1200).map(|n| (n.to_string(), n as f64)).collect();
+        check_ranges(&bounds, &numbers, true);
+    }
+
+    #[test]
+    fn test_decimal_ranges() {
+        let bounds = [
+            None,
+            Some(("-1.25", false)),
+            Some(("-1", true)),
+            Some(("0", false)),
+            Some(("0", true)),
+            Some(("0.05", true)),
+            Some(("0.5", false)),
+            Some(("1", false)),
+            Some(("1", true)),
+            Some(("1.25", true)),
+            Some(("10.5", false)),
+        ];
+        // the numbers from -12 to 12 by thousandths, written with all three
+        // fraction digits and without trailing zeros
+        let mut numbers = Vec::new();
+        for thousandths in -12000i32..=12000 {
+            let sign = if thousandths < 0 { "-" } else { "" };
+            let (integer, fraction) = (thousandths.abs() / 1000, thousandths.abs() % 1000);
+            let value = thousandths as f64 / 1000.0;
+            numbers.push((format!("{}{}.{:03}", sign, integer, fraction), value));
+            let fraction = format!("{:03}", fraction);
+            let fraction = fraction.trim_end_matches('0');
+            if fraction.is_empty() {
+                numbers.push((format!("{}{}", sign, integer), value));
+            } else {
+                numbers.push((format!("{}{}.{}", sign, integer, fraction), value));
+            }
+        }
+        check_ranges(&bounds, &numbers, false);
+    }
+
+    #[test]
+    fn test_unbounded_above() {
+        let regex = range_regex(
+            Some(&Bound::exclusive(Decimal::parse("0.5").unwrap())),
+            None,
+            false,
+            &NumberSyntax::default(),
+        );
+        let regex = Regex::new(&format!("^({})$", regex.unwrap())).unwrap();
+        for text in ["0.51", "1", "99999999999999999999", "12345.6789"] {
+            assert!(regex.is_match(text), "{}", text);
+        }
+        for text in ["0.5", "0.500", "0.4999", "-1", "00"] {
+            assert!(!regex.is_match(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_number_syntax() {
+        let syntax = NumberSyntax {
+            leading_zeros: true,
+            plus_sign: true,
+            bare_fractions: true,
+            ..NumberSyntax::default()
+        };
+        let bound = |value| Bound::inclusive(Decimal::parse(value).unwrap());
+        let regex = range_regex(Some(&bound("-1")), Some(&bound("1.5")), false, &syntax);
+        let regex = Regex::new(&format!("^({})$", regex.unwrap())).unwrap();
+        for text in [
+            "0", "+0.5", ".5", "-.5", "+.25", "001", "-001", "+01.5", "1",
+        ] {
+            assert!(regex.is_match(text), "{}", text);
+        }
+        for text in [
+            ".", "+", "-", "+-1", "-1.5", "+1.6", "002", ".", "0.", "+.", "1e0",
+        ] {
+            assert!(!regex.is_match(text), "{}", text);
+        }
+
+        let regex = range_regex(Some(&bound("2")), None, true, &syntax);
+        let regex = Regex::new(&format!("^({})$", regex.unwrap())).unwrap();
+        assert!(regex.is_match("+0002"));
+        assert!(!regex.is_match("+01"));
+    }
+}
@@ -0,0 +1,414 @@
+use std::fmt::Write;
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+use regex_syntax::Parser;
+use serde_json::Value;
+
+use crate::guidance::{build_regex_from_schema_with_options, follow_refs, RegexOptions};
+
+// extra repetitions drawn for unbounded and very wide quantifiers
+static MAX_EXTRA_REPEATS: u32 = 8;
+static UNBOUNDED_EXTRA_REPEATS: u32 = 3;
+
+/// Draws `n` random strings matching the regex, reproducibly for a given seed.
+///
+/// Alternatives are picked uniformly and characters lean towards printable
+/// ASCII when a class allows it, so samples stay readable. Open-ended
+/// quantifiers only repeat a few times beyond their minimum.
+pub fn samples(regex: &str, n: usize, seed: u64) -> Result<Vec<String>> {
+    let hir = Parser::new()
+        .parse(regex)
+        .map_err(|e| anyhow!("Failed to parse regex: {}", e))?;
+
+    let mut rng = SplitMix64(seed);
+    (0..n)
+        .map(|_| {
+            let mut out = String::new();
+            sample(&hir, &mut rng, &mut out)?;
+            Ok(out)
+        })
+        .collect()
+}
+
+fn sample(hir: &Hir, rng: &mut SplitMix64, out: &mut String) -> Result<()> {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(Look::Start | Look::End) => {}
+        HirKind::Look(look) => return Err(anyhow!("{:?} can't be sampled", look)),
+        HirKind::Literal(literal) => out.push_str(
+            std::str::from_utf8(&literal.0)
+                .map_err(|_| anyhow!("Byte literals can't be sampled"))?,
+        ),
+        HirKind::Class(Class::Unicode(class)) => {
+            let ranges: Vec<(u32, u32)> = class
+                .iter()
+                .map(|r| (r.start() as u32, r.end() as u32))
+                .collect();
+            out.push(sample_char(&ranges, rng)?);
+        }
+        HirKind::Class(Class::Bytes(class)) => {
+            let ranges: Vec<(u32, u32)> = class
+                .iter()
+                .filter(|r| r.start().is_ascii())
+                .map(|r| (u32::from(r.start()), u32::from(r.end().min(0x7F))))
+                .collect();
+            out.push(sample_char(&ranges, rng)?);
+        }
+        HirKind::Repetition(repetition) => {
+            let min = repetition.min;
+            let max = repetition.max.map_or(min + UNBOUNDED_EXTRA_REPEATS, |max| {
+                max.min(min + MAX_EXTRA_REPEATS)
+            });
+            let count = min + rng.below(u64::from(max - min) + 1) as u32;
+            for _ in 0..count {
+                sample(&repetition.sub, rng, out)?;
+            }
+        }
+        HirKind::Capture(capture) => sample(&capture.sub, rng, out)?,
+        HirKind::Concat(subs) => {
+            for sub in subs {
+                sample(sub, rng, out)?;
+            }
+        }
+        HirKind::Alternation(subs) => {
+            let choice = rng.below(subs.len() as u64) as usize;
+            sample(&subs[choice], rng, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// How [`examples`] writes documents, in the order they're tried.
+#[derive(Debug, Clone, Copy)]
+enum Layout {
+    /// A line for each member, indented by two spaces a level.
+    Indented,
+    /// A space after each `:` and `,`.
+    Spaced,
+    /// Without whitespace.
+    Compact,
+}
+
+/// The `examples`, `default` and `const` values of a schema, written as
+/// documents its regex with `options` accepts, for few-shot prompts.
+///
+/// Besides the values given for the whole schema, a document is put together
+/// from those given for the properties of objects. Documents are written
+/// indented, with spaces, or without whitespace, the first way the regex
+/// accepts, with the properties in the order of the regex. Values it accepts
+/// in none of them, like examples not following the schema, are left out.
+pub fn examples(json: &str, options: &RegexOptions) -> Result<Vec<String>> {
+    let schema: Value = serde_json::from_str(json)?;
+    let regex = build_regex_from_schema_with_options(json, options)?;
+    let checker = Regex::new(&format!("^(?:{})$", regex))
+        .map_err(|e| anyhow!("Failed to build regex: {}", e))?;
+
+    let mut values = given_values(&schema);
+    values.extend(from_properties(&schema, &schema));
+    let mut documents = Vec::new();
+    for value in values {
+        for layout in [Layout::Indented, Layout::Spaced, Layout::Compact] {
+            let mut document = String::new();
+            write_value(
+                &mut document,
+                &value,
+                Some(&schema),
+                &schema,
+                layout,
+                options,
+                0,
+            )?;
+            if checker.is_match(&document) {
+                if !documents.contains(&document) {
+                    documents.push(document);
+                }
+                break;
+            }
+        }
+    }
+    Ok(documents)
+}
+
+/// The `examples`, `default` and `const` values of `schema` itself.
+fn given_values(schema: &Value) -> Vec<Value> {
+    let mut values: Vec<Value> = schema
+        .get("examples")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    values.extend(schema.get("default").cloned());
+    values.extend(schema.get("const").cloned());
+    values
+}
+
+/// A value of `schema`: the first given for it or in its `enum`, or one
+/// [`from_properties`].
+fn composed(schema: &Value, root: &Value) -> Option<Value> {
+    let schema = follow_refs(schema, root).ok()?;
+    let first_enum = schema
+        .get("enum")
+        .and_then(Value::as_array)
+        .and_then(|values| values.first());
+    given_values(schema)
+        .into_iter()
+        .next()
+        .or_else(|| first_enum.cloned())
+        .or_else(|| from_properties(schema, root))
+}
+
+/// An object of the values of the properties of `schema`, which needs one
+/// for each required property.
+fn from_properties(schema: &Value, root: &Value) -> Option<Value> {
+    let schema = follow_refs(schema, root).ok()?;
+    let properties = schema.get("properties")?.as_object()?;
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let mut object = serde_json::Map::new();
+    for (name, property) in properties {
+        match composed(property, root) {
+            Some(value) => {
+                object.insert(name.clone(), value);
+            }
+            None if required.contains(&name.as_str()) => return None,
+            None => {}
+        }
+    }
+    Some(Value::Object(object))
+}
+
+/// A member of an object or an array: its name, if any, value and schema.
+type Member<'a> = (Option<&'a String>, &'a Value, Option<&'a Value>);
+
+/// Writes `value` in `layout`, with the members of objects in the order the
+/// regex of `schema` has them.
+fn write_value(
+    out: &mut String,
+    value: &Value,
+    schema: Option<&Value>,
+    root: &Value,
+    layout: Layout,
+    options: &RegexOptions,
+    depth: usize,
+) -> Result<()> {
+    let schema = schema.and_then(|schema| follow_refs(schema, root).ok());
+    let (open, close, members): (char, char, Vec<Member>) = match value {
+        Value::Object(object) => {
+            let properties = schema
+                .and_then(|schema| schema.get("properties"))
+                .and_then(Value::as_object);
+            let mut names: Vec<&String> = object.keys().collect();
+            if options.sort_properties {
+                names.sort();
+            } else if let Some(properties) = properties {
+                // listed properties first, in the order of the schema
+                let position = |name: &String| {
+                    properties
+                        .keys()
+                        .position(|key| key == name)
+                        .unwrap_or(properties.len())
+                };
+                names.sort_by_key(|name| position(name));
+            }
+            let members = names
+                .into_iter()
+                .map(|name| {
+                    let property = properties.and_then(|properties| properties.get(name));
+                    (Some(name), &object[name], property)
+                })
+                .collect();
+            ('{', '}', members)
+        }
+        Value::Array(items) => {
+            let item_schema = schema.and_then(|schema| schema.get("items"));
+            let members = items.iter().map(|item| (None, item, item_schema)).collect();
+            ('[', ']', members)
+        }
+        _ => {
+            out.push_str(&serde_json::to_string(value)?);
+            return Ok(());
+        }
+    };
+
+    out.push(open);
+    for (i, (name, member, member_schema)) in members.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+            if let Layout::Spaced = layout {
+                out.push(' ');
+            }
+        }
+        if let Layout::Indented = layout {
+            write!(out, "\n{:indent$}", "", indent = 2 * (depth + 1))?;
+        }
+        if let Some(name) = name {
+            out.push_str(&serde_json::to_string(name)?);
+            out.push(':');
+            if !matches!(layout, Layout::Compact) {
+                out.push(' ');
+            }
+        }
+        write_value(
+            out,
+            member,
+            *member_schema,
+            root,
+            layout,
+            options,
+            depth + 1,
+        )?;
+    }
+    if let (Layout::Indented, false) = (layout, members.is_empty()) {
+        write!(out, "\n{:indent$}", "", indent = 2 * depth)?;
+    }
+    out.push(close);
+    Ok(())
+}
+
+fn sample_char(ranges: &[(u32, u32)], rng: &mut SplitMix64) -> Result<char> {
+    let printable: Vec<(u32, u32)> = ranges
+        .iter()
+        .filter(|&&(start, end)| start <= 0x7E && end >= 0x20)
+        .map(|&(start, end)| (start.max(0x20), end.min(0x7E)))
+        .collect();
+
+    // the surrogates aren't characters, though classes like `]-\u{10FFFF}` span them
+    let scalars: Vec<(u32, u32)> = ranges
+        .iter()
+        .flat_map(|&(start, end)| [(start, end.min(0xD7FF)), (start.max(0xE000), end)])
+        .filter(|&(start, end)| start <= end)
+        .collect();
+
+    // mostly printable ASCII, with the occasional character from the whole class
+    let ranges = if !printable.is_empty() && rng.below(10) > 0 {
+        &printable[..]
+    } else {
+        &scalars[..]
+    };
+    let total: u64 = ranges.iter().map(|&(s, e)| u64::from(e - s) + 1).sum();
+    if total == 0 {
+        return Err(anyhow!("Empty character classes can't be sampled"));
+    }
+
+    let mut index = rng.below(total);
+    for &(start, end) in ranges {
+        let size = u64::from(end - start) + 1;
+        if index < size {
+            return char::from_u32(start + index as u32)
+                .ok_or_else(|| anyhow!("Invalid character in class"));
+        }
+        index -= size;
+    }
+    unreachable!("index is below the total size of the ranges")
+}
+
+/// Small deterministic generator, good enough for picking samples.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+    use crate::guidance::build_regex_from_schema;
+
+    #[test]
+    fn test_samples_match() {
+        let schema = r#"{
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "minLength": 1, "maxLength": 10},
+                "age": {"type": "integer"},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}}
+            },
+            "required": ["name", "age"]
+        }"#;
+        let regex = build_regex_from_schema(schema, None).unwrap();
+        let checker = Regex::new(&format!("^(?:{})$", regex)).unwrap();
+
+        let drawn = samples(&regex, 20, 42).unwrap();
+        assert_eq!(drawn.len(), 20);
+        for sample in &drawn {
+            assert!(checker.is_match(sample), "{} doesn't match", sample);
+        }
+    }
+
+    #[test]
+    fn test_examples() {
+        let schema = r#"{
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "examples": ["Ann"]},
+                "age": {"type": "integer", "default": 41},
+                "role": {"enum": ["admin", "user"]}
+            },
+            "required": ["name", "age"],
+            "examples": [{"name": "Bo", "age": 7}, {"name": 1}]
+        }"#;
+        let options = RegexOptions {
+            sort_properties: true,
+            ..RegexOptions::default()
+        };
+        assert_eq!(
+            examples(schema, &options).unwrap(),
+            [
+                r#"{"age": 7, "name": "Bo"}"#,
+                r#"{"age": 41, "name": "Ann", "role": "admin"}"#,
+            ]
+        );
+
+        // in the whitespace the regex allows
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            ..options
+        };
+        assert_eq!(
+            examples(schema, &options).unwrap()[0],
+            r#"{"age":7,"name":"Bo"}"#
+        );
+        let options = RegexOptions {
+            sort_properties: true,
+            ..RegexOptions::preset(crate::guidance::Preset::Pretty)
+        };
+        assert_eq!(
+            examples(schema, &options).unwrap()[0],
+            "{\n  \"age\": 7,\n  \"name\": \"Bo\"\n}"
+        );
+    }
+
+    #[test]
+    fn test_samples_skip_surrogates() {
+        // the class of a string character spans the surrogates
+        let regex = build_regex_from_schema(r#"{"type": "string"}"#, None).unwrap();
+        let checker = Regex::new(&format!("^(?:{})$", regex)).unwrap();
+        for seed in 0..40 {
+            for sample in samples(&regex, 300, seed).unwrap() {
+                assert!(checker.is_match(&sample), "{} doesn't match", sample);
+            }
+        }
+    }
+
+    #[test]
+    fn test_samples_are_reproducible() {
+        let regex = "[a-z]{3}|[0-9]+";
+        assert_eq!(samples(regex, 5, 7).unwrap(), samples(regex, 5, 7).unwrap());
+        assert_ne!(samples(regex, 5, 7).unwrap(), samples(regex, 5, 8).unwrap());
+    }
+}
@@ -0,0 +1,168 @@
+//! Constrained sampling for inference loops that hand out plain logits, like
+//! those of llama.cpp bindings, backed by a [`Guide`].
+//!
+//! This doesn't depend on any binding: the logits of a step are masked either
+//! as a slice indexed by token id or as candidates of a token id and a logit,
+//! which is what bindings such as `llama-cpp-2` give out. The loop samples
+//! from what is left, and passes the sampled token back to
+//! [`GuidedSampler::accept`]:
+//!
+//! ```
+//! use guidance_rs::compat::{Guide, Index, Vocabulary};
+//! use guidance_rs::sampler::GuidedSampler;
+//!
+//! let mut vocabulary = Vocabulary::new(0);
+//! for (id, token) in ["a", "b", "1"].iter().enumerate() {
+//!     vocabulary.try_insert(*token, id as u32 + 1)?;
+//! }
+//! let index = Index::new("a[b]?1", &vocabulary)?;
+//! let mut sampler = GuidedSampler::from_guide(Guide::new(index))?;
+//! while !sampler.is_finished() {
+//!     // the logits of the model for this step
+//!     let mut logits = vec![0.5, 1.0, 2.0, 0.25];
+//!     sampler.apply(&mut logits)?;
+//!     let (token, _) = logits
+//!         .iter()
+//!         .enumerate()
+//!         .max_by(|a, b| a.1.total_cmp(b.1))
+//!         .unwrap();
+//!     sampler.accept(token as u32)?;
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Token ids are those of the [`Vocabulary`](crate::compat::Vocabulary) the
+//! schema was compiled for, which has to be built from the model's tokens and
+//! with its EOS token.
+use anyhow::{anyhow, Result};
+
+use crate::compat::{CompiledSchema, Guide, TokenId};
+
+/// Masks the logits of each step to the tokens a [`Guide`] allows.
+#[derive(Debug, Clone)]
+pub struct GuidedSampler {
+    guide: Guide,
+    allowed: Vec<bool>,
+}
+
+impl GuidedSampler {
+    /// A sampler walking its own guide over the index of `compiled`.
+    pub fn new(compiled: &CompiledSchema) -> Result<Self> {
+        Self::from_guide(Guide::new_from(compiled))
+    }
+
+    /// A sampler starting from the state of `guide`, e.g. one with
+    /// [`Guide::with_max_tokens`].
+    pub fn from_guide(guide: Guide) -> Result<Self> {
+        let mut sampler = Self {
+            guide,
+            allowed: Vec::new(),
+        };
+        sampler.set_allowed(sampler.guide.get_tokens()?);
+        Ok(sampler)
+    }
+
+    fn set_allowed(&mut self, tokens: Vec<TokenId>) {
+        self.allowed.clear();
+        let len = tokens.iter().max().map_or(0, |&max| max as usize + 1);
+        self.allowed.resize(len, false);
+        for token_id in tokens {
+            self.allowed[token_id as usize] = true;
+        }
+    }
+
+    pub fn is_allowed(&self, token_id: TokenId) -> bool {
+        self.allowed
+            .get(token_id as usize)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// `logit`, or `-inf` when `token_id` isn't allowed.
+    pub fn masked_logit(&self, token_id: TokenId, logit: f32) -> f32 {
+        if self.is_allowed(token_id) {
+            logit
+        } else {
+            f32::NEG_INFINITY
+        }
+    }
+
+    /// Sets the logits of the tokens not allowed to `-inf`, `logits` being
+    /// indexed by token id.
+    pub fn apply(&self, logits: &mut [f32]) -> Result<()> {
+        if self.allowed.len() > logits.len() {
+            return Err(anyhow!(
+                "Token id {} is outside the {} logits",
+                self.allowed.len() - 1,
+                logits.len()
+            ));
+        }
+        for (token_id, logit) in logits.iter_mut().enumerate() {
+            *logit = self.masked_logit(token_id as TokenId, *logit);
+        }
+        Ok(())
+    }
+
+    /// Sets the logits of the candidates not allowed to `-inf`.
+    pub fn apply_candidates(&self, candidates: &mut [(TokenId, f32)]) {
+        for (token_id, logit) in candidates {
+            *logit = self.masked_logit(*token_id, *logit);
+        }
+    }
+
+    /// Advances the guide with the sampled token.
+    pub fn accept(&mut self, token_id: TokenId) -> Result<()> {
+        let tokens = self.guide.advance(token_id)?;
+        self.set_allowed(tokens);
+        Ok(())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.guide.is_finished()
+    }
+
+    pub fn guide(&self) -> &Guide {
+        &self.guide
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compat::{Index, Vocabulary};
+
+    fn sampler() -> GuidedSampler {
+        let mut vocabulary = Vocabulary::new(0);
+        for (id, token) in ["a", "b", "1"].iter().enumerate() {
+            vocabulary.try_insert(*token, id as TokenId + 1).unwrap();
+        }
+        let index = Index::new("a[b]?1", &vocabulary).unwrap();
+        GuidedSampler::from_guide(Guide::new(index)).unwrap()
+    }
+
+    #[test]
+    fn test_logits_are_masked() {
+        let mut sampler = sampler();
+        let mut logits = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+        sampler.apply(&mut logits).unwrap();
+        let inf = f32::NEG_INFINITY;
+        assert_eq!(logits, vec![inf, 2.0, inf, inf, inf]);
+
+        sampler.accept(1).unwrap();
+        let mut candidates = vec![(3, 0.5), (2, 0.25), (0, 1.0)];
+        sampler.apply_candidates(&mut candidates);
+        assert_eq!(candidates, vec![(3, 0.5), (2, 0.25), (0, inf)]);
+
+        sampler.accept(3).unwrap();
+        assert!(sampler.is_finished());
+        assert!(sampler.is_allowed(0));
+        assert!(!sampler.is_allowed(3));
+    }
+
+    #[test]
+    fn test_disallowed_token_is_rejected() {
+        let mut sampler = sampler();
+        assert!(sampler.accept(2).is_err());
+        assert!(sampler.apply(&mut [0.0; 1]).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+//! Checks of the `pattern`s of schemas before they're spliced into the regex,
+//! as one pattern of a tenant can make the automaton of a whole batch blow up.
+
+use anyhow::Result;
+use regex_syntax::hir::{Class, Hir, HirKind, Repetition};
+
+use crate::dialect::{self, Dialect};
+use crate::error::SchemaError;
+use crate::guidance::Generator;
+
+/// `pattern`, with the repetitions of unbounded repetitions that can be
+/// written as one repetition rewritten, like `(a+)*` as `a*`. The others
+/// without a fixed part telling the repeats apart, like `(\w+\s*)+`, are left
+/// with a warning, as backtracking engines can take exponential time on them.
+/// A pattern over the complexity budget of the generator is an error.
+///
+/// Patterns the regex crate can't parse are left to fail later.
+pub(crate) fn checked_pattern(pattern: &str, generator: &Generator) -> Result<String> {
+    let Ok(hir) = regex_syntax::Parser::new().parse(pattern) else {
+        return Ok(pattern.to_string());
+    };
+
+    if let Some(budget) = generator.max_pattern_complexity {
+        let complexity = complexity(&hir);
+        if complexity > budget {
+            return Err(SchemaError::new(format!(
+                "the pattern has a complexity of {}, over the budget of {}",
+                complexity, budget
+            ))
+            .with_keyword("pattern")
+            .with_hint("Bound repetitions by fewer, or raise max_pattern_complexity")
+            .into());
+        }
+    }
+
+    let mut nested = false;
+    let rewritten = flatten(&hir, &mut nested);
+    if nested {
+        generator.warn(
+            SchemaError::new(
+                "the pattern repeats an unbounded repetition, which backtracking engines can take exponential time on",
+            )
+            .with_keyword("pattern"),
+        );
+    }
+    if rewritten == hir {
+        return Ok(pattern.to_string());
+    }
+    dialect::print(&rewritten, Dialect::Rust)
+}
+
+/// Roughly how many automaton states `hir` compiles to: its literal bytes and
+/// class ranges, times how many times repetitions write them.
+fn complexity(hir: &Hir) -> usize {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => 1,
+        HirKind::Literal(literal) => literal.0.len(),
+        HirKind::Class(Class::Unicode(class)) => class.ranges().len().max(1),
+        HirKind::Class(Class::Bytes(class)) => class.ranges().len().max(1),
+        HirKind::Repetition(repetition) => {
+            let times = repetition.max.unwrap_or(repetition.min.saturating_add(1));
+            complexity(&repetition.sub).saturating_mul(times.max(1) as usize)
+        }
+        HirKind::Capture(capture) => complexity(&capture.sub),
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => subs
+            .iter()
+            .fold(0, |sum, sub| sum.saturating_add(complexity(sub))),
+    }
+}
+
+/// `hir` with `(x{n,}){m,}` written as `x{m*n,}`, which matches the same when
+/// `m` or `n` is at least 1, or `n` at most 1. Sets `nested` when other
+/// unbounded repetitions are repeated without a fixed part.
+fn flatten(hir: &Hir, nested: &mut bool) -> Hir {
+    match hir.kind() {
+        HirKind::Repetition(outer) => {
+            let sub = flatten(&outer.sub, nested);
+            if outer.max.is_some() || !is_unbounded(&sub) {
+                return Hir::repetition(Repetition {
+                    sub: Box::new(sub),
+                    ..outer.clone()
+                });
+            }
+            match uncaptured(&sub).kind() {
+                HirKind::Repetition(inner)
+                    if inner.max.is_none() && (outer.min > 0 || inner.min <= 1) =>
+                {
+                    Hir::repetition(Repetition {
+                        min: outer.min.saturating_mul(inner.min),
+                        sub: inner.sub.clone(),
+                        ..outer.clone()
+                    })
+                }
+                _ => {
+                    *nested |= !has_fixed_part(&sub);
+                    Hir::repetition(Repetition {
+                        sub: Box::new(sub),
+                        ..outer.clone()
+                    })
+                }
+            }
+        }
+        HirKind::Capture(capture) => {
+            let mut capture = capture.clone();
+            capture.sub = Box::new(flatten(&capture.sub, nested));
+            Hir::capture(capture)
+        }
+        HirKind::Concat(subs) => Hir::concat(subs.iter().map(|sub| flatten(sub, nested)).collect()),
+        HirKind::Alternation(subs) => {
+            Hir::alternation(subs.iter().map(|sub| flatten(sub, nested)).collect())
+        }
+        _ => hir.clone(),
+    }
+}
+
+fn uncaptured(hir: &Hir) -> &Hir {
+    match hir.kind() {
+        HirKind::Capture(capture) => uncaptured(&capture.sub),
+        _ => hir,
+    }
+}
+
+/// Whether `hir` has a repetition without a maximum, outside of lookarounds.
+fn is_unbounded(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Repetition(repetition) => {
+            repetition.max.is_none() || is_unbounded(&repetition.sub)
+        }
+        HirKind::Capture(capture) => is_unbounded(&capture.sub),
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => subs.iter().any(is_unbounded),
+        _ => false,
+    }
+}
+
+/// Whether every match of `hir` has a literal or a class outside of
+/// unbounded repetitions, like the `-` of `-[a-z]+`.
+fn has_fixed_part(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Literal(_) | HirKind::Class(_) => true,
+        HirKind::Repetition(repetition) => {
+            repetition.min > 0 && repetition.max.is_some() && has_fixed_part(&repetition.sub)
+        }
+        HirKind::Capture(capture) => has_fixed_part(&capture.sub),
+        HirKind::Concat(subs) => subs.iter().any(has_fixed_part),
+        HirKind::Alternation(subs) => subs.iter().all(has_fixed_part),
+        HirKind::Empty | HirKind::Look(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn check(pattern: &str, max_pattern_complexity: Option<usize>) -> Result<(String, usize)> {
+        let schema = Value::Null;
+        let generator =
+            Generator::new("", &schema).with_max_pattern_complexity(max_pattern_complexity);
+        let pattern = checked_pattern(pattern, &generator)?;
+        Ok((pattern, generator.warnings().len()))
+    }
+
+    #[test]
+    fn test_nested_repetitions() {
+        assert_eq!(check("(a+)+", None).unwrap(), ("a+".to_string(), 0));
+        assert_eq!(
+            check("x(?:[0-9]*)+y", None).unwrap(),
+            ("x[0-9]*y".to_string(), 0)
+        );
+        assert_eq!(check("(a{2,})+", None).unwrap(), ("a{2,}".to_string(), 0));
+        // `(a{2,})*` matches `` and `aa` but not `a`, which no single repetition does
+        assert_eq!(
+            check("(a{2,})*", None).unwrap(),
+            ("(a{2,})*".to_string(), 1)
+        );
+        assert_eq!(
+            check(r"(\w+\s*)+", None).unwrap(),
+            (r"(\w+\s*)+".to_string(), 1)
+        );
+        assert_eq!(check("(ab+)+c", None).unwrap(), ("(ab+)+c".to_string(), 0));
+        assert_eq!(check("[a-z]+(-[a-z]+)*", None).unwrap().1, 0);
+        assert_eq!(check("(", None).unwrap(), ("(".to_string(), 0));
+    }
+
+    #[test]
+    fn test_complexity_budget() {
+        assert!(check("[a-z]{10}", Some(10)).is_ok());
+        let error = check("([a-z]{10}){10}", Some(99)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the pattern has a complexity of 100, over the budget of 99"
+        );
+
+        let schema = json!({"type": "string", "pattern": "^(\\w{100}){100}$"}).to_string();
+        let options = crate::guidance::RegexOptions {
+            max_pattern_complexity: Some(10_000),
+            ..Default::default()
+        };
+        let error = crate::guidance::build_regex_from_schema_with_options(&schema, &options);
+        assert!(error.is_err());
+    }
+}
@@ -0,0 +1,643 @@
+//! Minimal HTTP service exposing the generator to non-Rust callers.
+//!
+//! Every endpoint takes a JSON body with a `schema` (an object, or a string
+//! holding one), an optional `whitespace_pattern`, and optional `overrides`
+//! applied to the schema as a JSON Merge Patch:
+//!
+//! - `POST /compile` returns the `regex`, plus the `gbnf` grammar when
+//!   `"output": "gbnf"` is requested.
+//! - `POST /validate` checks a `document` string and returns its `result`:
+//!   `match`, `mismatch` with the failing `offset` and `byte` and the bytes
+//!   `expected` there as a regex class, or `incomplete`.
+//! - `POST /index` builds the token index for a `vocabulary` mapping token
+//!   text to ids, with an `eos_token_id`.
+//!
+//! Compiled regexes and indexes are kept in LRU caches shared by all
+//! connections, which a fixed number of worker threads answer. Schemas are
+//! compiled with limits on their size, and indexes with a limit on the memory
+//! of their DFA, so a schema expanding without end gets an error response
+//! instead of taking the server down. A client that stops reading or writing
+//! loses its connection after a timeout, instead of holding a worker.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+
+use crate::automaton::{self, DfaBuildOptions, Validation};
+use crate::compat::{Index, IndexOptions, Vocabulary};
+use crate::gbnf;
+use crate::guidance::{build_regex_from_schema_with_options, RegexOptions};
+use crate::patch::merge_overrides;
+
+/// Largest request body accepted, vocabularies included.
+static MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+
+/// Longest regex compiled by default, see [`Server::with_limits`].
+pub const DEFAULT_MAX_REGEX_LEN: usize = 16 * 1024 * 1024;
+
+/// Most subschemas expanded by default, see [`Server::with_limits`].
+pub const DEFAULT_MAX_NODES: usize = 100_000;
+
+/// Most memory the DFA of an index takes by default, see
+/// [`Server::with_max_dfa_bytes`].
+pub const DEFAULT_MAX_DFA_BYTES: usize = 512 * 1024 * 1024;
+
+/// How long reading a request or writing a response waits by default, see
+/// [`Server::with_timeout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A schema and whitespace pattern, as given in a request.
+type RegexKey = (String, Option<String>);
+
+pub struct Server {
+    regexes: Mutex<LruCache<RegexKey, Arc<String>>>,
+    /// By a hash of the schema, the options and the vocabulary, see
+    /// [`Server::index_key`].
+    indexes: Mutex<LruCache<u64, Arc<Index>>>,
+    max_regex_len: Option<usize>,
+    max_nodes: Option<usize>,
+    max_dfa_bytes: Option<usize>,
+    timeout: Option<Duration>,
+    workers: usize,
+}
+
+impl Server {
+    /// Creates a server caching up to `cache_capacity` regexes and as many
+    /// indexes, with the default limits and a worker per CPU.
+    pub fn new(cache_capacity: usize) -> Self {
+        Self {
+            regexes: Mutex::new(LruCache::new(cache_capacity)),
+            indexes: Mutex::new(LruCache::new(cache_capacity)),
+            max_regex_len: Some(DEFAULT_MAX_REGEX_LEN),
+            max_nodes: Some(DEFAULT_MAX_NODES),
+            max_dfa_bytes: Some(DEFAULT_MAX_DFA_BYTES),
+            timeout: Some(DEFAULT_TIMEOUT),
+            workers: thread::available_parallelism().map_or(4, |n| n.get()),
+        }
+    }
+
+    /// Answers requests whose regex would be longer than `max_regex_len`
+    /// bytes, or whose schema expands to more than `max_nodes` subschemas,
+    /// with an error, like [`RegexOptions::max_regex_len`] and
+    /// [`RegexOptions::max_nodes`].
+    pub fn with_limits(mut self, max_regex_len: Option<usize>, max_nodes: Option<usize>) -> Self {
+        self.max_regex_len = max_regex_len;
+        self.max_nodes = max_nodes;
+        self
+    }
+
+    /// Answers index requests whose DFA would take more than
+    /// `max_dfa_bytes` with an error, like [`DfaBuildOptions::max_memory_bytes`].
+    pub fn with_max_dfa_bytes(mut self, max_dfa_bytes: Option<usize>) -> Self {
+        self.max_dfa_bytes = max_dfa_bytes;
+        self
+    }
+
+    /// Closes connections whose request takes longer than `timeout` to read
+    /// or whose response takes as long to write, or never with `None`.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        // a zero timeout isn't accepted by sockets
+        self.timeout = timeout.filter(|timeout| !timeout.is_zero());
+        self
+    }
+
+    /// Answers connections on `workers` threads, at least one. Connections
+    /// beyond those wait in a queue as long, and the rest in the listener's
+    /// backlog.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Threads answering requests, see [`Server::with_workers`].
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Accepts connections until the listener fails.
+    pub fn serve(self, addr: impl ToSocketAddrs) -> Result<()> {
+        let listener = TcpListener::bind(addr).context("Failed to bind the server address")?;
+        self.serve_listener(listener)
+    }
+
+    /// Like [`Server::serve`], on a bound listener.
+    pub fn serve_listener(self, listener: TcpListener) -> Result<()> {
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(self.workers);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let server = Arc::new(self);
+        for _ in 0..server.workers {
+            let server = Arc::clone(&server);
+            let receiver = Arc::clone(&receiver);
+            thread::spawn(move || loop {
+                let Ok(stream) = receiver.lock().unwrap().recv() else {
+                    return;
+                };
+                // the client is gone when the response can't be written, and
+                // a panic only loses its connection, not the worker
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| server.handle_connection(stream)));
+            });
+        }
+        for stream in listener.incoming() {
+            let stream = stream.context("Failed to accept a connection")?;
+            sender
+                .send(stream)
+                .map_err(|_| anyhow!("The server's workers have stopped"))?;
+        }
+        Ok(())
+    }
+
+    fn handle_connection(&self, stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+        let mut reader = BufReader::new(&stream);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+
+        let (status, response) = if content_length > MAX_BODY_SIZE {
+            (413, json!({"error": "Request body is too large"}))
+        } else {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            self.handle(method, path, &body)
+        };
+
+        let body = response.to_string();
+        let reason = match status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Payload Too Large",
+        };
+        write!(
+            &stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            reason,
+            body.len(),
+            body
+        )?;
+        Ok(())
+    }
+
+    /// Answers a request with a status code and a JSON body.
+    pub fn handle(&self, method: &str, path: &str, body: &[u8]) -> (u16, Value) {
+        let endpoint = match path {
+            "/compile" => Self::compile,
+            "/validate" => Self::validate,
+            "/index" => Self::index,
+            _ => return (404, json!({"error": format!("Unknown endpoint {}", path)})),
+        };
+        if method != "POST" {
+            return (405, json!({"error": "Endpoints only accept POST"}));
+        }
+
+        let result = serde_json::from_slice(body)
+            .map_err(|e| anyhow!("Invalid JSON body: {}", e))
+            .and_then(|request| endpoint(self, &request));
+        match result {
+            Ok(response) => (200, response),
+            Err(e) => (400, json!({"error": format!("{:#}", e)})),
+        }
+    }
+
+    fn compile(&self, request: &Value) -> Result<Value> {
+        let regex = self.regex(request)?;
+        match request.get("output").and_then(Value::as_str) {
+            None | Some("regex") => Ok(json!({"regex": *regex})),
+            Some("gbnf") => Ok(json!({"regex": *regex, "gbnf": gbnf::regex_to_gbnf(&regex)?})),
+            Some(output) => Err(anyhow!("Unknown output {}", output)),
+        }
+    }
+
+    fn validate(&self, request: &Value) -> Result<Value> {
+        let regex = self.regex(request)?;
+        let document = request
+            .get("document")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("'document' should be a string"))?;
+
+        Ok(match automaton::validate(&regex, document.as_bytes())? {
+            Validation::Match => json!({"result": "match"}),
+            Validation::Mismatch(mismatch) => json!({
+                "result": "mismatch",
+                "offset": mismatch.offset,
+                "byte": mismatch.byte,
+                "expected": mismatch.expected.to_string(),
+            }),
+            Validation::Incomplete => json!({"result": "incomplete"}),
+        })
+    }
+
+    fn index(&self, request: &Value) -> Result<Value> {
+        let schema = schema(request)?;
+        let whitespace_pattern = whitespace_pattern(request);
+        let vocabulary = vocabulary(request)?;
+        let key = self.index_key(&schema, whitespace_pattern.as_deref(), &vocabulary)?;
+        let index = self.cached_index(key, || {
+            let regex = self.compiled_regex(schema.clone(), whitespace_pattern.clone())?;
+            self.build_index(&regex, &vocabulary)
+        })?;
+
+        let transitions: BTreeMap<_, BTreeMap<_, _>> = index
+            .transitions()
+            .iter()
+            .map(|(state, next)| (state.to_string(), next.iter().collect()))
+            .collect();
+        let mut final_states: Vec<_> = index.final_states().iter().collect();
+        final_states.sort();
+        Ok(json!({
+            "initial_state": index.initial_state(),
+            "final_states": final_states,
+            "transitions": transitions,
+        }))
+    }
+
+    fn regex(&self, request: &Value) -> Result<Arc<String>> {
+        self.compiled_regex(schema(request)?, whitespace_pattern(request))
+    }
+
+    pub(crate) fn compiled_regex(
+        &self,
+        schema: String,
+        whitespace_pattern: Option<String>,
+    ) -> Result<Arc<String>> {
+        let key = (schema, whitespace_pattern);
+        if let Some(regex) = self.regexes.lock().unwrap().get(&key) {
+            return Ok(regex);
+        }
+        let options = RegexOptions {
+            whitespace_pattern: key.1.clone(),
+            max_regex_len: self.max_regex_len,
+            max_nodes: self.max_nodes,
+            ..RegexOptions::default()
+        };
+        let regex = Arc::new(build_regex_from_schema_with_options(&key.0, &options)?);
+        self.regexes.lock().unwrap().insert(key, Arc::clone(&regex));
+        Ok(regex)
+    }
+
+    /// The key of the index of `schema` for `vocabulary`, a hash of the
+    /// schema as serde_json writes it, the options the server builds it
+    /// with, and the tokens sorted with their ids, so requests differing in
+    /// how their JSON is written share an index.
+    pub(crate) fn index_key(
+        &self,
+        schema: &str,
+        whitespace_pattern: Option<&str>,
+        vocabulary: &Vocabulary,
+    ) -> Result<u64> {
+        let schema: Value = serde_json::from_str(schema).context("Invalid 'schema'")?;
+        let mut tokens: Vec<(&Vec<u8>, Vec<u32>)> = vocabulary
+            .tokens()
+            .iter()
+            .map(|(token, ids)| {
+                let mut ids = ids.clone();
+                ids.sort_unstable();
+                (token, ids)
+            })
+            .collect();
+        tokens.sort_unstable();
+
+        let mut hasher = DefaultHasher::new();
+        schema.to_string().hash(&mut hasher);
+        whitespace_pattern.hash(&mut hasher);
+        (self.max_regex_len, self.max_nodes, self.max_dfa_bytes).hash(&mut hasher);
+        vocabulary.eos_token_id().hash(&mut hasher);
+        tokens.hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    /// The index of `regex`, within the server's limit on the memory of its DFA.
+    pub(crate) fn build_index(&self, regex: &str, vocabulary: &Vocabulary) -> Result<Index> {
+        let options = IndexOptions {
+            dfa: DfaBuildOptions {
+                max_memory_bytes: self.max_dfa_bytes,
+            },
+            ..IndexOptions::default()
+        };
+        Index::with_options(regex, vocabulary, &options)
+    }
+
+    /// The index cached under `key`, see [`Server::index_key`], building it
+    /// on a miss.
+    pub(crate) fn cached_index(
+        &self,
+        key: u64,
+        build: impl FnOnce() -> Result<Index>,
+    ) -> Result<Arc<Index>> {
+        if let Some(index) = self.indexes.lock().unwrap().get(&key) {
+            return Ok(index);
+        }
+        let index = Arc::new(build()?);
+        self.indexes.lock().unwrap().insert(key, Arc::clone(&index));
+        Ok(index)
+    }
+}
+
+/// The schema of a request, with its overrides.
+fn schema(request: &Value) -> Result<String> {
+    let schema = match request.get("schema") {
+        Some(Value::String(schema)) => schema.clone(),
+        Some(schema @ Value::Object(_)) => schema.to_string(),
+        _ => return Err(anyhow!("'schema' should be an object or a string")),
+    };
+    match request.get("overrides") {
+        Some(overrides) => {
+            let base: Value = serde_json::from_str(&schema).context("Invalid 'schema'")?;
+            Ok(merge_overrides(&base, overrides).to_string())
+        }
+        None => Ok(schema),
+    }
+}
+
+fn whitespace_pattern(request: &Value) -> Option<String> {
+    request
+        .get("whitespace_pattern")
+        .and_then(Value::as_str)
+        .map(String::from)
+}
+
+fn vocabulary(request: &Value) -> Result<Vocabulary> {
+    let eos_token_id = request
+        .get("eos_token_id")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| anyhow!("'eos_token_id' should be a token id"))?;
+    let tokens = request
+        .get("vocabulary")
+        .and_then(Value::as_object)
+        .ok_or_else(|| anyhow!("'vocabulary' should map tokens to ids"))?;
+
+    let mut vocabulary = Vocabulary::new(eos_token_id.try_into()?);
+    for (token, ids) in tokens {
+        let ids = match ids {
+            Value::Array(ids) => ids.iter().collect(),
+            id => vec![id],
+        };
+        for id in ids {
+            let id = id
+                .as_u64()
+                .ok_or_else(|| anyhow!("Invalid id for token {:?}", token))?;
+            vocabulary.try_insert(token.as_bytes(), id.try_into()?)?;
+        }
+    }
+    Ok(vocabulary)
+}
+
+/// Least recently used cache. Eviction scans every entry, which is cheap
+/// for the few hundred entries a server keeps.
+struct LruCache<K, V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<K, (V, u64)>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        self.tick += 1;
+        let (value, last_used) = self.entries.get_mut(key)?;
+        *last_used = self.tick;
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(key, (value, self.tick));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoints() {
+        let server = Server::new(4);
+        let schema = json!({"type": "boolean"});
+
+        let (status, response) = server.handle(
+            "POST",
+            "/compile",
+            json!({"schema": schema}).to_string().as_bytes(),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(response, json!({"regex": "(?:true|false)"}));
+
+        let request = json!({"schema": schema, "overrides": {"const": true}});
+        let (_, response) = server.handle("POST", "/compile", request.to_string().as_bytes());
+        assert_eq!(response, json!({"regex": "true"}));
+
+        let (_, response) = server.handle(
+            "POST",
+            "/validate",
+            json!({"schema": schema, "document": "trap"})
+                .to_string()
+                .as_bytes(),
+        );
+        assert_eq!(
+            response,
+            json!({"result": "mismatch", "offset": 2, "byte": b'a', "expected": "[u]"})
+        );
+
+        let request = json!({
+            "schema": schema,
+            "vocabulary": {"true": 1, "false": 2},
+            "eos_token_id": 0
+        });
+        let (status, response) = server.handle("POST", "/index", request.to_string().as_bytes());
+        assert_eq!(status, 200);
+        assert_eq!(response["final_states"].as_array().unwrap().len(), 1);
+        let initial = response["initial_state"].to_string();
+        assert_eq!(
+            response["transitions"][&initial].as_object().unwrap().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let server = Server::new(4);
+        assert_eq!(server.handle("POST", "/nope", b"{}").0, 404);
+        assert_eq!(server.handle("GET", "/compile", b"").0, 405);
+        assert_eq!(server.handle("POST", "/compile", b"not json").0, 400);
+        assert_eq!(server.handle("POST", "/compile", b"{}").0, 400);
+    }
+
+    #[test]
+    fn test_recursive_schema() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || Server::new(4).with_workers(2).serve_listener(listener));
+
+        let post = |body: Value| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let body = body.to_string();
+            write!(
+                stream,
+                "POST /compile HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let schema = json!({
+            "$defs": {"n": {"type": "object", "properties": {"next": {"$ref": "#/$defs/n"}}}},
+            "$ref": "#/$defs/n"
+        });
+        let response = post(json!({"schema": schema}));
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+        assert!(response.contains("recursive"), "{}", response);
+
+        let response = post(json!({"schema": {"type": "boolean"}}));
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    }
+
+    #[test]
+    fn test_limits() {
+        let server = Server::new(4).with_limits(Some(10), None);
+        let request = json!({"schema": {"type": "string"}});
+        let (status, response) = server.handle("POST", "/compile", request.to_string().as_bytes());
+        assert_eq!(status, 400);
+        assert!(response["error"].as_str().unwrap().contains("10"));
+    }
+
+    #[test]
+    fn test_index_limits_and_key() {
+        let server = Server::new(4).with_max_dfa_bytes(Some(1000));
+        let request = json!({
+            "schema": {"type": "string", "maxLength": 100},
+            "vocabulary": {"a": 1},
+            "eos_token_id": 0
+        });
+        let (status, response) = server.handle("POST", "/index", request.to_string().as_bytes());
+        assert_eq!(status, 400);
+        assert!(response["error"].as_str().unwrap().contains("1000"));
+
+        // the same schema and vocabulary, written differently
+        let server = Server::new(4);
+        let vocabulary = |tokens: &[(&str, u32)]| {
+            let mut vocabulary = Vocabulary::new(0);
+            for &(token, id) in tokens {
+                vocabulary.try_insert(token, id).unwrap();
+            }
+            vocabulary
+        };
+        let key = server
+            .index_key(
+                r#"{"type": "boolean"}"#,
+                None,
+                &vocabulary(&[("true", 1), ("false", 2)]),
+            )
+            .unwrap();
+        let same = server
+            .index_key(
+                r#"{ "type":"boolean" }"#,
+                None,
+                &vocabulary(&[("false", 2), ("true", 1)]),
+            )
+            .unwrap();
+        assert_eq!(key, same);
+        let other = server
+            .index_key(
+                r#"{"type": "boolean"}"#,
+                Some(""),
+                &vocabulary(&[("true", 1), ("false", 2)]),
+            )
+            .unwrap();
+        assert_ne!(key, other);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            Server::new(4)
+                .with_workers(1)
+                .with_timeout(Some(Duration::from_millis(100)))
+                .serve_listener(listener)
+        });
+
+        // a client sending nothing is let go, and the only worker answers the next
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let body = json!({"schema": {"type": "boolean"}}).to_string();
+        write!(
+            stream,
+            "POST /compile HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert_eq!(stalled.read(&mut [0; 1]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_lru_cache() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(1));
+        cache.insert("c", 3);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"c"), Some(3));
+    }
+}
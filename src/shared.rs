@@ -0,0 +1,99 @@
+//! Subexpressions a regex repeats, like the regex of a `$ref` target used in
+//! several places, for backends that can write them once and refer to them,
+//! like the rules of grammars. Regexes can't refer back to a group, so the
+//! regex itself keeps them inlined.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use anyhow::Result;
+use regex_syntax::hir::{Hir, HirKind};
+
+/// The keys of the subexpressions of `hir` written more than once once the
+/// repeated ones are written once, where `key` tells which subexpressions
+/// are the same, `None` for those not worth sharing, like short ones.
+///
+/// The outermost repeated subexpressions are shared, and those only repeated
+/// inside one of them aren't, as that one is written once.
+pub(crate) fn shared_subexpressions<K: Hash + Eq + Clone>(
+    hir: &Hir,
+    key: &mut impl FnMut(&Hir) -> Result<Option<K>>,
+) -> Result<HashSet<K>> {
+    let mut counts = HashMap::new();
+    count(hir, key, &mut counts)?;
+
+    let mut written = HashMap::new();
+    count_written(hir, key, &counts, &mut written)?;
+    Ok(written
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(key, _)| key)
+        .collect())
+}
+
+/// Counts every subexpression of `hir`, by key.
+fn count<K: Hash + Eq>(
+    hir: &Hir,
+    key: &mut impl FnMut(&Hir) -> Result<Option<K>>,
+    counts: &mut HashMap<K, usize>,
+) -> Result<()> {
+    if let Some(key) = key(hir)? {
+        *counts.entry(key).or_default() += 1;
+    }
+    children(hir).try_for_each(|child| count(child, key, counts))
+}
+
+/// Counts the subexpressions of `hir` still written when those repeated in
+/// `counts` are written once, their contents included.
+fn count_written<K: Hash + Eq + Clone>(
+    hir: &Hir,
+    key: &mut impl FnMut(&Hir) -> Result<Option<K>>,
+    counts: &HashMap<K, usize>,
+    written: &mut HashMap<K, usize>,
+) -> Result<()> {
+    if let Some(key) = key(hir)? {
+        let times = written.entry(key.clone()).or_default();
+        *times += 1;
+        if counts[&key] > 1 && *times > 1 {
+            return Ok(());
+        }
+    }
+    children(hir).try_for_each(|child| count_written(child, key, counts, written))
+}
+
+fn children(hir: &Hir) -> impl Iterator<Item = &Hir> {
+    let subs: &[Hir] = match hir.kind() {
+        HirKind::Repetition(repetition) => std::slice::from_ref(&repetition.sub),
+        HirKind::Capture(capture) => std::slice::from_ref(&capture.sub),
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => subs,
+        _ => &[],
+    };
+    subs.iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use regex_syntax::Parser;
+
+    use super::*;
+
+    fn shared(regex: &str, min_len: usize) -> HashSet<String> {
+        let hir = Parser::new().parse(regex).unwrap();
+        let mut key = |hir: &Hir| Ok(Some(hir.to_string()).filter(|text| text.len() >= min_len));
+        shared_subexpressions(&hir, &mut key).unwrap()
+    }
+
+    #[test]
+    fn test_shared_subexpressions() {
+        // `[a-z]+` is only repeated inside the shared group, written once
+        let regex = "(x[a-z]+y),(x[a-z]+y)";
+        assert_eq!(
+            shared(regex, 5),
+            HashSet::from(["((?:x[a-z]+y))".to_string()])
+        );
+        assert_eq!(shared(regex, 100), HashSet::new());
+
+        let regex = "(?:a[0-9]+)|b[0-9]+";
+        assert_eq!(shared(regex, 5), HashSet::from([r"[0-9]+".to_string()]));
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use regex_syntax::hir::{Capture, Hir, HirKind, Repetition};
+use regex_syntax::Parser;
+
+use crate::dialect::{self, Dialect};
+
+/// Rewrites a regex into a smaller one that matches the same inputs.
+///
+/// The pattern is parsed, which already merges adjacent literals, drops
+/// non-capturing groups and folds alternations of single characters into
+/// classes. On top of that, repeated alternation branches are removed,
+/// optional branches make the whole alternation optional (`x|x?` becomes
+/// `x?`), what every branch of an alternation starts with is matched once
+/// (`ab|ac` becomes `a[bc]`) and nested optional repetitions are collapsed.
+/// Capture groups are kept.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(regex_len = regex.len()))
+)]
+pub fn simplify(regex: &str) -> Result<String> {
+    let hir = Parser::new()
+        .parse(regex)
+        .map_err(|e| anyhow!("Failed to parse regex: {}", e))?;
+    dialect::print(&simplify_hir(hir), Dialect::Rust)
+}
+
+fn simplify_hir(hir: Hir) -> Hir {
+    match hir.into_kind() {
+        HirKind::Empty => Hir::empty(),
+        HirKind::Literal(literal) => Hir::literal(literal.0),
+        HirKind::Class(class) => Hir::class(class),
+        HirKind::Look(look) => Hir::look(look),
+        HirKind::Repetition(repetition) => {
+            let optional = is_optional(&repetition);
+            let sub = simplify_hir(*repetition.sub);
+            match sub.kind() {
+                // `(?:x?)?`, `(?:x*)?` and `(?:x+)?` only need one repetition
+                HirKind::Repetition(inner) if optional && inner.greedy && inner.min <= 1 => {
+                    Hir::repetition(Repetition {
+                        min: 0,
+                        ..inner.clone()
+                    })
+                }
+                _ => Hir::repetition(Repetition {
+                    sub: Box::new(sub),
+                    ..repetition
+                }),
+            }
+        }
+        HirKind::Capture(capture) => Hir::capture(Capture {
+            sub: Box::new(simplify_hir(*capture.sub)),
+            ..capture
+        }),
+        HirKind::Concat(subs) => Hir::concat(subs.into_iter().map(simplify_hir).collect()),
+        HirKind::Alternation(subs) => alternation(subs.into_iter().map(simplify_hir).collect()),
+    }
+}
+
+fn alternation(branches: Vec<Hir>) -> Hir {
+    let mut optional = false;
+    let mut unique: Vec<Hir> = Vec::with_capacity(branches.len());
+    for branch in branches {
+        let branch = match branch.into_kind() {
+            HirKind::Empty => {
+                optional = true;
+                continue;
+            }
+            HirKind::Repetition(repetition) if is_optional(&repetition) => {
+                optional = true;
+                *repetition.sub
+            }
+            kind => rebuild(kind),
+        };
+        if !unique.contains(&branch) {
+            unique.push(branch);
+        }
+    }
+
+    let alternation = match unique.len() {
+        0 => return Hir::empty(),
+        1 => unique.pop().expect("one branch"),
+        _ => factor_prefix(unique),
+    };
+    if optional {
+        simplify_hir(Hir::repetition(Repetition {
+            min: 0,
+            max: Some(1),
+            greedy: true,
+            sub: Box::new(alternation),
+        }))
+    } else {
+        alternation
+    }
+}
+
+/// `ab|ac` as `a(?:b|c)`, matching what every branch starts with once.
+fn factor_prefix(branches: Vec<Hir>) -> Hir {
+    let mut branches: Vec<Vec<Hir>> = branches
+        .into_iter()
+        .map(|branch| match branch.into_kind() {
+            HirKind::Concat(subs) => subs,
+            kind => vec![rebuild(kind)],
+        })
+        .collect();
+
+    let mut prefix = Vec::new();
+    while let Some(first) = branches[0].first().cloned() {
+        if branches.iter().all(|branch| branch.first() == Some(&first)) {
+            for branch in &mut branches {
+                branch.remove(0);
+            }
+            prefix.push(first);
+            continue;
+        }
+
+        // literals starting with the same characters
+        let literals: Option<Vec<Box<[u8]>>> = branches
+            .iter()
+            .map(|branch| match branch.first().map(Hir::kind) {
+                Some(HirKind::Literal(literal)) => Some(literal.0.clone()),
+                _ => None,
+            })
+            .collect();
+        if let Some(literals) = literals {
+            let common = common_prefix_len(&literals);
+            if common > 0 {
+                for (branch, literal) in branches.iter_mut().zip(&literals) {
+                    if literal.len() == common {
+                        branch.remove(0);
+                    } else {
+                        branch[0] = Hir::literal(&literal[common..]);
+                    }
+                }
+                prefix.push(Hir::literal(&literals[0][..common]));
+            }
+        }
+        break;
+    }
+
+    let branches = branches.into_iter().map(Hir::concat).collect();
+    if prefix.is_empty() {
+        return Hir::alternation(branches);
+    }
+    prefix.push(alternation(branches));
+    Hir::concat(prefix)
+}
+
+/// The length of the longest common prefix of `literals`, ending on a
+/// character boundary.
+fn common_prefix_len(literals: &[Box<[u8]>]) -> usize {
+    let first = &literals[0];
+    let mut len = literals[1..].iter().fold(first.len(), |len, literal| {
+        first[..len]
+            .iter()
+            .zip(literal.iter())
+            .take_while(|(a, b)| a == b)
+            .count()
+    });
+    while len > 0 && std::str::from_utf8(&first[..len]).is_err() {
+        len -= 1;
+    }
+    len
+}
+
+fn is_optional(repetition: &Repetition) -> bool {
+    repetition.min == 0 && repetition.max == Some(1) && repetition.greedy
+}
+
+/// Rebuilds an already simplified node from its kind.
+fn rebuild(kind: HirKind) -> Hir {
+    match kind {
+        HirKind::Empty => Hir::empty(),
+        HirKind::Literal(literal) => Hir::literal(literal.0),
+        HirKind::Class(class) => Hir::class(class),
+        HirKind::Look(look) => Hir::look(look),
+        HirKind::Repetition(repetition) => Hir::repetition(repetition),
+        HirKind::Capture(capture) => Hir::capture(capture),
+        HirKind::Concat(subs) => Hir::concat(subs),
+        HirKind::Alternation(subs) => Hir::alternation(subs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::diff;
+    use crate::guidance::build_regex_from_schema;
+
+    #[test]
+    fn test_simplify() {
+        assert_eq!(simplify("(?:ab|(?:ab)?)").unwrap(), "(?:ab)?");
+        assert_eq!(simplify("(?:a|b|a|)c").unwrap(), "[ab]?c");
+        assert_eq!(simplify("(?:(?:x+)?)y").unwrap(), "x*y");
+        assert_eq!(
+            simplify(r#"[^"\\\x00-\x1F]\d"#).unwrap(),
+            r#"[^\x00-\x1F"\\]\d"#
+        );
+        assert_eq!(simplify("(a|a)").unwrap(), "(a)");
+        assert_eq!(simplify("(?:abc|abd|ab)").unwrap(), "ab[cd]?");
+        assert_eq!(simplify("(?:xé|xè)").unwrap(), r"x[\xE8\xE9]");
+        assert_eq!(
+            simplify(r"(?:-?(?:0|[1-9][0-9]*)|-?(?:0|[1-9][0-9]*)(?:\.[0-9]+)?)").unwrap(),
+            r"\-?(?:0|[1-9][0-9]*)(?:\.[0-9]+)?"
+        );
+    }
+
+    #[test]
+    fn test_simplified_schema_keeps_meaning() {
+        let schema = r#"{"type": "object", "properties": {"a": {"type": "boolean"}, "b": {"enum": ["x", "y", null]}, "c": {"type": "string", "format": "date"}}}"#;
+        let regex = build_regex_from_schema(schema, None).unwrap();
+        let simplified = simplify(&regex).unwrap();
+        assert!(simplified.len() < regex.len());
+        assert!(diff(&regex, &simplified).unwrap().is_equivalent());
+    }
+}
@@ -0,0 +1,249 @@
+//! Documents split into their fixed text and their values, for engines that
+//! append the text as is and only sample the values.
+//!
+//! Objects whose properties are all required and scalar `const`s are fixed
+//! text. Anything else, like an optional property or an array, is a value,
+//! with its regex and the JSON Pointer of where it is in the document.
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::guidance::{
+    configured_generator, non_capturing, Generator, RegexOptions, WhitespacePositions,
+};
+use crate::keywords;
+
+/// Keywords taking precedence over `const` and `enum` in the generator.
+const BEFORE_CONSTANTS: &[&str] = &["properties", "allOf", "anyOf", "oneOf", "prefixItems"];
+
+/// A piece of a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part {
+    /// Text every document has at this place.
+    Text(String),
+    /// A value the regex allows several forms of.
+    Value {
+        /// JSON Pointer of the value in the document.
+        pointer: String,
+        regex: String,
+    },
+}
+
+/// The fixed text and the values of the documents of a schema, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skeleton {
+    pub parts: Vec<Part>,
+}
+
+impl Skeleton {
+    /// The fixed text with a `{{pointer}}` placeholder for each value, like
+    /// `{"id":{{/id}},"tags":{{/tags}}}`.
+    pub fn template(&self) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Value { pointer, .. } => format!("{{{{{}}}}}", pointer),
+            })
+            .collect()
+    }
+
+    /// The values by pointer, with their regexes.
+    pub fn values(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Text(_) => None,
+            Part::Value { pointer, regex } => Some((pointer.as_str(), regex.as_str())),
+        })
+    }
+
+    /// The regex of the whole documents: the text escaped, and the regexes of
+    /// the values in between.
+    pub fn regex(&self) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => regex::escape(text),
+                Part::Value { regex, .. } => format!("(?:{})", regex),
+            })
+            .collect()
+    }
+}
+
+/// Splits the documents of `json` into their fixed text and their values.
+///
+/// The documents are written without whitespace, whatever the whitespace
+/// patterns of `options`, so that the text between values is fixed.
+pub fn skeleton(json: &str, options: &RegexOptions) -> Result<Skeleton> {
+    let schema: Value = serde_json::from_str(json)?;
+    let options = RegexOptions {
+        whitespace_positions: WhitespacePositions::default(),
+        ..options.clone()
+    };
+    let generator = configured_generator("", &schema, &options);
+    let mut parts = Vec::new();
+    split(&schema, &generator, "", &options, &mut parts)?;
+    Ok(Skeleton { parts })
+}
+
+fn split(
+    schema: &Value,
+    generator: &Generator,
+    pointer: &str,
+    options: &RegexOptions,
+    parts: &mut Vec<Part>,
+) -> Result<()> {
+    if let Some(properties) = fixed_properties(schema, generator)? {
+        push_text(parts, "{");
+        let at_properties = generator.at("properties");
+        for (i, (name, value)) in properties.into_iter().enumerate() {
+            let separator = if i > 0 { "," } else { "" };
+            push_text(
+                parts,
+                &format!("{}{}:", separator, serde_json::to_string(name)?),
+            );
+            let pointer = format!("{}/{}", pointer, name.replace('~', "~0").replace('/', "~1"));
+            split(
+                value,
+                &at_properties.at(name.as_str()),
+                &pointer,
+                options,
+                parts,
+            )?;
+        }
+        push_text(parts, "}");
+        return Ok(());
+    }
+
+    if let Some(value) = constant(schema) {
+        // for the errors of a `const` not of its `type`
+        generator.to_regex(schema)?;
+        push_text(parts, &serde_json::to_string(value)?);
+        return Ok(());
+    }
+
+    let regex = generator.to_regex(schema)?;
+    parts.push(Part::Value {
+        pointer: pointer.to_string(),
+        regex: if options.capture_groups {
+            regex
+        } else {
+            non_capturing(&regex)
+        },
+    });
+    Ok(())
+}
+
+/// The properties of `schema` in the order they're written, when it's an
+/// object having all of them.
+fn fixed_properties<'a>(
+    schema: &'a Value,
+    generator: &Generator,
+) -> Result<Option<Vec<(&'a String, &'a Value)>>> {
+    let Some(obj) = schema.as_object() else {
+        return Ok(None);
+    };
+    let Some(properties) = keywords::get_object(obj, "properties")? else {
+        return Ok(None);
+    };
+    let required = keywords::get_strings(obj, "required")?.unwrap_or_default();
+    if !generator.all_properties_required
+        && !properties
+            .keys()
+            .all(|name| required.contains(&name.as_str()))
+    {
+        return Ok(None);
+    }
+
+    let mut properties: Vec<_> = properties.iter().collect();
+    if generator.sort_properties {
+        properties.sort_by_key(|&(name, _)| name);
+    }
+    Ok(Some(properties))
+}
+
+/// The only value of `schema`, when it's a scalar `const` or `enum`.
+fn constant(schema: &Value) -> Option<&Value> {
+    let obj = schema.as_object()?;
+    if BEFORE_CONSTANTS
+        .iter()
+        .any(|keyword| obj.contains_key(*keyword))
+    {
+        return None;
+    }
+    let value = match (obj.get("const"), obj.get("enum")) {
+        (Some(value), None) => value,
+        (Some(value), Some(Value::Array(values))) if values.contains(value) => value,
+        (None, Some(Value::Array(values))) if values.len() == 1 => &values[0],
+        _ => return None,
+    };
+    (!value.is_array() && !value.is_object()).then_some(value)
+}
+
+fn push_text(parts: &mut Vec<Part>, text: &str) {
+    match parts.last_mut() {
+        Some(Part::Text(last)) => last.push_str(text),
+        _ => parts.push(Part::Text(text.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guidance::build_regex_from_schema_with_options;
+    use regex::Regex;
+    use serde_json::json;
+
+    #[test]
+    fn test_skeleton() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "kind": {"const": "user"},
+                "id": {"type": "integer"},
+                "profile": {
+                    "type": "object",
+                    "properties": {"a/b": {"type": "boolean"}},
+                    "required": ["a/b"]
+                },
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["kind", "id", "profile", "tags"]
+        })
+        .to_string();
+        let options = RegexOptions {
+            sort_properties: true,
+            ..RegexOptions::default()
+        };
+        let skeleton = skeleton(&schema, &options).unwrap();
+
+        assert_eq!(
+            skeleton.template(),
+            r#"{"id":{{/id}},"kind":"user","profile":{"a/b":{{/profile/a~1b}}},"tags":{{/tags}}}"#
+        );
+        let pointers: Vec<&str> = skeleton.values().map(|(pointer, _)| pointer).collect();
+        assert_eq!(pointers, ["/id", "/profile/a~1b", "/tags"]);
+        assert_eq!(skeleton.values().nth(1).unwrap().1, "(?:true|false)");
+
+        let regex = Regex::new(&format!("^(?:{})$", skeleton.regex())).unwrap();
+        let document = r#"{"id":7,"kind":"user","profile":{"a/b":true},"tags":["x","y"]}"#;
+        assert!(regex.is_match(document));
+        let full = build_regex_from_schema_with_options(&schema, &options).unwrap();
+        assert!(Regex::new(&format!("^(?:{})$", full))
+            .unwrap()
+            .is_match(document));
+    }
+
+    #[test]
+    fn test_optional_properties_are_values() {
+        let schema = json!({"properties": {"a": {"type": "integer"}}}).to_string();
+        let skeleton = skeleton(&schema, &RegexOptions::default()).unwrap();
+        assert_eq!(skeleton.template(), "{{}}");
+
+        let options = RegexOptions {
+            treat_all_properties_required: true,
+            ..RegexOptions::default()
+        };
+        let skeleton = super::skeleton(&schema, &options).unwrap();
+        assert_eq!(skeleton.template(), r#"{"a":{{/a}}}"#);
+    }
+}
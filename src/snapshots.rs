@@ -0,0 +1,146 @@
+//! Snapshots of the regexes of the schema corpus in `snapshots/schemas`.
+//!
+//! Each `schemas/<name>.json` has the regex this crate generates for it
+//! stored next to the corpus as `regex_for_<name>_json.txt`. They guard
+//! against unintended changes, and are no reference: the output of Outlines
+//! is in `reference/`. Snapshots are compared by the inputs they match, so
+//! rewriting a regex without changing its meaning doesn't break them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::diff::{self, Counterexample};
+use crate::guidance::build_regex_from_schema;
+
+/// A schema whose regex no longer matches the same inputs as its snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotMismatch {
+    pub schema: PathBuf,
+    /// `None` when the snapshot file is missing.
+    pub counterexample: Option<Counterexample>,
+}
+
+/// The schemas of the corpus, sorted by name.
+pub fn corpus(dir: &Path) -> Result<Vec<PathBuf>> {
+    let schemas_dir = dir.join("schemas");
+    let mut schemas = Vec::new();
+    for entry in fs::read_dir(&schemas_dir)
+        .with_context(|| format!("Failed to read {}", schemas_dir.display()))?
+    {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            schemas.push(path);
+        }
+    }
+    schemas.sort();
+    Ok(schemas)
+}
+
+pub fn snapshot_path(dir: &Path, schema: &Path) -> PathBuf {
+    let name = schema.file_stem().unwrap_or_default().to_string_lossy();
+    dir.join(format!("regex_for_{}_json.txt", name))
+}
+
+/// Regenerates the snapshot of every schema in the corpus and returns their paths.
+pub fn generate(dir: &Path) -> Result<Vec<PathBuf>> {
+    corpus(dir)?
+        .iter()
+        .map(|schema| {
+            let regex = compile(schema)?;
+            let path = snapshot_path(dir, schema);
+            fs::write(&path, regex)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Compares every schema of the corpus with its snapshot.
+pub fn check(dir: &Path) -> Result<Vec<SnapshotMismatch>> {
+    let mut mismatches = Vec::new();
+    for schema in corpus(dir)? {
+        let regex = compile(&schema)?;
+        let Ok(snapshot) = fs::read_to_string(snapshot_path(dir, &schema)) else {
+            mismatches.push(SnapshotMismatch {
+                schema,
+                counterexample: None,
+            });
+            continue;
+        };
+
+        if let Some(counterexample) = diff::diff(&regex, snapshot.trim_end())?.counterexample {
+            mismatches.push(SnapshotMismatch {
+                schema,
+                counterexample: Some(counterexample),
+            });
+        }
+    }
+    Ok(mismatches)
+}
+
+fn compile(schema: &Path) -> Result<String> {
+    let json = fs::read_to_string(schema)
+        .with_context(|| format!("Failed to read {}", schema.display()))?;
+    build_regex_from_schema(&json, None)
+        .with_context(|| format!("Failed to compile {}", schema.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guidance::{build_regex_from_schema_with_options, RegexOptions};
+
+    #[test]
+    fn test_reference_snapshots() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots");
+        assert!(!corpus(&dir).unwrap().is_empty());
+        assert_eq!(
+            check(&dir).unwrap(),
+            [],
+            "run `cargo run -- gen-snapshots` after intended changes"
+        );
+    }
+
+    #[test]
+    fn test_outlines_reference() {
+        // Outlines requires an item in the arrays of unconstrained values,
+        // which legacy doesn't copy: only legacy matches `[]`
+        let divergences = [("empty", "[]")];
+
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut references = 0;
+        for entry in fs::read_dir(root.join("reference")).unwrap() {
+            let path = entry.unwrap().path();
+            let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let Some(name) = file_name
+                .strip_prefix("regex_for_")
+                .and_then(|name| name.strip_suffix("_json.txt"))
+            else {
+                continue;
+            };
+            let schema = root
+                .join("snapshots/schemas")
+                .join(format!("{}.json", name));
+            let json = fs::read_to_string(&schema).unwrap();
+            let regex =
+                build_regex_from_schema_with_options(&json, &RegexOptions::legacy()).unwrap();
+            let reference = fs::read_to_string(&path).unwrap();
+
+            let counterexample = diff::diff(&regex, reference.trim_end())
+                .unwrap()
+                .counterexample;
+            let expected = divergences
+                .iter()
+                .find(|&&(diverging, _)| diverging == name)
+                .map(|&(_, input)| Counterexample {
+                    input: input.to_string(),
+                    matched_by_left: true,
+                });
+            assert_eq!(counterexample, expected, "{}", name);
+            references += 1;
+        }
+        assert!(references > 0);
+    }
+}
@@ -0,0 +1,543 @@
+use anyhow::{anyhow, Result};
+use regex::escape;
+use serde_json::Value;
+
+use crate::error::SchemaError;
+use crate::guidance::{
+    configured_generator, finish_regex, parse_with_options, resolve_whitespace_pattern, Generator,
+    RegexOptions,
+};
+use crate::handle_types::get_num_items_pattern;
+use crate::keywords;
+
+/// Builds a regex for a TOML document equivalent to the JSON schema.
+///
+/// The top level must be an object with `properties`. Nested objects with
+/// `properties` become `[table]` sections and arrays of such objects become
+/// `[[array-of-tables]]` sections; everything else is written as
+/// `key = value` with inline arrays and inline tables. Key/value lines come
+/// before sections, as TOML requires, and every line ends with a newline.
+/// TOML has no null, so schemas that allow `null` are rejected.
+pub fn build_toml_regex_from_schema(json: &str) -> Result<String> {
+    build_toml_regex_from_schema_with_options(json, &RegexOptions::default())
+}
+
+/// Like [`build_toml_regex_from_schema`], with the scalars generated with
+/// `options`, whose `whitespace_pattern` is empty unless set.
+pub fn build_toml_regex_from_schema_with_options(
+    json: &str,
+    options: &RegexOptions,
+) -> Result<String> {
+    let json_value = parse_with_options(json, options)?;
+    let whitespace_pattern =
+        resolve_whitespace_pattern(Some(options.whitespace_pattern.as_deref().unwrap_or("")))?;
+    let generator = configured_generator(&whitespace_pattern, &json_value, options);
+
+    let regex = generator.resolve(&json_value, |generator, schema| match table(schema) {
+        Some(obj) => TomlGenerator.table_body(generator, obj, &[]),
+        None => Err(anyhow!(
+            "TOML documents must be tables: the schema needs 'properties' at the top level"
+        )),
+    })?;
+    finish_regex(&regex, options)
+}
+
+/// A line or a section of a table, optional unless its key is required.
+enum Entry {
+    Line(String),
+    Section(String),
+}
+
+/// The schema as a table, when it has `properties`.
+fn table(schema: &Value) -> Option<&serde_json::Map<String, Value>> {
+    schema
+        .as_object()
+        .filter(|obj| obj.contains_key("properties"))
+}
+
+struct TomlGenerator;
+
+impl TomlGenerator {
+    /// Key/value lines of a table followed by its sub-table sections.
+    fn table_body(
+        &self,
+        generator: &Generator,
+        obj: &serde_json::Map<String, Value>,
+        path: &[String],
+    ) -> Result<String> {
+        let (properties, required) = properties_of(obj)?;
+        let properties_generator = generator.at("properties");
+
+        let mut lines = String::new();
+        let mut sections = String::new();
+        for (name, value) in properties {
+            let is_required = required.contains(&name.as_str());
+            let mut table_path = path.to_vec();
+            table_path.push(render_key(name)?);
+
+            let generator = properties_generator.at(name.as_str());
+            match self.entry(&generator, value, &table_path, is_required)? {
+                Entry::Line(line) => lines += &line,
+                Entry::Section(section) => sections += &section,
+            }
+        }
+
+        Ok(format!("{}{}", lines, sections))
+    }
+
+    /// The entry of the property at the end of `table_path`.
+    fn entry(
+        &self,
+        generator: &Generator,
+        schema: &Value,
+        table_path: &[String],
+        is_required: bool,
+    ) -> Result<Entry> {
+        generator.resolve(schema, |generator, schema| {
+            if let Some(table) = table(schema) {
+                let section = format!(
+                    r"\n?\[{}\]\n{}",
+                    table_path.join(r"\."),
+                    self.table_body(generator, table, table_path)?
+                );
+                return Ok(Entry::Section(optional_unless(section, is_required)));
+            }
+
+            if let Some(entry) = self.array_of_tables(generator, schema, table_path, is_required)? {
+                return Ok(entry);
+            }
+
+            let line = format!(
+                r"{} = {}\n",
+                table_path.last().unwrap(),
+                self.inline(generator, schema)?
+            );
+            Ok(Entry::Line(optional_unless(line, is_required)))
+        })
+    }
+
+    /// The `[[array-of-tables]]` sections of an array of objects with
+    /// `properties`, `None` for other schemas.
+    fn array_of_tables(
+        &self,
+        generator: &Generator,
+        schema: &Value,
+        table_path: &[String],
+        is_required: bool,
+    ) -> Result<Option<Entry>> {
+        let Some(array) = schema.as_object() else {
+            return Ok(None);
+        };
+        let overrides_type = ["allOf", "anyOf", "oneOf", "prefixItems", "enum", "const"]
+            .iter()
+            .any(|key| array.contains_key(*key));
+        if overrides_type || array.get("type").and_then(Value::as_str) != Some("array") {
+            return Ok(None);
+        }
+        let Some(items) = array.get("items") else {
+            return Ok(None);
+        };
+
+        generator.at("items").resolve(items, |generator, items| {
+            let Some(items) = table(items) else {
+                return Ok(None);
+            };
+            let min_items = keywords::get_u64(array, "minItems")?;
+            let max_items = keywords::get_u64(array, "maxItems")?;
+            check_unique_items(generator, array, max_items)?;
+            let Some(num_repeats) = get_num_items_pattern(min_items, max_items) else {
+                // an array that must be empty can only be written inline
+                let line = format!(r"{} = \[\]\n", table_path.last().unwrap());
+                return Ok(Some(Entry::Line(optional_unless(line, is_required))));
+            };
+            let section = format!(
+                r"\n?\[\[{}\]\]\n{}",
+                table_path.join(r"\."),
+                self.table_body(generator, items, table_path)?
+            );
+            let repeated = format!("{0}({0}){1}", section, num_repeats);
+            let allow_empty = !is_required || min_items.unwrap_or(0) == 0;
+            Ok(Some(Entry::Section(optional_unless(
+                repeated,
+                !allow_empty,
+            ))))
+        })
+    }
+
+    /// Pattern for a value written on a single line.
+    fn inline(&self, generator: &Generator, schema: &Value) -> Result<String> {
+        generator.resolve(schema, |generator, schema| {
+            let obj = schema
+                .as_object()
+                .ok_or_else(|| anyhow!("Invalid JSON Schema: expected an object"))?;
+
+            if obj.contains_key("properties") {
+                return self.inline_table(generator, obj);
+            }
+
+            for key in ["anyOf", "oneOf"] {
+                if let Some(branches) = keywords::get_array(obj, key)? {
+                    let branches_generator = generator.at(key);
+                    let patterns: Result<Vec<String>> = branches
+                        .iter()
+                        .enumerate()
+                        .map(|(i, branch)| self.inline(&branches_generator.at(i), branch))
+                        .collect();
+                    return Ok(format!("({})", patterns?.join("|")));
+                }
+            }
+
+            let (keyword, literals): (&str, Vec<&Value>) =
+                match (keywords::get_array(obj, "enum")?, obj.get("const")) {
+                    (Some(values), _) => ("enum", values.iter().collect()),
+                    (_, Some(value)) => ("const", vec![value]),
+                    _ => ("", vec![]),
+                };
+            if literals
+                .iter()
+                .any(|value| !matches!(value, Value::Bool(_) | Value::Number(_) | Value::String(_)))
+            {
+                return Err(SchemaError::new(
+                    "Only string, number and boolean literals can be written in TOML",
+                )
+                .with_keyword(keyword)
+                .into());
+            }
+            if !literals.is_empty() {
+                return Ok(format!("({})", generator.to_regex(schema)?));
+            }
+
+            match keywords::get_str(obj, "type")? {
+                Some("string") | Some("integer") | Some("number") | Some("boolean") => {
+                    Ok(format!("({})", generator.to_regex(schema)?))
+                }
+                Some("array") => self.inline_array(generator, obj),
+                Some("null") => Err(SchemaError::new("TOML has no null value")
+                    .with_keyword("type")
+                    .into()),
+                _ => Err(anyhow!(
+                    "Unsupported JSON Schema structure for the TOML target: {}",
+                    schema
+                )),
+            }
+        })
+    }
+
+    fn inline_array(
+        &self,
+        generator: &Generator,
+        obj: &serde_json::Map<String, Value>,
+    ) -> Result<String> {
+        let min_items = keywords::get_u64(obj, "minItems")?;
+        let max_items = keywords::get_u64(obj, "maxItems")?;
+        check_unique_items(generator, obj, max_items)?;
+        let Some(num_repeats) = get_num_items_pattern(min_items, max_items) else {
+            return Ok(r"\[\]".to_string());
+        };
+        let items = obj
+            .get("items")
+            .ok_or_else(|| anyhow!("Arrays need 'items' for the TOML target"))?;
+        let item = self.inline(&generator.at("items"), items)?;
+        let allow_empty = if min_items.unwrap_or(0) == 0 { "?" } else { "" };
+
+        Ok(format!(
+            r"\[[ ]?({0}(,[ ]?{0}){1}){2}[ ]?\]",
+            item, num_repeats, allow_empty
+        ))
+    }
+
+    fn inline_table(
+        &self,
+        generator: &Generator,
+        obj: &serde_json::Map<String, Value>,
+    ) -> Result<String> {
+        let (properties, required) = properties_of(obj)?;
+        let properties_generator = generator.at("properties");
+
+        let pairs: Vec<(String, bool)> = properties
+            .iter()
+            .map(|(name, value)| {
+                let value = self.inline(&properties_generator.at(name.as_str()), value)?;
+                let pair = format!("{} = {}", render_key(name)?, value);
+                Ok((pair, required.contains(&name.as_str())))
+            })
+            .collect::<Result<_>>()?;
+
+        if pairs.is_empty() {
+            return Ok(r"\{\}".to_string());
+        }
+
+        let first_required = pairs
+            .iter()
+            .position(|(_, required)| *required)
+            .unwrap_or(pairs.len() - 1);
+
+        // one branch per pair that can be the first one present
+        let branches: Vec<String> = (0..=first_required)
+            .map(|first| {
+                let mut branch = pairs[first].0.clone();
+                for (pair, required) in &pairs[first + 1..] {
+                    branch += &optional_unless(format!(", {}", pair), *required);
+                }
+                branch
+            })
+            .collect();
+
+        let allow_empty = if pairs.iter().any(|(_, required)| *required) {
+            ""
+        } else {
+            "?"
+        };
+        Ok(format!(
+            r"\{{[ ]?(({}){})[ ]?\}}",
+            branches.join("|"),
+            allow_empty
+        ))
+    }
+}
+
+fn properties_of(
+    obj: &serde_json::Map<String, Value>,
+) -> Result<(&serde_json::Map<String, Value>, Vec<&str>)> {
+    let properties = keywords::required(keywords::get_object(obj, "properties")?, "properties")?;
+    let required = keywords::get_strings(obj, "required")?.unwrap_or_default();
+
+    Ok((properties, required))
+}
+
+/// Reports `uniqueItems`, which arrays are written without.
+fn check_unique_items(
+    generator: &Generator,
+    obj: &serde_json::Map<String, Value>,
+    max_items: Option<u64>,
+) -> Result<()> {
+    if keywords::get_bool(obj, "uniqueItems")? == Some(true) && max_items.is_none_or(|max| max > 1)
+    {
+        generator.unenforced(
+            SchemaError::new("uniqueItems isn't enforced in TOML arrays")
+                .with_keyword("uniqueItems"),
+        )?;
+    }
+    Ok(())
+}
+
+fn optional_unless(pattern: String, required: bool) -> String {
+    if required {
+        pattern
+    } else {
+        format!("({})?", pattern)
+    }
+}
+
+/// Bare keys are used when allowed, quoted keys otherwise.
+fn render_key(name: &str) -> Result<String> {
+    let is_bare = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if is_bare {
+        Ok(escape(name))
+    } else {
+        Ok(escape(&serde_json::to_string(name)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use serde_json::json;
+
+    use super::*;
+
+    fn toml_regex(schema: &Value) -> Regex {
+        let regex = build_toml_regex_from_schema(&schema.to_string()).unwrap();
+        Regex::new(&format!("^(?:{})$", regex)).unwrap()
+    }
+
+    #[test]
+    fn test_key_values() {
+        let regex = toml_regex(&json!({
+            "type": "object",
+            "properties": {
+                "debug": {"type": "boolean"},
+                "name": {"type": "string"},
+                "retries": {"type": "integer"}
+            },
+            "required": ["name"]
+        }));
+
+        assert!(regex.is_match("debug = true\nname = \"agent\"\nretries = 3\n"));
+        assert!(regex.is_match("name = \"agent\"\n"));
+        assert!(!regex.is_match("debug = true\n"));
+        assert!(!regex.is_match("name = agent\n"));
+    }
+
+    #[test]
+    fn test_tables_come_after_key_values() {
+        let regex = toml_regex(&json!({
+            "type": "object",
+            "properties": {
+                "server": {
+                    "type": "object",
+                    "properties": {"port": {"type": "integer"}},
+                    "required": ["port"]
+                },
+                "title": {"type": "string"}
+            },
+            "required": ["server", "title"]
+        }));
+
+        assert!(regex.is_match("title = \"x\"\n\n[server]\nport = 80\n"));
+        assert!(regex.is_match("title = \"x\"\n[server]\nport = 80\n"));
+        assert!(!regex.is_match("[server]\nport = 80\ntitle = \"x\"\n"));
+    }
+
+    #[test]
+    fn test_array_of_tables() {
+        let regex = toml_regex(&json!({
+            "type": "object",
+            "properties": {
+                "tools": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {"name": {"type": "string"}},
+                        "required": ["name"]
+                    },
+                    "minItems": 1
+                }
+            },
+            "required": ["tools"]
+        }));
+
+        assert!(regex.is_match("[[tools]]\nname = \"a\"\n\n[[tools]]\nname = \"b\"\n"));
+        assert!(!regex.is_match(""));
+    }
+
+    #[test]
+    fn test_inline_arrays_and_tables() {
+        let regex = toml_regex(&json!({
+            "type": "object",
+            "properties": {
+                "point": {
+                    "type": "object",
+                    "properties": {
+                        "x": {"type": "integer"},
+                        "y": {"type": "integer"}
+                    },
+                    "required": ["x", "y"]
+                },
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["tags"]
+        }));
+
+        // `point` has properties and so becomes a table, `tags` stays inline
+        assert!(regex.is_match("tags = [\"a\", \"b\"]\n[point]\nx = 1\ny = 2\n"));
+        assert!(regex.is_match("tags = []\n"));
+
+        let regex = toml_regex(&json!({
+            "type": "object",
+            "properties": {
+                "points": {
+                    "type": "array",
+                    "items": {"anyOf": [{"type": "integer"}, {"type": "string"}]}
+                },
+                "size": {"anyOf": [
+                    {"type": "integer"},
+                    {"type": "object", "properties": {"w": {"type": "integer"}}}
+                ]}
+            },
+            "required": ["points", "size"]
+        }));
+        assert!(regex.is_match("points = [1, \"2\"]\nsize = { w = 3 }\n"));
+        assert!(regex.is_match("points = [1]\nsize = {}\n"));
+    }
+
+    #[test]
+    fn test_quoted_keys() {
+        let regex = toml_regex(&json!({
+            "type": "object",
+            "properties": {"first name": {"type": "string"}},
+            "required": ["first name"]
+        }));
+
+        assert!(regex.is_match("\"first name\" = \"Ada\"\n"));
+    }
+
+    #[test]
+    fn test_null_is_rejected() {
+        let schema = json!({"type": "object", "properties": {"a": {"type": "null"}}});
+        assert!(build_toml_regex_from_schema(&schema.to_string()).is_err());
+
+        let schema = json!({"type": "object", "properties": {"a": {"enum": ["x", null]}}});
+        assert!(build_toml_regex_from_schema(&schema.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_options() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"ports": {"type": "array", "items": {"type": "integer"}, "uniqueItems": true}},
+            "required": ["ports"]
+        });
+        let options = RegexOptions {
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let regex =
+            build_toml_regex_from_schema_with_options(&schema.to_string(), &options).unwrap();
+        let regex = Regex::new(&regex).unwrap();
+        assert!(regex.is_match("ports = [80, 443]\n"));
+        assert!(!regex.is_match("ports = [80, 443]\n\n[extra]\n"));
+
+        let options = RegexOptions {
+            strict: true,
+            ..RegexOptions::default()
+        };
+        let error =
+            build_toml_regex_from_schema_with_options(&schema.to_string(), &options).unwrap_err();
+        let error = SchemaError::find(&error).unwrap();
+        assert_eq!(
+            error.pointer.as_deref(),
+            Some("/properties/ports/uniqueItems")
+        );
+    }
+
+    #[test]
+    fn test_errors_are_located() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "servers": {"type": "array", "items": {"$ref": "#/$defs/server"}}
+            },
+            "$defs": {"server": {"type": "object", "properties": {"host": {"type": "null"}}}}
+        });
+        let error = build_toml_regex_from_schema(&schema.to_string()).unwrap_err();
+        let error = SchemaError::find(&error).unwrap();
+        assert_eq!(
+            error.pointer.as_deref(),
+            Some("/$defs/server/properties/host/type")
+        );
+
+        let schema = json!({
+            "$ref": "#/$defs/node",
+            "$defs": {
+                "node": {"type": "object", "properties": {"child": {"$ref": "#/$defs/node"}}}
+            }
+        });
+        let error = build_toml_regex_from_schema(&schema.to_string()).unwrap_err();
+        let error = SchemaError::find(&error).unwrap();
+        assert!(error.message.contains("recursive"));
+        assert_eq!(
+            error.pointer.as_deref(),
+            Some("/$defs/node/properties/child/$ref")
+        );
+    }
+
+    #[test]
+    fn test_top_level_must_be_a_table() {
+        assert!(build_toml_regex_from_schema(r#"{"type": "string"}"#).is_err());
+    }
+}
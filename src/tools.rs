@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Context, Result};
+use regex::escape;
+use serde_json::json;
+use serde_json::Value;
+
+use crate::guidance::{
+    check_with_options, configured_generator, finish_regex, resolve_whitespace_pattern,
+    RegexOptions,
+};
+
+/// A function/tool the model may call: its name plus a JSON schema for its arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolSchema {
+    pub name: String,
+    pub parameters: Value,
+}
+
+impl ToolSchema {
+    pub fn new(name: &str, parameters: Value) -> Self {
+        Self {
+            name: name.to_string(),
+            parameters,
+        }
+    }
+
+    /// Reads an OpenAI-style tool definition, either
+    /// `{"type": "function", "function": {"name": ..., "parameters": ...}}`
+    /// or the bare `{"name": ..., "parameters": ...}` function object.
+    pub fn from_openai(tool: &Value) -> Result<Self> {
+        let function = tool.get("function").unwrap_or(tool);
+        let name = function
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("Tool definition is missing a string 'name'"))?;
+        // a tool without parameters takes no arguments
+        let parameters = function
+            .get("parameters")
+            .cloned()
+            .unwrap_or_else(|| json!({"type": "object", "properties": {}}));
+
+        Ok(Self::new(name, parameters))
+    }
+}
+
+/// Builds a regex for `{"name": <tool name>, "arguments": <tool arguments>}` where
+/// the arguments are constrained by the schema of whichever tool was named.
+pub fn build_regex_for_tool_calls(
+    tools: &[ToolSchema],
+    whitespace_pattern: Option<&str>,
+) -> Result<String> {
+    let options = RegexOptions {
+        whitespace_pattern: whitespace_pattern.map(str::to_string),
+        ..RegexOptions::default()
+    };
+    build_regex_for_tool_calls_with_options(tools, &options)
+}
+
+/// Like [`build_regex_for_tool_calls`], with the arguments generated with
+/// `options`.
+pub fn build_regex_for_tool_calls_with_options(
+    tools: &[ToolSchema],
+    options: &RegexOptions,
+) -> Result<String> {
+    if tools.is_empty() {
+        return Err(anyhow!("At least one tool is required"));
+    }
+    let mut names = HashSet::new();
+    if let Some(tool) = tools.iter().find(|tool| !names.insert(&tool.name)) {
+        return Err(anyhow!("Tool '{}' is defined more than once", tool.name));
+    }
+
+    let whitespace_pattern = resolve_whitespace_pattern(options.whitespace_pattern.as_deref())?;
+    let whitespace_pattern = whitespace_pattern.as_ref();
+
+    let calls: Result<Vec<String>> = tools
+        .iter()
+        .map(|tool| {
+            let context = || format!("Failed to compile parameters of tool '{}'", tool.name);
+            check_with_options(&tool.parameters, options).with_context(context)?;
+
+            let name = escape(&serde_json::to_string(&tool.name)?);
+            let arguments = configured_generator(whitespace_pattern, &tool.parameters, options)
+                .to_regex(&tool.parameters)
+                .with_context(context)?;
+
+            Ok(format!(
+                r#"\{{{0}"name"{0}:{0}{1}{0},{0}"arguments"{0}:{0}({2}){0}\}}"#,
+                whitespace_pattern, name, arguments
+            ))
+        })
+        .collect();
+
+    finish_regex(&format!("({})", calls?.join("|")), options)
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+    use crate::error::SchemaError;
+
+    fn tools() -> Vec<ToolSchema> {
+        vec![
+            ToolSchema::from_openai(&json!({
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {"city": {"type": "string"}},
+                        "required": ["city"]
+                    }
+                }
+            }))
+            .unwrap(),
+            ToolSchema::from_openai(&json!({
+                "name": "set_alarm",
+                "parameters": {
+                    "type": "object",
+                    "properties": {"hour": {"type": "integer"}},
+                    "required": ["hour"]
+                }
+            }))
+            .unwrap(),
+            ToolSchema::from_openai(&json!({"name": "stop"})).unwrap(),
+        ]
+    }
+
+    fn is_full_match(regex: &str, text: &str) -> bool {
+        Regex::new(&format!("^(?:{})$", regex))
+            .unwrap()
+            .is_match(text)
+    }
+
+    #[test]
+    fn test_arguments_follow_the_selected_tool() {
+        let regex = build_regex_for_tool_calls(&tools(), Some("")).unwrap();
+
+        assert!(is_full_match(
+            &regex,
+            r#"{"name":"get_weather","arguments":{"city":"Paris"}}"#
+        ));
+        assert!(is_full_match(
+            &regex,
+            r#"{"name":"set_alarm","arguments":{"hour":7}}"#
+        ));
+        assert!(is_full_match(&regex, r#"{"name":"stop","arguments":{}}"#));
+
+        // arguments of one tool don't validate against another
+        assert!(!is_full_match(
+            &regex,
+            r#"{"name":"set_alarm","arguments":{"city":"Paris"}}"#
+        ));
+        assert!(!is_full_match(
+            &regex,
+            r#"{"name":"unknown","arguments":{}}"#
+        ));
+    }
+
+    #[test]
+    fn test_tool_name_is_escaped() {
+        let tools = [ToolSchema::new(
+            "a.b",
+            json!({"type": "object", "properties": {}}),
+        )];
+        let regex = build_regex_for_tool_calls(&tools, Some("")).unwrap();
+
+        assert!(is_full_match(&regex, r#"{"name":"a.b","arguments":{}}"#));
+        assert!(!is_full_match(&regex, r#"{"name":"axb","arguments":{}}"#));
+    }
+
+    #[test]
+    fn test_options() {
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            closed_objects: true,
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let regex = build_regex_for_tool_calls_with_options(&tools(), &options).unwrap();
+        assert!(regex.starts_with('^') && regex.ends_with('$'));
+        let regex = Regex::new(&regex).unwrap();
+        assert!(regex.is_match(r#"{"name":"stop","arguments":{}}"#));
+        assert!(!regex.is_match(r#"{"name":"stop","arguments":{"a":1}}"#));
+
+        // errors point into the parameters of their tool
+        let tools = [ToolSchema::new(
+            "f",
+            json!({"type": "object", "properties": {"a": {"type": "string", "format": "nope"}}}),
+        )];
+        let error = build_regex_for_tool_calls_with_options(&tools, &options).unwrap_err();
+        assert!(error.to_string().contains("tool 'f'"));
+        assert_eq!(
+            SchemaError::find(&error).unwrap().pointer.as_deref(),
+            Some("/properties/a")
+        );
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let mut tools = tools();
+        tools.push(ToolSchema::from_openai(&json!({"name": "set_alarm"})).unwrap());
+        let error = build_regex_for_tool_calls(&tools, None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Tool 'set_alarm' is defined more than once"
+        );
+    }
+
+    #[test]
+    fn test_missing_name() {
+        assert!(ToolSchema::from_openai(&json!({"parameters": {}})).is_err());
+    }
+
+    #[test]
+    fn test_no_tools() {
+        assert!(build_regex_for_tool_calls(&[], None).is_err());
+    }
+}
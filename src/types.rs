@@ -1,13 +1,70 @@
-// allow `\"`, `\\`, or any character which isn't a control sequence
-pub static STRING_INNER: &str = r#"([^"\\\x00-\x1F\x7F-\x9F]|\\["\\])"#;
-pub static STRING: &str = r#""([^"\\\x00-\x1F\x7F-\x9F]|\\["\\])*""#;
+use crate::patterns::{
+    BOOLEAN, BYTE, DATE, DATE_TIME, IDN_HOSTNAME, INTEGER, IRI, NULL, NUMBER, TIME, URI_TEMPLATE,
+    UUID,
+};
+pub use crate::patterns::{STRING, STRING_INNER, WHITESPACE};
+/// Forms of numbers to accept besides the ones of JSON, for output read by
+/// parsers that allow them too, or to leave out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NumberSyntax {
+    /// Zeros before the integer part, like `007`.
+    pub leading_zeros: bool,
+    /// A `+` sign, like `+1`.
+    pub plus_sign: bool,
+    /// A fraction without the integer part, like `.5`.
+    pub bare_fractions: bool,
+    /// A fraction on every number of `type: number`, like `5.0` instead of
+    /// `5`, for output read as floats. Integers are written without one.
+    pub force_decimal_for_number: bool,
+}
 
-static INTEGER: &str = r#"(-)?(0|[1-9][0-9]*)"#;
-static NUMBER: &str = r#"((-)?(0|[1-9][0-9]*))(\.[0-9]+)?([eE][+-][0-9]+)?"#;
-static BOOLEAN: &str = r#"(true|false)"#;
-static NULL: &str = r#"null"#;
+impl NumberSyntax {
+    /// Regex for a number with an integer part of `0` or of `[1-9]` then
+    /// `more_digits`, followed by `\.` and `fraction_digits` if given and
+    /// then `exponent`. The same as [`JsonType::Integer`] and
+    /// [`JsonType::Number`] with the defaults of both.
+    pub fn number_regex(
+        &self,
+        more_digits: &str,
+        fraction_digits: Option<&str>,
+        exponent: &str,
+    ) -> String {
+        let sign = if self.plus_sign { "([-+])?" } else { "(-)?" };
+        let zeros = if self.leading_zeros { "0*" } else { "" };
+        let integer = format!("{}(0|[1-9]{})", zeros, more_digits);
+        let optional = if self.force_decimal_for_number {
+            ""
+        } else {
+            "?"
+        };
+        match fraction_digits {
+            None => format!("{}{}", sign, integer),
+            Some(fraction) if self.bare_fractions => format!(
+                r"{}({}(\.{}){}|\.{}){}",
+                sign, integer, fraction, optional, fraction, exponent
+            ),
+            Some(fraction) => format!(
+                r"({}{})(\.{}){}{}",
+                sign, integer, fraction, optional, exponent
+            ),
+        }
+    }
 
-pub static WHITESPACE: &str = r#"[ ]?"#;
+    /// What comes before the integer part of positive numbers.
+    pub fn positive_prefix(&self) -> String {
+        let plus = if self.plus_sign { r"\+?" } else { "" };
+        format!("{}{}", plus, self.zeros())
+    }
+
+    /// What comes between the `-` and the integer part of negative numbers.
+    pub fn zeros(&self) -> &'static str {
+        if self.leading_zeros {
+            "0*"
+        } else {
+            ""
+        }
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub enum JsonType {
@@ -41,17 +98,16 @@ impl JsonType {
     // }
 }
 
-static DATE_TIME: &str = r#""(-?(?:[1-9][0-9]*)?[0-9]{4})-(1[0-2]|0[1-9])-(3[01]|0[1-9]|[12][0-9])T(2[0-3]|[01][0-9]):([0-5][0-9]):([0-5][0-9])(\.[0-9]{3})?(Z)?""#;
-static DATE: &str = r#""(?:\d{4})-(?:0[1-9]|1[0-2])-(?:0[1-9]|[1-2][0-9]|3[0-1])""#;
-static TIME: &str = r#""(2[0-3]|[01][0-9]):([0-5][0-9]):([0-5][0-9])(\\.[0-9]+)?(Z)?""#;
-static UUID: &str = r#""[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}""#;
-
 #[derive(Debug, PartialEq)]
 pub enum FormatType {
     DateTime,
     Date,
     Time,
     Uuid,
+    Byte,
+    Iri,
+    UriTemplate,
+    IdnHostname,
 }
 
 impl FormatType {
@@ -61,15 +117,24 @@ impl FormatType {
             FormatType::Date => DATE,
             FormatType::Time => TIME,
             FormatType::Uuid => UUID,
+            FormatType::Byte => BYTE,
+            FormatType::Iri => IRI,
+            FormatType::UriTemplate => URI_TEMPLATE,
+            FormatType::IdnHostname => IDN_HOSTNAME,
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<FormatType> {
         match s {
             "date-time" => Some(FormatType::DateTime),
             "date" => Some(FormatType::Date),
             "time" => Some(FormatType::Time),
             "uuid" => Some(FormatType::Uuid),
+            "byte" => Some(FormatType::Byte),
+            "iri" => Some(FormatType::Iri),
+            "uri-template" => Some(FormatType::UriTemplate),
+            "idn-hostname" => Some(FormatType::IdnHostname),
             _ => None,
         }
     }
@@ -0,0 +1,543 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+
+use crate::guidance::{non_capturing, resolve_whitespace_pattern, to_regex};
+
+/// Builds a regex for values of the TypeScript type `root` declared in `source`.
+pub fn build_regex_from_typescript(
+    source: &str,
+    root: &str,
+    whitespace_pattern: Option<&str>,
+) -> Result<String> {
+    let schema = typescript_to_schema(source, root)?;
+    let whitespace_pattern = resolve_whitespace_pattern(whitespace_pattern)?;
+    Ok(non_capturing(&to_regex(
+        &schema,
+        Some(&*whitespace_pattern),
+        &schema,
+    )?))
+}
+
+/// Lowers the TypeScript declarations in `source` to a JSON schema for `root`.
+///
+/// Supported: `interface` (with `extends` of other interfaces) and `type`
+/// aliases, optional and `readonly` members, `string`, `number`, `boolean`,
+/// `null`, `any`/`unknown`, literal types, unions, `T[]`, `Array<T>`,
+/// `Record<string, T>`, tuples and inline object types. Declarations become
+/// `$defs` entries; recursive types are rejected.
+pub fn typescript_to_schema(source: &str, root: &str) -> Result<Value> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        declarations: Map::new(),
+        extends: HashMap::new(),
+    };
+    parser.declarations()?;
+    let declarations = parser.resolve_extends()?;
+
+    if !declarations.contains_key(root) {
+        return Err(anyhow!("Type {} is not declared", root));
+    }
+    for name in declarations.keys() {
+        check_not_recursive(&declarations, name, &mut HashSet::new())?;
+    }
+
+    Ok(json!({"$ref": format!("#/$defs/{}", root), "$defs": declarations}))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(String),
+    Punct(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+            }
+            '"' | '\'' => {
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(anyhow!("Unterminated string literal")),
+                        Some(&q) if q == c => break,
+                        Some('\\') => {
+                            let escaped = chars
+                                .get(i + 1)
+                                .ok_or_else(|| anyhow!("Unterminated string literal"))?;
+                            value.push(match escaped {
+                                'n' => '\n',
+                                't' => '\t',
+                                'r' => '\r',
+                                other => *other,
+                            });
+                            i += 2;
+                            continue;
+                        }
+                        Some(&other) => value.push(other),
+                    }
+                    i += 1;
+                }
+                i += 1;
+                tokens.push(Token::Str(value));
+            }
+            '-' | '0'..='9' => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                // an exponent, like `1e3` or `2.5E-4`
+                if matches!(chars.get(i), Some('e' | 'E')) {
+                    let sign = usize::from(matches!(chars.get(i + 1), Some('+' | '-')));
+                    if chars.get(i + 1 + sign).is_some_and(char::is_ascii_digit) {
+                        i += 1 + sign;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(Token::Num(chars[start..i].iter().collect()));
+            }
+            _ if c.is_alphabetic() || c == '_' || c == '$' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$')
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            '{' | '}' | '(' | ')' | '[' | ']' | '<' | '>' | ':' | ';' | ',' | '|' | '?' | '=' => {
+                tokens.push(Token::Punct(c));
+                i += 1;
+            }
+            _ => return Err(anyhow!("Unsupported TypeScript syntax at '{}'", c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    declarations: Map<String, Value>,
+    extends: HashMap<String, Vec<String>>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unexpected end of TypeScript source"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, punct: char) -> bool {
+        if self.peek() == Some(&Token::Punct(punct)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: char) -> Result<()> {
+        match self.next()? {
+            Token::Punct(c) if c == punct => Ok(()),
+            token => Err(anyhow!("Expected '{}', found {:?}", punct, token)),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            token => Err(anyhow!("Expected an identifier, found {:?}", token)),
+        }
+    }
+
+    fn declarations(&mut self) -> Result<()> {
+        while self.peek().is_some() {
+            let mut keyword = self.ident()?;
+            if keyword == "export" {
+                keyword = self.ident()?;
+            }
+
+            match keyword.as_str() {
+                "interface" => {
+                    let name = self.ident()?;
+                    if self.peek() == Some(&Token::Ident("extends".to_string())) {
+                        self.pos += 1;
+                        let mut bases = vec![self.ident()?];
+                        while self.eat(',') {
+                            bases.push(self.ident()?);
+                        }
+                        self.extends.insert(name.clone(), bases);
+                    }
+                    self.expect('{')?;
+                    let schema = self.object_members()?;
+                    self.declarations.insert(name, schema);
+                }
+                "type" => {
+                    let name = self.ident()?;
+                    if self.peek() == Some(&Token::Punct('<')) {
+                        return Err(anyhow!("Generic type {} is not supported", name));
+                    }
+                    self.expect('=')?;
+                    let schema = self.union()?;
+                    self.eat(';');
+                    self.declarations.insert(name, schema);
+                }
+                other => return Err(anyhow!("Unsupported declaration '{}'", other)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Members of an object type, after its opening brace.
+    fn object_members(&mut self) -> Result<Value> {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+
+        while !self.eat('}') {
+            let mut name = match self.next()? {
+                Token::Ident(name) | Token::Str(name) => name,
+                token => return Err(anyhow!("Expected a member name, found {:?}", token)),
+            };
+            if name == "readonly" && !matches!(self.peek(), Some(Token::Punct('?' | ':'))) {
+                name = self.ident()?;
+            }
+
+            let optional = self.eat('?');
+            self.expect(':')?;
+            properties.insert(name.clone(), self.union_of(optional)?);
+            if !optional {
+                required.push(name);
+            }
+
+            if !self.eat(';') && !self.eat(',') && self.peek() != Some(&Token::Punct('}')) {
+                return Err(anyhow!("Expected ';' after member"));
+            }
+        }
+
+        Ok(json!({"type": "object", "properties": properties, "required": required}))
+    }
+
+    fn union(&mut self) -> Result<Value> {
+        self.union_of(false)
+    }
+
+    /// A union, which for an `optional` member can hold `undefined`: it
+    /// stands for leaving the member out, which has no JSON value.
+    fn union_of(&mut self, optional: bool) -> Result<Value> {
+        let undefined = Token::Ident("undefined".to_string());
+        self.eat('|');
+        let mut members = Vec::new();
+        loop {
+            if optional && self.peek() == Some(&undefined) {
+                self.pos += 1;
+            } else {
+                members.push(self.postfix()?);
+            }
+            if !self.eat('|') {
+                break;
+            }
+        }
+
+        if members.is_empty() {
+            return Err(anyhow!("An optional member can't only be undefined"));
+        }
+        if members.len() == 1 {
+            return Ok(members.pop().unwrap());
+        }
+        // unions of literals read better as an enum
+        if members.iter().all(|member| member.get("const").is_some()) {
+            let values: Vec<Value> = members.into_iter().map(|m| m["const"].clone()).collect();
+            return Ok(json!({"enum": values}));
+        }
+        Ok(json!({"anyOf": members}))
+    }
+
+    fn postfix(&mut self) -> Result<Value> {
+        let mut schema = self.primary()?;
+        while self.peek() == Some(&Token::Punct('[')) {
+            self.pos += 1;
+            self.expect(']')?;
+            schema = json!({"type": "array", "items": schema});
+        }
+        Ok(schema)
+    }
+
+    fn primary(&mut self) -> Result<Value> {
+        match self.next()? {
+            Token::Str(value) => Ok(json!({"const": value})),
+            Token::Num(value) => {
+                let number: serde_json::Number = value
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid number literal {}", value))?;
+                // `1e3` is the integer 1000, as JSON writes it
+                let number = match number.as_f64() {
+                    Some(float)
+                        if number.is_f64() && float.fract() == 0.0 && float.abs() < 1e15 =>
+                    {
+                        serde_json::Number::from(float as i64)
+                    }
+                    _ => number,
+                };
+                Ok(json!({"const": number}))
+            }
+            Token::Punct('(') => {
+                let schema = self.union()?;
+                self.expect(')')?;
+                Ok(schema)
+            }
+            Token::Punct('{') => self.object_members(),
+            Token::Punct('[') => {
+                let mut items = Vec::new();
+                while !self.eat(']') {
+                    items.push(self.union()?);
+                    if !self.eat(',') && self.peek() != Some(&Token::Punct(']')) {
+                        return Err(anyhow!("Expected ',' in tuple type"));
+                    }
+                }
+                Ok(json!({"type": "array", "prefixItems": items}))
+            }
+            Token::Ident(name) => match name.as_str() {
+                "string" => Ok(json!({"type": "string"})),
+                "number" => Ok(json!({"type": "number"})),
+                "boolean" => Ok(json!({"type": "boolean"})),
+                "null" => Ok(json!({"type": "null"})),
+                "true" => Ok(json!({"const": true})),
+                "false" => Ok(json!({"const": false})),
+                "any" | "unknown" => Ok(json!({})),
+                "Array" => {
+                    self.expect('<')?;
+                    let items = self.union()?;
+                    self.expect('>')?;
+                    Ok(json!({"type": "array", "items": items}))
+                }
+                "Record" => {
+                    self.expect('<')?;
+                    if self.ident()? != "string" {
+                        return Err(anyhow!("Only Record<string, T> is supported"));
+                    }
+                    self.expect(',')?;
+                    let values = self.union()?;
+                    self.expect('>')?;
+                    Ok(json!({"type": "object", "additionalProperties": values}))
+                }
+                _ => Ok(json!({"$ref": format!("#/$defs/{}", name)})),
+            },
+            token => Err(anyhow!("Unexpected {:?} in type", token)),
+        }
+    }
+
+    /// Copies the members of base interfaces into the interfaces extending them.
+    fn resolve_extends(mut self) -> Result<Map<String, Value>> {
+        let mut resolved = HashSet::new();
+        let names: Vec<String> = self.extends.keys().cloned().collect();
+        for name in names {
+            self.resolve_interface(&name, &mut resolved, &mut Vec::new())?;
+        }
+        Ok(self.declarations)
+    }
+
+    fn resolve_interface(
+        &mut self,
+        name: &str,
+        resolved: &mut HashSet<String>,
+        stack: &mut Vec<String>,
+    ) -> Result<()> {
+        if resolved.contains(name) {
+            return Ok(());
+        }
+        if stack.iter().any(|seen| seen == name) {
+            return Err(anyhow!("Interface {} extends itself", name));
+        }
+        let Some(bases) = self.extends.get(name).cloned() else {
+            return Ok(());
+        };
+
+        stack.push(name.to_string());
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for base in &bases {
+            self.resolve_interface(base, resolved, stack)?;
+            let base_schema = self
+                .declarations
+                .get(base)
+                .filter(|schema| schema.get("properties").is_some())
+                .ok_or_else(|| anyhow!("{} can only extend a declared interface", name))?;
+            properties.extend(base_schema["properties"].as_object().unwrap().clone());
+            required.extend(base_schema["required"].as_array().unwrap().clone());
+        }
+        stack.pop();
+
+        let schema = self.declarations.get_mut(name).unwrap();
+        properties.extend(schema["properties"].as_object().unwrap().clone());
+        required.extend(schema["required"].as_array().unwrap().clone());
+        schema["properties"] = Value::Object(properties);
+        schema["required"] = Value::Array(required);
+
+        resolved.insert(name.to_string());
+        Ok(())
+    }
+}
+
+fn check_not_recursive(
+    declarations: &Map<String, Value>,
+    name: &str,
+    stack: &mut HashSet<String>,
+) -> Result<()> {
+    if !stack.insert(name.to_string()) {
+        return Err(anyhow!(
+            "Recursive type {} can't be mapped to a regex",
+            name
+        ));
+    }
+    let schema = declarations
+        .get(name)
+        .ok_or_else(|| anyhow!("Type {} is not declared", name))?;
+    for reference in references(schema) {
+        check_not_recursive(declarations, reference, stack)?;
+    }
+    stack.remove(name);
+    Ok(())
+}
+
+fn references(schema: &Value) -> Vec<&str> {
+    match schema {
+        Value::Object(obj) => match obj.get("$ref").and_then(Value::as_str) {
+            Some(reference) => vec![reference.trim_start_matches("#/$defs/")],
+            None => obj.values().flat_map(references).collect(),
+        },
+        Value::Array(values) => values.iter().flat_map(references).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+
+    static SOURCE: &str = r#"
+        // a user of the shop
+        export interface Entity {
+            id: number;
+        }
+
+        interface User extends Entity {
+            readonly name: string;
+            role: Role;
+            /* free-form settings */
+            settings: Record<string, boolean>;
+            tags?: string[];
+        }
+
+        type Role = "admin" | 'guest';
+        type Point = [number, number | null];
+    "#;
+
+    fn is_full_match(regex: &str, text: &str) -> bool {
+        Regex::new(&format!("^(?:{})$", regex))
+            .unwrap()
+            .is_match(text)
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = typescript_to_schema(SOURCE, "User").unwrap();
+
+        assert_eq!(schema["$ref"], "#/$defs/User");
+        assert_eq!(schema["$defs"]["Role"], json!({"enum": ["admin", "guest"]}));
+        assert_eq!(
+            schema["$defs"]["User"]["required"],
+            json!(["id", "name", "role", "settings"])
+        );
+        assert_eq!(
+            schema["$defs"]["Point"],
+            json!({
+                "type": "array",
+                "prefixItems": [
+                    {"type": "number"},
+                    {"anyOf": [{"type": "number"}, {"type": "null"}]}
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_regex() {
+        let regex = build_regex_from_typescript(SOURCE, "User", Some("")).unwrap();
+
+        assert!(is_full_match(
+            &regex,
+            r#"{"id":1,"name":"Ada","role":"admin","settings":{}}"#
+        ));
+        assert!(is_full_match(
+            &regex,
+            r#"{"id":1,"name":"Ada","role":"guest","settings":{"dark":true},"tags":["a"]}"#
+        ));
+        assert!(!is_full_match(
+            &regex,
+            r#"{"id":1,"name":"Ada","role":"owner","settings":{}}"#
+        ));
+    }
+
+    #[test]
+    fn test_undefined_and_exponents() {
+        let source =
+            "interface A { x?: string | undefined; y?: undefined | 1e3; z: 2.5E-1 | -1e+2 }";
+        let schema = typescript_to_schema(source, "A").unwrap();
+        let properties = &schema["$defs"]["A"]["properties"];
+        assert_eq!(properties["x"], json!({"type": "string"}));
+        assert_eq!(properties["y"], json!({"const": 1000}));
+        assert_eq!(properties["z"], json!({"enum": [0.25, -100]}));
+        assert_eq!(schema["$defs"]["A"]["required"], json!(["z"]));
+
+        // only optional members can be left out
+        assert!(typescript_to_schema("interface A { x: string | undefined }", "A").is_err());
+        assert!(typescript_to_schema("interface A { x?: undefined }", "A").is_err());
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert!(typescript_to_schema("type A = B;", "A").is_err());
+        assert!(typescript_to_schema("interface Node { next: Node | null }", "Node").is_err());
+        assert!(typescript_to_schema("type A = string & number;", "A").is_err());
+        assert!(typescript_to_schema("type A = string;", "B").is_err());
+    }
+}
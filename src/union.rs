@@ -0,0 +1,122 @@
+//! One regex for several schemas, for endpoints accepting several shapes of
+//! response, which then need to know which shape they got.
+
+use anyhow::{anyhow, Context, Result};
+use regex::RegexSet;
+
+use crate::guidance::{build_regex_from_schema_with_options, RegexOptions};
+
+/// The regex matching the documents of any of several schemas, and the
+/// regexes of each of them to tell a document's schemas apart.
+#[derive(Debug, Clone)]
+pub struct Union {
+    regex: String,
+    branches: Vec<String>,
+    set: RegexSet,
+}
+
+impl Union {
+    /// The regex of the documents of all the schemas, for the index or any
+    /// other automaton.
+    pub fn regex(&self) -> &str {
+        &self.regex
+    }
+
+    /// The regexes of the schemas, in order.
+    pub fn branches(&self) -> &[String] {
+        &self.branches
+    }
+
+    /// The first of the schemas a whole document matches, or `None` when it
+    /// doesn't match the union.
+    pub fn branch(&self, document: &str) -> Option<usize> {
+        self.set.matches(document).iter().next()
+    }
+
+    /// Every schema a whole document matches, as schemas can overlap, like an
+    /// object with optional properties and one with required ones.
+    pub fn matching_branches(&self, document: &str) -> Vec<usize> {
+        self.set.matches(document).iter().collect()
+    }
+}
+
+/// Builds the regex matching the documents of any of `schemas`, each with
+/// `options`.
+pub fn build_union(schemas: &[&str], options: &RegexOptions) -> Result<Union> {
+    if schemas.is_empty() {
+        return Err(anyhow!("At least one schema is required"));
+    }
+
+    let branches = schemas
+        .iter()
+        .enumerate()
+        .map(|(i, schema)| {
+            build_regex_from_schema_with_options(schema, options)
+                .with_context(|| format!("Failed to build the regex of schema {}", i))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let regex = branches
+        .iter()
+        .map(|branch| format!("(?:{})", branch))
+        .collect::<Vec<_>>()
+        .join("|");
+    let set = RegexSet::new(branches.iter().map(|branch| format!("^(?:{})$", branch)))
+        .map_err(|e| anyhow!("Failed to build the regexes of the schemas: {}", e))?;
+
+    Ok(Union {
+        regex,
+        branches,
+        set,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+
+    #[test]
+    fn test_union_branches() {
+        let options = RegexOptions {
+            whitespace_pattern: Some(String::new()),
+            ..RegexOptions::default()
+        };
+        let union = build_union(
+            &[
+                r#"{"type": "object", "properties": {"answer": {"type": "string"}}, "required": ["answer"]}"#,
+                r#"{"type": "object", "properties": {"error": {"type": "integer"}}, "required": ["error"]}"#,
+                r#"{"type": "object", "properties": {"error": {"type": "integer"}}}"#,
+            ],
+            &options,
+        )
+        .unwrap();
+        assert_eq!(union.branches().len(), 3);
+
+        let regex = Regex::new(&format!("^(?:{})$", union.regex())).unwrap();
+        for (document, branches) in [
+            (r#"{"answer":"yes"}"#, vec![0]),
+            (r#"{"error":404}"#, vec![1, 2]),
+            ("{}", vec![2]),
+            (r#"{"answer":404}"#, vec![]),
+        ] {
+            assert_eq!(
+                regex.is_match(document),
+                !branches.is_empty(),
+                "{}",
+                document
+            );
+            assert_eq!(union.matching_branches(document), branches, "{}", document);
+            assert_eq!(union.branch(document), branches.first().copied());
+        }
+    }
+
+    #[test]
+    fn test_union_errors() {
+        let options = RegexOptions::default();
+        assert!(build_union(&[], &options).is_err());
+
+        let error = build_union(&[r#"{"type": "string"}"#, "{"], &options).unwrap_err();
+        assert_eq!(error.to_string(), "Failed to build the regex of schema 1");
+    }
+}
@@ -0,0 +1,455 @@
+use anyhow::{anyhow, Result};
+use regex::escape;
+use serde_json::Value;
+
+use crate::error::SchemaError;
+use crate::guidance::{
+    configured_generator, finish_regex, parse_with_options, resolve_whitespace_pattern, Generator,
+    RegexOptions,
+};
+use crate::handle_types::get_num_items_pattern;
+use crate::keywords;
+
+// character data with the predefined entities
+static TEXT_CHAR: &str = r"([^<&]|&(amp|lt|gt|quot|apos);)";
+static ATTRIBUTE_CHAR: &str = r#"([^<&"]|&(amp|lt|gt|quot|apos);)"#;
+static XML_WHITESPACE: &str = r"[ \t\r\n]*";
+
+/// Builds a regex for an XML document equivalent to the JSON schema (experimental).
+///
+/// The document is a single `root` element. Object properties become child
+/// elements in declaration order, or attributes when marked with the OpenAPI
+/// `"xml": {"attribute": true}` annotation. Arrays repeat their item element,
+/// wrapped in an outer element when `"xml": {"wrapped": true}`, and `xml.name`
+/// renames elements. Scalars are written as character data; `null` has no
+/// XML representation and is rejected.
+pub fn build_xml_regex_from_schema(json: &str, root: &str) -> Result<String> {
+    build_xml_regex_from_schema_with_options(json, root, &RegexOptions::default())
+}
+
+/// Like [`build_xml_regex_from_schema`], with the numbers and booleans
+/// generated with `options`.
+pub fn build_xml_regex_from_schema_with_options(
+    json: &str,
+    root: &str,
+    options: &RegexOptions,
+) -> Result<String> {
+    let json_value = parse_with_options(json, options)?;
+    let whitespace_pattern =
+        resolve_whitespace_pattern(Some(options.whitespace_pattern.as_deref().unwrap_or("")))?;
+    let generator = configured_generator(&whitespace_pattern, &json_value, options);
+
+    let root_name = element_name(&json_value, root)?;
+    let regex = format!(
+        r"(<\?xml[^>]*\?>{0})?{1}{0}",
+        XML_WHITESPACE,
+        XmlGenerator.element(&generator, &json_value, &root_name)?
+    );
+    finish_regex(&regex, options)
+}
+
+struct XmlGenerator;
+
+impl XmlGenerator {
+    /// Pattern for one `<name>...</name>` element holding a value of `schema`.
+    fn element(&self, generator: &Generator, schema: &Value, name: &str) -> Result<String> {
+        generator.resolve(schema, |generator, schema| {
+            let obj = schema
+                .as_object()
+                .ok_or_else(|| anyhow!("Invalid JSON Schema: expected an object"))?;
+            let tag = escape(name);
+
+            if obj.contains_key("properties") {
+                let (attributes, children) = self.object_content(generator, obj)?;
+                return Ok(format!(
+                    "<{0}{1}>{2}{3}</{0}>",
+                    tag, attributes, children, XML_WHITESPACE
+                ));
+            }
+
+            if obj.get("type").and_then(Value::as_str) == Some("array") {
+                // a bare array element holds its items as children
+                return Ok(format!(
+                    "<{0}>{1}{2}</{0}>",
+                    tag,
+                    self.array_elements(generator, obj, name, true)?,
+                    XML_WHITESPACE
+                ));
+            }
+
+            Ok(format!(
+                "<{0}>{1}</{0}>",
+                tag,
+                self.scalar(generator, schema, TEXT_CHAR)?
+            ))
+        })
+    }
+
+    /// Attribute and child element patterns of an object.
+    fn object_content(
+        &self,
+        generator: &Generator,
+        obj: &serde_json::Map<String, Value>,
+    ) -> Result<(String, String)> {
+        let properties =
+            keywords::required(keywords::get_object(obj, "properties")?, "properties")?;
+        let required_properties = keywords::get_strings(obj, "required")?.unwrap_or_default();
+        let properties_generator = generator.at("properties");
+
+        let mut attributes = String::new();
+        let mut children = String::new();
+        for (property, value) in properties {
+            let is_required = required_properties.contains(&property.as_str());
+            let generator = properties_generator.at(property.as_str());
+
+            let (pattern, is_attribute) = generator.resolve(value, |generator, value| {
+                let name = element_name(value, property)?;
+                let is_attribute = value
+                    .pointer("/xml/attribute")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+                let is_array = value.get("type").and_then(Value::as_str) == Some("array");
+
+                let pattern = if is_attribute {
+                    format!(
+                        r#"[ \t\r\n]+{}="{}""#,
+                        escape(&name),
+                        self.scalar(generator, value, ATTRIBUTE_CHAR)?
+                    )
+                } else if is_array && !is_wrapped(value) {
+                    let obj = value.as_object().unwrap();
+                    // the elements are optional already unless required
+                    return Ok((
+                        self.array_elements(generator, obj, &name, is_required)?,
+                        false,
+                    ));
+                } else {
+                    format!(
+                        "{}{}",
+                        XML_WHITESPACE,
+                        self.element(generator, value, &name)?
+                    )
+                };
+
+                if is_required {
+                    Ok((pattern, is_attribute))
+                } else {
+                    Ok((format!("({})?", pattern), is_attribute))
+                }
+            })?;
+
+            if is_attribute {
+                attributes += &pattern;
+            } else {
+                children += &pattern;
+            }
+        }
+
+        Ok((attributes, children))
+    }
+
+    /// Repeated item elements of an array, named after the array itself.
+    fn array_elements(
+        &self,
+        generator: &Generator,
+        obj: &serde_json::Map<String, Value>,
+        name: &str,
+        is_required: bool,
+    ) -> Result<String> {
+        let min_items = keywords::get_u64(obj, "minItems")?;
+        let max_items = keywords::get_u64(obj, "maxItems")?;
+        if keywords::get_bool(obj, "uniqueItems")? == Some(true)
+            && max_items.is_none_or(|max| max > 1)
+        {
+            generator.unenforced(
+                SchemaError::new("uniqueItems isn't enforced in XML elements")
+                    .with_keyword("uniqueItems"),
+            )?;
+        }
+        let Some(num_repeats) = get_num_items_pattern(min_items, max_items) else {
+            return Ok(String::new());
+        };
+
+        let items = obj
+            .get("items")
+            .ok_or_else(|| anyhow!("Arrays need 'items' for the XML target"))?;
+        let item = generator.at("items").resolve(items, |generator, items| {
+            // unnamed items take the name of the array
+            let item_name = element_name(items, name)?;
+            Ok(format!(
+                "{}{}",
+                XML_WHITESPACE,
+                self.element(generator, items, &item_name)?
+            ))
+        })?;
+        let allow_empty = if !is_required || min_items.unwrap_or(0) == 0 {
+            "?"
+        } else {
+            ""
+        };
+
+        Ok(format!("({0}({0}){1}){2}", item, num_repeats, allow_empty))
+    }
+
+    /// Pattern for a scalar written as text made of `char_pattern`.
+    fn scalar(&self, generator: &Generator, schema: &Value, char_pattern: &str) -> Result<String> {
+        let obj = schema
+            .as_object()
+            .ok_or_else(|| anyhow!("Invalid JSON Schema: expected an object"))?;
+
+        let literals: Option<(&str, Vec<&Value>)> =
+            match (keywords::get_array(obj, "enum")?, obj.get("const")) {
+                (Some(values), _) => Some(("enum", values.iter().collect())),
+                (_, Some(value)) => Some(("const", vec![value])),
+                _ => None,
+            };
+        if let Some((keyword, literals)) = literals {
+            let choices: Result<Vec<String>> = literals
+                .into_iter()
+                .map(|literal| match literal {
+                    Value::String(s) => Ok(escape(&escape_text(s))),
+                    Value::Bool(_) | Value::Number(_) => Ok(escape(&literal.to_string())),
+                    _ => Err(SchemaError::new(
+                        "Only string, number and boolean literals can be written in XML",
+                    )
+                    .with_keyword(keyword)
+                    .into()),
+                })
+                .collect();
+            return Ok(format!("({})", choices?.join("|")));
+        }
+
+        match keywords::get_str(obj, "type")? {
+            Some("string") => {
+                if let Some(keyword) = ["pattern", "format"]
+                    .into_iter()
+                    .find(|keyword| obj.contains_key(*keyword))
+                {
+                    return Err(SchemaError::new(format!(
+                        "'{}' is not supported by the XML target yet",
+                        keyword
+                    ))
+                    .with_keyword(keyword)
+                    .into());
+                }
+                let min_length = keywords::get_u64(obj, "minLength")?.unwrap_or(0);
+                let max_length =
+                    keywords::get_u64(obj, "maxLength")?.map_or(String::new(), |n| n.to_string());
+                Ok(format!("{}{{{},{}}}", char_pattern, min_length, max_length))
+            }
+            Some("integer") | Some("number") | Some("boolean") => {
+                Ok(format!("({})", generator.to_regex(schema)?))
+            }
+            Some("null") => Err(SchemaError::new("XML has no null value")
+                .with_keyword("type")
+                .into()),
+            _ => Err(anyhow!(
+                "Unsupported JSON Schema structure for the XML target: {}",
+                schema
+            )),
+        }
+    }
+}
+
+fn is_wrapped(schema: &Value) -> bool {
+    schema
+        .pointer("/xml/wrapped")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// The element name for a schema: its `xml.name` if present, `default` otherwise.
+fn element_name(schema: &Value, default: &str) -> Result<String> {
+    let name = schema
+        .pointer("/xml/name")
+        .and_then(Value::as_str)
+        .unwrap_or(default);
+
+    let is_valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !is_valid {
+        return Err(anyhow!("'{}' is not a valid XML element name", name));
+    }
+
+    Ok(name.to_string())
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use serde_json::json;
+
+    use super::*;
+
+    fn xml_regex(schema: &Value, root: &str) -> Regex {
+        let regex = build_xml_regex_from_schema(&schema.to_string(), root).unwrap();
+        Regex::new(&format!("^(?:{})$", regex)).unwrap()
+    }
+
+    #[test]
+    fn test_elements() {
+        let regex = xml_regex(
+            &json!({
+                "type": "object",
+                "properties": {
+                    "age": {"type": "integer"},
+                    "name": {"type": "string"}
+                },
+                "required": ["name"]
+            }),
+            "person",
+        );
+
+        assert!(regex.is_match("<person><age>36</age><name>Ada</name></person>"));
+        assert!(regex
+            .is_match("<?xml version=\"1.0\"?>\n<person>\n  <name>A &amp; B</name>\n</person>\n"));
+        assert!(!regex.is_match("<person><age>36</age></person>"));
+        assert!(!regex.is_match("<person><name>A & B</name></person>"));
+    }
+
+    #[test]
+    fn test_attributes() {
+        let regex = xml_regex(
+            &json!({
+                "type": "object",
+                "properties": {
+                    "id": {"type": "integer", "xml": {"attribute": true}},
+                    "title": {"type": "string"}
+                },
+                "required": ["id", "title"]
+            }),
+            "book",
+        );
+
+        assert!(regex.is_match(r#"<book id="7"><title>Dune</title></book>"#));
+        assert!(!regex.is_match(r#"<book id="x"><title>Dune</title></book>"#));
+    }
+
+    #[test]
+    fn test_arrays() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "tag": {"type": "array", "items": {"type": "string"}, "minItems": 1},
+                "urls": {
+                    "type": "array",
+                    "items": {"type": "string", "xml": {"name": "url"}},
+                    "xml": {"wrapped": true}
+                }
+            },
+            "required": ["tag"]
+        });
+        let regex = xml_regex(&schema, "post");
+
+        assert!(regex.is_match("<post><tag>a</tag><tag>b</tag></post>"));
+        assert!(regex.is_match("<post><tag>a</tag><urls><url>x</url><url>y</url></urls></post>"));
+        assert!(!regex.is_match("<post></post>"));
+    }
+
+    #[test]
+    fn test_enums_are_escaped() {
+        let regex = xml_regex(&json!({"enum": ["a<b", 3]}), "value");
+
+        assert!(regex.is_match("<value>a&lt;b</value>"));
+        assert!(regex.is_match("<value>3</value>"));
+        assert!(!regex.is_match("<value>a<b</value>"));
+    }
+
+    #[test]
+    fn test_dotted_names() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"a.b": {"type": "array", "items": {"type": "integer"}}}
+        });
+        let regex = xml_regex(&schema, "root");
+
+        assert!(regex.is_match("<root><a.b>1</a.b><a.b>2</a.b></root>"));
+        assert!(!regex.is_match("<root><axb>1</axb></root>"));
+    }
+
+    #[test]
+    fn test_options() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "port": {"type": "array", "items": {"type": "integer"}, "uniqueItems": true}
+            }
+        });
+        let options = RegexOptions {
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let regex =
+            build_xml_regex_from_schema_with_options(&schema.to_string(), "server", &options)
+                .unwrap();
+        let regex = Regex::new(&regex).unwrap();
+        assert!(regex.is_match("<server><port>80</port><port>443</port></server>"));
+        assert!(!regex.is_match("<server></server><server></server>"));
+
+        let options = RegexOptions {
+            strict: true,
+            ..RegexOptions::default()
+        };
+        let error =
+            build_xml_regex_from_schema_with_options(&schema.to_string(), "server", &options)
+                .unwrap_err();
+        let error = SchemaError::find(&error).unwrap();
+        assert_eq!(
+            error.pointer.as_deref(),
+            Some("/properties/port/uniqueItems")
+        );
+    }
+
+    #[test]
+    fn test_errors_are_located() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"author": {"$ref": "#/$defs/person"}},
+            "$defs": {
+                "person": {
+                    "type": "object",
+                    "properties": {"email": {"type": "string", "format": "email"}}
+                }
+            }
+        });
+        let error = build_xml_regex_from_schema(&schema.to_string(), "book").unwrap_err();
+        let error = SchemaError::find(&error).unwrap();
+        assert_eq!(
+            error.pointer.as_deref(),
+            Some("/$defs/person/properties/email/format")
+        );
+
+        let schema = json!({
+            "$ref": "#/$defs/node",
+            "$defs": {
+                "node": {"type": "object", "properties": {"child": {"$ref": "#/$defs/node"}}}
+            }
+        });
+        let error = build_xml_regex_from_schema(&schema.to_string(), "node").unwrap_err();
+        let error = SchemaError::find(&error).unwrap();
+        assert!(error.message.contains("recursive"));
+        assert_eq!(
+            error.pointer.as_deref(),
+            Some("/$defs/node/properties/child/$ref")
+        );
+    }
+
+    #[test]
+    fn test_invalid_element_name() {
+        let schema = json!({"type": "object", "properties": {"first name": {"type": "string"}}});
+        assert!(build_xml_regex_from_schema(&schema.to_string(), "root").is_err());
+        assert!(build_xml_regex_from_schema(r#"{"type": "null"}"#, "root").is_err());
+    }
+}
@@ -0,0 +1,478 @@
+use anyhow::{anyhow, Result};
+use regex::escape;
+use serde_json::Value;
+
+use crate::error::SchemaError;
+use crate::guidance::{
+    configured_generator, finish_regex, parse_with_options, resolve_whitespace_pattern, Generator,
+    RegexOptions,
+};
+use crate::handle_types::get_num_items_pattern;
+use crate::keywords;
+
+/// Builds a regex for a YAML document equivalent to the JSON schema.
+///
+/// Objects with `properties` become block mappings and arrays with `items`
+/// become block sequences, nested `indent` spaces deeper than their key.
+/// Every other value (scalars, enums, unions, unconstrained values) is
+/// written in flow style, i.e. as compact JSON, which is valid YAML.
+/// Each line, including the last one, ends with a newline.
+pub fn build_yaml_regex_from_schema(json: &str, indent: usize) -> Result<String> {
+    build_yaml_regex_from_schema_with_options(json, indent, &RegexOptions::default())
+}
+
+/// Like [`build_yaml_regex_from_schema`], with the flow values generated
+/// with `options`, whose `whitespace_pattern` is empty unless set.
+pub fn build_yaml_regex_from_schema_with_options(
+    json: &str,
+    indent: usize,
+    options: &RegexOptions,
+) -> Result<String> {
+    if indent == 0 {
+        return Err(anyhow!("YAML indent must be at least one space"));
+    }
+
+    let json_value = parse_with_options(json, options)?;
+    let whitespace_pattern =
+        resolve_whitespace_pattern(Some(options.whitespace_pattern.as_deref().unwrap_or("")))?;
+    let generator = configured_generator(&whitespace_pattern, &json_value, options);
+
+    let regex = YamlGenerator { indent }.document(&generator, &json_value)?;
+    finish_regex(&regex, options)
+}
+
+enum Node<'a> {
+    Mapping(&'a serde_json::Map<String, Value>),
+    Sequence(&'a serde_json::Map<String, Value>),
+    Flow(&'a Value),
+}
+
+fn node(schema: &Value) -> Result<Node<'_>> {
+    let obj = match schema {
+        Value::Object(obj) => obj,
+        _ => return Err(anyhow!("Invalid JSON Schema: expected an object")),
+    };
+
+    let overrides_type = ["allOf", "anyOf", "oneOf", "prefixItems", "enum", "const"]
+        .iter()
+        .any(|key| obj.contains_key(*key));
+
+    if obj.contains_key("properties") {
+        Ok(Node::Mapping(obj))
+    } else if !overrides_type
+        && obj.get("type").and_then(Value::as_str) == Some("array")
+        && obj.contains_key("items")
+    {
+        Ok(Node::Sequence(obj))
+    } else {
+        Ok(Node::Flow(schema))
+    }
+}
+
+struct YamlGenerator {
+    indent: usize,
+}
+
+impl YamlGenerator {
+    fn document(&self, generator: &Generator, schema: &Value) -> Result<String> {
+        generator.resolve(schema, |generator, schema| match node(schema)? {
+            Node::Mapping(obj) => Ok(match self.mapping(generator, obj, 0, "")? {
+                (Some(lines), true) => format!(r"(?:{}|\{{\}}\n)", lines),
+                (Some(lines), false) => lines,
+                (None, _) => r"\{\}\n".to_string(),
+            }),
+            Node::Sequence(obj) => Ok(match self.sequence(generator, obj, 0, "")? {
+                (Some(lines), true) => format!(r"(?:{}|\[\]\n)", lines),
+                (Some(lines), false) => lines,
+                (None, _) => r"\[\]\n".to_string(),
+            }),
+            Node::Flow(value) => Ok(format!(r"{}\n", flow(generator, value)?)),
+        })
+    }
+
+    /// Pattern for what follows `key:`, up to and including the line break.
+    fn after_key(&self, generator: &Generator, schema: &Value, column: usize) -> Result<String> {
+        let nested_column = column + self.indent;
+        let nested_indent = " ".repeat(nested_column);
+
+        generator.resolve(schema, |generator, schema| match node(schema)? {
+            Node::Mapping(obj) => Ok(
+                match self.mapping(generator, obj, nested_column, &nested_indent)? {
+                    (Some(lines), true) => format!(r"(?: \{{\}}\n|\n{})", lines),
+                    (Some(lines), false) => format!(r"\n{}", lines),
+                    (None, _) => r" \{\}\n".to_string(),
+                },
+            ),
+            Node::Sequence(obj) => Ok(
+                match self.sequence(generator, obj, nested_column, &nested_indent)? {
+                    (Some(lines), true) => format!(r"(?: \[\]\n|\n{})", lines),
+                    (Some(lines), false) => format!(r"\n{}", lines),
+                    (None, _) => r" \[\]\n".to_string(),
+                },
+            ),
+            Node::Flow(value) => Ok(format!(r" {}\n", flow(generator, value)?)),
+        })
+    }
+
+    /// Pattern for the lines of a non-empty block mapping at `column`, and whether
+    /// the mapping may also be empty. The first line starts with `first_prefix`
+    /// instead of the indentation, which lets a mapping open a sequence entry.
+    fn mapping(
+        &self,
+        generator: &Generator,
+        obj: &serde_json::Map<String, Value>,
+        column: usize,
+        first_prefix: &str,
+    ) -> Result<(Option<String>, bool)> {
+        let properties =
+            keywords::required(keywords::get_object(obj, "properties")?, "properties")?;
+        let required_properties = keywords::get_strings(obj, "required")?.unwrap_or_default();
+
+        let properties_generator = generator.at("properties");
+        let lines: Vec<(String, bool)> = properties
+            .iter()
+            .map(|(name, value)| {
+                let after_key =
+                    self.after_key(&properties_generator.at(name.as_str()), value, column)?;
+                let line = format!("{}:{}", render_key(name)?, after_key);
+                Ok((line, required_properties.contains(&name.as_str())))
+            })
+            .collect::<Result<_>>()?;
+
+        if lines.is_empty() {
+            return Ok((None, true));
+        }
+
+        let indentation = " ".repeat(column);
+        let first_required = lines
+            .iter()
+            .position(|(_, required)| *required)
+            .unwrap_or(lines.len() - 1);
+
+        // one branch per property that can be the first one present
+        let branches: Vec<String> = (0..=first_required)
+            .map(|first| {
+                let mut branch = format!("{}{}", first_prefix, lines[first].0);
+                for (line, required) in &lines[first + 1..] {
+                    if *required {
+                        branch += &format!("{}{}", indentation, line);
+                    } else {
+                        branch += &format!("({}{})?", indentation, line);
+                    }
+                }
+                branch
+            })
+            .collect();
+
+        let can_be_empty = !lines.iter().any(|(_, required)| *required);
+        Ok((Some(format!("(?:{})", branches.join("|"))), can_be_empty))
+    }
+
+    /// Pattern for the entries of a non-empty block sequence at `column`, and
+    /// whether the sequence may also be empty.
+    fn sequence(
+        &self,
+        generator: &Generator,
+        obj: &serde_json::Map<String, Value>,
+        column: usize,
+        first_prefix: &str,
+    ) -> Result<(Option<String>, bool)> {
+        let min_items = keywords::get_u64(obj, "minItems")?;
+        let max_items = keywords::get_u64(obj, "maxItems")?;
+
+        if keywords::get_bool(obj, "uniqueItems")? == Some(true)
+            && max_items.is_none_or(|max| max > 1)
+        {
+            generator.unenforced(
+                SchemaError::new("uniqueItems isn't enforced in block sequences")
+                    .with_keyword("uniqueItems"),
+            )?;
+        }
+
+        let Some(num_repeats) = get_num_items_pattern(min_items, max_items) else {
+            return Ok((None, true));
+        };
+
+        let items = keywords::required(obj.get("items"), "items")?;
+        let items_generator = generator.at("items");
+        let indentation = " ".repeat(column);
+
+        let first_prefix = format!("{}- ", first_prefix);
+        let first_entry = self.entry(&items_generator, items, column, &first_prefix)?;
+        let entry = self.entry(
+            &items_generator,
+            items,
+            column,
+            &format!("{}- ", indentation),
+        )?;
+
+        Ok((
+            Some(format!("{}({}){}", first_entry, entry, num_repeats)),
+            min_items.unwrap_or(0) == 0,
+        ))
+    }
+
+    /// Pattern for a single sequence entry whose first line starts with `prefix`.
+    fn entry(
+        &self,
+        generator: &Generator,
+        items: &Value,
+        column: usize,
+        prefix: &str,
+    ) -> Result<String> {
+        let prefix_pattern = escape(prefix);
+        let nested_column = column + 2;
+
+        generator.resolve(items, |generator, items| match node(items)? {
+            Node::Mapping(obj) => {
+                Ok(match self.mapping(generator, obj, nested_column, prefix)? {
+                    (Some(lines), true) => format!(r"(?:{}|{}\{{\}}\n)", lines, prefix_pattern),
+                    (Some(lines), false) => lines,
+                    (None, _) => format!(r"{}\{{\}}\n", prefix_pattern),
+                })
+            }
+            Node::Sequence(obj) => Ok(
+                match self.sequence(generator, obj, nested_column, prefix)? {
+                    (Some(lines), true) => format!(r"(?:{}|{}\[\]\n)", lines, prefix_pattern),
+                    (Some(lines), false) => lines,
+                    (None, _) => format!(r"{}\[\]\n", prefix_pattern),
+                },
+            ),
+            Node::Flow(value) => Ok(format!(r"{}{}\n", prefix_pattern, flow(generator, value)?)),
+        })
+    }
+}
+
+/// Pattern for a value written in flow style, as JSON.
+fn flow(generator: &Generator, schema: &Value) -> Result<String> {
+    Ok(format!("({})", generator.to_regex(schema)?))
+}
+
+/// Keys are written as plain scalars when that's unambiguous, quoted otherwise.
+fn render_key(name: &str) -> Result<String> {
+    let is_plain = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !["true", "false", "null", "yes", "no", "on", "off", "y", "n"]
+            .contains(&name.to_ascii_lowercase().as_str());
+
+    if is_plain {
+        Ok(escape(name))
+    } else {
+        Ok(escape(&serde_json::to_string(name)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use serde_json::json;
+
+    use super::*;
+
+    fn yaml_regex(schema: &Value, indent: usize) -> Regex {
+        let regex = build_yaml_regex_from_schema(&schema.to_string(), indent).unwrap();
+        Regex::new(&format!("^(?:{})$", regex)).unwrap()
+    }
+
+    #[test]
+    fn test_flat_mapping() {
+        let regex = yaml_regex(
+            &json!({
+                "type": "object",
+                "properties": {
+                    "id": {"type": "integer"},
+                    "name": {"type": "string"}
+                },
+                "required": ["name"]
+            }),
+            2,
+        );
+
+        assert!(regex.is_match("id: 36\nname: \"Ada\"\n"));
+        assert!(regex.is_match("name: \"Ada\"\n"));
+        assert!(!regex.is_match("id: 36\n"));
+        assert!(!regex.is_match("id: \"36\"\nname: \"Ada\"\n"));
+    }
+
+    #[test]
+    fn test_optional_properties() {
+        let regex = yaml_regex(
+            &json!({
+                "type": "object",
+                "properties": {
+                    "a": {"type": "integer"},
+                    "b": {"type": "integer"}
+                }
+            }),
+            2,
+        );
+
+        assert!(regex.is_match("a: 1\nb: 2\n"));
+        assert!(regex.is_match("b: 2\n"));
+        assert!(regex.is_match("{}\n"));
+        assert!(!regex.is_match(""));
+    }
+
+    #[test]
+    fn test_nested_mapping_and_sequence() {
+        let regex = yaml_regex(
+            &json!({
+                "type": "object",
+                "properties": {
+                    "server": {
+                        "type": "object",
+                        "properties": {
+                            "host": {"type": "string"},
+                            "ports": {"type": "array", "items": {"type": "integer"}, "minItems": 1}
+                        },
+                        "required": ["host", "ports"]
+                    }
+                },
+                "required": ["server"]
+            }),
+            4,
+        );
+
+        assert!(regex.is_match(
+            "server:\n    host: \"localhost\"\n    ports:\n        - 80\n        - 443\n"
+        ));
+        assert!(!regex.is_match("server:\n  host: \"localhost\"\n  ports:\n    - 80\n"));
+        assert!(!regex.is_match("server:\n    host: \"localhost\"\n    ports: []\n"));
+    }
+
+    #[test]
+    fn test_sequence_of_mappings() {
+        let regex = yaml_regex(
+            &json!({
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "id": {"type": "integer"},
+                        "tags": {"type": "array", "items": {"type": "string"}}
+                    },
+                    "required": ["id"]
+                }
+            }),
+            2,
+        );
+
+        assert!(regex.is_match("- id: 1\n- id: 2\n  tags:\n    - \"x\"\n"));
+        assert!(regex.is_match("- id: 1\n  tags: []\n"));
+        assert!(regex.is_match("[]\n"));
+        assert!(!regex.is_match("- id: 1\n tags: []\n"));
+    }
+
+    #[test]
+    fn test_refs_and_flow_values() {
+        let regex = yaml_regex(
+            &json!({
+                "type": "object",
+                "properties": {
+                    "color": {"enum": ["red", "green"]},
+                    "owner": {"$ref": "#/definitions/person"}
+                },
+                "required": ["owner", "color"],
+                "definitions": {
+                    "person": {
+                        "type": "object",
+                        "properties": {"name": {"type": "string"}},
+                        "required": ["name"]
+                    }
+                }
+            }),
+            2,
+        );
+
+        assert!(regex.is_match("color: \"red\"\nowner:\n  name: \"Ada\"\n"));
+        assert!(!regex.is_match("color: \"blue\"\nowner:\n  name: \"Ada\"\n"));
+    }
+
+    #[test]
+    fn test_keys_needing_quotes() {
+        let regex = yaml_regex(
+            &json!({
+                "type": "object",
+                "properties": {
+                    "first name": {"type": "string"},
+                    "yes": {"type": "boolean"}
+                },
+                "required": ["yes", "first name"]
+            }),
+            2,
+        );
+
+        assert!(regex.is_match("\"first name\": \"Ada\"\n\"yes\": true\n"));
+        assert!(!regex.is_match("\"first name\": \"Ada\"\nyes: true\n"));
+    }
+
+    #[test]
+    fn test_options() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "point": {"type": "array", "prefixItems": [{"type": "integer"}, {"type": "integer"}]},
+                "ports": {"type": "array", "items": {"type": "integer"}, "uniqueItems": true}
+            },
+            "required": ["point", "ports"]
+        });
+        let options = RegexOptions {
+            whitespace_pattern: Some("[ ]?".to_string()),
+            anchored: true,
+            ..RegexOptions::default()
+        };
+        let regex =
+            build_yaml_regex_from_schema_with_options(&schema.to_string(), 2, &options).unwrap();
+        let regex = Regex::new(&regex).unwrap();
+        assert!(regex.is_match("point: [1, 2]\nports:\n  - 80\n"));
+        assert!(!regex.is_match("point: [1, 2]\nports: [80]\n"));
+
+        let options = RegexOptions {
+            strict: true,
+            ..RegexOptions::default()
+        };
+        let error = build_yaml_regex_from_schema_with_options(&schema.to_string(), 2, &options)
+            .unwrap_err();
+        let error = SchemaError::find(&error).unwrap();
+        assert_eq!(
+            error.pointer.as_deref(),
+            Some("/properties/ports/uniqueItems")
+        );
+    }
+
+    #[test]
+    fn test_errors_are_located() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "tags": {"type": "array", "items": {"$ref": "#/$defs/tag"}}
+            },
+            "$defs": {"tag": {"type": "object", "properties": {"name": {"pattern": "a+"}}}}
+        });
+        let error = build_yaml_regex_from_schema(&schema.to_string(), 2).unwrap_err();
+        let error = SchemaError::find(&error).unwrap();
+        assert_eq!(error.pointer.as_deref(), Some("/$defs/tag/properties/name"));
+
+        let schema = json!({
+            "$ref": "#/$defs/node",
+            "$defs": {
+                "node": {"type": "object", "properties": {"child": {"$ref": "#/$defs/node"}}}
+            }
+        });
+        let error = build_yaml_regex_from_schema(&schema.to_string(), 2).unwrap_err();
+        let error = SchemaError::find(&error).unwrap();
+        assert!(error.message.contains("recursive"));
+        assert_eq!(
+            error.pointer.as_deref(),
+            Some("/$defs/node/properties/child/$ref")
+        );
+    }
+
+    #[test]
+    fn test_zero_indent() {
+        assert!(build_yaml_regex_from_schema(r#"{"type": "string"}"#, 0).is_err());
+    }
+}